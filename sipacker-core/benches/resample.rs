@@ -0,0 +1,54 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use rubato::Resampler;
+use sipacker_core::resample::StreamResampler;
+
+const DEVICE_SAMPLE_RATE: usize = 48000;
+const G711_SAMPLE_RATE: usize = 8000;
+const CALLBACK_FRAMES: usize = 480;
+
+fn callback_data() -> Vec<f32> {
+    (0..CALLBACK_FRAMES)
+        .map(|i| (i as f32 * 0.05).sin() * 0.5)
+        .collect()
+}
+
+fn rebuild_per_callback(c: &mut Criterion) {
+    let data = callback_data();
+    c.bench_function("resample rebuild per callback", |b| {
+        b.iter_batched(
+            || data.clone(),
+            |data| {
+                let mut resampler = rubato::FftFixedIn::<f32>::new(
+                    DEVICE_SAMPLE_RATE,
+                    G711_SAMPLE_RATE,
+                    data.len(),
+                    4,
+                    1,
+                )
+                .unwrap();
+                resampler.process(&[data], None).unwrap().concat()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn persistent_resampler(c: &mut Criterion) {
+    let data = callback_data();
+    let mut resampler = StreamResampler::new(
+        DEVICE_SAMPLE_RATE,
+        G711_SAMPLE_RATE,
+        DEVICE_SAMPLE_RATE / 100,
+    )
+    .unwrap();
+    let mut output = Vec::with_capacity(CALLBACK_FRAMES);
+    c.bench_function("resample persistent", |b| {
+        b.iter(|| {
+            output.clear();
+            resampler.process(&data, &mut output).unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, rebuild_per_callback, persistent_resampler);
+criterion_main!(benches);
@@ -0,0 +1,1066 @@
+use crate::{
+    pipeline::{self, AudioConsumer, AudioProducer, LatencyBudget},
+    player,
+    tone::{Tone, ToneGenerator, ToneSlot},
+};
+
+use std::{
+    fmt::Display,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Result;
+use cpal::traits::{DeviceTrait, HostTrait};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioBackend {
+    Cpal,
+    Null,
+    // continuous sine of the frequency (Hz) as the capture source and a discard sink
+    Tone(f32),
+}
+
+impl FromStr for AudioBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cpal" => Ok(Self::Cpal),
+            "null" => Ok(Self::Null),
+            "tone" => Ok(Self::Tone(DEFAULT_TEST_TONE_FREQUENCY)),
+            _ => match s.strip_prefix("tone:").map(str::parse::<f32>) {
+                Some(Ok(frequency)) if frequency > 0.0 && frequency < 4000.0 => {
+                    Ok(Self::Tone(frequency))
+                }
+                Some(_) => Err(anyhow::Error::msg(format!(
+                    "Invalid tone frequency: {s}. It must be in range (0; 4000) Hz"
+                ))),
+                None => Err(anyhow::Error::msg(format!("Unknown audio backend: {s}"))),
+            },
+        }
+    }
+}
+
+impl Display for AudioBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cpal => write!(f, "cpal"),
+            Self::Null => write!(f, "null"),
+            Self::Tone(frequency) => write!(f, "tone:{frequency}"),
+        }
+    }
+}
+
+const DEFAULT_TEST_TONE_FREQUENCY: f32 = 440.0;
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+const SUPPORTED_SAMPLE_FORMATS: [cpal::SampleFormat; 10] = [
+    cpal::SampleFormat::I8,
+    cpal::SampleFormat::I16,
+    cpal::SampleFormat::I32,
+    cpal::SampleFormat::I64,
+    cpal::SampleFormat::U8,
+    cpal::SampleFormat::U16,
+    cpal::SampleFormat::U32,
+    cpal::SampleFormat::U64,
+    cpal::SampleFormat::F32,
+    cpal::SampleFormat::F64,
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaptureChannel {
+    Index(usize),
+    #[default]
+    Mix,
+}
+
+impl FromStr for CaptureChannel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mix" => Ok(Self::Mix),
+            _ => s
+                .parse()
+                .map(Self::Index)
+                .map_err(|_err| anyhow::Error::msg(format!("Invalid capture channel: {s}"))),
+        }
+    }
+}
+
+impl Display for CaptureChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{index}"),
+            Self::Mix => write!(f, "mix"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioConfig {
+    pub backend: AudioBackend,
+    pub files: AudioFiles,
+    pub capture_channel: CaptureChannel,
+    pub sample_rate: Option<u32>,
+    pub buffer_size: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioFiles {
+    pub input: Option<PathBuf>,
+    pub input_looped: bool,
+    pub output: Option<PathBuf>,
+}
+
+/// The input and the output audio streams of the configured backend.
+pub struct AudioSystem {
+    _host: cpal::Host,
+    out_device: Device<direction::Output>,
+    in_device: Device<direction::Input>,
+    pipe_capacity: usize,
+    tone: ToneSlot,
+    tone_only_output: bool,
+    level: LevelSlot,
+    meter_only_input: bool,
+    input_gain: Gain,
+    output_gain: Gain,
+}
+
+#[derive(Clone)]
+struct Gain(Arc<AtomicU32>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioLevel {
+    pub rms: f32,
+    pub peak: f32,
+}
+
+type LevelSlot = Arc<Mutex<LevelMeter>>;
+// the last error of the stream of the device, e.g. the unplugged headset
+type FaultSlot = Arc<Mutex<Option<String>>>;
+
+#[derive(Default)]
+struct LevelMeter {
+    sum_squares: f64,
+    peak: f32,
+    count: usize,
+}
+
+struct Device<D> {
+    name: String,
+    config: cpal::SupportedStreamConfig,
+    buffer_size: cpal::BufferSize,
+    stream: Option<direction::Stream>,
+    fault: FaultSlot,
+    direction: D,
+}
+
+impl AudioSystem {
+    pub fn build(audio_config: &AudioConfig) -> Result<Self, anyhow::Error> {
+        let host = cpal::default_host();
+        let tone = ToneSlot::default();
+        let output_gain = Gain::default();
+        let out_device = Device::<direction::Output>::build(
+            audio_config,
+            &host,
+            tone.clone(),
+            output_gain.clone(),
+        )?;
+        let level = LevelSlot::default();
+        let input_gain = Gain::default();
+        let in_device = Device::<direction::Input>::build(
+            audio_config,
+            &host,
+            level.clone(),
+            input_gain.clone(),
+        )?;
+        tracing::info!("Audio backend: {}", audio_config.backend);
+        Ok(Self {
+            _host: host,
+            out_device,
+            in_device,
+            pipe_capacity: pipeline::SAMPLE_RATE,
+            tone,
+            tone_only_output: false,
+            level,
+            meter_only_input: false,
+            input_gain,
+            output_gain,
+        })
+    }
+
+    pub fn create_output_stream(&mut self) -> Result<AudioProducer, anyhow::Error> {
+        if self.tone_only_output {
+            self.destroy_output_stream();
+        }
+        let (producer, consumer) = pipeline::audio_pipe(self.pipe_capacity);
+        self.out_device
+            .create_stream(direction::Channel::Output(consumer))?;
+        tracing::info!("Output stream is created");
+        Ok(producer)
+    }
+
+    pub fn destroy_output_stream(&mut self) {
+        self.out_device.destroy_stream();
+        self.tone_only_output = false;
+        tracing::info!("Output stream is destroyed");
+    }
+
+    pub fn play_tone(&mut self, tone: Tone) -> Result<(), anyhow::Error> {
+        self.play_tones(&[tone])
+    }
+
+    pub fn play_tones(&mut self, tones: &[Tone]) -> Result<(), anyhow::Error> {
+        if self.out_device.stream.is_none() {
+            let (_producer, consumer) = pipeline::audio_pipe(pipeline::FRAME_SAMPLES);
+            self.out_device
+                .create_stream(direction::Channel::Output(consumer))?;
+            self.tone_only_output = true;
+        }
+
+        let sample_rate = self.out_device.config.sample_rate().0;
+        let generator = ToneGenerator::sequence(tones.iter().copied(), sample_rate)
+            .ok_or(anyhow::Error::msg(format!("Unsupported tones: {tones:?}")))?;
+        let _ = self.lock_tone().replace(generator);
+        tracing::debug!("Playing the tones: {tones:?}");
+        Ok(())
+    }
+
+    pub fn stop_tone(&mut self) {
+        self.lock_tone().take();
+    }
+
+    fn lock_tone(&self) -> std::sync::MutexGuard<'_, Option<ToneGenerator>> {
+        self.tone.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn create_input_stream(&mut self) -> Result<AudioConsumer, anyhow::Error> {
+        if self.meter_only_input {
+            self.destroy_input_stream();
+        }
+        let (producer, consumer) = pipeline::audio_pipe(self.pipe_capacity);
+        self.in_device
+            .create_stream(direction::Channel::Input(producer))?;
+        tracing::info!("Input stream is created");
+        Ok(consumer)
+    }
+
+    pub fn destroy_input_stream(&mut self) {
+        self.in_device.destroy_stream();
+        self.meter_only_input = false;
+        tracing::info!("Input stream is destroyed");
+    }
+
+    pub fn start_meter(&mut self) -> Result<(), anyhow::Error> {
+        if self.in_device.stream.is_none() {
+            let (producer, _consumer) = pipeline::audio_pipe(pipeline::FRAME_SAMPLES);
+            self.in_device
+                .create_stream(direction::Channel::Input(producer))?;
+            self.meter_only_input = true;
+        }
+        Ok(())
+    }
+
+    pub fn stop_meter(&mut self) {
+        if self.meter_only_input {
+            self.destroy_input_stream();
+        }
+    }
+
+    pub fn input_name(&self) -> &str {
+        &self.in_device.name
+    }
+
+    pub fn output_name(&self) -> &str {
+        &self.out_device.name
+    }
+
+    pub fn pipe_capacity(&self) -> usize {
+        self.pipe_capacity
+    }
+
+    pub fn set_latency(&mut self, latency: &LatencyBudget) {
+        self.pipe_capacity = latency.pipe_capacity;
+        tracing::info!(
+            "Audio channel capacity is set to {} samples",
+            self.pipe_capacity
+        );
+    }
+
+    pub fn set_input_volume(&mut self, percent: u32) {
+        self.input_gain.set(percent as f32 / 100.0);
+        tracing::info!("Input volume is set to {percent}%");
+    }
+
+    pub fn set_output_volume(&mut self, percent: u32) {
+        self.output_gain.set(percent as f32 / 100.0);
+        tracing::info!("Output volume is set to {percent}%");
+    }
+
+    /// The last failure of the audio streams, e.g. the device which is gone during the call.
+    /// It's cleared once the stream of the failed device is created again.
+    pub fn fault(&self) -> Option<String> {
+        [&self.out_device.fault, &self.in_device.fault]
+            .into_iter()
+            .find_map(|fault| fault.lock().unwrap_or_else(|err| err.into_inner()).clone())
+    }
+
+    pub fn take_input_level(&mut self) -> Option<AudioLevel> {
+        self.level
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take()
+    }
+}
+
+impl Default for Gain {
+    fn default() -> Self {
+        Self(Arc::new(AtomicU32::new(1.0_f32.to_bits())))
+    }
+}
+
+impl Gain {
+    fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn set(&self, gain: f32) {
+        self.0.store(gain.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl LevelMeter {
+    fn measure(&mut self, samples: &[f32]) {
+        for sample in samples {
+            self.sum_squares += (*sample as f64).powi(2);
+            self.peak = self.peak.max(sample.abs());
+        }
+        self.count += samples.len();
+    }
+
+    fn take(&mut self) -> Option<AudioLevel> {
+        if self.count == 0 {
+            return None;
+        }
+        let level = AudioLevel {
+            rms: (self.sum_squares / self.count as f64).sqrt() as f32,
+            peak: self.peak,
+        };
+        *self = Self::default();
+        Some(level)
+    }
+}
+
+impl<D: direction::DirectionTrait> Device<D> {
+    fn destroy_stream(&mut self) {
+        self.stream.take();
+    }
+
+    fn create_stream(&mut self, channel: direction::Channel) -> Result<(), anyhow::Error> {
+        if self.stream.is_some() {
+            return Err(anyhow::Error::msg(
+                "Could not create a stream. It is already created",
+            ));
+        }
+
+        let sample_format: cpal::SampleFormat = self.config.sample_format();
+        let stream = match sample_format {
+            cpal::SampleFormat::I8 => self.run_stream::<i8>(channel),
+            cpal::SampleFormat::I16 => self.run_stream::<i16>(channel),
+            cpal::SampleFormat::I32 => self.run_stream::<i32>(channel),
+            cpal::SampleFormat::I64 => self.run_stream::<i64>(channel),
+            cpal::SampleFormat::U8 => self.run_stream::<u8>(channel),
+            cpal::SampleFormat::U16 => self.run_stream::<u16>(channel),
+            cpal::SampleFormat::U32 => self.run_stream::<u32>(channel),
+            cpal::SampleFormat::U64 => self.run_stream::<u64>(channel),
+            cpal::SampleFormat::F32 => self.run_stream::<f32>(channel),
+            cpal::SampleFormat::F64 => self.run_stream::<f64>(channel),
+            sample_format => Err(anyhow::Error::msg(format!(
+                "Could not create a stream. Unsupported sample format '{sample_format}'"
+            ))),
+        };
+        *self.fault.lock().unwrap_or_else(|err| err.into_inner()) = stream
+            .as_ref()
+            .err()
+            .map(|err| format!("{}: {err}", self.name));
+        self.stream = Some(stream?);
+        Ok(())
+    }
+
+    fn run_stream<T>(&self, channel: direction::Channel) -> Result<direction::Stream>
+    where
+        T: cpal::SizedSample + dasp_sample::conv::ToSample<f32> + cpal::FromSample<f32> + Default,
+    {
+        let mut config = cpal::StreamConfig::from(self.config.clone());
+        config.buffer_size = self.buffer_size.clone();
+        self.direction
+            .build_stream::<T>(config, channel, self.fault.clone())
+    }
+
+    fn select_config<I>(
+        default_config: Option<cpal::SupportedStreamConfig>,
+        supported_configs: I,
+        sample_rate: Option<u32>,
+    ) -> Result<cpal::SupportedStreamConfig>
+    where
+        I: IntoIterator<Item = cpal::SupportedStreamConfigRange>,
+    {
+        let preferred_rate = cpal::SampleRate(sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE));
+        let is_compatible = |format: cpal::SampleFormat| SUPPORTED_SAMPLE_FORMATS.contains(&format);
+        let contains_preferred_rate = |range: &cpal::SupportedStreamConfigRange| {
+            range.min_sample_rate() <= preferred_rate && preferred_rate <= range.max_sample_rate()
+        };
+
+        let compatible: Vec<_> = supported_configs
+            .into_iter()
+            .filter(|range| is_compatible(range.sample_format()))
+            .collect();
+        let preferred = compatible.iter().find(|range| {
+            range.sample_format() == cpal::SampleFormat::I16 && contains_preferred_rate(range)
+        });
+        if let Some(range) = preferred {
+            return Ok(range.clone().with_sample_rate(preferred_rate));
+        }
+        if sample_rate.is_some() {
+            // the requested rate takes precedence over the default config
+            if let Some(range) = compatible
+                .iter()
+                .find(|range| contains_preferred_rate(range))
+            {
+                return Ok(range.clone().with_sample_rate(preferred_rate));
+            }
+            tracing::warn!(
+                "The device doesn't support the sample rate {} Hz",
+                preferred_rate.0
+            );
+        }
+        if let Some(config) = default_config.filter(|config| is_compatible(config.sample_format()))
+        {
+            return Ok(config);
+        }
+        compatible
+            .iter()
+            .find(|range| contains_preferred_rate(range))
+            .map(|range| range.clone().with_sample_rate(preferred_rate))
+            .or_else(|| {
+                compatible
+                    .into_iter()
+                    .next()
+                    .map(|range| range.with_max_sample_rate())
+            })
+            .ok_or(anyhow::Error::msg(
+                "The device doesn't support any compatible stream config",
+            ))
+    }
+
+    fn select_buffer_size(
+        config: &cpal::SupportedStreamConfig,
+        buffer_size: Option<u32>,
+    ) -> cpal::BufferSize {
+        let Some(frames) = buffer_size else {
+            return cpal::BufferSize::Default;
+        };
+        match config.buffer_size() {
+            cpal::SupportedBufferSize::Range { min, max } if frames < *min || frames > *max => {
+                let clamped = frames.clamp(*min, *max);
+                tracing::warn!(
+                    "The buffer size {frames} is out of the supported range [{min}; {max}], {clamped} is used"
+                );
+                cpal::BufferSize::Fixed(clamped)
+            }
+            _ => cpal::BufferSize::Fixed(frames),
+        }
+    }
+
+    // the virtual devices produce and consume mono G.711 rate samples, no resampling is needed
+    fn virtual_config() -> cpal::SupportedStreamConfig {
+        cpal::SupportedStreamConfig::new(
+            1,
+            cpal::SampleRate(8000),
+            cpal::SupportedBufferSize::Unknown,
+            cpal::SampleFormat::F32,
+        )
+    }
+}
+
+impl Device<direction::Input> {
+    fn build(
+        audio_config: &AudioConfig,
+        host: &cpal::Host,
+        level: LevelSlot,
+        gain: Gain,
+    ) -> Result<Self, anyhow::Error> {
+        let files = &audio_config.files;
+        let (name, source, config, buffer_size) = match (&audio_config.backend, &files.input) {
+            (_, Some(path)) => {
+                let samples = player::load_samples(path)?;
+                tracing::info!("Audio input is read from {path:?}");
+                (
+                    format!("file {path:?}"),
+                    direction::InputSource::File {
+                        samples: samples.into(),
+                        looped: files.input_looped,
+                    },
+                    Self::virtual_config(),
+                    cpal::BufferSize::Default,
+                )
+            }
+            (AudioBackend::Cpal, None) => {
+                let device = host
+                    .default_input_device()
+                    .ok_or(anyhow::Error::msg("Could not create input device"))?;
+                let config = Self::select_config(
+                    device.default_input_config().ok(),
+                    device.supported_input_configs()?,
+                    audio_config.sample_rate,
+                )?;
+                let buffer_size = Self::select_buffer_size(&config, audio_config.buffer_size);
+                tracing::info!("Input stream config: {config:?}, buffer size: {buffer_size:?}");
+                (
+                    device
+                        .name()
+                        .unwrap_or_else(|_err| "unknown device".to_owned()),
+                    direction::InputSource::Device(device),
+                    config,
+                    buffer_size,
+                )
+            }
+            (AudioBackend::Null, None) => (
+                "silence".to_owned(),
+                direction::InputSource::Silence,
+                Self::virtual_config(),
+                cpal::BufferSize::Default,
+            ),
+            (AudioBackend::Tone(frequency), None) => (
+                format!("{frequency} Hz tone"),
+                direction::InputSource::Tone(*frequency),
+                Self::virtual_config(),
+                cpal::BufferSize::Default,
+            ),
+        };
+        Ok(Self {
+            name,
+            config,
+            buffer_size,
+            stream: None,
+            fault: FaultSlot::default(),
+            direction: direction::Input {
+                source,
+                capture_channel: audio_config.capture_channel,
+                level,
+                gain,
+            },
+        })
+    }
+}
+
+impl Device<direction::Output> {
+    fn build(
+        audio_config: &AudioConfig,
+        host: &cpal::Host,
+        tone: ToneSlot,
+        gain: Gain,
+    ) -> Result<Self, anyhow::Error> {
+        let (name, sink, config, buffer_size) =
+            match (&audio_config.backend, &audio_config.files.output) {
+                (_, Some(path)) => {
+                    tracing::info!("Audio output is written to {path:?}");
+                    (
+                        format!("file {path:?}"),
+                        direction::OutputSink::File(path.clone()),
+                        Self::virtual_config(),
+                        cpal::BufferSize::Default,
+                    )
+                }
+                (AudioBackend::Cpal, None) => {
+                    let device = host
+                        .default_output_device()
+                        .ok_or(anyhow::Error::msg("Could not create output device"))?;
+                    let config = Self::select_config(
+                        device.default_output_config().ok(),
+                        device.supported_output_configs()?,
+                        audio_config.sample_rate,
+                    )?;
+                    let buffer_size = Self::select_buffer_size(&config, audio_config.buffer_size);
+                    tracing::info!(
+                        "Output stream config: {config:?}, buffer size: {buffer_size:?}"
+                    );
+                    (
+                        device
+                            .name()
+                            .unwrap_or_else(|_err| "unknown device".to_owned()),
+                        direction::OutputSink::Device(device),
+                        config,
+                        buffer_size,
+                    )
+                }
+                (AudioBackend::Null | AudioBackend::Tone(_), None) => (
+                    "discard".to_owned(),
+                    direction::OutputSink::Discard,
+                    Self::virtual_config(),
+                    cpal::BufferSize::Default,
+                ),
+            };
+        Ok(Self {
+            name,
+            config,
+            buffer_size,
+            stream: None,
+            fault: FaultSlot::default(),
+            direction: direction::Output { sink, tone, gain },
+        })
+    }
+}
+
+mod direction {
+    use crate::{
+        audio::{CaptureChannel, FaultSlot, Gain, LevelSlot},
+        drift::{Adjustment, DriftCompensator},
+        pipeline::{self, AudioConsumer, AudioProducer},
+        resample::StreamResampler,
+        tone::ToneSlot,
+    };
+
+    use std::{
+        collections::VecDeque,
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use anyhow::Result;
+    use cpal::{
+        traits::{DeviceTrait, StreamTrait},
+        Sample,
+    };
+
+    // 10 ms chunks keep the latency added by the resampler low
+    const CHUNKS_PER_SECOND: usize = 100;
+    const VIRTUAL_PERIOD: Duration = Duration::from_millis(20);
+
+    pub enum Channel {
+        Input(AudioProducer),
+        Output(AudioConsumer),
+    }
+
+    struct InputPipeline {
+        producer: AudioProducer,
+        resampler: StreamResampler,
+        data: Vec<f32>,
+        resampled: Vec<f32>,
+    }
+
+    struct OutputPipeline {
+        consumer: AudioConsumer,
+        drift: DriftCompensator,
+        resampler: StreamResampler,
+        frame: [f32; pipeline::FRAME_SAMPLES],
+        resampled: Vec<f32>,
+        pending: VecDeque<f32>,
+    }
+
+    pub enum Stream {
+        Device(cpal::Stream),
+        Virtual(VirtualStream),
+    }
+
+    pub struct VirtualStream {
+        stop: Arc<AtomicBool>,
+        thread: Option<thread::JoinHandle<()>>,
+    }
+
+    pub trait DirectionTrait {
+        fn build_stream<T>(
+            &self,
+            config: cpal::StreamConfig,
+            channel: Channel,
+            fault: FaultSlot,
+        ) -> Result<Stream>
+        where
+            T: cpal::SizedSample
+                + dasp_sample::conv::ToSample<f32>
+                + cpal::FromSample<f32>
+                + Default;
+    }
+
+    pub enum InputSource {
+        Device(cpal::Device),
+        Silence,
+        Tone(f32),
+        File { samples: Arc<[f32]>, looped: bool },
+    }
+
+    pub enum OutputSink {
+        Device(cpal::Device),
+        Discard,
+        // the file is rewritten by every new stream
+        File(PathBuf),
+    }
+
+    pub struct Input {
+        pub source: InputSource,
+        pub capture_channel: CaptureChannel,
+        pub level: LevelSlot,
+        pub gain: Gain,
+    }
+    pub struct Output {
+        pub sink: OutputSink,
+        pub tone: ToneSlot,
+        pub gain: Gain,
+    }
+
+    impl VirtualStream {
+        fn spawn<F: FnMut() + Send + 'static>(period: Duration, mut callback: F) -> Self {
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread = {
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    let mut next = Instant::now();
+                    while !stop.load(Ordering::Relaxed) {
+                        callback();
+                        next += period;
+                        thread::sleep(next.saturating_duration_since(Instant::now()));
+                    }
+                })
+            };
+            Self {
+                stop,
+                thread: Some(thread),
+            }
+        }
+    }
+
+    impl Drop for VirtualStream {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    impl Input {
+        fn read_stream_data<T>(
+            input: &[T],
+            channels: usize,
+            capture_channel: CaptureChannel,
+            pipeline: &mut InputPipeline,
+            level: &LevelSlot,
+            gain: f32,
+        ) where
+            T: cpal::Sample + dasp_sample::conv::ToSample<f32>,
+        {
+            pipeline.data.clear();
+            pipeline.data.extend(input.chunks(channels).map(|frame| {
+                let sample = match capture_channel {
+                    CaptureChannel::Index(index) => frame[index].to_sample::<f32>(),
+                    CaptureChannel::Mix => {
+                        frame.iter().map(|s| s.to_sample::<f32>()).sum::<f32>() / frame.len() as f32
+                    }
+                };
+                (sample * gain).clamp(-1.0, 1.0)
+            }));
+            level
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .measure(&pipeline.data);
+            pipeline.resampled.clear();
+            if let Err(err) = pipeline
+                .resampler
+                .process(&pipeline.data, &mut pipeline.resampled)
+            {
+                tracing::error!("Could not resample the input data: {err}");
+                return;
+            }
+            // the samples are dropped if the call task doesn't keep up
+            pipeline.producer.push(&pipeline.resampled);
+        }
+    }
+
+    impl DirectionTrait for Input {
+        fn build_stream<T>(
+            &self,
+            config: cpal::StreamConfig,
+            channel: Channel,
+            fault: FaultSlot,
+        ) -> Result<Stream>
+        where
+            T: cpal::SizedSample
+                + dasp_sample::conv::ToSample<f32>
+                + cpal::FromSample<f32>
+                + Default,
+        {
+            let producer = if let Channel::Input(producer) = channel {
+                producer
+            } else {
+                return Err(anyhow::Error::msg("The Input channel is expected"));
+            };
+
+            let channels = config.channels as usize;
+            let sample_rate = config.sample_rate.0 as usize;
+            let err_fn = move |err| {
+                tracing::error!("an error occurred on input stream {err}");
+                *fault.lock().unwrap_or_else(|err| err.into_inner()) =
+                    Some(format!("input stream: {err}"));
+            };
+            let capture_channel = match (&self.source, self.capture_channel) {
+                (InputSource::Device(_), CaptureChannel::Index(index)) if index >= channels => {
+                    return Err(anyhow::Error::msg(format!(
+                        "Could not capture the channel {index}. The input device has {channels} channels"
+                    )));
+                }
+                (InputSource::Device(_), capture_channel) => capture_channel,
+                _ => CaptureChannel::Mix,
+            };
+            let level = self.level.clone();
+            let gain = self.gain.clone();
+            let chunk_size = sample_rate / CHUNKS_PER_SECOND;
+            let mut pipeline = InputPipeline {
+                producer,
+                resampler: StreamResampler::new(sample_rate, pipeline::SAMPLE_RATE, chunk_size)?,
+                data: Vec::with_capacity(chunk_size * 4),
+                resampled: Vec::with_capacity(pipeline::FRAME_SAMPLES * 4),
+            };
+
+            let device = match &self.source {
+                InputSource::Device(device) => device,
+                InputSource::Silence => {
+                    let data = vec![0.0_f32; frames_per_period(&config) * channels];
+                    let stream = VirtualStream::spawn(VIRTUAL_PERIOD, move || {
+                        Self::read_stream_data(
+                            &data,
+                            channels,
+                            capture_channel,
+                            &mut pipeline,
+                            &level,
+                            gain.get(),
+                        )
+                    });
+                    return Ok(Stream::Virtual(stream));
+                }
+                InputSource::Tone(frequency) => {
+                    let mut data = vec![0.0_f32; frames_per_period(&config) * channels];
+                    let amplitude = 0.5;
+                    let phase_step =
+                        std::f32::consts::TAU * frequency / config.sample_rate.0 as f32;
+                    let mut phase = 0.0_f32;
+                    let stream = VirtualStream::spawn(VIRTUAL_PERIOD, move || {
+                        for frame in data.chunks_mut(channels) {
+                            frame.fill(amplitude * phase.sin());
+                            phase = (phase + phase_step) % std::f32::consts::TAU;
+                        }
+                        Self::read_stream_data(
+                            &data,
+                            channels,
+                            capture_channel,
+                            &mut pipeline,
+                            &level,
+                            gain.get(),
+                        )
+                    });
+                    return Ok(Stream::Virtual(stream));
+                }
+                InputSource::File { samples, looped } => {
+                    let samples = samples.clone();
+                    let looped = *looped;
+                    let mut position = 0;
+                    let mut data = vec![0.0_f32; frames_per_period(&config) * channels];
+                    let stream = VirtualStream::spawn(VIRTUAL_PERIOD, move || {
+                        for frame in data.chunks_mut(channels) {
+                            if looped && position >= samples.len() {
+                                position = 0;
+                            }
+                            frame.fill(samples.get(position).copied().unwrap_or(0.0));
+                            position += 1;
+                        }
+                        Self::read_stream_data(
+                            &data,
+                            channels,
+                            capture_channel,
+                            &mut pipeline,
+                            &level,
+                            gain.get(),
+                        )
+                    });
+                    return Ok(Stream::Virtual(stream));
+                }
+            };
+
+            let stream = device.build_input_stream(
+                &config,
+                move |data: &[T], _: &cpal::InputCallbackInfo| {
+                    Self::read_stream_data(
+                        data,
+                        channels,
+                        capture_channel,
+                        &mut pipeline,
+                        &level,
+                        gain.get(),
+                    )
+                },
+                err_fn,
+                None,
+            )?;
+            stream.play()?;
+            Ok(Stream::Device(stream))
+        }
+    }
+
+    impl Output {
+        fn write_stream_data<T>(
+            output: &mut [T],
+            channels: usize,
+            pipeline: &mut OutputPipeline,
+            tone: &ToneSlot,
+            gain: f32,
+        ) where
+            T: cpal::Sample + cpal::FromSample<f32> + Default,
+        {
+            // the resampled data left after the callback is played by the next one
+            let frames = output.len() / channels;
+            // the device clock drifts from the RTP one, the playback latency is kept bounded
+            let mut adjustment = pipeline.drift.update(pipeline.consumer.occupancy().len);
+            while pipeline.pending.len() < frames
+                && pipeline
+                    .consumer
+                    .pop_frame_adjusted(&mut pipeline.frame, adjustment)
+            {
+                adjustment = Adjustment::None;
+                pipeline.resampled.clear();
+                if let Err(err) = pipeline
+                    .resampler
+                    .process(&pipeline.frame, &mut pipeline.resampled)
+                {
+                    tracing::error!("Could not resample the output data: {err}");
+                    break;
+                }
+                pipeline.pending.extend(pipeline.resampled.iter());
+            }
+
+            let mut tone = tone.lock().unwrap_or_else(|err| err.into_inner());
+            output.fill(T::default());
+            for frame in output.chunks_mut(channels) {
+                let voice = pipeline.pending.pop_front();
+                let tone_sample = tone.as_mut().and_then(|tone| tone.next_sample());
+                if voice.is_some() || tone_sample.is_some() {
+                    let s = (voice.unwrap_or(0.0) + tone_sample.unwrap_or(0.0)) * gain;
+                    frame.fill(T::from_sample_(s.clamp(-1.0, 1.0)));
+                }
+            }
+            if tone.as_mut().is_some_and(|tone| tone.is_finished()) {
+                tone.take();
+            }
+        }
+    }
+
+    impl DirectionTrait for Output {
+        fn build_stream<T>(
+            &self,
+            config: cpal::StreamConfig,
+            channel: Channel,
+            fault: FaultSlot,
+        ) -> Result<Stream>
+        where
+            T: cpal::SizedSample
+                + dasp_sample::conv::ToSample<f32>
+                + cpal::FromSample<f32>
+                + Default,
+        {
+            let consumer = if let Channel::Output(consumer) = channel {
+                consumer
+            } else {
+                return Err(anyhow::Error::msg("The Output channel is expected"));
+            };
+
+            let channels = config.channels as usize;
+            let sample_rate = config.sample_rate.0 as usize;
+            let err_fn = move |err| {
+                tracing::error!("an error occurred on output stream {err}");
+                *fault.lock().unwrap_or_else(|err| err.into_inner()) =
+                    Some(format!("output stream: {err}"));
+            };
+            let tone = self.tone.clone();
+            let gain = self.gain.clone();
+            let mut pipeline = OutputPipeline {
+                consumer,
+                drift: DriftCompensator::default(),
+                resampler: StreamResampler::new(
+                    pipeline::SAMPLE_RATE,
+                    sample_rate,
+                    pipeline::FRAME_SAMPLES,
+                )?,
+                frame: [0.0; pipeline::FRAME_SAMPLES],
+                resampled: Vec::with_capacity(sample_rate / CHUNKS_PER_SECOND),
+                pending: VecDeque::with_capacity(sample_rate / CHUNKS_PER_SECOND * 4),
+            };
+
+            let device = match &self.sink {
+                OutputSink::Device(device) => device,
+                OutputSink::Discard => {
+                    let mut data = vec![0.0_f32; frames_per_period(&config) * channels];
+                    let stream = VirtualStream::spawn(VIRTUAL_PERIOD, move || {
+                        Self::write_stream_data(
+                            &mut data,
+                            channels,
+                            &mut pipeline,
+                            &tone,
+                            gain.get(),
+                        )
+                    });
+                    return Ok(Stream::Virtual(stream));
+                }
+                OutputSink::File(path) => {
+                    let spec = hound::WavSpec {
+                        channels: channels as u16,
+                        sample_rate: config.sample_rate.0,
+                        bits_per_sample: 16,
+                        sample_format: hound::SampleFormat::Int,
+                    };
+                    // the writer is finalized when the stream is dropped
+                    let mut writer = hound::WavWriter::create(path, spec)?;
+                    let mut data = vec![0.0_f32; frames_per_period(&config) * channels];
+                    let stream = VirtualStream::spawn(VIRTUAL_PERIOD, move || {
+                        Self::write_stream_data(
+                            &mut data,
+                            channels,
+                            &mut pipeline,
+                            &tone,
+                            gain.get(),
+                        );
+                        for sample in &data {
+                            if let Err(err) = writer.write_sample(sample.to_sample::<i16>()) {
+                                tracing::error!("Could not write the audio output: {err}");
+                                break;
+                            }
+                        }
+                    });
+                    return Ok(Stream::Virtual(stream));
+                }
+            };
+
+            let stream = device.build_output_stream(
+                &config,
+                move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                    Self::write_stream_data(data, channels, &mut pipeline, &tone, gain.get())
+                },
+                err_fn,
+                None,
+            )?;
+            stream.play()?;
+            Ok(Stream::Device(stream))
+        }
+    }
+
+    fn frames_per_period(config: &cpal::StreamConfig) -> usize {
+        (config.sample_rate.0 as u128 * VIRTUAL_PERIOD.as_millis() / 1000) as usize
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt::Display;
+
+/// The state of the watched extension, see [`crate::user_agent::UserAgent::watch_lines`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineState {
+    Idle,
+    Ringing,
+    Busy,
+}
+
+impl Display for LineState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match self {
+            Self::Idle => "idle",
+            Self::Ringing => "ringing",
+            Self::Busy => "busy",
+        };
+        write!(f, "{state}")
+    }
+}
+
+// the dialog-info document (RFC 4235) of the NOTIFY: busy if one of the dialogs is confirmed,
+// ringing if one is being set up, idle without them. The partial document is taken as the
+// whole state of the line
+pub(crate) fn line_state(dialog_info: &str) -> LineState {
+    let mut state = LineState::Idle;
+    for element in dialog_info.split("<state").skip(1) {
+        let Some((_, content)) = element.split_once('>') else {
+            continue;
+        };
+        match content.split('<').next().unwrap_or_default().trim() {
+            "confirmed" => return LineState::Busy,
+            "trying" | "proceeding" | "early" => state = LineState::Ringing,
+            _ => {}
+        }
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dialog_info(state: &str, dialogs: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\"?>\r\n\
+             <dialog-info xmlns=\"urn:ietf:params:xml:ns:dialog-info\" version=\"3\" \
+             state=\"{state}\" entity=\"sip:1002@10.0.0.1\">\r\n\
+             {dialogs}\
+             </dialog-info>\r\n"
+        )
+    }
+
+    fn dialog(state: &str) -> String {
+        format!(
+            "<dialog id=\"d1\" direction=\"recipient\">\r\n\
+             \x20 <state event=\"replaced\">{state}</state>\r\n\
+             </dialog>\r\n"
+        )
+    }
+
+    #[test]
+    fn line_state_of_the_dialog_states() {
+        for (state, expected) in [
+            ("trying", LineState::Ringing),
+            ("proceeding", LineState::Ringing),
+            ("early", LineState::Ringing),
+            ("confirmed", LineState::Busy),
+            ("terminated", LineState::Idle),
+        ] {
+            let document = dialog_info("full", &dialog(state));
+            assert_eq!(line_state(&document), expected, "{state}");
+        }
+    }
+
+    #[test]
+    fn line_state_is_busy_with_one_confirmed_dialog() {
+        let dialogs = [dialog("early"), dialog("confirmed"), dialog("terminated")].concat();
+        assert_eq!(line_state(&dialog_info("full", &dialogs)), LineState::Busy);
+        let dialogs = [dialog("terminated"), dialog(" early ")].concat();
+        assert_eq!(
+            line_state(&dialog_info("full", &dialogs)),
+            LineState::Ringing
+        );
+    }
+
+    #[test]
+    fn line_state_is_idle_without_the_state() {
+        assert_eq!(line_state(&dialog_info("full", "")), LineState::Idle);
+        let dialog = "<dialog id=\"d1\"><duration>12</duration></dialog>\r\n";
+        assert_eq!(line_state(&dialog_info("full", dialog)), LineState::Idle);
+        assert_eq!(line_state("<dialog><state"), LineState::Idle);
+    }
+
+    #[test]
+    fn line_state_takes_the_partial_document_as_the_whole_state() {
+        let partial = dialog_info("partial", &dialog("confirmed"));
+        assert_eq!(line_state(&partial), LineState::Busy);
+        let partial = dialog_info("partial", &dialog("terminated"));
+        assert_eq!(line_state(&partial), LineState::Idle);
+    }
+}
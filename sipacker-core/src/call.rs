@@ -0,0 +1,856 @@
+use crate::{
+    capture::{CaptureTap, RtpAddresses},
+    drift::DriftCompensator,
+    dtmf::{DtmfDetector, DtmfTap},
+    jitter_buffer::JitterBuffer,
+    mixer::AudioBus,
+    pipeline::{self, AudioConsumer, AudioProducer, LatencyBudget},
+    player::{PlaybackMode, PlaybackTap},
+    recorder::{RecordingMode, RecordingTap},
+    stats::{AudioStats, CallStats, StatsTap},
+    transaction::DialogUsage,
+};
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use bytesstr::BytesStr;
+use enum_dispatch::enum_dispatch;
+use ezk_sip::{Codec, MediaSession, RtpReceiver, RtpSender};
+use ezk_sip_core::IncomingRequest;
+use ezk_sip_types::{
+    msg::StatusLine,
+    uri::{sip::SipUri, NameAddr},
+    StatusCode,
+};
+use ezk_sip_ua::dialog::UsageGuard;
+use tokio::{
+    select,
+    sync::mpsc,
+    task::{JoinError, JoinHandle},
+    time::MissedTickBehavior,
+};
+use tokio_util::sync::CancellationToken;
+
+type CallInner = ezk_sip::Call<MediaSession>;
+type IncomingCallInner = ezk_sip::IncomingCall<MediaSession>;
+type OutgoingCallInner = ezk_sip::OutboundCall<MediaSession>;
+
+const DETECTED_DTMF_CAPACITY: usize = 16;
+// keeps the NAT/SBC media pinhole open when there is nothing to send, RFC 6263
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+// the minimal RTCP interval, RFC 3550 6.2
+const XR_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct Call {
+    state: State,
+    taps: CallTaps,
+    started: Instant,
+}
+
+/// The state of the active call, reported by [`crate::user_agent::UserAgent::status`]. The
+/// duration is counted from the start of the call or from its establishment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallStatus {
+    Incoming(Duration),
+    Outgoing(Duration),
+    Established(Duration),
+}
+
+#[derive(Clone, Default)]
+struct CallTaps {
+    recording: RecordingTap,
+    playback: PlaybackTap,
+    dtmf: DtmfTap,
+    stats: StatsTap,
+    capture: CaptureTap,
+}
+
+impl Call {
+    pub fn from_outgoing(
+        outgoing_call: OutgoingCallInner,
+        audio_sender: AudioProducer,
+        audio_receiver: AudioConsumer,
+        waiting_timeout: Duration,
+        latency: LatencyBudget,
+        capture: CaptureTap,
+    ) -> Self {
+        let taps = CallTaps {
+            capture,
+            ..Default::default()
+        };
+        let state = OutgoingCall::new(
+            outgoing_call,
+            audio_sender,
+            audio_receiver,
+            waiting_timeout,
+            latency,
+            taps.clone(),
+        );
+        Self {
+            state: state.into(),
+            taps,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn from_incoming(
+        incoming_call: IncomingCallInner,
+        action_receiver: mpsc::Receiver<IncomingCallAction>,
+        latency: LatencyBudget,
+        capture: CaptureTap,
+    ) -> Self {
+        let taps = CallTaps {
+            capture,
+            ..Default::default()
+        };
+        let state = IncomingCall::new(incoming_call, action_receiver, latency, taps.clone());
+        Self {
+            state: state.into(),
+            taps,
+            started: Instant::now(),
+        }
+    }
+
+    // cancel-safe, the call doesn't change until the returned ready is passed to run
+    pub(crate) async fn wait(&mut self) -> Ready {
+        self.state.wait().await
+    }
+
+    pub(crate) async fn run(self, ready: Ready) -> Result<(Option<Self>, Option<Event>)> {
+        let taps = self.taps;
+        let started = self.started;
+        let (state, event) = self.state.run(ready).await.inspect_err(|_err| {
+            taps.stop();
+        })?;
+        match state {
+            Some(state) => Ok((
+                Some(Self {
+                    state,
+                    taps,
+                    started,
+                }),
+                event,
+            )),
+            None => {
+                taps.stop();
+                Ok((None, event))
+            }
+        }
+    }
+
+    pub async fn terminate(self) -> Result<()> {
+        self.taps.stop();
+        self.state.terminate().await
+    }
+
+    // the final response to the INVITE if the call is terminated now
+    pub fn final_status(&self) -> u16 {
+        self.state.final_status()
+    }
+
+    pub(crate) fn attach_to(&self, bus: &AudioBus) {
+        bus.attach_call(self.taps.playback.clone(), self.taps.recording.clone());
+    }
+
+    pub fn start_recording(&self, path: &Path, mode: RecordingMode) -> Result<()> {
+        self.taps.recording.start(path, mode)
+    }
+
+    pub fn stop_recording(&self) -> Result<()> {
+        self.taps.recording.stop()
+    }
+
+    pub fn start_playback(&self, path: &Path, mode: PlaybackMode) -> Result<()> {
+        self.taps.playback.start(path, mode)
+    }
+
+    pub fn stop_playback(&self) -> Result<()> {
+        self.taps.playback.stop()
+    }
+
+    pub fn send_dtmf(&self, digits: &str) -> Result<()> {
+        if !matches!(self.state, State::EstablishedCall(_)) {
+            return Err(anyhow::Error::msg(
+                "Could not send DTMF. The call is not established",
+            ));
+        }
+        self.taps.dtmf.send(digits)
+    }
+
+    pub async fn refer(&mut self, target: SipUri) -> Result<()> {
+        let State::EstablishedCall(call) = &mut self.state else {
+            return Err(anyhow::Error::msg(
+                "Could not transfer the call. The call is not established",
+            ));
+        };
+        call.call
+            .refer(NameAddr::uri(target))
+            .await
+            .map_err(|err| anyhow::Error::msg(err.to_string()))?;
+        Ok(())
+    }
+
+    pub fn status(&self) -> CallStatus {
+        match &self.state {
+            State::IncomingCall(_) => CallStatus::Incoming(self.started.elapsed()),
+            State::OutgoingCall(_) => CallStatus::Outgoing(self.started.elapsed()),
+            State::EstablishedCall(call) => CallStatus::Established(call.since.elapsed()),
+        }
+    }
+
+    pub fn stats(&self) -> CallStats {
+        self.taps.stats.snapshot()
+    }
+
+    pub fn audio_stats(&self) -> AudioStats {
+        self.taps.stats.audio_snapshot()
+    }
+
+    pub fn stats_tap(&self) -> StatsTap {
+        self.taps.stats.clone()
+    }
+}
+
+impl CallTaps {
+    fn stop(&self) {
+        let _ = self.recording.stop();
+        let _ = self.playback.stop();
+        self.dtmf.stop();
+    }
+}
+
+pub enum Event {
+    Progress(StatusLine),
+    Established,
+    // the code of the final response to the INVITE
+    Terminated(u16),
+    DtmfReceived(char),
+    Request(IncomingRequest),
+}
+
+pub(crate) enum Ready {
+    Calling(Result<Result<CallInner>, JoinError>),
+    Progress(StatusLine),
+    Action(Option<IncomingCallAction>),
+    Call(Result<ezk_sip::CallEvent>),
+    Dtmf(char),
+    Request(IncomingRequest),
+}
+
+#[enum_dispatch()]
+trait StateTrait {
+    async fn wait(&mut self) -> Ready;
+    async fn run(self, ready: Ready) -> Result<(Option<State>, Option<Event>)>;
+    async fn terminate(self) -> Result<()>;
+    fn final_status(&self) -> u16;
+}
+
+/// The final failure response to the INVITE of the outgoing call.
+#[derive(Debug)]
+pub struct Rejected(pub StatusLine);
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = self.0.code;
+        let reason = self.0.reason.as_deref().or(code.text());
+        write!(f, "The call is rejected with {}", code.into_u16())?;
+        match reason {
+            Some(reason) => write!(f, " {reason}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+#[enum_dispatch(StateTrait)]
+enum State {
+    IncomingCall,
+    OutgoingCall,
+    EstablishedCall,
+}
+
+struct OutgoingCall {
+    audio_sender: AudioProducer,
+    audio_receiver: AudioConsumer,
+    calling_task: JoinHandle<Result<CallInner>>,
+    provisional_responses: mpsc::Receiver<StatusLine>,
+    cancellation: CancellationToken,
+    latency: LatencyBudget,
+    taps: CallTaps,
+}
+
+impl OutgoingCall {
+    fn new(
+        mut outgoing_call: OutgoingCallInner,
+        audio_sender: AudioProducer,
+        audio_receiver: AudioConsumer,
+        waiting_timeout: Duration,
+        latency: LatencyBudget,
+        taps: CallTaps,
+    ) -> Self {
+        let provisional_responses = outgoing_call.provisional_responses();
+        let cancellation = CancellationToken::new();
+        let calling_task = tokio::spawn(Self::run_calling_task(
+            outgoing_call,
+            cancellation.clone(),
+            waiting_timeout,
+        ));
+        Self {
+            audio_sender,
+            audio_receiver,
+            calling_task,
+            provisional_responses,
+            cancellation,
+            latency,
+            taps,
+        }
+    }
+
+    async fn run_calling_task(
+        mut outgoing_call: ezk_sip::OutboundCall<MediaSession>,
+        cancellation: CancellationToken,
+        waiting_duration: Duration,
+    ) -> Result<CallInner> {
+        let completed_call = select! {
+            _ = cancellation.cancelled() => Err(anyhow::Error::msg("Outbound call is cancelled")),
+            _ = tokio::time::sleep(waiting_duration) => Err(anyhow::Error::msg("Outbound call is timed out")),
+            completed = outgoing_call.wait_for_completion() => completed.map_err(completion_error),
+        };
+
+        if completed_call.is_err() {
+            outgoing_call.cancel().await?;
+        }
+        let completed_call = completed_call?;
+
+        select! {
+            _ = cancellation.cancelled() => Err(anyhow::Error::msg("Outbound call is cancelled")),
+            call = completed_call.finish() => call.map_err(completion_error),
+        }
+    }
+}
+
+// the failure response is kept apart for the final status of the call
+fn completion_error<E: std::fmt::Display>(
+    err: ezk_sip::MakeCallCompletionError<E>,
+) -> anyhow::Error {
+    match err {
+        ezk_sip::MakeCallCompletionError::Failed(line) => Rejected(line).into(),
+        err => anyhow::Error::msg(err.to_string()),
+    }
+}
+
+impl StateTrait for OutgoingCall {
+    async fn wait(&mut self) -> Ready {
+        select! {
+            result = &mut self.calling_task => Ready::Calling(result),
+            Some(line) = self.provisional_responses.recv() => Ready::Progress(line),
+        }
+    }
+
+    async fn run(self, ready: Ready) -> Result<(Option<State>, Option<Event>)> {
+        let result = match ready {
+            Ready::Calling(result) => result,
+            Ready::Progress(line) => {
+                return Ok((Some(self.into()), Some(Event::Progress(line))));
+            }
+            _ => return Ok((Some(self.into()), None)),
+        };
+        let call = result??;
+        let state = EstablishedCall::new(
+            call,
+            self.audio_sender,
+            self.audio_receiver,
+            self.latency,
+            self.taps,
+        );
+        Ok((Some(state.into()), Some(Event::Established)))
+    }
+
+    async fn terminate(self) -> Result<()> {
+        self.cancellation.cancel();
+        let _ = self.calling_task.await?;
+        Ok(())
+    }
+
+    // the cancelled INVITE is answered by 487 Request Terminated, RFC 3261 9.2
+    fn final_status(&self) -> u16 {
+        StatusCode::REQUEST_TERMINATED.into_u16()
+    }
+}
+
+struct IncomingCall {
+    incoming_call: IncomingCallInner,
+    action_receiver: mpsc::Receiver<IncomingCallAction>,
+    latency: LatencyBudget,
+    taps: CallTaps,
+}
+
+pub enum IncomingCallAction {
+    Decline,
+    Accept {
+        audio_sender: AudioProducer,
+        audio_receiver: AudioConsumer,
+    },
+}
+
+impl IncomingCall {
+    fn new(
+        incoming_call: IncomingCallInner,
+        action_receiver: mpsc::Receiver<IncomingCallAction>,
+        latency: LatencyBudget,
+        taps: CallTaps,
+    ) -> Self {
+        Self {
+            incoming_call,
+            action_receiver,
+            latency,
+            taps,
+        }
+    }
+
+    async fn handle_action(self, action: IncomingCallAction) -> Result<(Option<State>, Event)> {
+        match action {
+            IncomingCallAction::Decline => {
+                self.incoming_call
+                    .decline(
+                        StatusCode::DECLINE,
+                        BytesStr::from_static("The call is declined").into(),
+                    )
+                    .await?;
+
+                Ok((None, Event::Terminated(StatusCode::DECLINE.into_u16())))
+            }
+            IncomingCallAction::Accept {
+                audio_sender,
+                audio_receiver,
+            } => {
+                let call = self.incoming_call.accept().await?;
+                let state = EstablishedCall::new(
+                    call,
+                    audio_sender,
+                    audio_receiver,
+                    self.latency,
+                    self.taps,
+                );
+                Ok((Some(state.into()), Event::Established))
+            }
+        }
+    }
+}
+
+impl StateTrait for IncomingCall {
+    async fn wait(&mut self) -> Ready {
+        Ready::Action(self.action_receiver.recv().await)
+    }
+
+    async fn run(self, ready: Ready) -> Result<(Option<State>, Option<Event>)> {
+        match ready {
+            Ready::Action(Some(action)) => self
+                .handle_action(action)
+                .await
+                .map(|(state, event)| (state, Some(event))),
+            Ready::Action(None) => {
+                let err = "The incoming call actions are disconnected";
+                let _ = self
+                    .incoming_call
+                    .decline(
+                        StatusCode::SERVER_INTERNAL_ERROR,
+                        BytesStr::from(err).into(),
+                    )
+                    .await;
+                Err(anyhow::Error::msg(err))
+            }
+            _ => Ok((Some(self.into()), None)),
+        }
+    }
+
+    async fn terminate(self) -> Result<()> {
+        self.incoming_call
+            .decline(
+                StatusCode::DECLINE,
+                BytesStr::from_static("The call is terminated").into(),
+            )
+            .await
+            .map_err(|err| err.into())
+    }
+
+    fn final_status(&self) -> u16 {
+        StatusCode::DECLINE.into_u16()
+    }
+}
+
+struct EstablishedCall {
+    sending_channel: SendingChannel,
+    receiving_channel: ReceivingChannel,
+    call: CallInner,
+    latency: LatencyBudget,
+    taps: CallTaps,
+    since: Instant,
+    detected_dtmf: (mpsc::Sender<char>, mpsc::Receiver<char>),
+    // the usage stays in the dialog until the call is dropped
+    _usage_guard: UsageGuard,
+    dialog_requests: mpsc::Receiver<IncomingRequest>,
+}
+
+enum SendingChannel {
+    Waiting(AudioConsumer),
+    Established(JoinHandle<()>),
+}
+
+enum ReceivingChannel {
+    Waiting(AudioProducer),
+    Established(JoinHandle<()>),
+}
+
+impl EstablishedCall {
+    fn new(
+        call: CallInner,
+        audio_sender: AudioProducer,
+        audio_receiver: AudioConsumer,
+        latency: LatencyBudget,
+        taps: CallTaps,
+    ) -> Self {
+        let (usage, dialog_requests) = DialogUsage::new();
+        let usage_guard = call.dialog().register_usage(usage);
+        Self {
+            call,
+            sending_channel: SendingChannel::Waiting(audio_receiver),
+            receiving_channel: ReceivingChannel::Waiting(audio_sender),
+            latency,
+            taps,
+            since: Instant::now(),
+            detected_dtmf: mpsc::channel(DETECTED_DTMF_CAPACITY),
+            _usage_guard: usage_guard,
+            dialog_requests,
+        }
+    }
+
+    fn run_sending_task(mut self, mut sender: RtpSender, codec: Codec) -> Self {
+        let ptime = self
+            .latency
+            .negotiate_ptime(codec.ptime(), codec.max_ptime());
+        tracing::debug!("Sending ptime is {ptime:?}");
+        self.sending_channel = if let SendingChannel::Waiting(mut audio_receiver) =
+            self.sending_channel
+        {
+            let mut rtp_factory = rtp::RtpFactory::new(codec.pt);
+            tracing::debug!("RTP payload format: {:?}", rtp_factory.format());
+            let frame_samples = (pipeline::SAMPLE_RATE as u128 * ptime.as_millis() / 1000) as usize;
+            let mut frame = vec![0.0; frame_samples];
+            let max_input_samples = self.latency.max_input_samples.max(frame_samples * 2);
+            let mut interval = tokio::time::interval(ptime);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let taps = self.taps.clone();
+            let addresses = RtpAddresses {
+                local: sender.local_addr(),
+                remote: sender.remote_addr(),
+            };
+            let sending_task = tokio::spawn(async move {
+                let mut last_sent = Instant::now();
+                let mut last_report = Instant::now();
+                // the capture device clock drifts from the sending interval one
+                let mut drift = DriftCompensator::default();
+                loop {
+                    interval.tick().await;
+                    taps.stats.log_periodically();
+                    // the XR packet is appended to the compound RTCP packets of the session
+                    if last_report.elapsed() >= XR_REPORT_INTERVAL {
+                        last_report = Instant::now();
+                        sender.set_rtcp_extension(taps.stats.voip_metrics_report().encode());
+                    }
+                    // the oldest data is dropped to keep the latency bounded when the sending is delayed
+                    audio_receiver.limit(max_input_samples);
+                    taps.stats.update_input_channel(audio_receiver.occupancy());
+                    let adjustment = drift.update(audio_receiver.occupancy().len);
+                    let popped = audio_receiver.pop_frame_adjusted(&mut frame, adjustment);
+                    // the DTMF tones replace the whole frame, they are sent even without the audio
+                    if !popped && !taps.dtmf.is_active() {
+                        // the timestamp keeps running during the silence, RFC 3550 5.1
+                        rtp_factory.skip_payload(frame_samples);
+                        if last_sent.elapsed() < KEEPALIVE_INTERVAL {
+                            continue;
+                        }
+                        last_sent = Instant::now();
+                        tracing::debug!("Sending RTP keepalive");
+                        let packet = rtp_factory.create_keepalive_packet();
+                        taps.capture.on_sent(addresses, &packet);
+                        if sender.send(packet).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+
+                    last_sent = Instant::now();
+                    taps.dtmf.process(&mut frame);
+                    let payload = pipeline::encode_alaw(&frame);
+                    let packet = rtp_factory.create_rtp_packet(payload);
+                    taps.stats.on_sent(&packet);
+                    taps.capture.on_sent(addresses, &packet);
+                    if sender.send(packet).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            SendingChannel::Established(sending_task)
+        } else {
+            panic!("The sending channel must be in waiting state");
+        };
+
+        self
+    }
+
+    fn run_receiving_task(mut self, mut receiver: RtpReceiver, _codec: Codec) -> Self {
+        self.receiving_channel =
+            if let ReceivingChannel::Waiting(mut audio_sender) = self.receiving_channel {
+                let mut jitter_buffer = JitterBuffer::new(
+                    self.latency.ptime,
+                    pipeline::SAMPLE_RATE as u32,
+                    self.latency.jitter_target_depth,
+                    self.latency.jitter_max_depth,
+                );
+                let mut interval = tokio::time::interval(self.latency.ptime);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                let taps = self.taps.clone();
+                let detected_dtmf = self.detected_dtmf.0.clone();
+                let mut rtcp_receiver = receiver.rtcp();
+                let addresses = RtpAddresses {
+                    local: receiver.local_addr(),
+                    remote: receiver.remote_addr(),
+                };
+                let receiver_task = tokio::spawn(async move {
+                    let mut decoded = Vec::new();
+                    let mut dtmf_detector = DtmfDetector::new();
+                    loop {
+                        select! {
+                            packet = receiver.recv() => {
+                                let Some(packet) = packet else {
+                                    break;
+                                };
+                                taps.stats.on_received(&packet);
+                                taps.capture.on_received(addresses, &packet);
+                                jitter_buffer.push(packet);
+                                if jitter_buffer.ptime() != interval.period() {
+                                    tracing::debug!("Peer ptime is {:?}", jitter_buffer.ptime());
+                                    interval = tokio::time::interval(jitter_buffer.ptime());
+                                    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                                }
+                            }
+                            Some(rtcp) = rtcp_receiver.recv() => taps.stats.on_rtcp(&rtcp),
+                            _ = interval.tick() => {
+                                if let Some(payload) = jitter_buffer.pop() {
+                                    decoded.clear();
+                                    pipeline::decode_alaw(&payload, &mut decoded);
+                                    for digit in dtmf_detector.process(&decoded) {
+                                        let _ = detected_dtmf.try_send(digit);
+                                    }
+                                    audio_sender.push(&decoded);
+                                }
+                                taps.stats.update_jitter_buffer(jitter_buffer.stats());
+                                taps.stats.update_output_channel(audio_sender.occupancy());
+                            }
+                        }
+                    }
+                });
+                ReceivingChannel::Established(receiver_task)
+            } else {
+                panic!("The receiving channel must be in waiting state");
+            };
+
+        self
+    }
+}
+
+impl StateTrait for EstablishedCall {
+    async fn wait(&mut self) -> Ready {
+        select! {
+            run_res = self.call.run() => Ready::Call(run_res.map_err(anyhow::Error::from)),
+            Some(digit) = self.detected_dtmf.1.recv() => Ready::Dtmf(digit),
+            Some(request) = self.dialog_requests.recv() => Ready::Request(request),
+        }
+    }
+
+    async fn run(self, ready: Ready) -> Result<(Option<State>, Option<Event>)> {
+        let run_res = match ready {
+            Ready::Call(run_res) => run_res,
+            Ready::Dtmf(digit) => {
+                tracing::info!("Received DTMF {digit} in-band");
+                return Ok((Some(self.into()), Some(Event::DtmfReceived(digit))));
+            }
+            Ready::Request(request) => {
+                return Ok((Some(self.into()), Some(Event::Request(request))));
+            }
+            _ => return Ok((Some(self.into()), None)),
+        };
+
+        match run_res {
+            Ok(event) => match event {
+                ezk_sip::CallEvent::Media(event) => {
+                    let new_self = match event {
+                        ezk_sip::MediaEvent::SenderAdded { sender, codec } => {
+                            self.run_sending_task(sender, codec)
+                        }
+                        ezk_sip::MediaEvent::ReceiverAdded { receiver, codec } => {
+                            self.run_receiving_task(receiver, codec)
+                        }
+                    };
+                    Ok((Some(new_self.into()), None))
+                }
+                ezk_sip::CallEvent::Terminated => {
+                    self.terminate().await?;
+                    Ok((None, Some(Event::Terminated(StatusCode::OK.into_u16()))))
+                }
+            },
+            Err(err) => {
+                self.terminate().await?;
+                Err(err)
+            }
+        }
+    }
+
+    async fn terminate(self) -> Result<()> {
+        self.call.terminate().await?;
+
+        if let SendingChannel::Established(task) = self.sending_channel {
+            task.abort();
+            let _ = task.await;
+        }
+
+        if let ReceivingChannel::Established(task) = self.receiving_channel {
+            task.abort();
+            let _ = task.await;
+        }
+
+        Ok(())
+    }
+
+    fn final_status(&self) -> u16 {
+        StatusCode::OK.into_u16()
+    }
+}
+
+mod rtp {
+    use std::hash::{BuildHasher, Hasher};
+
+    use bytes::Bytes;
+    use ezk_rtp::{RtpExtensions, RtpPacket, RtpTimestamp, SequenceNumber, Ssrc};
+
+    // unassigned static payload type, RFC 3551 6
+    const KEEPALIVE_PT: u8 = 20;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PayloadFormat {
+        pub clock_rate: u32,
+        pub bytes_per_second: u32,
+    }
+
+    impl PayloadFormat {
+        const G711: Self = Self {
+            clock_rate: 8000,
+            bytes_per_second: 8000,
+        };
+
+        pub fn from_pt(pt: u8) -> Self {
+            match pt {
+                0 | 8 => Self::G711,
+                3 => Self {
+                    clock_rate: 8000,
+                    bytes_per_second: 1650,
+                },
+                // the clock rate of G.722 is 8000 for historical reasons, RFC 3551 4.5.2
+                9 => Self {
+                    clock_rate: 8000,
+                    bytes_per_second: 8000,
+                },
+                18 => Self {
+                    clock_rate: 8000,
+                    bytes_per_second: 1000,
+                },
+                _ => {
+                    tracing::warn!("Unknown payload type {pt}, G.711 payload format is assumed");
+                    Self::G711
+                }
+            }
+        }
+
+        fn timestamp_increment(&self, payload_len: usize) -> u32 {
+            (payload_len as u64 * self.clock_rate as u64 / self.bytes_per_second as u64) as u32
+        }
+    }
+
+    pub struct RtpFactory {
+        rtp_sequence_number: SequenceNumber,
+        rtp_timestamp: RtpTimestamp,
+        rtp_pt: u8,
+        format: PayloadFormat,
+    }
+
+    impl RtpFactory {
+        pub fn new(rtp_pt: u8) -> Self {
+            // the initial values should be random, RFC 3550 5.1
+            let random = random_u64();
+            Self {
+                rtp_sequence_number: SequenceNumber(random as u16),
+                rtp_timestamp: RtpTimestamp((random >> 32) as u32),
+                rtp_pt,
+                format: PayloadFormat::from_pt(rtp_pt),
+            }
+        }
+
+        pub fn format(&self) -> PayloadFormat {
+            self.format
+        }
+
+        pub fn create_rtp_packet(&mut self, payload: Bytes) -> RtpPacket {
+            let timestamp_increment = self.format.timestamp_increment(payload.len());
+            let packet = RtpPacket {
+                pt: self.rtp_pt,
+                sequence_number: self.rtp_sequence_number,
+                timestamp: self.rtp_timestamp,
+                payload,
+                ssrc: Ssrc(0),
+                extensions: RtpExtensions::default(),
+            };
+
+            self.rtp_sequence_number = SequenceNumber(self.rtp_sequence_number.0.wrapping_add(1));
+            self.rtp_timestamp =
+                RtpTimestamp(self.rtp_timestamp.0.wrapping_add(timestamp_increment));
+            packet
+        }
+
+        pub fn skip_payload(&mut self, payload_len: usize) {
+            let timestamp_increment = self.format.timestamp_increment(payload_len);
+            self.rtp_timestamp =
+                RtpTimestamp(self.rtp_timestamp.0.wrapping_add(timestamp_increment));
+        }
+
+        // an empty packet of the payload type not negotiated in SDP, RFC 6263 4.6
+        pub fn create_keepalive_packet(&mut self) -> RtpPacket {
+            let packet = RtpPacket {
+                pt: KEEPALIVE_PT,
+                sequence_number: self.rtp_sequence_number,
+                timestamp: self.rtp_timestamp,
+                payload: Bytes::new(),
+                ssrc: Ssrc(0),
+                extensions: RtpExtensions::default(),
+            };
+            self.rtp_sequence_number = SequenceNumber(self.rtp_sequence_number.0.wrapping_add(1));
+            packet
+        }
+    }
+
+    fn random_u64() -> u64 {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        hasher.finish()
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use ezk_rtp::RtpPacket;
+use ezk_sip_core::transport::{MessageTap, MessageTpInfo, TpHandle};
+use tokio::sync::{broadcast, mpsc};
+
+// the headers are dropped rather than delaying the media when the capture falls behind
+const CAPACITY: usize = 256;
+// the lagging subscriber misses the oldest messages
+const SIP_CAPACITY: usize = 256;
+
+/// Whether the packet is sent to the remote or received from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// The SIP message as the transport sends or receives it.
+#[derive(Debug, Clone)]
+pub struct SipMessage {
+    pub direction: Direction,
+    pub time: SystemTime,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    pub bytes: Bytes,
+}
+
+/// The fixed RTP header of a sent or received packet, RFC 3550 5.1, without the payload.
+#[derive(Debug, Clone)]
+pub struct RtpHeader {
+    pub direction: Direction,
+    pub time: SystemTime,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    pub header: Bytes,
+    pub packet_len: usize,
+}
+
+impl RtpHeader {
+    // the SSRC is filled in by the RTP session of ezk, it isn't known here
+    fn of(direction: Direction, addresses: RtpAddresses, packet: &RtpPacket) -> Self {
+        let mut header = BytesMut::with_capacity(12);
+        // version 2, no padding, no extension, no CSRC
+        header.put_u8(0x80);
+        header.put_u8(packet.pt & 0x7f);
+        header.put_u16(packet.sequence_number.0);
+        header.put_u32(packet.timestamp.0);
+        header.put_u32(packet.ssrc.0);
+        let header = header.freeze();
+        Self {
+            direction,
+            time: SystemTime::now(),
+            local: addresses.local,
+            remote: addresses.remote,
+            packet_len: header.len() + packet.payload.len(),
+            header,
+        }
+    }
+}
+
+// the media socket and the remote of the RTP session
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RtpAddresses {
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct CaptureTap {
+    sender: Arc<Mutex<Option<mpsc::Sender<RtpHeader>>>>,
+}
+
+impl CaptureTap {
+    pub fn start(&self) -> mpsc::Receiver<RtpHeader> {
+        let (sender, receiver) = mpsc::channel(CAPACITY);
+        *self.lock() = Some(sender);
+        receiver
+    }
+
+    pub fn stop(&self) {
+        self.lock().take();
+    }
+
+    pub fn on_sent(&self, addresses: RtpAddresses, packet: &RtpPacket) {
+        self.push(Direction::Sent, addresses, packet);
+    }
+
+    pub fn on_received(&self, addresses: RtpAddresses, packet: &RtpPacket) {
+        self.push(Direction::Received, addresses, packet);
+    }
+
+    fn push(&self, direction: Direction, addresses: RtpAddresses, packet: &RtpPacket) {
+        if let Some(sender) = self.lock().as_ref() {
+            let _ = sender.try_send(RtpHeader::of(direction, addresses, packet));
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<mpsc::Sender<RtpHeader>>> {
+        self.sender.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+// the messages are taken where the endpoint hands them to the transport and where it gets them
+#[derive(Clone)]
+pub(crate) struct SipTap {
+    sender: broadcast::Sender<SipMessage>,
+}
+
+impl Default for SipTap {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(SIP_CAPACITY).0,
+        }
+    }
+}
+
+impl SipTap {
+    pub fn subscribe(&self) -> broadcast::Receiver<SipMessage> {
+        self.sender.subscribe()
+    }
+
+    fn push(&self, message: SipMessage) {
+        // there may be no subscribers
+        let _ = self.sender.send(message);
+    }
+}
+
+impl MessageTap for SipTap {
+    fn on_sent(&self, transport: &TpHandle, destination: SocketAddr, buffer: &Bytes) {
+        self.push(SipMessage {
+            direction: Direction::Sent,
+            time: SystemTime::now(),
+            local: transport.bound(),
+            remote: destination,
+            bytes: buffer.clone(),
+        });
+    }
+
+    fn on_received(&self, tp_info: &MessageTpInfo) {
+        self.push(SipMessage {
+            direction: Direction::Received,
+            time: tp_info.timestamp,
+            local: tp_info.transport.bound(),
+            remote: tp_info.source,
+            bytes: tp_info.buffer.clone(),
+        });
+    }
+}
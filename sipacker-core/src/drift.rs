@@ -0,0 +1,57 @@
+use crate::pipeline;
+
+use std::time::{Duration, Instant};
+
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+const SMOOTHING: f64 = 0.02;
+// the compensation starts when the level drifts by 10 ms and stops when it's back within 5 ms
+const START_THRESHOLD: f64 = pipeline::FRAME_SAMPLES as f64;
+const STOP_THRESHOLD: f64 = START_THRESHOLD / 2.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Adjustment {
+    #[default]
+    None,
+    // one sample is dropped, the queue grows because the producer clock is faster
+    Drop,
+    // one sample is repeated, the queue starves because the consumer clock is faster
+    Repeat,
+}
+
+// keeps the level of the queue between two clock domains (the audio device and the RTP clock)
+// bounded by dropping or repeating single samples, at most one sample per update
+#[derive(Default)]
+pub struct DriftCompensator {
+    started: Option<Instant>,
+    reference: Option<f64>,
+    average: f64,
+    adjustment: Adjustment,
+}
+
+impl DriftCompensator {
+    pub fn update(&mut self, level: usize) -> Adjustment {
+        let level = level as f64;
+        let started = *self.started.get_or_insert_with(|| {
+            self.average = level;
+            Instant::now()
+        });
+        self.average += (level - self.average) * SMOOTHING;
+
+        let Some(reference) = self.reference else {
+            if started.elapsed() >= SETTLE_TIME {
+                self.reference = Some(self.average);
+            }
+            return Adjustment::None;
+        };
+
+        let deviation = self.average - reference;
+        self.adjustment = match self.adjustment {
+            Adjustment::None if deviation > START_THRESHOLD => Adjustment::Drop,
+            Adjustment::None if deviation < -START_THRESHOLD => Adjustment::Repeat,
+            Adjustment::Drop if deviation < STOP_THRESHOLD => Adjustment::None,
+            Adjustment::Repeat if deviation > -STOP_THRESHOLD => Adjustment::None,
+            adjustment => adjustment,
+        };
+        self.adjustment
+    }
+}
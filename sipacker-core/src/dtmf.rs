@@ -0,0 +1,187 @@
+use crate::{
+    pipeline,
+    tone::{self, Tone, ToneGenerator},
+};
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+
+// the digit tone lasts 100 ms, the pause between the digits must be at least 40 ms, ITU-T Q.24
+const PAUSE_SAMPLES: usize = pipeline::SAMPLE_RATE / 10;
+pub const PAUSE: char = ',';
+const DIALING_PAUSE_SAMPLES: usize = pipeline::SAMPLE_RATE / 2;
+
+pub fn is_valid_symbol(symbol: char) -> bool {
+    symbol == PAUSE || tone::dtmf_frequencies(symbol).is_some()
+}
+
+// in-band DTMF for the gateways that understand neither RFC 4733 nor SIP INFO: the tones
+// replace the microphone audio before it's encoded
+#[derive(Clone, Default)]
+pub(crate) struct DtmfTap {
+    dtmf: Arc<Mutex<InbandDtmf>>,
+}
+
+#[derive(Default)]
+struct InbandDtmf {
+    digits: VecDeque<char>,
+    tone: Option<ToneGenerator>,
+    pause: usize,
+}
+
+impl DtmfTap {
+    pub fn send(&self, digits: &str) -> Result<()> {
+        if let Some(digit) = digits.chars().find(|digit| !is_valid_symbol(*digit)) {
+            return Err(anyhow::Error::msg(format!("Invalid DTMF digit: {digit}")));
+        }
+        self.lock().digits.extend(digits.chars());
+        tracing::info!("Sending DTMF {digits} in-band");
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        *self.lock() = InbandDtmf::default();
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.lock().is_active()
+    }
+
+    pub fn process(&self, frame: &mut [f32]) {
+        let mut dtmf = self.lock();
+        if !dtmf.is_active() {
+            return;
+        }
+        for sample in frame {
+            *sample = dtmf.next_sample();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, InbandDtmf> {
+        self.dtmf.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl InbandDtmf {
+    fn is_active(&self) -> bool {
+        self.tone.is_some() || self.pause > 0 || !self.digits.is_empty()
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        if let Some(tone) = self.tone.as_mut() {
+            if let Some(sample) = tone.next_sample() {
+                return sample;
+            }
+            self.tone = None;
+            self.pause = PAUSE_SAMPLES;
+        }
+        if self.pause > 0 {
+            self.pause -= 1;
+            return 0.0;
+        }
+
+        let digit = self.digits.pop_front();
+        if digit == Some(PAUSE) {
+            self.pause = DIALING_PAUSE_SAMPLES;
+            return 0.0;
+        }
+        let tone = digit
+            .and_then(|digit| ToneGenerator::new(Tone::Dtmf(digit), pipeline::SAMPLE_RATE as u32));
+        self.tone = tone;
+        self.tone
+            .as_mut()
+            .and_then(|tone| tone.next_sample())
+            .unwrap_or(0.0)
+    }
+}
+
+// the Goertzel block of the DTMF receivers, ~25 ms at 8 kHz, it separates the frequencies
+// of the rows and the columns
+const DETECTION_BLOCK: usize = 205;
+// the share of the block energy in the two tones, 1.0 for the pure digit
+const MIN_TONE_SHARE: f32 = 0.6;
+// around -45 dBFS
+const MIN_BLOCK_POWER: f32 = 3e-5;
+// 8 dB between the row and the column tones, ITU-T Q.24
+const MAX_TWIST: f32 = 6.3;
+const ROWS: [f32; 4] = [697.0, 770.0, 852.0, 941.0];
+const COLUMNS: [f32; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+const DIGITS: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+pub(crate) struct DtmfDetector {
+    block: Vec<f32>,
+    candidate: Option<char>,
+    reported: Option<char>,
+}
+
+impl DtmfDetector {
+    pub fn new() -> Self {
+        Self {
+            block: Vec::with_capacity(DETECTION_BLOCK),
+            candidate: None,
+            reported: None,
+        }
+    }
+
+    pub fn process(&mut self, frame: &[f32]) -> Vec<char> {
+        let mut digits = Vec::new();
+        for sample in frame {
+            self.block.push(*sample);
+            if self.block.len() < DETECTION_BLOCK {
+                continue;
+            }
+            let digit = detect(&self.block);
+            self.block.clear();
+            // the digit or the pause lasts two blocks at least, the single blocks are ignored
+            if digit == self.candidate && digit != self.reported {
+                digits.extend(digit);
+                self.reported = digit;
+            }
+            self.candidate = digit;
+        }
+        digits
+    }
+}
+
+fn detect(block: &[f32]) -> Option<char> {
+    let energy: f32 = block.iter().map(|sample| sample * sample).sum();
+    if energy / (block.len() as f32) < MIN_BLOCK_POWER {
+        return None;
+    }
+    let (row, row_power) = strongest(block, &ROWS);
+    let (column, column_power) = strongest(block, &COLUMNS);
+    // the power of the pure tone of the amplitude A is (A N / 2)^2 and its energy A^2 N / 2
+    let share = (row_power + column_power) / (energy * block.len() as f32 / 2.0);
+    let twist = row_power / column_power.max(f32::EPSILON);
+    (share >= MIN_TONE_SHARE && (1.0 / MAX_TWIST..=MAX_TWIST).contains(&twist))
+        .then_some(DIGITS[row][column])
+}
+
+fn strongest(block: &[f32], frequencies: &[f32; 4]) -> (usize, f32) {
+    frequencies
+        .iter()
+        .map(|frequency| goertzel(block, *frequency))
+        .enumerate()
+        .max_by(|(_, left), (_, right)| left.total_cmp(right))
+        .unwrap_or_default()
+}
+
+fn goertzel(block: &[f32], frequency: f32) -> f32 {
+    let coeff = 2.0 * (2.0 * std::f32::consts::PI * frequency / pipeline::SAMPLE_RATE as f32).cos();
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for sample in block {
+        let s0 = sample + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    s1 * s1 + s2 * s2 - coeff * s1 * s2
+}
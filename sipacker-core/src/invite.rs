@@ -0,0 +1,209 @@
+use crate::{transaction::Message, user_agent::Caller};
+use ezk_sip_core::IncomingRequest;
+
+/// The headers of the received INVITE beyond From, carried by
+/// [`UserAgentEvent::IncomingCall`](crate::user_agent::UserAgentEvent::IncomingCall).
+#[derive(Debug, Clone, Default)]
+pub struct IncomingInvite {
+    /// The identity the proxy asserted for the caller: the P-Asserted-Identity (RFC 3325) or
+    /// the Remote-Party-ID. The first one of the identities is taken, e.g. the SIP URI before
+    /// the tel one.
+    pub asserted: Option<Caller>,
+    /// The users the call was forwarded from, the last one first: the History-Info (RFC 7044)
+    /// or the Diversion (RFC 5806).
+    pub forwarded_from: Vec<String>,
+    /// The Subject (RFC 3261 20.36), e.g. the reason of the call.
+    pub subject: Option<String>,
+    /// The PBX asks to answer the call at once, e.g. the intercom or the paging: the
+    /// Answer-Mode (RFC 5373) is Auto, the Alert-Info is alert-autoanswer or the Call-Info has
+    /// answer-after. The delay isn't taken into account.
+    pub auto_answer: bool,
+}
+
+impl IncomingInvite {
+    /// None if it's not an INVITE.
+    pub fn parse(invite: &[u8]) -> Option<Self> {
+        let request = Message::parse(invite)?;
+        if request.method() != Some("INVITE") {
+            return None;
+        }
+        Some(Self::of_message(&request))
+    }
+
+    pub(crate) fn of(invite: &IncomingRequest) -> Self {
+        Self::of_message(&Message::of_request(invite))
+    }
+
+    fn of_message(request: &Message) -> Self {
+        let asserted = ["p-asserted-identity", "remote-party-id"]
+            .into_iter()
+            .find_map(|name| request.header_values(name).first().copied())
+            .map(Caller::of_header);
+        Self {
+            asserted,
+            forwarded_from: forwarded_from(request),
+            subject: request
+                .header("subject")
+                .filter(|subject| !subject.is_empty())
+                .map(str::to_owned),
+            auto_answer: auto_answer(request),
+        }
+    }
+}
+
+// History-Info lists the targets in the order of the retargeting, the last one is the user
+// agent itself, Diversion lists the diverting users starting from the last one
+fn forwarded_from(request: &Message) -> Vec<String> {
+    let history = request.header_values("history-info");
+    let mut users: Vec<_> = if history.is_empty() {
+        request
+            .header_values("diversion")
+            .into_iter()
+            .filter_map(user)
+            .collect()
+    } else {
+        history.into_iter().rev().skip(1).filter_map(user).collect()
+    };
+    users.dedup();
+    users
+}
+
+// "Answer-Mode: Auto", "Alert-Info: <http://127.0.0.1>;info=alert-autoanswer;delay=0" or
+// "Call-Info: <sip:pbx>;answer-after=0"
+fn auto_answer(request: &Message) -> bool {
+    let mut answer_modes = ["answer-mode", "priv-answer-mode"]
+        .into_iter()
+        .flat_map(|name| request.header_values(name));
+    let has_param = |name: &str, param: &str| {
+        request
+            .header_values(name)
+            .iter()
+            .any(|value| value.to_ascii_lowercase().contains(param))
+    };
+    answer_modes.any(|mode| {
+        let mode = mode.split(';').next().unwrap_or_default();
+        mode.trim().eq_ignore_ascii_case("auto")
+    }) || has_param("alert-info", "alert-autoanswer")
+        || has_param("call-info", "answer-after=")
+}
+
+// the user of "<sip:1001@host?Reason=SIP%3Bcause%3D302>;index=1.1" or the number of the tel URI
+fn user(entry: &str) -> Option<String> {
+    let uri = match entry.split_once('<') {
+        Some((_, rest)) => rest.split('>').next().unwrap_or(rest),
+        None => entry,
+    };
+    let uri = uri.split(['?', ';']).next()?.trim();
+    let (scheme, rest) = uri.split_once(':')?;
+    let user = match scheme.to_ascii_lowercase().as_str() {
+        "sip" | "sips" => rest.split_once('@').map_or(rest, |(user, _)| user),
+        "tel" => rest,
+        _ => return None,
+    };
+    (!user.is_empty()).then(|| user.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invite(headers: &str) -> IncomingInvite {
+        let message = format!(
+            "INVITE sip:1002@10.0.0.1 SIP/2.0\r\n\
+             From: <sip:1001@10.0.0.1>;tag=a1\r\n\
+             To: <sip:1002@10.0.0.1>\r\n\
+             {headers}\r\n"
+        );
+        IncomingInvite::parse(message.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn parse_rejects_other_methods() {
+        let message = b"MESSAGE sip:1002@10.0.0.1 SIP/2.0\r\nFrom: <sip:1001@10.0.0.1>\r\n\r\n";
+        assert!(IncomingInvite::parse(message).is_none());
+    }
+
+    #[test]
+    fn parse_takes_first_asserted_identity() {
+        let invite = invite(
+            "P-Asserted-Identity: \"Alice, Sales\" <sip:1001@pbx>, <tel:+15551234>\r\n\
+             Remote-Party-ID: <sip:9999@pbx>\r\n",
+        );
+        let asserted = invite.asserted.unwrap();
+        assert_eq!(asserted.uri, "sip:1001@pbx");
+        assert_eq!(asserted.display_name.as_deref(), Some("Alice, Sales"));
+    }
+
+    #[test]
+    fn parse_falls_back_to_remote_party_id() {
+        let invite = invite("Remote-Party-ID: <sip:1001@pbx>;party=calling;screen=yes\r\n");
+        let asserted = invite.asserted.unwrap();
+        assert_eq!(asserted.uri, "sip:1001@pbx");
+        assert_eq!(asserted.display_name, None);
+    }
+
+    #[test]
+    fn history_info_lists_last_diverting_user_first() {
+        let invite = invite(
+            "History-Info: <sip:1001@pbx>;index=1, <sip:1003@pbx?Reason=SIP%3Bcause%3D302>;index=1.1\r\n\
+             History-Info: <sip:1002@pbx>;index=1.1.1\r\n",
+        );
+        assert_eq!(invite.forwarded_from, ["1003", "1001"]);
+    }
+
+    #[test]
+    fn history_info_wins_over_diversion() {
+        let invite = invite(
+            "Diversion: <sip:2000@pbx>;reason=unconditional\r\n\
+             History-Info: <sip:1001@pbx>;index=1, <sip:1002@pbx>;index=1.1\r\n",
+        );
+        assert_eq!(invite.forwarded_from, ["1001"]);
+    }
+
+    #[test]
+    fn diversion_keeps_order_and_takes_tel_numbers() {
+        let invite = invite(
+            "Diversion: <sip:1003@pbx>;reason=no-answer, <tel:+15551234>;reason=user-busy\r\n",
+        );
+        assert_eq!(invite.forwarded_from, ["1003", "+15551234"]);
+    }
+
+    #[test]
+    fn forwarded_from_drops_repeated_users() {
+        let invite = invite(
+            "History-Info: <sip:1001@pbx>;index=1, <sip:1001@pbx>;index=1.1, <sip:1002@pbx>;index=1.1.1\r\n",
+        );
+        assert_eq!(invite.forwarded_from, ["1001"]);
+    }
+
+    #[test]
+    fn not_forwarded_without_headers() {
+        assert!(invite("").forwarded_from.is_empty());
+    }
+
+    #[test]
+    fn answer_mode_auto_answers() {
+        assert!(invite("Answer-Mode: Auto;require\r\n").auto_answer);
+        assert!(invite("Priv-Answer-Mode: auto\r\n").auto_answer);
+        assert!(!invite("Answer-Mode: Manual\r\n").auto_answer);
+    }
+
+    #[test]
+    fn alert_info_and_call_info_auto_answer() {
+        assert!(
+            invite("Alert-Info: <http://127.0.0.1>;info=alert-autoanswer;delay=0\r\n").auto_answer
+        );
+        assert!(invite("Call-Info: <sip:pbx>;answer-after=0\r\n").auto_answer);
+        assert!(!invite("Alert-Info: <http://127.0.0.1/ring.wav>\r\n").auto_answer);
+        assert!(!invite("").auto_answer);
+    }
+
+    #[test]
+    fn subject_is_taken_from_compact_form() {
+        assert_eq!(
+            invite("s: escalation #4211\r\n").subject.as_deref(),
+            Some("escalation #4211")
+        );
+        assert_eq!(invite("Subject: \r\n").subject, None);
+    }
+}
@@ -0,0 +1,251 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bytes::Bytes;
+use ezk_rtp::RtpPacket;
+
+// the packets older than that are considered as the sequence number reset, RFC 3550 A.1
+const MAX_MISORDER: i16 = 100;
+
+// fixed-depth jitter buffer: reorders the packets by the sequence number and plays them out
+// by one per frame once the target depth is reached
+pub(crate) struct JitterBuffer {
+    slots: VecDeque<Option<Bytes>>,
+    next: Option<u16>,
+    playing: bool,
+    ptime: Duration,
+    clock_rate: u32,
+    newest: Option<(u16, u32)>,
+    // the samples per packet of the last measurement, the ptime is changed when two agree
+    measured: Option<u32>,
+    target_depth: usize,
+    max_depth: usize,
+    late: u64,
+    dropped: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JitterBufferStats {
+    pub depth: usize,
+    pub delay: Duration,
+    pub target_depth: usize,
+    pub late: u64,
+    pub dropped: u64,
+}
+
+impl JitterBuffer {
+    pub fn new(ptime: Duration, clock_rate: u32, target_depth: usize, max_depth: usize) -> Self {
+        Self {
+            slots: VecDeque::with_capacity(max_depth),
+            next: None,
+            playing: false,
+            ptime,
+            clock_rate,
+            newest: None,
+            measured: None,
+            target_depth,
+            max_depth: max_depth.max(target_depth),
+            late: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn push(&mut self, packet: RtpPacket) {
+        let sequence_number = packet.sequence_number.0;
+        let next = *self.next.get_or_insert(sequence_number);
+        let offset = sequence_number.wrapping_sub(next) as i16;
+        if offset < -MAX_MISORDER {
+            self.slots.clear();
+            self.next = Some(sequence_number);
+            self.newest = None;
+            return self.push(packet);
+        }
+        if offset < 0 {
+            self.late += 1;
+            return;
+        }
+        self.measure_ptime(sequence_number, packet.timestamp.0);
+
+        let mut offset = offset as usize;
+        if offset >= self.max_depth {
+            // the buffer is overflowed or the peer jumped ahead, the oldest packets are dropped
+            let excess = offset + 1 - self.max_depth;
+            let drained = excess.min(self.slots.len());
+            self.dropped += self
+                .slots
+                .drain(..drained)
+                .filter(|slot| slot.is_some())
+                .count() as u64;
+            self.next = Some(next.wrapping_add(excess as u16));
+            offset = self.max_depth - 1;
+        }
+
+        if self.slots.len() <= offset {
+            self.slots.resize(offset + 1, None);
+        }
+        if self.slots[offset].replace(packet.payload).is_some() {
+            self.dropped += 1;
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Bytes> {
+        if !self.playing {
+            if self.depth() < self.target_depth {
+                return None;
+            }
+            self.playing = true;
+        }
+
+        let Some(slot) = self.slots.pop_front() else {
+            self.playing = false;
+            return None;
+        };
+        self.next = self.next.map(|next| next.wrapping_add(1));
+        slot
+    }
+
+    pub fn ptime(&self) -> Duration {
+        self.ptime
+    }
+
+    pub fn stats(&self) -> JitterBufferStats {
+        JitterBufferStats {
+            depth: self.depth(),
+            delay: self.ptime * self.slots.len() as u32,
+            target_depth: self.target_depth,
+            late: self.late,
+            dropped: self.dropped,
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    // the timestamp delta over the sequence number delta, the packets of the same timestamp
+    // (the DTMF events, the keepalives) don't change it and the single jump after the silence
+    // doesn't either
+    fn measure_ptime(&mut self, sequence_number: u16, timestamp: u32) {
+        let Some((newest_sequence_number, newest_timestamp)) = self.newest else {
+            self.newest = Some((sequence_number, timestamp));
+            return;
+        };
+        let packets = sequence_number.wrapping_sub(newest_sequence_number) as i16;
+        if packets <= 0 {
+            return;
+        }
+        self.newest = Some((sequence_number, timestamp));
+        let samples = timestamp.wrapping_sub(newest_timestamp) / packets as u32;
+        if samples == 0 || samples > self.clock_rate {
+            return;
+        }
+        if self.measured.replace(samples) == Some(samples) {
+            self.ptime = Duration::from_micros(samples as u64 * 1_000_000 / self.clock_rate as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ezk_rtp::{RtpExtensions, RtpTimestamp, SequenceNumber, Ssrc};
+
+    const PTIME: Duration = Duration::from_millis(20);
+
+    fn packet(sequence_number: u16, timestamp: u32) -> RtpPacket {
+        RtpPacket {
+            pt: 8,
+            sequence_number: SequenceNumber(sequence_number),
+            timestamp: RtpTimestamp(timestamp),
+            payload: Bytes::from(sequence_number.to_be_bytes().to_vec()),
+            ssrc: Ssrc(0),
+            extensions: RtpExtensions::default(),
+        }
+    }
+
+    fn sequence_number(payload: Option<Bytes>) -> Option<u16> {
+        payload.map(|payload| u16::from_be_bytes([payload[0], payload[1]]))
+    }
+
+    #[test]
+    fn reordered_packets_are_played_in_sequence() {
+        let mut buffer = JitterBuffer::new(PTIME, 8000, 3, 10);
+        for sequence_number in [10, 12, 11] {
+            buffer.push(packet(sequence_number, sequence_number as u32 * 160));
+        }
+        let played: Vec<_> = (0..3).map(|_| sequence_number(buffer.pop())).collect();
+        assert_eq!(played, [Some(10), Some(11), Some(12)]);
+        assert_eq!(buffer.stats().late, 0);
+    }
+
+    #[test]
+    fn lost_packet_leaves_the_gap() {
+        let mut buffer = JitterBuffer::new(PTIME, 8000, 2, 10);
+        buffer.push(packet(1, 160));
+        buffer.push(packet(3, 480));
+        let played: Vec<_> = (0..3).map(|_| sequence_number(buffer.pop())).collect();
+        assert_eq!(played, [Some(1), None, Some(3)]);
+    }
+
+    #[test]
+    fn packet_after_its_playout_is_late() {
+        let mut buffer = JitterBuffer::new(PTIME, 8000, 1, 10);
+        buffer.push(packet(5, 800));
+        assert_eq!(sequence_number(buffer.pop()), Some(5));
+        buffer.push(packet(4, 640));
+        assert_eq!(buffer.stats().late, 1);
+        assert_eq!(buffer.stats().depth, 0);
+    }
+
+    #[test]
+    fn overflow_drops_the_oldest_packets() {
+        let mut buffer = JitterBuffer::new(PTIME, 8000, 2, 4);
+        for sequence_number in 0..6 {
+            buffer.push(packet(sequence_number, sequence_number as u32 * 160));
+        }
+        let stats = buffer.stats();
+        assert_eq!(stats.dropped, 2);
+        assert_eq!(stats.depth, 4);
+        assert_eq!(sequence_number(buffer.pop()), Some(2));
+    }
+
+    #[test]
+    fn duplicated_packet_is_dropped() {
+        let mut buffer = JitterBuffer::new(PTIME, 8000, 1, 4);
+        buffer.push(packet(7, 1120));
+        buffer.push(packet(7, 1120));
+        assert_eq!(buffer.stats().dropped, 1);
+        assert_eq!(buffer.stats().depth, 1);
+    }
+
+    #[test]
+    fn ptime_is_measured_by_the_timestamps() {
+        let mut buffer = JitterBuffer::new(PTIME, 8000, 2, 10);
+        buffer.push(packet(1, 240));
+        buffer.push(packet(2, 480));
+        assert_eq!(buffer.ptime(), PTIME);
+        // the lost packet in between doesn't double it
+        buffer.push(packet(4, 960));
+        assert_eq!(buffer.ptime(), Duration::from_millis(30));
+        assert_eq!(buffer.stats().delay, Duration::from_millis(30) * 4);
+    }
+
+    #[test]
+    fn ptime_ignores_the_jump_after_the_silence() {
+        let mut buffer = JitterBuffer::new(PTIME, 8000, 2, 10);
+        buffer.push(packet(1, 160));
+        buffer.push(packet(2, 320));
+        buffer.push(packet(3, 4320));
+        buffer.push(packet(4, 4480));
+        assert_eq!(buffer.ptime(), PTIME);
+    }
+
+    #[test]
+    fn ptime_ignores_the_same_timestamp_and_the_reordered_packets() {
+        let mut buffer = JitterBuffer::new(PTIME, 8000, 2, 10);
+        buffer.push(packet(1, 160));
+        buffer.push(packet(3, 160));
+        buffer.push(packet(2, 80));
+        assert_eq!(buffer.ptime(), PTIME);
+    }
+}
@@ -0,0 +1,71 @@
+//! The SIP user agent of sipacker: the registration, the calls and their audio.
+//!
+//! [`user_agent::UserAgent`] is the entry point: it registers on the registrar, makes and
+//! answers the calls while [`user_agent::UserAgent::run`] is polled and broadcasts what
+//! happened as [`user_agent::UserAgentEvent`]s with the ids of the calls to every subscriber
+//! of [`user_agent::UserAgent::subscribe`]. The audio of the calls is exchanged through the
+//! [`pipeline::AudioProducer`]/[`pipeline::AudioConsumer`] ring buffers of 8 kHz PCM samples,
+//! [`audio::AudioSystem`] connects them to the sound devices (or the files, the tone, the
+//! null backend) and [`mixer::AudioBus`] shares them between the calls (with their playback
+//! and recording), the loopback and the tones.
+//!
+//! ```ignore
+//! use sipacker_core::{pipeline::LatencyBudget, user_agent::{SrtpPolicy, UserAgent}};
+//!
+//! let latency = LatencyBudget::new(Duration::from_millis(60), Duration::from_millis(20));
+//! let mut user_agent = UserAgent::build("0.0.0.0:5060".parse()?, latency).await?;
+//! let mut events = user_agent.subscribe();
+//! user_agent.register("1001", "secret", registrar, SrtpPolicy::Optional, None).await?;
+//! loop {
+//!     user_agent.run().await?;
+//!     while let Ok(event) = events.try_recv() {
+//!         println!("{event:?}");
+//!     }
+//! }
+//! ```
+
+/// The audio streams of the sound devices and the other backends.
+pub mod audio;
+/// The Busy Lamp Field: the states of the watched extensions, RFC 4235.
+pub mod blf;
+/// The state of a single call and its media taps.
+pub mod call;
+/// The SIP messages and the RTP headers of the calls for the packet captures.
+pub mod capture;
+/// The clock drift compensation between the call and the sound device.
+pub mod drift;
+/// The in-band DTMF: the sending, the detection and the dialing symbols.
+pub mod dtmf;
+/// The headers of the received INVITE which the SIP client doesn't hand over.
+pub mod invite;
+/// The reordering of the incoming RTP packets.
+pub mod jitter_buffer;
+/// The echo of the microphone to the speaker for the audio checks.
+pub mod loopback;
+/// The audio bus between the audio streams, the calls and the tones.
+pub mod mixer;
+/// The Message Waiting Indication: the voicemail counts, RFC 3842.
+pub mod mwi;
+/// The ring buffers of the PCM samples and the latency budget.
+pub mod pipeline;
+/// The playback of the audio files into the call.
+pub mod player;
+/// The presence of the user agent: the PIDF documents, RFC 3863.
+pub mod presence;
+/// The MOS estimation of the call quality.
+pub mod quality;
+/// The recording of the call audio to the WAV files.
+pub mod recorder;
+/// The sample rate conversion of the audio streams.
+pub mod resample;
+/// The RTCP XR report blocks, RFC 3611.
+pub mod rtcp_xr;
+/// The RTP and the audio statistics of the call.
+pub mod stats;
+/// The tones and the tone generators.
+pub mod tone;
+/// The standalone SIP requests on the endpoint of the SIP client: MESSAGE, OPTIONS, PUBLISH and
+/// SUBSCRIBE.
+mod transaction;
+/// The user agent: the registration and the calls.
+pub mod user_agent;
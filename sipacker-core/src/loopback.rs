@@ -0,0 +1,49 @@
+use crate::pipeline::{self, AudioConsumer, AudioProducer};
+
+use std::{collections::VecDeque, time::Duration};
+
+use tokio::{
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior},
+};
+
+pub struct Loopback {
+    task: JoinHandle<()>,
+}
+
+impl Loopback {
+    pub fn start(
+        mut audio_receiver: AudioConsumer,
+        mut audio_sender: AudioProducer,
+        delay: Duration,
+    ) -> Self {
+        let task = tokio::spawn(async move {
+            let mut delayed = VecDeque::new();
+            let mut frame = [0.0; pipeline::FRAME_SAMPLES];
+            let mut decoded = Vec::with_capacity(pipeline::FRAME_SAMPLES);
+            let mut interval = tokio::time::interval(Duration::from_millis(10));
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                while audio_receiver.pop_frame(&mut frame) {
+                    delayed.push_back((Instant::now(), pipeline::encode_alaw(&frame)));
+                }
+                while delayed
+                    .front()
+                    .is_some_and(|(captured, _)| captured.elapsed() >= delay)
+                {
+                    if let Some((_, alaw)) = delayed.pop_front() {
+                        decoded.clear();
+                        pipeline::decode_alaw(&alaw, &mut decoded);
+                        audio_sender.push(&decoded);
+                    }
+                }
+            }
+        });
+        Self { task }
+    }
+
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    pipeline::{self, AudioConsumer, AudioProducer},
+    player::PlaybackTap,
+    recorder::RecordingTap,
+    tone::{Tone, ToneGenerator},
+};
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+
+// bounds the work of a tick when the task is delayed, the rest is mixed by the next ticks
+const MAX_FRAMES_PER_TICK: usize = 4;
+
+// sits between the audio streams and the call tasks: sums the sources (remote audio, tones)
+// into the output stream and fans the captured audio (with the file playback) out to the
+// sinks, the recording of the call takes both
+pub struct AudioBus {
+    routes: Arc<Mutex<Routes>>,
+    capacity: usize,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct Routes {
+    sources: Vec<AudioConsumer>,
+    sinks: Vec<AudioProducer>,
+    tone: Option<ToneGenerator>,
+    playback: PlaybackTap,
+    recording: RecordingTap,
+}
+
+impl AudioBus {
+    pub fn start(mut input: AudioConsumer, mut output: AudioProducer, capacity: usize) -> Self {
+        let routes = Arc::new(Mutex::new(Routes::default()));
+        let task = {
+            let routes = routes.clone();
+            tokio::spawn(async move {
+                let mut frame = [0.0; pipeline::FRAME_SAMPLES];
+                let mut mixed = [0.0; pipeline::FRAME_SAMPLES];
+                let mut interval = tokio::time::interval(Duration::from_millis(10));
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    let mut active = routes.lock().unwrap_or_else(|err| err.into_inner());
+                    active.remove_abandoned();
+                    while input.pop_frame(&mut frame) {
+                        active.playback.process(&mut frame);
+                        active.recording.push_local(&frame);
+                        for sink in active.sinks.iter_mut() {
+                            sink.push(&frame);
+                        }
+                    }
+                    let mut tone = active.tone.is_some();
+                    for _ in 0..MAX_FRAMES_PER_TICK {
+                        let popped = active.mix_frame(&mut frame, &mut mixed);
+                        if popped {
+                            active.recording.push_remote(&mixed);
+                        }
+                        if tone {
+                            tone = false;
+                            active.mix_tone(&mut mixed);
+                        } else if !popped {
+                            break;
+                        }
+                        output.push(&mixed);
+                    }
+                }
+            })
+        };
+        Self {
+            routes,
+            capacity,
+            task,
+        }
+    }
+
+    pub fn add_source(&self) -> AudioProducer {
+        let (producer, consumer) = pipeline::audio_pipe(self.capacity);
+        self.lock().sources.push(consumer);
+        producer
+    }
+
+    pub fn add_sink(&self) -> AudioConsumer {
+        let (producer, consumer) = pipeline::audio_pipe(self.capacity);
+        self.lock().sinks.push(producer);
+        consumer
+    }
+
+    pub fn play_tones(&self, tones: &[Tone]) -> Result<()> {
+        let generator =
+            ToneGenerator::sequence(tones.iter().copied(), pipeline::SAMPLE_RATE as u32)
+                .ok_or(anyhow::Error::msg(format!("Unsupported tones: {tones:?}")))?;
+        let _ = self.lock().tone.replace(generator);
+        tracing::debug!("Playing the tones: {tones:?}");
+        Ok(())
+    }
+
+    pub fn stop_tone(&self) {
+        self.lock().tone.take();
+    }
+
+    pub(crate) fn attach_call(&self, playback: PlaybackTap, recording: RecordingTap) {
+        let mut routes = self.lock();
+        routes.playback = playback;
+        routes.recording = recording;
+    }
+
+    pub fn stop(self) {
+        self.task.abort();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Routes> {
+        self.routes.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Routes {
+    fn remove_abandoned(&mut self) {
+        self.sources.retain(|source| {
+            !source.is_abandoned() || source.occupancy().len >= pipeline::FRAME_SAMPLES
+        });
+        self.sinks.retain(|sink| !sink.is_abandoned());
+    }
+
+    fn mix_frame(&mut self, frame: &mut [f32], mixed: &mut [f32]) -> bool {
+        let mut popped = false;
+        mixed.fill(0.0);
+        for source in self.sources.iter_mut() {
+            if source.pop_frame(frame) {
+                popped = true;
+                mixed
+                    .iter_mut()
+                    .zip(frame.iter())
+                    .for_each(|(mixed, sample)| *mixed += sample);
+            }
+        }
+        mixed
+            .iter_mut()
+            .for_each(|sample| *sample = sample.clamp(-1.0, 1.0));
+        popped
+    }
+
+    fn mix_tone(&mut self, mixed: &mut [f32]) {
+        let Some(tone) = self.tone.as_mut() else {
+            return;
+        };
+        for sample in mixed.iter_mut() {
+            let Some(tone) = tone.next_sample() else {
+                break;
+            };
+            *sample = (*sample + tone).clamp(-1.0, 1.0);
+        }
+        if tone.is_finished() {
+            self.tone = None;
+        }
+    }
+}
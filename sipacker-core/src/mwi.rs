@@ -0,0 +1,102 @@
+use std::fmt::Display;
+
+/// The voicemail box of the registered user, see [`crate::user_agent::UserAgent::watch_mailbox`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageSummary {
+    pub waiting: bool,
+    /// The new and the old voice messages.
+    pub new: u32,
+    pub old: u32,
+    /// The URI of the voicemail box, if the server tells it.
+    pub account: Option<String>,
+}
+
+impl Display for MessageSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} new / {} old", self.new, self.old)
+    }
+}
+
+// the simple-message-summary body (RFC 3842) of the NOTIFY:
+//   Messages-Waiting: yes
+//   Message-Account: sip:*97@example.com
+//   Voice-Message: 2/8 (0/2)
+// the urgent messages in the parentheses are counted in the others, none without
+// Messages-Waiting
+pub(crate) fn parse(body: &str) -> Option<MessageSummary> {
+    let mut waiting = None;
+    let mut summary = MessageSummary::default();
+    for line in body.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "messages-waiting" => waiting = Some(value.eq_ignore_ascii_case("yes")),
+            "message-account" => summary.account = Some(value.to_owned()),
+            "voice-message" => {
+                let counts = value.split('(').next().unwrap_or_default();
+                if let Some((new, old)) = counts.split_once('/') {
+                    summary.new = new.trim().parse().unwrap_or_default();
+                    summary.old = old.trim().parse().unwrap_or_default();
+                }
+            }
+            _ => {}
+        }
+    }
+    summary.waiting = waiting?;
+    Some(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_takes_the_counts_and_the_account() {
+        let body = "Messages-Waiting: yes\r\n\
+                    Message-Account: sip:*97@10.0.0.1\r\n\
+                    Voice-Message: 2/8 (0/2)\r\n";
+        assert_eq!(
+            parse(body),
+            Some(MessageSummary {
+                waiting: true,
+                new: 2,
+                old: 8,
+                account: Some("sip:*97@10.0.0.1".to_owned()),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_takes_no_waiting_messages() {
+        let summary = parse("Messages-Waiting: no\r\nVoice-Message: 0/3\r\n").unwrap();
+        assert!(!summary.waiting);
+        assert_eq!((summary.new, summary.old), (0, 3));
+        assert_eq!(summary.account, None);
+    }
+
+    #[test]
+    fn parse_ignores_the_case_and_the_whitespaces() {
+        let body = "messages-waiting:YES\n  VOICE-MESSAGE :  4 / 1  ( 1 / 0 )\n";
+        let summary = parse(body).unwrap();
+        assert!(summary.waiting);
+        assert_eq!((summary.new, summary.old), (4, 1));
+    }
+
+    #[test]
+    fn parse_needs_messages_waiting() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("Voice-Message: 2/8 (0/2)\r\n"), None);
+        assert_eq!(parse("Messages-Waiting yes\r\n"), None);
+    }
+
+    #[test]
+    fn parse_skips_the_malformed_counts() {
+        let summary = parse("Messages-Waiting: maybe\r\nVoice-Message: two/eight\r\n").unwrap();
+        assert!(!summary.waiting);
+        assert_eq!((summary.new, summary.old), (0, 0));
+        let summary = parse("Messages-Waiting: yes\r\nVoice-Message: 5\r\n").unwrap();
+        assert_eq!((summary.new, summary.old), (0, 0));
+    }
+}
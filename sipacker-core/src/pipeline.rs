@@ -0,0 +1,187 @@
+use crate::{drift::Adjustment, stats::ChannelOccupancy};
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use dasp_sample::Sample;
+use ringbuf::{
+    traits::{Consumer, Observer, Producer, Split},
+    HeapCons, HeapProd, HeapRb,
+};
+
+pub const SAMPLE_RATE: usize = 8000;
+pub const FRAME_SAMPLES: usize = SAMPLE_RATE / 100;
+
+/// The sizes of the audio buffers derived from the single latency target: the lower target
+/// reduces the mouth-to-ear delay, the higher one absorbs more network jitter and scheduling
+/// delays without the dropouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyBudget {
+    pub ptime: Duration,
+    pub jitter_target_depth: usize,
+    pub jitter_max_depth: usize,
+    pub max_input_samples: usize,
+    pub pipe_capacity: usize,
+}
+
+impl LatencyBudget {
+    pub fn new(target: Duration, ptime: Duration) -> Self {
+        let target = target.clamp(ptime, Duration::from_secs(1));
+        let samples =
+            |duration: Duration| (SAMPLE_RATE as u128 * duration.as_millis() / 1000) as usize;
+        let jitter_target_depth = (target.as_millis() / ptime.as_millis()).max(1) as usize;
+        Self {
+            ptime,
+            jitter_target_depth,
+            jitter_max_depth: jitter_target_depth * 3,
+            max_input_samples: samples(target).max(samples(ptime * 2)),
+            pipe_capacity: samples(target * 4).max(SAMPLE_RATE / 5),
+        }
+    }
+
+    /// The packetization time of the sent audio: the peer's `ptime` if it's supported, otherwise
+    /// the local one, not above the peer's `maxptime` (RFC 4566 6).
+    pub fn negotiate_ptime(
+        &self,
+        ptime: Option<Duration>,
+        max_ptime: Option<Duration>,
+    ) -> Duration {
+        let supported = |ptime: &Duration| PTIMES_MS.contains(&ptime.as_millis());
+        let ptime = ptime.filter(supported).unwrap_or(self.ptime);
+        match max_ptime {
+            Some(max_ptime) if ptime > max_ptime => PTIMES_MS
+                .iter()
+                .rev()
+                .map(|ms| Duration::from_millis(*ms as u64))
+                .find(|ptime| *ptime <= max_ptime)
+                .unwrap_or(Duration::from_millis(PTIMES_MS[0] as u64)),
+            _ => ptime,
+        }
+    }
+}
+
+pub const PTIMES_MS: [u128; 4] = [10, 20, 30, 40];
+
+pub struct AudioProducer(HeapProd<f32>);
+pub struct AudioConsumer(HeapCons<f32>);
+
+pub fn audio_pipe(capacity: usize) -> (AudioProducer, AudioConsumer) {
+    let (producer, consumer) = HeapRb::<f32>::new(capacity).split();
+    (AudioProducer(producer), AudioConsumer(consumer))
+}
+
+impl AudioProducer {
+    pub fn push(&mut self, samples: &[f32]) -> usize {
+        self.0.push_slice(samples)
+    }
+
+    pub fn is_abandoned(&self) -> bool {
+        !self.0.read_is_held()
+    }
+
+    pub fn occupancy(&self) -> ChannelOccupancy {
+        ChannelOccupancy {
+            len: self.0.occupied_len(),
+            capacity: self.0.capacity().get(),
+        }
+    }
+}
+
+impl AudioConsumer {
+    pub fn pop_frame(&mut self, frame: &mut [f32]) -> bool {
+        if self.0.occupied_len() < frame.len() {
+            return false;
+        }
+        self.0.pop_slice(frame) == frame.len()
+    }
+
+    // pops the frame with one sample dropped or repeated to compensate the clock drift
+    pub fn pop_frame_adjusted(&mut self, frame: &mut [f32], adjustment: Adjustment) -> bool {
+        match adjustment {
+            Adjustment::None => self.pop_frame(frame),
+            Adjustment::Drop => {
+                if self.0.occupied_len() > frame.len() {
+                    self.0.skip(1);
+                }
+                self.pop_frame(frame)
+            }
+            Adjustment::Repeat => {
+                let Some((first, rest)) = frame.split_first_mut() else {
+                    return false;
+                };
+                if !self.pop_frame(rest) {
+                    return false;
+                }
+                *first = rest.first().copied().unwrap_or_default();
+                true
+            }
+        }
+    }
+
+    pub fn limit(&mut self, max_len: usize) -> usize {
+        let excess = self.0.occupied_len().saturating_sub(max_len);
+        self.0.skip(excess)
+    }
+
+    pub fn is_abandoned(&self) -> bool {
+        !self.0.write_is_held()
+    }
+
+    pub fn occupancy(&self) -> ChannelOccupancy {
+        ChannelOccupancy {
+            len: self.0.occupied_len(),
+            capacity: self.0.capacity().get(),
+        }
+    }
+}
+
+pub fn encode_alaw(samples: &[f32]) -> Bytes {
+    samples
+        .iter()
+        .map(|sample| ezk_g711::alaw::encode(sample.to_sample()))
+        .collect()
+}
+
+pub fn decode_alaw(alaw: &[u8], samples: &mut Vec<f32>) {
+    samples.extend(
+        alaw.iter()
+            .map(|sample| ezk_g711::alaw::decode(*sample).to_sample::<f32>()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(ptime_ms: u64) -> LatencyBudget {
+        LatencyBudget::new(Duration::from_millis(60), Duration::from_millis(ptime_ms))
+    }
+
+    #[test]
+    fn local_ptime_without_peer_preference() {
+        assert_eq!(
+            budget(20).negotiate_ptime(None, None),
+            Duration::from_millis(20)
+        );
+    }
+
+    #[test]
+    fn peer_ptime_is_honored() {
+        let ptime = budget(20).negotiate_ptime(Some(Duration::from_millis(30)), None);
+        assert_eq!(ptime, Duration::from_millis(30));
+    }
+
+    #[test]
+    fn unsupported_peer_ptime_is_ignored() {
+        let ptime = budget(20).negotiate_ptime(Some(Duration::from_millis(25)), None);
+        assert_eq!(ptime, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn ptime_is_limited_by_max_ptime() {
+        let ptime = budget(40).negotiate_ptime(None, Some(Duration::from_millis(25)));
+        assert_eq!(ptime, Duration::from_millis(20));
+        let ptime = budget(20).negotiate_ptime(None, Some(Duration::from_millis(5)));
+        assert_eq!(ptime, Duration::from_millis(10));
+    }
+}
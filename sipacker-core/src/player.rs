@@ -0,0 +1,186 @@
+use crate::resample::StreamResampler;
+
+use std::{
+    fmt::Display,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+
+const SAMPLE_RATE: usize = 8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackMode {
+    Mix,
+    Replace,
+}
+
+impl FromStr for PlaybackMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mix" => Ok(Self::Mix),
+            "replace" => Ok(Self::Replace),
+            _ => Err(anyhow::Error::msg(format!("Unknown playback mode: {s}"))),
+        }
+    }
+}
+
+impl Display for PlaybackMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mix => write!(f, "mix"),
+            Self::Replace => write!(f, "replace"),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct PlaybackTap {
+    playback: Arc<Mutex<Option<Playback>>>,
+}
+
+impl PlaybackTap {
+    pub fn start(&self, path: &Path, mode: PlaybackMode) -> Result<()> {
+        let playback = Playback::load(path, mode)?;
+        let _ = self.lock().replace(playback);
+        tracing::info!("Playback of {path:?} ({mode}) is started");
+        Ok(())
+    }
+
+    pub fn stop(&self) -> Result<()> {
+        self.lock()
+            .take()
+            .map(|_| tracing::info!("Playback is stopped"))
+            .ok_or(anyhow::Error::msg("There is no active playback"))
+    }
+
+    pub fn process(&self, frame: &mut [f32]) {
+        let mut playback = self.lock();
+        let Some(active) = playback.as_mut() else {
+            return;
+        };
+
+        active.apply(frame);
+        if active.is_finished() {
+            tracing::info!("Playback is finished");
+            playback.take();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Playback>> {
+        self.playback.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+struct Playback {
+    samples: Vec<f32>,
+    position: usize,
+    mode: PlaybackMode,
+}
+
+impl Playback {
+    fn load(path: &Path, mode: PlaybackMode) -> Result<Self> {
+        Ok(Self {
+            samples: load_samples(path)?,
+            position: 0,
+            mode,
+        })
+    }
+
+    fn apply(&mut self, frame: &mut [f32]) {
+        for sample in frame {
+            let file = self.samples.get(self.position).copied().unwrap_or(0.0);
+            self.position += 1;
+            *sample = match self.mode {
+                PlaybackMode::Mix => (*sample + file).clamp(-1.0, 1.0),
+                PlaybackMode::Replace => file,
+            };
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.position >= self.samples.len()
+    }
+}
+
+pub(crate) fn load_samples(path: &Path) -> Result<Vec<f32>> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    let (samples, sample_rate) = match extension.as_deref() {
+        Some("wav") => decode::read_wav(path)?,
+        Some("ogg") => decode::read_ogg(path)?,
+        _ => {
+            return Err(anyhow::Error::msg(
+                "Unsupported file format. Only WAV and OGG are supported",
+            ))
+        }
+    };
+    resample(samples, sample_rate)
+}
+
+fn resample(data: Vec<f32>, sample_rate_in: usize) -> Result<Vec<f32>> {
+    if sample_rate_in == SAMPLE_RATE || data.is_empty() {
+        return Ok(data);
+    }
+
+    let chunk_size = 1024;
+    let mut resampler = StreamResampler::new(sample_rate_in, SAMPLE_RATE, chunk_size)?;
+    let mut output = Vec::with_capacity(data.len() * SAMPLE_RATE / sample_rate_in + chunk_size);
+    resampler.process(&data, &mut output)?;
+    resampler.flush(&mut output)?;
+    Ok(output)
+}
+
+mod decode {
+    use std::{fs::File, path::Path};
+
+    use anyhow::Result;
+    use dasp_sample::Sample;
+
+    pub fn read_wav(path: &Path) -> Result<(Vec<f32>, usize)> {
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        let samples: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|s| s.map(|s| s as f32 / scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        Ok((
+            downmix(&samples, spec.channels as usize),
+            spec.sample_rate as usize,
+        ))
+    }
+
+    pub fn read_ogg(path: &Path) -> Result<(Vec<f32>, usize)> {
+        let mut reader = lewton::inside_ogg::OggStreamReader::new(File::open(path)?)?;
+        let channels = reader.ident_hdr.audio_channels as usize;
+        let sample_rate = reader.ident_hdr.audio_sample_rate as usize;
+
+        let mut samples = Vec::new();
+        while let Some(packet) = reader.read_dec_packet_itl()? {
+            samples.extend(packet.into_iter().map(|s| s.to_sample::<f32>()));
+        }
+        Ok((downmix(&samples, channels), sample_rate))
+    }
+
+    fn downmix(samples: &[f32], channels: usize) -> Vec<f32> {
+        if channels <= 1 {
+            return samples.to_vec();
+        }
+        samples
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect()
+    }
+}
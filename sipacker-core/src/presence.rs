@@ -0,0 +1,155 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::Result;
+
+/// The state published to the presence server with PUBLISH.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresenceStatus {
+    Available,
+    Busy,
+    Away,
+}
+
+impl PresenceStatus {
+    fn activity(self) -> Option<&'static str> {
+        match self {
+            Self::Available => None,
+            Self::Busy => Some("busy"),
+            Self::Away => Some("away"),
+        }
+    }
+}
+
+impl FromStr for PresenceStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "available" => Ok(Self::Available),
+            "busy" => Ok(Self::Busy),
+            "away" => Ok(Self::Away),
+            _ => Err(anyhow::Error::msg(format!("Unknown presence status: {s}"))),
+        }
+    }
+}
+
+impl Display for PresenceStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            Self::Available => "available",
+            Self::Busy => "busy",
+            Self::Away => "away",
+        };
+        write!(f, "{status}")
+    }
+}
+
+// the PIDF document (RFC 3863) with the RPID activity (RFC 4480) of the entity, the note
+// defaults to the status so the softphones without RPID show it too
+pub(crate) fn pidf(entity: &str, status: PresenceStatus, note: Option<&str>) -> String {
+    let note = escape(note.unwrap_or(&status.to_string()));
+    let person = match status.activity() {
+        Some(activity) => format!(
+            "  <dm:person id=\"p1\">\r\n    <rpid:activities><rpid:{activity}/></rpid:activities>\r\n  </dm:person>\r\n"
+        ),
+        None => String::new(),
+    };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n\
+         <presence xmlns=\"urn:ietf:params:xml:ns:pidf\" \
+         xmlns:dm=\"urn:ietf:params:xml:ns:pidf:data-model\" \
+         xmlns:rpid=\"urn:ietf:params:xml:ns:pidf:rpid\" entity=\"{entity}\">\r\n\
+         \x20 <tuple id=\"t1\">\r\n\
+         \x20   <status><basic>open</basic></status>\r\n\
+         \x20   <note>{note}</note>\r\n\
+         \x20 </tuple>\r\n\
+         {person}\
+         </presence>\r\n"
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTITY: &str = "sip:1001@10.0.0.1";
+
+    #[test]
+    fn pidf_is_open_with_every_status() {
+        // busy and away are still reachable, the withdrawal is the PUBLISH of Expires 0
+        for status in [
+            PresenceStatus::Available,
+            PresenceStatus::Busy,
+            PresenceStatus::Away,
+        ] {
+            let pidf = pidf(ENTITY, status, None);
+            assert!(pidf.contains("<status><basic>open</basic></status>"));
+            assert!(!pidf.contains("closed"));
+        }
+    }
+
+    #[test]
+    fn pidf_has_the_activity_of_the_status() {
+        let busy = pidf(ENTITY, PresenceStatus::Busy, None);
+        assert!(busy.contains("<rpid:activities><rpid:busy/></rpid:activities>"));
+        let away = pidf(ENTITY, PresenceStatus::Away, None);
+        assert!(away.contains("<rpid:activities><rpid:away/></rpid:activities>"));
+        let available = pidf(ENTITY, PresenceStatus::Available, None);
+        assert!(!available.contains("<dm:person"));
+    }
+
+    #[test]
+    fn pidf_note_defaults_to_the_status() {
+        let pidf = pidf(ENTITY, PresenceStatus::Away, None);
+        assert!(pidf.contains("<note>away</note>"));
+    }
+
+    #[test]
+    fn pidf_escapes_the_note() {
+        let pidf = pidf(
+            ENTITY,
+            PresenceStatus::Busy,
+            Some(r#"<In a "call"> with Q&A"#),
+        );
+        assert!(pidf.contains("<note>&lt;In a &quot;call&quot;&gt; with Q&amp;A</note>"));
+    }
+
+    #[test]
+    fn pidf_is_the_presence_of_the_entity() {
+        let pidf = pidf(ENTITY, PresenceStatus::Available, Some("In the office"));
+        assert_eq!(
+            pidf,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n\
+             <presence xmlns=\"urn:ietf:params:xml:ns:pidf\" \
+             xmlns:dm=\"urn:ietf:params:xml:ns:pidf:data-model\" \
+             xmlns:rpid=\"urn:ietf:params:xml:ns:pidf:rpid\" entity=\"sip:1001@10.0.0.1\">\r\n\
+             \x20 <tuple id=\"t1\">\r\n\
+             \x20   <status><basic>open</basic></status>\r\n\
+             \x20   <note>In the office</note>\r\n\
+             \x20 </tuple>\r\n\
+             </presence>\r\n"
+        );
+    }
+
+    #[test]
+    fn status_is_parsed_from_its_name() {
+        for status in [
+            PresenceStatus::Available,
+            PresenceStatus::Busy,
+            PresenceStatus::Away,
+        ] {
+            assert_eq!(
+                status.to_string().parse::<PresenceStatus>().unwrap(),
+                status
+            );
+        }
+        assert!("offline".parse::<PresenceStatus>().is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::stats::CallStats;
+
+use std::fmt::Display;
+
+// G.711 equipment impairment and packet-loss robustness factors, ITU-T G.113 Appendix I
+const G711_IE: f64 = 0.0;
+const G711_BPL: f64 = 4.3;
+// packetization (20 ms) plus the assumed playout buffering, the network delay is not measured
+const BASE_DELAY_MS: f64 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityEstimate {
+    pub r_factor: f64,
+    pub mos: f64,
+    pub delay_ms: f64,
+}
+
+impl Display for QualityEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MOS {:.1} (R {:.0})", self.mos, self.r_factor)
+    }
+}
+
+// simplified E-model, ITU-T G.107
+pub fn estimate(stats: &CallStats) -> QualityEstimate {
+    // the jitter buffer has to absorb about twice the interarrival jitter
+    let delay_ms = BASE_DELAY_MS + 2.0 * stats.jitter_ms;
+    let delay_impairment = if delay_ms > 177.3 {
+        0.024 * delay_ms + 0.11 * (delay_ms - 177.3)
+    } else {
+        0.024 * delay_ms
+    };
+
+    let loss = stats.loss_percent;
+    let burst_ratio = burst_ratio(stats);
+    let equipment_impairment = G711_IE + (95.0 - G711_IE) * loss / (loss / burst_ratio + G711_BPL);
+
+    let r_factor = (93.2 - delay_impairment - equipment_impairment).clamp(0.0, 100.0);
+    QualityEstimate {
+        r_factor,
+        mos: mos_from_r(r_factor),
+        delay_ms,
+    }
+}
+
+fn mos_from_r(r: f64) -> f64 {
+    if r <= 0.0 {
+        1.0
+    } else if r >= 100.0 {
+        4.5
+    } else {
+        1.0 + 0.035 * r + r * (r - 60.0) * (100.0 - r) * 7e-6
+    }
+}
+
+// BurstR of G.107: 1 for random loss, greater than 1 for bursty loss
+fn burst_ratio(stats: &CallStats) -> f64 {
+    let loss = stats.loss_percent / 100.0;
+    let burst_density = stats.burst.burst_density as f64 / 256.0;
+    if loss <= 0.0 || loss >= 1.0 || burst_density <= loss {
+        1.0
+    } else {
+        burst_density / loss
+    }
+}
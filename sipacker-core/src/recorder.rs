@@ -0,0 +1,166 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    fs::File,
+    io::BufWriter,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use dasp_sample::Sample;
+
+const SAMPLE_RATE: u32 = 8000;
+// if one direction is silent (no packets), the other one is written with silence after this lag
+const MAX_LAG_SAMPLES: usize = SAMPLE_RATE as usize / 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingMode {
+    Mono,
+    Stereo,
+    Remote,
+}
+
+impl FromStr for RecordingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mono" => Ok(Self::Mono),
+            "stereo" => Ok(Self::Stereo),
+            "remote" => Ok(Self::Remote),
+            _ => Err(anyhow::Error::msg(format!("Unknown recording mode: {s}"))),
+        }
+    }
+}
+
+impl Display for RecordingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mono => write!(f, "mono"),
+            Self::Stereo => write!(f, "stereo"),
+            Self::Remote => write!(f, "remote"),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct RecordingTap {
+    recorder: Arc<Mutex<Option<Recorder>>>,
+}
+
+impl RecordingTap {
+    pub fn start(&self, path: &Path, mode: RecordingMode) -> Result<()> {
+        let mut recorder = self.lock();
+        if recorder.is_some() {
+            return Err(anyhow::Error::msg("The call is being recorded already"));
+        }
+        *recorder = Some(Recorder::create(path, mode)?);
+        Ok(())
+    }
+
+    pub fn stop(&self) -> Result<()> {
+        let recorder = self
+            .lock()
+            .take()
+            .ok_or(anyhow::Error::msg("The call is not being recorded"))?;
+        recorder.finalize()
+    }
+
+    pub fn push_local(&self, samples: &[f32]) {
+        if let Some(recorder) = self
+            .lock()
+            .as_mut()
+            .filter(|recorder| recorder.mode != RecordingMode::Remote)
+        {
+            recorder
+                .local
+                .extend(samples.iter().map(|s| s.to_sample::<i16>()));
+            recorder.write_available();
+        }
+    }
+
+    pub fn push_remote(&self, samples: &[f32]) {
+        if let Some(recorder) = self.lock().as_mut() {
+            recorder
+                .remote
+                .extend(samples.iter().map(|s| s.to_sample::<i16>()));
+            recorder.write_available();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Recorder>> {
+        self.recorder.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+struct Recorder {
+    writer: hound::WavWriter<BufWriter<File>>,
+    mode: RecordingMode,
+    local: VecDeque<i16>,
+    remote: VecDeque<i16>,
+}
+
+impl Recorder {
+    fn create(path: &Path, mode: RecordingMode) -> Result<Self> {
+        let channels = match mode {
+            RecordingMode::Mono | RecordingMode::Remote => 1,
+            RecordingMode::Stereo => 2,
+        };
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer = hound::WavWriter::create(path, spec)?;
+        tracing::info!("Recording to {path:?} ({mode}) is started");
+        Ok(Self {
+            writer,
+            mode,
+            local: VecDeque::new(),
+            remote: VecDeque::new(),
+        })
+    }
+
+    fn write_available(&mut self) {
+        if let Err(err) = self.write_frames(false) {
+            tracing::warn!("Recording err: {err}");
+        }
+    }
+
+    fn write_frames(&mut self, flush: bool) -> Result<()> {
+        loop {
+            let lagging = self.mode == RecordingMode::Remote
+                || self.local.len().max(self.remote.len()) > MAX_LAG_SAMPLES;
+            let (local, remote) = match (self.local.front(), self.remote.front()) {
+                (Some(local), Some(remote)) => (*local, *remote),
+                (Some(local), None) if lagging || flush => (*local, 0),
+                (None, Some(remote)) if lagging || flush => (0, *remote),
+                _ => return Ok(()),
+            };
+            self.local.pop_front();
+            self.remote.pop_front();
+
+            match self.mode {
+                RecordingMode::Mono | RecordingMode::Remote => {
+                    let mixed =
+                        (local as i32 + remote as i32).clamp(i16::MIN as i32, i16::MAX as i32);
+                    self.writer.write_sample(mixed as i16)?;
+                }
+                RecordingMode::Stereo => {
+                    self.writer.write_sample(local)?;
+                    self.writer.write_sample(remote)?;
+                }
+            }
+        }
+    }
+
+    fn finalize(mut self) -> Result<()> {
+        self.write_frames(true)?;
+        self.writer.finalize()?;
+        tracing::info!("Recording is stopped");
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use rubato::Resampler;
+
+// keeps the resampler state between the audio callbacks and processes the data by fixed chunks,
+// the remainder is buffered until the next call
+pub struct StreamResampler {
+    resampler: Option<rubato::FftFixedIn<f32>>,
+    input: Vec<f32>,
+    output: Vec<Vec<f32>>,
+    chunk_size: usize,
+}
+
+impl StreamResampler {
+    pub fn new(sample_rate_in: usize, sample_rate_out: usize, chunk_size: usize) -> Result<Self> {
+        let sub_chunks = 4;
+        let channels_count = 1;
+        let resampler = if sample_rate_in == sample_rate_out {
+            None
+        } else {
+            Some(rubato::FftFixedIn::<f32>::new(
+                sample_rate_in,
+                sample_rate_out,
+                chunk_size,
+                sub_chunks,
+                channels_count,
+            )?)
+        };
+        let output = resampler
+            .as_ref()
+            .map(|resampler| resampler.output_buffer_allocate(true))
+            .unwrap_or_default();
+
+        Ok(Self {
+            resampler,
+            input: Vec::with_capacity(chunk_size * 2),
+            output,
+            chunk_size,
+        })
+    }
+
+    pub fn process(&mut self, data: &[f32], output: &mut Vec<f32>) -> Result<()> {
+        let Some(resampler) = self.resampler.as_mut() else {
+            output.extend_from_slice(data);
+            return Ok(());
+        };
+
+        self.input.extend_from_slice(data);
+        let mut processed = 0;
+        while self.input.len() - processed >= self.chunk_size {
+            let chunk = &self.input[processed..processed + self.chunk_size];
+            let (_, written) = resampler.process_into_buffer(&[chunk], &mut self.output, None)?;
+            output.extend_from_slice(&self.output[0][..written]);
+            processed += self.chunk_size;
+        }
+        self.input.drain(..processed);
+        Ok(())
+    }
+
+    pub fn flush(&mut self, output: &mut Vec<f32>) -> Result<()> {
+        if self.resampler.is_none() || self.input.is_empty() {
+            return Ok(());
+        }
+        let padding = vec![0.0; self.chunk_size - self.input.len()];
+        self.process(&padding, output)
+    }
+}
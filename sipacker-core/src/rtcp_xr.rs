@@ -0,0 +1,376 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+const RTCP_VERSION: u8 = 2;
+const XR_PACKET_TYPE: u8 = 207;
+const VOIP_METRICS_BLOCK_TYPE: u8 = 7;
+// the block length in 32-bit words minus one, RFC 3611 4.7
+const VOIP_METRICS_BLOCK_LENGTH: u16 = 8;
+// the metric is unavailable, RFC 3611 4.7
+pub const UNAVAILABLE: u8 = 127;
+pub const DEFAULT_GMIN: u8 = 16;
+
+// VoIP Metrics Report Block, RFC 3611 4.7
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoipMetrics {
+    pub loss_rate: u8,
+    pub discard_rate: u8,
+    pub burst_density: u8,
+    pub gap_density: u8,
+    pub burst_duration_ms: u16,
+    pub gap_duration_ms: u16,
+    pub round_trip_delay_ms: u16,
+    pub end_system_delay_ms: u16,
+    pub signal_level: u8,
+    pub noise_level: u8,
+    pub rerl: u8,
+    pub gmin: u8,
+    pub r_factor: u8,
+    pub ext_r_factor: u8,
+    pub mos_lq: u8,
+    pub mos_cq: u8,
+    pub rx_config: u8,
+    pub jb_nominal_ms: u16,
+    pub jb_maximum_ms: u16,
+    pub jb_abs_max_ms: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoipMetricsReport {
+    pub sender_ssrc: u32,
+    pub source_ssrc: u32,
+    pub metrics: VoipMetrics,
+}
+
+impl Default for VoipMetrics {
+    fn default() -> Self {
+        Self {
+            loss_rate: 0,
+            discard_rate: 0,
+            burst_density: 0,
+            gap_density: 0,
+            burst_duration_ms: 0,
+            gap_duration_ms: 0,
+            round_trip_delay_ms: 0,
+            end_system_delay_ms: 0,
+            signal_level: UNAVAILABLE,
+            noise_level: UNAVAILABLE,
+            rerl: UNAVAILABLE,
+            gmin: DEFAULT_GMIN,
+            r_factor: UNAVAILABLE,
+            ext_r_factor: UNAVAILABLE,
+            mos_lq: UNAVAILABLE,
+            mos_cq: UNAVAILABLE,
+            rx_config: 0,
+            jb_nominal_ms: 0,
+            jb_maximum_ms: 0,
+            jb_abs_max_ms: 0,
+        }
+    }
+}
+
+impl Display for VoipMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let available = |value: u8| (value != UNAVAILABLE).then_some(value);
+        match (available(self.mos_lq), available(self.r_factor)) {
+            (Some(mos_lq), Some(r_factor)) => {
+                write!(f, "MOS {:.1} (R {r_factor})", mos_lq as f64 / 10.0)?
+            }
+            _ => write!(f, "MOS unavailable")?,
+        }
+        write!(
+            f,
+            ", loss {:.1}%, delay {} ms",
+            self.loss_rate as f64 * 100.0 / 256.0,
+            self.end_system_delay_ms
+        )
+    }
+}
+
+impl VoipMetricsReport {
+    pub fn encode(&self) -> Bytes {
+        let words = 2 + VOIP_METRICS_BLOCK_LENGTH + 1;
+        let mut buf = BytesMut::with_capacity(words as usize * 4);
+        buf.put_u8(RTCP_VERSION << 6);
+        buf.put_u8(XR_PACKET_TYPE);
+        buf.put_u16(words - 1);
+        buf.put_u32(self.sender_ssrc);
+
+        let m = &self.metrics;
+        buf.put_u8(VOIP_METRICS_BLOCK_TYPE);
+        buf.put_u8(0);
+        buf.put_u16(VOIP_METRICS_BLOCK_LENGTH);
+        buf.put_u32(self.source_ssrc);
+        buf.put_slice(&[m.loss_rate, m.discard_rate, m.burst_density, m.gap_density]);
+        buf.put_u16(m.burst_duration_ms);
+        buf.put_u16(m.gap_duration_ms);
+        buf.put_u16(m.round_trip_delay_ms);
+        buf.put_u16(m.end_system_delay_ms);
+        buf.put_slice(&[m.signal_level, m.noise_level, m.rerl, m.gmin]);
+        buf.put_slice(&[m.r_factor, m.ext_r_factor, m.mos_lq, m.mos_cq]);
+        buf.put_u8(m.rx_config);
+        buf.put_u8(0);
+        buf.put_u16(m.jb_nominal_ms);
+        buf.put_u16(m.jb_maximum_ms);
+        buf.put_u16(m.jb_abs_max_ms);
+        buf.freeze()
+    }
+
+    pub fn parse(mut data: &[u8]) -> Result<Vec<Self>> {
+        let mut reports = Vec::new();
+        while data.remaining() >= 4 {
+            let first = data[0];
+            let packet_type = data[1];
+            let length = (u16::from_be_bytes([data[2], data[3]]) as usize + 1) * 4;
+            if first >> 6 != RTCP_VERSION {
+                return Err(anyhow::Error::msg("Invalid RTCP version"));
+            }
+            if data.remaining() < length {
+                return Err(anyhow::Error::msg("RTCP packet is truncated"));
+            }
+
+            let (mut packet, rest) = data.split_at(length);
+            data = rest;
+            if packet_type != XR_PACKET_TYPE || packet.len() < 8 {
+                continue;
+            }
+
+            packet.advance(4);
+            let sender_ssrc = packet.get_u32();
+            while packet.remaining() >= 4 {
+                let block_type = packet.get_u8();
+                packet.advance(1);
+                let block_length = packet.get_u16() as usize * 4;
+                if packet.remaining() < block_length {
+                    return Err(anyhow::Error::msg("RTCP XR block is truncated"));
+                }
+                let (mut block, rest) = packet.split_at(block_length);
+                packet = rest;
+
+                if block_type == VOIP_METRICS_BLOCK_TYPE
+                    && block_length == VOIP_METRICS_BLOCK_LENGTH as usize * 4
+                {
+                    reports.push(Self::parse_voip_metrics(sender_ssrc, &mut block));
+                }
+            }
+        }
+        Ok(reports)
+    }
+
+    fn parse_voip_metrics(sender_ssrc: u32, block: &mut &[u8]) -> Self {
+        let source_ssrc = block.get_u32();
+        let mut metrics = VoipMetrics {
+            loss_rate: block.get_u8(),
+            discard_rate: block.get_u8(),
+            burst_density: block.get_u8(),
+            gap_density: block.get_u8(),
+            burst_duration_ms: block.get_u16(),
+            gap_duration_ms: block.get_u16(),
+            round_trip_delay_ms: block.get_u16(),
+            end_system_delay_ms: block.get_u16(),
+            signal_level: block.get_u8(),
+            noise_level: block.get_u8(),
+            rerl: block.get_u8(),
+            gmin: block.get_u8(),
+            r_factor: block.get_u8(),
+            ext_r_factor: block.get_u8(),
+            mos_lq: block.get_u8(),
+            mos_cq: block.get_u8(),
+            rx_config: block.get_u8(),
+            ..Default::default()
+        };
+        block.advance(1);
+        metrics.jb_nominal_ms = block.get_u16();
+        metrics.jb_maximum_ms = block.get_u16();
+        metrics.jb_abs_max_ms = block.get_u16();
+        Self {
+            sender_ssrc,
+            source_ssrc,
+            metrics,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BurstMetrics {
+    pub burst_density: u8,
+    pub gap_density: u8,
+    pub burst_duration_ms: u16,
+    pub gap_duration_ms: u16,
+}
+
+// burst/gap loss tracking, RFC 3611 Appendix A.2
+#[derive(Default)]
+pub(crate) struct BurstTracker {
+    pkt: u64,
+    lost: u64,
+    c11: u64,
+    c13: u64,
+    c14: u64,
+    c22: u64,
+    c23: u64,
+    c33: u64,
+}
+
+impl BurstTracker {
+    pub fn on_packet(&mut self, packet_lost: bool) {
+        if !packet_lost {
+            self.pkt += 1;
+            return;
+        }
+
+        if self.pkt >= DEFAULT_GMIN as u64 {
+            if self.lost == 1 {
+                self.c14 += 1;
+            } else {
+                self.c13 += 1;
+            }
+            self.lost = 1;
+            self.c11 += self.pkt;
+        } else {
+            self.lost += 1;
+            if self.pkt == 0 {
+                self.c33 += 1;
+            } else {
+                self.c23 += 1;
+                self.c22 += self.pkt - 1;
+            }
+        }
+        self.pkt = 0;
+    }
+
+    pub fn metrics(&self, packet_duration_ms: f64) -> BurstMetrics {
+        // the trailing received packets belong to the gap
+        let c11 = (self.c11 + self.pkt) as f64;
+        let (c13, c14, c22, c23, c33) = (
+            self.c13 as f64,
+            self.c14 as f64,
+            self.c22 as f64,
+            self.c23 as f64,
+            self.c33 as f64,
+        );
+        let (c31, c32) = (c13, c23);
+        let ctotal = c11 + c14 + c13 + c22 + c23 + c31 + c32 + c33;
+
+        let p32 = if c31 + c32 + c33 > 0.0 {
+            c32 / (c31 + c32 + c33)
+        } else {
+            0.0
+        };
+        let p23 = if c22 + c23 < 1.0 {
+            1.0
+        } else {
+            1.0 - c22 / (c22 + c23)
+        };
+
+        let burst_density = if p23 + p32 > 0.0 && c13 > 0.0 {
+            256.0 * p23 / (p23 + p32)
+        } else {
+            0.0
+        };
+        let gap_density = if c11 + c14 > 0.0 {
+            256.0 * c14 / (c11 + c14)
+        } else {
+            0.0
+        };
+        let (gap_duration, burst_duration) = if c13 > 0.0 {
+            let gap_duration = (c11 + c14 + c13) * packet_duration_ms / c13;
+            (
+                gap_duration,
+                ctotal * packet_duration_ms / c13 - gap_duration,
+            )
+        } else {
+            (ctotal * packet_duration_ms, 0.0)
+        };
+
+        BurstMetrics {
+            burst_density: burst_density.min(255.0) as u8,
+            gap_density: gap_density.min(255.0) as u8,
+            burst_duration_ms: burst_duration.min(u16::MAX as f64) as u16,
+            gap_duration_ms: gap_duration.min(u16::MAX as f64) as u16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> VoipMetricsReport {
+        VoipMetricsReport {
+            sender_ssrc: 0x1122_3344,
+            source_ssrc: 0x5566_7788,
+            metrics: VoipMetrics {
+                loss_rate: 12,
+                discard_rate: 3,
+                burst_density: 200,
+                gap_density: 4,
+                burst_duration_ms: 60,
+                gap_duration_ms: 5000,
+                round_trip_delay_ms: 120,
+                end_system_delay_ms: 40,
+                r_factor: 82,
+                mos_lq: 41,
+                jb_nominal_ms: 60,
+                jb_maximum_ms: 180,
+                jb_abs_max_ms: 180,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn receiver_report(ssrc: u32) -> Vec<u8> {
+        let mut packet = vec![RTCP_VERSION << 6, 201, 0, 1];
+        packet.extend_from_slice(&ssrc.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn encoded_report_is_parsed_back() {
+        let encoded = report().encode();
+        assert_eq!(encoded.len(), 44);
+        assert_eq!(VoipMetricsReport::parse(&encoded).unwrap(), [report()]);
+    }
+
+    #[test]
+    fn report_is_parsed_from_the_compound_packet() {
+        let mut compound = receiver_report(report().sender_ssrc);
+        compound.extend_from_slice(&report().encode());
+        assert_eq!(VoipMetricsReport::parse(&compound).unwrap(), [report()]);
+    }
+
+    #[test]
+    fn compound_without_the_xr_has_no_reports() {
+        let compound = receiver_report(1);
+        assert!(VoipMetricsReport::parse(&compound).unwrap().is_empty());
+    }
+
+    #[test]
+    fn other_xr_blocks_are_skipped() {
+        let encoded = report().encode();
+        let (header, voip_metrics) = encoded.split_at(8);
+        // the Receiver Reference Time block, RFC 3611 4.4
+        let reference_time = [4, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2];
+        let mut packet = header.to_vec();
+        packet.extend_from_slice(&reference_time);
+        packet.extend_from_slice(voip_metrics);
+        let words = (packet.len() / 4 - 1) as u16;
+        packet[2..4].copy_from_slice(&words.to_be_bytes());
+        assert_eq!(VoipMetricsReport::parse(&packet).unwrap(), [report()]);
+    }
+
+    #[test]
+    fn truncated_packet_is_rejected() {
+        let encoded = report().encode();
+        assert!(VoipMetricsReport::parse(&encoded[..encoded.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn invalid_version_is_rejected() {
+        let mut encoded = report().encode().to_vec();
+        encoded[0] = 1 << 6;
+        assert!(VoipMetricsReport::parse(&encoded).is_err());
+    }
+}
@@ -0,0 +1,385 @@
+use crate::{
+    jitter_buffer::JitterBufferStats,
+    quality::{self, QualityEstimate},
+    rtcp_xr::{BurstMetrics, BurstTracker, VoipMetrics, VoipMetricsReport},
+};
+
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ezk_rtp::RtpPacket;
+
+const CLOCK_RATE: f64 = 8000.0;
+// G.711 with 20 ms ptime
+const PACKET_DURATION_MS: f64 = 20.0;
+// the loop is bounded for the case of the sequence number reset by the peer
+const MAX_TRACKED_GAP: u64 = 1000;
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallStats {
+    pub duration: Duration,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub packets_lost: u64,
+    pub loss_percent: f64,
+    pub jitter_ms: f64,
+    pub send_bitrate_kbps: f64,
+    pub receive_bitrate_kbps: f64,
+    pub burst: BurstMetrics,
+    pub remote_metrics: Option<VoipMetrics>,
+}
+
+impl CallStats {
+    pub fn quality(&self) -> QualityEstimate {
+        quality::estimate(self)
+    }
+
+    pub fn has_media(&self) -> bool {
+        self.packets_sent > 0 || self.packets_received > 0
+    }
+
+    pub fn voip_metrics(&self) -> VoipMetrics {
+        let quality = self.quality();
+        VoipMetrics {
+            loss_rate: (self.loss_percent * 256.0 / 100.0).min(255.0) as u8,
+            burst_density: self.burst.burst_density,
+            gap_density: self.burst.gap_density,
+            burst_duration_ms: self.burst.burst_duration_ms,
+            gap_duration_ms: self.burst.gap_duration_ms,
+            end_system_delay_ms: quality.delay_ms as u16,
+            r_factor: quality.r_factor as u8,
+            mos_lq: (quality.mos * 10.0) as u8,
+            ..Default::default()
+        }
+    }
+}
+
+impl Display for CallStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "duration: {}s", self.duration.as_secs())?;
+        writeln!(
+            f,
+            "sent: {} packets, {} bytes, {:.1} kbps",
+            self.packets_sent, self.bytes_sent, self.send_bitrate_kbps
+        )?;
+        writeln!(
+            f,
+            "received: {} packets, {} bytes, {:.1} kbps",
+            self.packets_received, self.bytes_received, self.receive_bitrate_kbps
+        )?;
+        writeln!(
+            f,
+            "lost: {} packets ({:.1}%), jitter: {:.1} ms",
+            self.packets_lost, self.loss_percent, self.jitter_ms
+        )?;
+        write!(
+            f,
+            "burst: density {:.1}%, duration {} ms; gap: density {:.1}%, duration {} ms",
+            self.burst.burst_density as f64 * 100.0 / 256.0,
+            self.burst.burst_duration_ms,
+            self.burst.gap_density as f64 * 100.0 / 256.0,
+            self.burst.gap_duration_ms,
+        )?;
+        write!(f, "\nquality: {}", self.quality())?;
+        if let Some(metrics) = &self.remote_metrics {
+            write!(f, "\npeer quality: {}", metrics)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AudioStats {
+    pub jitter_buffer: JitterBufferStats,
+    pub input_channel: ChannelOccupancy,
+    pub output_channel: ChannelOccupancy,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChannelOccupancy {
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl Display for ChannelOccupancy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{} samples", self.len, self.capacity)
+    }
+}
+
+impl Display for AudioStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let jitter_buffer = &self.jitter_buffer;
+        writeln!(
+            f,
+            "jitter buffer: depth {} packets ({} ms), target {} packets",
+            jitter_buffer.depth,
+            jitter_buffer.delay.as_millis(),
+            jitter_buffer.target_depth
+        )?;
+        writeln!(
+            f,
+            "jitter buffer: late {} packets, dropped {} packets",
+            jitter_buffer.late, jitter_buffer.dropped
+        )?;
+        writeln!(f, "input channel: {}", self.input_channel)?;
+        write!(f, "output channel: {}", self.output_channel)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct StatsTap {
+    stats: Arc<Mutex<RtpStats>>,
+}
+
+impl Default for StatsTap {
+    fn default() -> Self {
+        Self {
+            stats: Arc::new(Mutex::new(RtpStats::new())),
+        }
+    }
+}
+
+impl StatsTap {
+    pub fn on_sent(&self, packet: &RtpPacket) {
+        let mut stats = self.lock();
+        stats.packets_sent += 1;
+        stats.bytes_sent += packet.payload.len() as u64;
+    }
+
+    pub fn on_received(&self, packet: &RtpPacket) {
+        self.lock().on_received(packet, Instant::now());
+    }
+
+    pub fn snapshot(&self) -> CallStats {
+        self.lock().snapshot()
+    }
+
+    // our RTP packets are sent with the zero SSRC
+    pub fn voip_metrics_report(&self) -> VoipMetricsReport {
+        let mut stats = self.lock();
+        VoipMetricsReport {
+            sender_ssrc: 0,
+            source_ssrc: stats.remote_ssrc.unwrap_or_default(),
+            metrics: stats.snapshot().voip_metrics(),
+        }
+    }
+
+    pub fn on_rtcp(&self, compound: &[u8]) {
+        match VoipMetricsReport::parse(compound) {
+            Ok(reports) => {
+                if let Some(report) = reports.into_iter().last() {
+                    self.lock().remote_metrics = Some(report.metrics);
+                }
+            }
+            Err(err) => tracing::debug!("Invalid RTCP packet: {err}"),
+        }
+    }
+
+    pub fn update_jitter_buffer(&self, stats: JitterBufferStats) {
+        self.lock().audio.jitter_buffer = stats;
+    }
+
+    pub fn update_input_channel(&self, occupancy: ChannelOccupancy) {
+        self.lock().audio.input_channel = occupancy;
+    }
+
+    pub fn update_output_channel(&self, occupancy: ChannelOccupancy) {
+        self.lock().audio.output_channel = occupancy;
+    }
+
+    pub fn audio_snapshot(&self) -> AudioStats {
+        self.lock().audio
+    }
+
+    pub fn log_periodically(&self) {
+        let mut stats = self.lock();
+        if stats.last_log.elapsed() >= LOG_INTERVAL {
+            stats.last_log = Instant::now();
+            let snapshot = stats.snapshot();
+            tracing::debug!(
+                "RTP stats: sent {} pkts {:.1} kbps, received {} pkts {:.1} kbps, lost {:.1}%, jitter {:.1} ms, {}",
+                snapshot.packets_sent,
+                snapshot.send_bitrate_kbps,
+                snapshot.packets_received,
+                snapshot.receive_bitrate_kbps,
+                snapshot.loss_percent,
+                snapshot.jitter_ms,
+                snapshot.quality(),
+            );
+            tracing::debug!("RTCP XR VoIP metrics: {:?}", snapshot.voip_metrics());
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RtpStats> {
+        self.stats.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+struct RtpStats {
+    started: Instant,
+    last_log: Instant,
+    packets_sent: u64,
+    bytes_sent: u64,
+    packets_received: u64,
+    bytes_received: u64,
+    sequence: Option<SequenceTracker>,
+    transit: Option<f64>,
+    jitter: f64,
+    bitrate_window: BitrateWindow,
+    bursts: BurstTracker,
+    audio: AudioStats,
+    remote_ssrc: Option<u32>,
+    remote_metrics: Option<VoipMetrics>,
+}
+
+struct SequenceTracker {
+    base: u64,
+    highest: u64,
+}
+
+struct BitrateWindow {
+    started: Instant,
+    bytes_sent: u64,
+    bytes_received: u64,
+    send_kbps: f64,
+    receive_kbps: f64,
+}
+
+impl RtpStats {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            last_log: now,
+            packets_sent: 0,
+            bytes_sent: 0,
+            packets_received: 0,
+            bytes_received: 0,
+            sequence: None,
+            transit: None,
+            jitter: 0.0,
+            bitrate_window: BitrateWindow {
+                started: now,
+                bytes_sent: 0,
+                bytes_received: 0,
+                send_kbps: 0.0,
+                receive_kbps: 0.0,
+            },
+            bursts: BurstTracker::default(),
+            audio: AudioStats::default(),
+            remote_ssrc: None,
+            remote_metrics: None,
+        }
+    }
+
+    fn on_received(&mut self, packet: &RtpPacket, arrival: Instant) {
+        self.packets_received += 1;
+        self.bytes_received += packet.payload.len() as u64;
+        self.remote_ssrc = Some(packet.ssrc.0);
+
+        let sequence_number = packet.sequence_number.0;
+        let lost = match self.sequence.as_mut() {
+            Some(sequence) => sequence.update(sequence_number),
+            None => {
+                self.sequence = Some(SequenceTracker {
+                    base: sequence_number as u64,
+                    highest: sequence_number as u64,
+                });
+                0
+            }
+        };
+        for _ in 0..lost.min(MAX_TRACKED_GAP) {
+            self.bursts.on_packet(true);
+        }
+        self.bursts.on_packet(false);
+
+        // interarrival jitter, RFC 3550 A.8
+        let arrival = arrival.duration_since(self.started).as_secs_f64() * CLOCK_RATE;
+        let transit = arrival - packet.timestamp.0 as f64;
+        if let Some(prev_transit) = self.transit {
+            let d = (transit - prev_transit).abs();
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+        self.transit = Some(transit);
+    }
+
+    fn packets_lost(&self) -> u64 {
+        self.sequence
+            .as_ref()
+            .map(|sequence| sequence.expected().saturating_sub(self.packets_received))
+            .unwrap_or_default()
+    }
+
+    fn snapshot(&mut self) -> CallStats {
+        self.bitrate_window
+            .update(self.bytes_sent, self.bytes_received);
+        let packets_lost = self.packets_lost();
+        let expected = self
+            .sequence
+            .as_ref()
+            .map(|sequence| sequence.expected())
+            .unwrap_or_default();
+        let loss_percent = if expected > 0 {
+            packets_lost as f64 * 100.0 / expected as f64
+        } else {
+            0.0
+        };
+
+        CallStats {
+            duration: self.started.elapsed(),
+            packets_sent: self.packets_sent,
+            bytes_sent: self.bytes_sent,
+            packets_received: self.packets_received,
+            bytes_received: self.bytes_received,
+            packets_lost,
+            loss_percent,
+            jitter_ms: self.jitter * 1000.0 / CLOCK_RATE,
+            send_bitrate_kbps: self.bitrate_window.send_kbps,
+            receive_bitrate_kbps: self.bitrate_window.receive_kbps,
+            burst: self.bursts.metrics(PACKET_DURATION_MS),
+            remote_metrics: self.remote_metrics.clone(),
+        }
+    }
+}
+
+impl SequenceTracker {
+    fn update(&mut self, sequence_number: u16) -> u64 {
+        let cycles = self.highest & !0xFFFF;
+        let highest = (self.highest & 0xFFFF) as u16;
+        let extended = if sequence_number < highest && highest - sequence_number > 0x8000 {
+            cycles + 0x1_0000 + sequence_number as u64
+        } else if sequence_number > highest && sequence_number - highest > 0x8000 {
+            cycles.saturating_sub(0x1_0000) + sequence_number as u64
+        } else {
+            cycles + sequence_number as u64
+        };
+        let lost = extended.saturating_sub(self.highest + 1);
+        self.highest = self.highest.max(extended);
+        lost
+    }
+
+    fn expected(&self) -> u64 {
+        self.highest - self.base + 1
+    }
+}
+
+impl BitrateWindow {
+    fn update(&mut self, bytes_sent: u64, bytes_received: u64) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed < 1.0 {
+            return;
+        }
+        self.send_kbps = (bytes_sent - self.bytes_sent) as f64 * 8.0 / elapsed / 1000.0;
+        self.receive_kbps = (bytes_received - self.bytes_received) as f64 * 8.0 / elapsed / 1000.0;
+        self.started = Instant::now();
+        self.bytes_sent = bytes_sent;
+        self.bytes_received = bytes_received;
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+};
+
+const AMPLITUDE: f32 = 0.15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    Dial,
+    Ringback,
+    Busy,
+    Beep,
+    Dtmf(char),
+    Key(char),
+}
+
+struct ToneSpec {
+    frequencies: (f32, f32),
+    on_ms: u32,
+    off_ms: u32,
+    repeats: Option<u32>,
+}
+
+impl Tone {
+    fn spec(&self) -> Option<ToneSpec> {
+        let spec = match self {
+            Self::Dial => ToneSpec {
+                frequencies: (350.0, 440.0),
+                on_ms: 1000,
+                off_ms: 0,
+                repeats: Some(1),
+            },
+            Self::Ringback => ToneSpec {
+                frequencies: (440.0, 480.0),
+                on_ms: 2000,
+                off_ms: 4000,
+                repeats: None,
+            },
+            Self::Busy => ToneSpec {
+                frequencies: (480.0, 620.0),
+                on_ms: 500,
+                off_ms: 500,
+                repeats: Some(4),
+            },
+            Self::Beep => ToneSpec {
+                frequencies: (1000.0, 1000.0),
+                on_ms: 200,
+                off_ms: 0,
+                repeats: Some(1),
+            },
+            Self::Dtmf(digit) => ToneSpec {
+                frequencies: dtmf_frequencies(*digit)?,
+                on_ms: 100,
+                off_ms: 0,
+                repeats: Some(1),
+            },
+            Self::Key(digit) => ToneSpec {
+                frequencies: dtmf_frequencies(*digit)?,
+                on_ms: 60,
+                off_ms: 40,
+                repeats: Some(1),
+            },
+        };
+        Some(spec)
+    }
+}
+
+pub(crate) fn dtmf_frequencies(digit: char) -> Option<(f32, f32)> {
+    let (row, column) = match digit.to_ascii_uppercase() {
+        '1' => (0, 0),
+        '2' => (0, 1),
+        '3' => (0, 2),
+        'A' => (0, 3),
+        '4' => (1, 0),
+        '5' => (1, 1),
+        '6' => (1, 2),
+        'B' => (1, 3),
+        '7' => (2, 0),
+        '8' => (2, 1),
+        '9' => (2, 2),
+        'C' => (2, 3),
+        '*' => (3, 0),
+        '0' => (3, 1),
+        '#' => (3, 2),
+        'D' => (3, 3),
+        _ => return None,
+    };
+    let rows = [697.0, 770.0, 852.0, 941.0];
+    let columns = [1209.0, 1336.0, 1477.0, 1633.0];
+    Some((rows[row], columns[column]))
+}
+
+pub(crate) type ToneSlot = Arc<Mutex<Option<ToneGenerator>>>;
+
+pub(crate) struct ToneGenerator {
+    spec: ToneSpec,
+    next: VecDeque<ToneSpec>,
+    sample_rate: u32,
+    position: u64,
+}
+
+impl ToneGenerator {
+    pub fn new(tone: Tone, sample_rate: u32) -> Option<Self> {
+        Self::sequence([tone], sample_rate)
+    }
+
+    pub fn sequence(tones: impl IntoIterator<Item = Tone>, sample_rate: u32) -> Option<Self> {
+        let mut specs = tones
+            .into_iter()
+            .map(|tone| tone.spec())
+            .collect::<Option<VecDeque<_>>>()?;
+        Some(Self {
+            spec: specs.pop_front()?,
+            next: specs,
+            sample_rate,
+            position: 0,
+        })
+    }
+
+    pub fn next_sample(&mut self) -> Option<f32> {
+        if self.is_current_finished() {
+            self.spec = self.next.pop_front()?;
+            self.position = 0;
+        }
+
+        let position = self.position;
+        self.position += 1;
+        if position % self.period() >= self.samples(self.spec.on_ms) {
+            return Some(0.0);
+        }
+
+        let t = position as f32 / self.sample_rate as f32;
+        let (f1, f2) = self.spec.frequencies;
+        Some(AMPLITUDE * ((2.0 * PI * f1 * t).sin() + (2.0 * PI * f2 * t).sin()))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.is_current_finished() && self.next.is_empty()
+    }
+
+    fn is_current_finished(&self) -> bool {
+        self.spec
+            .repeats
+            .is_some_and(|repeats| self.position >= self.period() * repeats as u64)
+    }
+
+    fn period(&self) -> u64 {
+        self.samples(self.spec.on_ms + self.spec.off_ms)
+    }
+
+    fn samples(&self, ms: u32) -> u64 {
+        ms as u64 * self.sample_rate as u64 / 1000
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    fn count_samples(mut generator: ToneGenerator) -> usize {
+        std::iter::from_fn(|| generator.next_sample()).count()
+    }
+
+    #[test]
+    fn sequence_plays_the_tones_one_after_another() {
+        let keys = ToneGenerator::sequence([Tone::Key('1'), Tone::Key('#')], SAMPLE_RATE).unwrap();
+        // 60 ms on and 40 ms off per key
+        assert_eq!(count_samples(keys), 1600);
+    }
+
+    #[test]
+    fn sequence_ends_with_the_endless_tone() {
+        let mut generator =
+            ToneGenerator::sequence([Tone::Dial, Tone::Ringback], SAMPLE_RATE).unwrap();
+        for _ in 0..SAMPLE_RATE * 10 {
+            assert!(generator.next_sample().is_some());
+        }
+        assert!(!generator.is_finished());
+    }
+
+    #[test]
+    fn sequence_with_the_unsupported_tone_is_rejected() {
+        assert!(ToneGenerator::sequence([Tone::Beep, Tone::Key('X')], SAMPLE_RATE).is_none());
+        assert!(ToneGenerator::sequence([], SAMPLE_RATE).is_none());
+    }
+
+    #[test]
+    fn busy_tone_is_finished_after_the_repeats() {
+        let busy = ToneGenerator::new(Tone::Busy, SAMPLE_RATE).unwrap();
+        assert_eq!(count_samples(busy), 4 * 8000);
+    }
+}
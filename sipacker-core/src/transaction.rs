@@ -0,0 +1,481 @@
+use anyhow::Result;
+use bytes::Bytes;
+use ezk_sip_auth::{
+    ClientAuthenticator, DigestAuthenticator, DigestCredentials, RequestParts, ResponseParts,
+};
+use ezk_sip_core::{
+    transaction::TsxResponse, transport::TargetTransportInfo, Endpoint, EndpointBuilder,
+    IncomingRequest, Layer, MayTake,
+};
+use ezk_sip_types::{
+    header::typed::{CSeq, CallID, Contact, ContentType, FromTo, MaxForwards},
+    uri::{
+        sip::{InvalidSipUri, SipUri},
+        NameAddr,
+    },
+    Headers, Method, Name, StatusCode,
+};
+use ezk_sip_ua::{dialog::Usage, util::random_string};
+use tokio::sync::mpsc;
+
+// the requests to the endpoint wait for the user agent, the flood beyond that is dropped
+const REQUESTS_CAPACITY: usize = 16;
+const USER_AGENT: &str = concat!("sipacker/", env!("CARGO_PKG_VERSION"));
+// the methods which the endpoint hands over to the user agent, added to its Allow
+const LAYER_METHODS: [Method; 4] = [
+    Method::MESSAGE,
+    Method::OPTIONS,
+    Method::NOTIFY,
+    Method::INFO,
+];
+
+#[derive(Clone)]
+pub(crate) struct Identity {
+    pub user_name: String,
+    pub credentials: DigestCredentials,
+    pub domain: String,
+    pub display_name: Option<String>,
+}
+
+impl Identity {
+    pub fn uri(&self) -> String {
+        format!("sip:{}@{}", self.user_name, self.domain)
+    }
+
+    pub fn uri_of(&self, user_name: &str) -> String {
+        format!("sip:{user_name}@{}", self.domain)
+    }
+
+    fn name_addr(&self) -> Result<NameAddr> {
+        let uri = parse_uri(&self.uri())?;
+        Ok(match &self.display_name {
+            Some(name) => NameAddr::new(name.clone(), uri),
+            None => NameAddr::uri(uri),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Request {
+    pub method: Method,
+    pub uri: String,
+    pub to: String,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Option<(String, String)>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Reply {
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Dialog {
+    pub call_id: String,
+    from_tag: String,
+    to_tag: Option<String>,
+    cseq: u32,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Message {
+    pub start_line: String,
+    headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Message {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let text = String::from_utf8_lossy(bytes);
+        let (head, body) = text.split_once("\r\n\r\n").unwrap_or((&text, ""));
+        let mut lines = head.split("\r\n");
+        let start_line = lines.next()?.trim().to_owned();
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (full_name(name.trim()), value.trim().to_owned()))
+            .collect();
+        Some(Self {
+            start_line,
+            headers,
+            body: body.to_owned(),
+        })
+    }
+
+    pub fn of_request(request: &IncomingRequest) -> Self {
+        Self {
+            start_line: request.to_string(),
+            headers: flatten(&request.headers),
+            body: String::from_utf8_lossy(&request.body).into_owned(),
+        }
+    }
+
+    fn of_response(response: &TsxResponse) -> Self {
+        Self {
+            start_line: format!("SIP/2.0 {}", response.line),
+            headers: flatten(&response.headers),
+            body: String::from_utf8_lossy(&response.body).into_owned(),
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = full_name(name);
+        self.headers
+            .iter()
+            .find(|(header, _)| *header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn header_values(&self, name: &str) -> Vec<&str> {
+        let name = full_name(name);
+        self.headers
+            .iter()
+            .filter(|(header, _)| *header == name)
+            .flat_map(|(_, value)| split_list(value))
+            .collect()
+    }
+
+    pub fn method(&self) -> Option<&str> {
+        if self.start_line.starts_with("SIP/2.0 ") {
+            return None;
+        }
+        self.start_line.split(' ').next()
+    }
+
+    pub fn status_code(&self) -> Option<u16> {
+        self.start_line
+            .strip_prefix("SIP/2.0 ")?
+            .split(' ')
+            .next()?
+            .parse()
+            .ok()
+    }
+
+    pub fn status(&self) -> &str {
+        self.start_line
+            .strip_prefix("SIP/2.0 ")
+            .unwrap_or(&self.start_line)
+    }
+}
+
+// the compact forms of RFC 3261 7.3.3 are stored in the full form, the names are lowercase
+fn full_name(name: &str) -> String {
+    let name = name.to_ascii_lowercase();
+    let full = match name.as_str() {
+        "v" => "via",
+        "f" => "from",
+        "t" => "to",
+        "i" => "call-id",
+        "m" => "contact",
+        "c" => "content-type",
+        "l" => "content-length",
+        "e" => "content-encoding",
+        "o" => "event",
+        "s" => "subject",
+        "k" => "supported",
+        _ => return name,
+    };
+    full.to_owned()
+}
+
+fn flatten(headers: &Headers) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| (full_name(name.as_print_str()), value.to_string()))
+        .collect()
+}
+
+pub(crate) fn header<'a>(headers: &'a Headers, name: &Name) -> Option<&'a str> {
+    headers.get_raw(name).next().map(|value| &**value)
+}
+
+// the requests out of the dialogs of ezk-sip which the user agent answers itself, the endpoint
+// runs the layer after the ones of the SIP client
+pub(crate) struct RequestLayer {
+    requests: mpsc::Sender<IncomingRequest>,
+}
+
+impl RequestLayer {
+    pub fn new() -> (Self, mpsc::Receiver<IncomingRequest>) {
+        let (requests, request_receiver) = mpsc::channel(REQUESTS_CAPACITY);
+        (Self { requests }, request_receiver)
+    }
+}
+
+#[async_trait::async_trait]
+impl Layer for RequestLayer {
+    fn name(&self) -> &'static str {
+        "sipacker-requests"
+    }
+
+    fn init(&mut self, endpoint: &mut EndpointBuilder) {
+        for method in LAYER_METHODS {
+            endpoint.add_allow(method);
+        }
+    }
+
+    async fn receive(&self, _endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if !LAYER_METHODS.contains(&request.line.method) {
+            return;
+        }
+        let source = request.tp_info.source;
+        if self.requests.try_send(request.take()).is_err() {
+            tracing::debug!("The request from {source} is dropped");
+        }
+    }
+}
+
+// the INFO and the OPTIONS within the dialog of the call, which the usage of the INVITE session
+// leaves to the other usages of the dialog
+pub(crate) struct DialogUsage {
+    requests: mpsc::Sender<IncomingRequest>,
+}
+
+impl DialogUsage {
+    pub fn new() -> (Self, mpsc::Receiver<IncomingRequest>) {
+        let (requests, request_receiver) = mpsc::channel(REQUESTS_CAPACITY);
+        (Self { requests }, request_receiver)
+    }
+}
+
+#[async_trait::async_trait]
+impl Usage for DialogUsage {
+    fn name(&self) -> &'static str {
+        "sipacker-dialog"
+    }
+
+    async fn receive(&self, _endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if !matches!(request.line.method, Method::INFO | Method::OPTIONS) {
+            return;
+        }
+        let source = request.tp_info.source;
+        if self.requests.try_send(request.take()).is_err() {
+            tracing::debug!("The request from {source} is dropped");
+        }
+    }
+}
+
+// the standalone requests and their responses on the endpoint of the SIP client, so they go
+// from the registered socket and come back to the registered Contact
+#[derive(Clone)]
+pub(crate) struct Transactions {
+    endpoint: Endpoint,
+}
+
+impl Transactions {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self { endpoint }
+    }
+
+    pub fn allowed(&self) -> String {
+        let allowed: Vec<_> = self
+            .endpoint
+            .allowed()
+            .iter()
+            .map(|allow| allow.0.to_string())
+            .collect();
+        allowed.join(", ")
+    }
+
+    pub fn respond(&self, request: IncomingRequest, code: StatusCode) {
+        self.respond_with(request, code, Reply::default())
+    }
+
+    // the out-of-dialog request gets the tag of To, the server transaction absorbs the
+    // retransmits of the request in the background
+    pub fn respond_with(&self, mut request: IncomingRequest, code: StatusCode, reply: Reply) {
+        if request.line.method == Method::ACK {
+            return;
+        }
+        let mut response = self.endpoint.create_response(&request, code, None);
+        let headers = &mut response.msg.headers;
+        if request.base_headers.to.tag.is_none() {
+            let _ = headers.edit(Name::TO, |to: &mut FromTo| to.tag = Some(random_string()));
+        }
+        headers.insert(Name::USER_AGENT, USER_AGENT);
+        for (name, value) in reply.headers {
+            headers.insert(name, value);
+        }
+        if let Some((content_type, content)) = reply.body {
+            headers.insert_named(&ContentType(content_type.into()));
+            response.msg.body = Bytes::from(content);
+        }
+        let transaction = self.endpoint.create_server_tsx(&mut request);
+        tokio::spawn(async move {
+            if let Err(err) = transaction.respond(response).await {
+                tracing::debug!("Responding err: {err}");
+            }
+        });
+    }
+
+    pub async fn send(&self, identity: &Identity, request: &Request) -> Result<Message> {
+        self.send_in(identity, request, &mut self.dialog()).await
+    }
+
+    pub fn dialog(&self) -> Dialog {
+        Dialog {
+            call_id: random_string().to_string(),
+            from_tag: random_string().to_string(),
+            to_tag: None,
+            cseq: 0,
+        }
+    }
+
+    // the request of the dialog, it gets the tag of the remote from the first 2xx
+    pub async fn send_in(
+        &self,
+        identity: &Identity,
+        request: &Request,
+        dialog: &mut Dialog,
+    ) -> Result<Message> {
+        let mut authenticator = DigestAuthenticator::new(identity.credentials.clone());
+        let mut target = self.target(request).await?;
+        // the authenticator gives up on the challenge it has already answered
+        let response = loop {
+            let mut message = self.create_request(identity, request, dialog, &target)?;
+            authenticator.authorize_request(&mut message.headers);
+            let mut transaction = self
+                .endpoint
+                .send_request(message, &mut target)
+                .await
+                .map_err(|err| Self::error(request, err))?;
+            let response = transaction
+                .receive_final()
+                .await
+                .map_err(|err| Self::error(request, err))?;
+            if !matches!(response.line.code.into_u16(), 401 | 407) {
+                break response;
+            }
+            let sent = &transaction.request().msg;
+            authenticator
+                .handle_rejection(
+                    RequestParts {
+                        line: &sent.line,
+                        headers: &sent.headers,
+                        body: &sent.body,
+                    },
+                    ResponseParts {
+                        line: &response.line,
+                        headers: &response.headers,
+                        body: &response.body,
+                    },
+                )
+                .map_err(|err| {
+                    anyhow::Error::msg(format!("{} is not authorized: {err}", request.method))
+                })?;
+        };
+        if dialog.to_tag.is_none() && matches!(response.line.code.into_u16(), 200..=299) {
+            dialog.to_tag = response
+                .base_headers
+                .to
+                .tag
+                .as_ref()
+                .map(ToString::to_string);
+        }
+        Ok(Message::of_response(&response))
+    }
+
+    // the single request, the challenge is returned as the final response
+    pub async fn send_once(&self, identity: &Identity, request: &Request) -> Result<Message> {
+        let mut dialog = self.dialog();
+        let mut target = self.target(request).await?;
+        let message = self.create_request(identity, request, &mut dialog, &target)?;
+        let mut transaction = self
+            .endpoint
+            .send_request(message, &mut target)
+            .await
+            .map_err(|err| Self::error(request, err))?;
+        let response = transaction
+            .receive_final()
+            .await
+            .map_err(|err| Self::error(request, err))?;
+        Ok(Message::of_response(&response))
+    }
+
+    async fn target(&self, request: &Request) -> Result<TargetTransportInfo> {
+        let uri = parse_uri(&request.uri)?;
+        let (transport, destination) = self
+            .endpoint
+            .select_transport(&uri)
+            .await
+            .map_err(|err| Self::error(request, err))?;
+        Ok(TargetTransportInfo {
+            via_host_port: None,
+            transport: Some((transport, destination)),
+        })
+    }
+
+    fn create_request(
+        &self,
+        identity: &Identity,
+        request: &Request,
+        dialog: &mut Dialog,
+        target: &TargetTransportInfo,
+    ) -> Result<ezk_sip_core::Request> {
+        dialog.cseq += 1;
+        let mut message =
+            ezk_sip_core::Request::new(request.method.clone(), parse_uri(&request.uri)?);
+        let headers = &mut message.headers;
+        headers.insert_named(&MaxForwards(70));
+        headers.insert_type(
+            Name::FROM,
+            &FromTo::new(identity.name_addr()?, Some(dialog.from_tag.clone().into())),
+        );
+        headers.insert_type(
+            Name::TO,
+            &FromTo::new(
+                NameAddr::uri(parse_uri(&request.to)?),
+                dialog.to_tag.clone().map(Into::into),
+            ),
+        );
+        headers.insert_named(&CallID::new(dialog.call_id.clone()));
+        headers.insert_named(&CSeq {
+            cseq: dialog.cseq,
+            method: request.method.clone(),
+        });
+        if let Some((transport, _)) = &target.transport {
+            let contact =
+                SipUri::new(transport.bound().into()).user(identity.user_name.clone().into());
+            headers.insert_named(&Contact::new(NameAddr::uri(contact)));
+        }
+        headers.insert(Name::USER_AGENT, USER_AGENT);
+        for (name, value) in &request.headers {
+            headers.insert(*name, value);
+        }
+        if let Some((content_type, content)) = &request.body {
+            headers.insert_named(&ContentType(content_type.clone().into()));
+            message.body = Bytes::from(content.clone());
+        }
+        Ok(message)
+    }
+
+    fn error(request: &Request, err: impl std::fmt::Display) -> anyhow::Error {
+        anyhow::Error::msg(format!("{} err: {err}", request.method))
+    }
+}
+
+fn parse_uri(uri: &str) -> Result<SipUri> {
+    uri.parse()
+        .map_err(|err: InvalidSipUri| anyhow::Error::msg(err.to_string()))
+}
+
+// the commas in the quoted display names and in the angle brackets don't separate the values
+fn split_list(value: &str) -> Vec<&str> {
+    let mut values = Vec::new();
+    let (mut quoted, mut bracketed, mut start) = (false, false, 0);
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '<' if !quoted => bracketed = true,
+            '>' if !quoted => bracketed = false,
+            ',' if !quoted && !bracketed => {
+                values.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    values.push(value[start..].trim());
+    values.retain(|value| !value.is_empty());
+    values
+}
@@ -0,0 +1,1739 @@
+use crate::{
+    audio::AudioLevel,
+    blf::{self, LineState},
+    call::{self, CallStatus},
+    capture::{CaptureTap, RtpHeader, SipMessage, SipTap},
+    dtmf,
+    invite::IncomingInvite,
+    mixer::AudioBus,
+    mwi::{self, MessageSummary},
+    pipeline::LatencyBudget,
+    player::PlaybackMode,
+    presence::{self, PresenceStatus},
+    recorder::RecordingMode,
+    stats::{AudioStats, CallStats},
+    transaction::{self, Dialog, Identity, Message, Reply, Request, RequestLayer, Transactions},
+};
+
+use std::{
+    fmt::Display,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use bytesstr::BytesStr;
+use ezk_rtc::AsyncSdpSession;
+use ezk_rtc_proto::{BundlePolicy, Options, RtcpMuxPolicy, TransportType};
+use ezk_sdp_types::{MediaType, SessionDescription, TransportProtocol};
+use ezk_sip::{Client, MediaSession, RegistrarConfig, Registration};
+use ezk_sip_auth::{DigestAuthenticator, DigestCredentials, DigestUser};
+use ezk_sip_core::IncomingRequest;
+use ezk_sip_types::{
+    header::typed::FromTo,
+    host::HostPort,
+    uri::{
+        sip::{InvalidSipUri, SipUri},
+        NameAddr,
+    },
+    Headers, Method, Name, StatusCode,
+};
+use tokio::{
+    select,
+    sync::{broadcast, mpsc},
+    task::JoinSet,
+    time::{Interval, MissedTickBehavior},
+};
+
+const EVENTS_CAPACITY: usize = 64;
+// the SIP client hands the incoming calls over on request only
+const INCOMING_CALL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const PUBLISH_EXPIRES: Duration = Duration::from_secs(3600);
+const SUBSCRIBE_EXPIRES: Duration = Duration::from_secs(3600);
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+const REFRESHED_CAPACITY: usize = 16;
+
+/// The number of the call since the start of the user agent, starting from 1.
+pub type CallId = u64;
+
+/// What happened to the registration and the calls, see [`UserAgent::subscribe`].
+#[derive(Debug, Clone)]
+pub enum UserAgentEvent {
+    AudioLevel(AudioLevel),
+    CallEstablished {
+        call_id: CallId,
+    },
+    /// The final failure response, the timeout or the transport error, followed by
+    /// `CallTerminated`.
+    CallFailed {
+        call_id: CallId,
+        reason: String,
+    },
+    /// The provisional response of the outgoing call other than 100, 180 and 183, e.g. 181 Call
+    /// Is Being Forwarded or 182 Queued.
+    CallProgress {
+        call_id: CallId,
+        code: u16,
+        reason: String,
+    },
+    /// The summary of the media of the call, sent before `CallTerminated`.
+    CallQuality {
+        call_id: CallId,
+        stats: CallStats,
+    },
+    /// The 180 Ringing of the outgoing call.
+    CallRinging {
+        call_id: CallId,
+    },
+    /// The 183 Session Progress of the outgoing call, e.g. the early media of the network.
+    CallSessionProgress {
+        call_id: CallId,
+    },
+    Calling {
+        call_id: CallId,
+        target: String,
+    },
+    /// The status is the final response to the INVITE, e.g. 200 of the answered call, 486 of the
+    /// busy remote or 487 of the cancelled one, empty without the response.
+    CallTerminated {
+        call_id: CallId,
+        status: Option<u16>,
+    },
+    /// The 100 Trying of the outgoing call.
+    CallTrying {
+        call_id: CallId,
+    },
+    /// The in-band digit of the remote or the one of its SIP INFO.
+    DtmfReceived {
+        call_id: CallId,
+        digit: char,
+    },
+    DtmfSent {
+        call_id: CallId,
+        digits: String,
+    },
+    IncomingCall {
+        call_id: CallId,
+        caller: Caller,
+        invite: IncomingInvite,
+    },
+    /// The SIP INFO (RFC 6086) of the call other than DTMF, accepted with 200.
+    InfoReceived {
+        call_id: CallId,
+        content_type: String,
+        body: String,
+    },
+    /// The watched extension changed its state, see [`UserAgent::watch_lines`].
+    LineStateChanged {
+        extension: String,
+        state: LineState,
+    },
+    /// The voicemail counts changed, see [`UserAgent::watch_mailbox`].
+    MessageWaiting {
+        summary: MessageSummary,
+    },
+    /// The instant message (SIP MESSAGE) out of the calls, accepted with 200.
+    MessageReceived {
+        from: Caller,
+        body: String,
+    },
+    /// The final response to the OPTIONS of [`UserAgent::ping`], the host is `None` for the
+    /// registrar.
+    PingAnswered {
+        host: Option<String>,
+        response: PingResponse,
+    },
+    /// The OPTIONS of [`UserAgent::ping`] got no response or could not be sent.
+    PingFailed {
+        host: Option<String>,
+        reason: String,
+    },
+    PlaybackStarted {
+        call_id: CallId,
+        path: PathBuf,
+    },
+    PlaybackStopped {
+        call_id: CallId,
+    },
+    RecordingStarted {
+        call_id: CallId,
+        path: PathBuf,
+    },
+    RecordingStopped {
+        call_id: CallId,
+    },
+    Registered {
+        user_name: String,
+        registrar_host: String,
+    },
+    Unregistered,
+}
+
+impl UserAgentEvent {
+    /// The call of the event, `None` for the registration and the audio level.
+    pub fn call_id(&self) -> Option<CallId> {
+        match self {
+            Self::CallEstablished { call_id }
+            | Self::CallFailed { call_id, .. }
+            | Self::CallProgress { call_id, .. }
+            | Self::CallQuality { call_id, .. }
+            | Self::CallRinging { call_id }
+            | Self::CallSessionProgress { call_id }
+            | Self::Calling { call_id, .. }
+            | Self::CallTerminated { call_id, .. }
+            | Self::CallTrying { call_id }
+            | Self::DtmfReceived { call_id, .. }
+            | Self::DtmfSent { call_id, .. }
+            | Self::IncomingCall { call_id, .. }
+            | Self::InfoReceived { call_id, .. }
+            | Self::PlaybackStarted { call_id, .. }
+            | Self::PlaybackStopped { call_id }
+            | Self::RecordingStarted { call_id, .. }
+            | Self::RecordingStopped { call_id } => Some(*call_id),
+            Self::AudioLevel(_)
+            | Self::LineStateChanged { .. }
+            | Self::MessageReceived { .. }
+            | Self::MessageWaiting { .. }
+            | Self::PingAnswered { .. }
+            | Self::PingFailed { .. }
+            | Self::Registered { .. }
+            | Self::Unregistered => None,
+        }
+    }
+}
+
+/// What the user agent has waited for, see [`UserAgent::next_update`].
+pub struct UserAgentUpdate(Update);
+
+enum Update {
+    Call(call::Ready),
+    IncomingCallPoll,
+    EndpointRequest(IncomingRequest),
+    Refresh,
+    Refreshed(Refreshed),
+}
+
+/// The remote party of the incoming call or the message, from its From header.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub uri: String,
+    pub display_name: Option<String>,
+}
+
+impl Caller {
+    fn of(from: &FromTo) -> Self {
+        Self {
+            uri: format!("{:?}", from.uri.uri),
+            display_name: from
+                .uri
+                .name
+                .as_ref()
+                .map(ToString::to_string)
+                .filter(|name| !name.is_empty()),
+        }
+    }
+
+    // "Alice" <sip:1001@host>;tag=a1 or sip:1001@host;tag=a1
+    pub(crate) fn of_header(from: &str) -> Self {
+        let (name, uri) = match from.split_once('<') {
+            Some((name, rest)) => (name, rest.split('>').next().unwrap_or(rest)),
+            None => ("", from.split(';').next().unwrap_or(from)),
+        };
+        let name = name.trim().trim_matches('"');
+        Self {
+            uri: uri.trim().to_owned(),
+            display_name: (!name.is_empty()).then(|| name.to_owned()),
+        }
+    }
+}
+
+/// The media encryption offered in the calls of the registration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SrtpPolicy {
+    #[default]
+    Disabled,
+    // RTP/AVP is offered with the SDES keys, SRTP is used if the peer answers with its key
+    Optional,
+    // only SDES-SRTP is offered, the inbound RTP/AVP offers are rejected with 488
+    Required,
+}
+
+impl FromStr for SrtpPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "disabled" => Ok(Self::Disabled),
+            "optional" => Ok(Self::Optional),
+            "required" => Ok(Self::Required),
+            _ => Err(anyhow::Error::msg(format!("Unknown SRTP policy: {s}"))),
+        }
+    }
+}
+
+impl Display for SrtpPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Disabled => write!(f, "disabled"),
+            Self::Optional => write!(f, "optional"),
+            Self::Required => write!(f, "required"),
+        }
+    }
+}
+
+impl SrtpPolicy {
+    fn offer_transport(&self) -> TransportType {
+        match self {
+            Self::Disabled | Self::Optional => TransportType::Rtp,
+            Self::Required => TransportType::SdesSrtp,
+        }
+    }
+
+    fn accepts(&self, offer: &SessionDescription) -> bool {
+        match self {
+            Self::Disabled | Self::Optional => true,
+            Self::Required => offer.media_descriptions.iter().any(|desc| {
+                desc.media.media_type == MediaType::Audio
+                    && matches!(
+                        desc.media.proto,
+                        TransportProtocol::RtpSavp | TransportProtocol::RtpSavpf
+                    )
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UserAgentStatus {
+    pub registration: Option<RegistrationStatus>,
+    pub presence: Option<PresenceStatus>,
+    pub lines: Vec<(String, Option<LineState>)>,
+    pub voicemail: Option<MessageSummary>,
+    pub call: Option<CallStatus>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RegistrationStatus {
+    pub user_name: String,
+    pub registrar_host: String,
+    pub srtp: SrtpPolicy,
+    pub duration: Duration,
+}
+
+/// The final response to the OPTIONS of [`UserAgent::ping`].
+#[derive(Debug, Clone)]
+pub struct PingResponse {
+    /// E.g. "200 OK", the challenge of the server counts as the response too.
+    pub status: String,
+    /// Since the first sending of the request, the retransmits are included.
+    pub round_trip: Duration,
+}
+
+impl Display for UserAgentStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.registration {
+            // the registration is refreshed by the SIP client, its expiry isn't exposed
+            Some(reg) => writeln!(
+                f,
+                "Registration: {} at {} (SRTP {}), registered for {}",
+                reg.user_name,
+                reg.registrar_host,
+                reg.srtp,
+                misc::format_duration(reg.duration)
+            )?,
+            None => writeln!(f, "Registration: not registered")?,
+        }
+        if let Some(presence) = self.presence {
+            writeln!(f, "Presence: {presence}")?;
+        }
+        if !self.lines.is_empty() {
+            let lines: Vec<_> = self
+                .lines
+                .iter()
+                .map(|(extension, state)| match state {
+                    Some(state) => format!("{extension} {state}"),
+                    None => format!("{extension} unknown"),
+                })
+                .collect();
+            writeln!(f, "Lines: {}", lines.join(", "))?;
+        }
+        if let Some(voicemail) = &self.voicemail {
+            writeln!(f, "Voicemail: {voicemail}")?;
+        }
+        match self.call {
+            Some(CallStatus::Incoming(duration)) => {
+                write!(f, "Call: incoming for {}", misc::format_duration(duration))
+            }
+            Some(CallStatus::Outgoing(duration)) => {
+                write!(f, "Call: calling for {}", misc::format_duration(duration))
+            }
+            Some(CallStatus::Established(duration)) => write!(
+                f,
+                "Call: established for {}",
+                misc::format_duration(duration)
+            ),
+            None => write!(f, "Call: no call"),
+        }
+    }
+}
+
+/// The SIP client with a single registration and at most one call at a time.
+pub struct UserAgent {
+    sip_client: Client,
+    ip_addr: IpAddr,
+    local_addr: SocketAddr,
+    latency: LatencyBudget,
+    call_timeout: Duration,
+    events: broadcast::Sender<UserAgentEvent>,
+    reg_data: Option<RegData>,
+    call_id: CallId,
+    call: Option<call::Call>,
+    in_call_action_sender: Option<mpsc::Sender<call::IncomingCallAction>>,
+    incoming_call_poll: Interval,
+    capture: CaptureTap,
+    sip_tap: SipTap,
+    transactions: Transactions,
+    endpoint_requests: mpsc::Receiver<IncomingRequest>,
+    presence: Option<Publication>,
+    subscriptions: Vec<Subscription>,
+    refreshed: (mpsc::Sender<Refreshed>, mpsc::Receiver<Refreshed>),
+}
+
+struct RegData {
+    pub registration: Registration,
+    pub credentials: DigestCredentials,
+    pub registrar_host: HostPort,
+    pub srtp: SrtpPolicy,
+    pub user_name: String,
+    pub display_name: Option<String>,
+    pub registered_at: Instant,
+}
+
+struct Publication {
+    status: PresenceStatus,
+    note: Option<String>,
+    // the entity tag of the server (RFC 3903), the refreshes and the removal refer to it
+    etag: String,
+    refresh_at: Instant,
+}
+
+struct Subscription {
+    package: Package,
+    dialog: Dialog,
+    refresh_at: Instant,
+    // the Call-ID of the new dialog the refresh in flight falls back to, its NOTIFY may come
+    // before the result
+    renewal: Option<String>,
+}
+
+struct Refreshed {
+    call_id: String,
+    result: Result<(Dialog, Duration)>,
+}
+
+enum Package {
+    // RFC 4235, the line of the watched extension
+    Dialog {
+        extension: String,
+        state: Option<LineState>,
+    },
+    // RFC 3842, the voicemail box of the registered user
+    MessageSummary(Option<MessageSummary>),
+}
+
+impl Package {
+    fn target<'a>(&'a self, identity: &'a Identity) -> &'a str {
+        match self {
+            Self::Dialog { extension, .. } => extension,
+            Self::MessageSummary(_) => &identity.user_name,
+        }
+    }
+
+    fn event(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::Dialog { .. } => ("dialog", "application/dialog-info+xml"),
+            Self::MessageSummary(_) => ("message-summary", "application/simple-message-summary"),
+        }
+    }
+
+    fn update(&mut self, body: &str) -> Option<UserAgentEvent> {
+        match self {
+            Self::Dialog { extension, state } => {
+                let line_state = blf::line_state(body);
+                if *state == Some(line_state) {
+                    return None;
+                }
+                *state = Some(line_state);
+                Some(UserAgentEvent::LineStateChanged {
+                    extension: extension.clone(),
+                    state: line_state,
+                })
+            }
+            Self::MessageSummary(summary) => {
+                let notified = mwi::parse(body)?;
+                if summary.as_ref() == Some(&notified) {
+                    return None;
+                }
+                *summary = Some(notified.clone());
+                Some(UserAgentEvent::MessageWaiting { summary: notified })
+            }
+        }
+    }
+}
+
+impl UserAgent {
+    pub async fn build(udp_socket: SocketAddr, latency: LatencyBudget) -> Result<Self> {
+        let ip_addr = udp_socket.ip();
+        let mut incoming_call_poll = tokio::time::interval(INCOMING_CALL_POLL_INTERVAL);
+        incoming_call_poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let (request_layer, endpoint_requests) = RequestLayer::new();
+        let sip_tap = SipTap::default();
+        let sip_client = ezk_sip::ClientBuilder::new()
+            .listen_udp(udp_socket)
+            .add_layer(request_layer)
+            .add_message_tap(sip_tap.clone())
+            .build()
+            .await?;
+        let transactions = Transactions::new(sip_client.endpoint().clone());
+
+        Ok(Self {
+            sip_client,
+            ip_addr,
+            local_addr: udp_socket,
+            latency,
+            call_timeout: Duration::from_secs(10),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            reg_data: None,
+            call_id: 0,
+            call: None,
+            in_call_action_sender: None,
+            incoming_call_poll,
+            capture: CaptureTap::default(),
+            sip_tap,
+            transactions,
+            endpoint_requests,
+            presence: None,
+            subscriptions: Vec::new(),
+            refreshed: mpsc::channel(REFRESHED_CAPACITY),
+        })
+    }
+
+    pub fn set_call_timeout(&mut self, timeout: Duration) {
+        self.call_timeout = timeout;
+    }
+
+    /// The events from now on, each subscriber receives all of them.
+    pub fn subscribe(&self) -> broadcast::Receiver<UserAgentEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, event: UserAgentEvent) {
+        let _ = self.events.send(event);
+    }
+
+    pub fn is_registered(&self) -> bool {
+        self.reg_data.is_some()
+    }
+
+    pub fn has_active_call(&self) -> bool {
+        self.call.is_some()
+    }
+
+    pub fn has_incoming_call(&self) -> bool {
+        self.in_call_action_sender.is_some()
+    }
+
+    pub fn active_call_id(&self) -> Option<CallId> {
+        self.call.as_ref().map(|_| self.call_id)
+    }
+
+    /// The address the SIP messages are sent from and received on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The RTP headers of the current and the next calls until [`Self::stop_rtp_capture`],
+    /// the headers are dropped if the receiver falls behind.
+    pub fn start_rtp_capture(&self) -> mpsc::Receiver<RtpHeader> {
+        self.capture.start()
+    }
+
+    pub fn stop_rtp_capture(&self) {
+        self.capture.stop();
+    }
+
+    /// The SIP messages the user agent sends and receives from now on, with the addresses of the
+    /// transport, the messages are dropped if the receiver falls behind.
+    pub fn subscribe_sip_messages(&self) -> broadcast::Receiver<SipMessage> {
+        self.sip_tap.subscribe()
+    }
+
+    /// Registers the user at the registrar, the display name goes to From of the calls and the
+    /// standalone requests. The previous registration is unregistered first.
+    pub async fn register(
+        &mut self,
+        user_name: &str,
+        password: &str,
+        registrar_host: HostPort,
+        srtp: SrtpPolicy,
+        display_name: Option<String>,
+    ) -> Result<()> {
+        // the new binding may have the Contact of the previous one, so that one is removed first
+        if self.reg_data.is_some() {
+            self.unregister_and_wait().await;
+        }
+        // the Request-URI of REGISTER has no user part, RFC 3261 10.2
+        let registrar = SipUri::new(registrar_host.clone());
+        let mut credentials = DigestCredentials::new();
+        credentials.set_default(DigestUser::new(user_name, password.as_bytes()));
+        let user_name = user_name.to_owned();
+        // the SIP client builds From of the INVITE out of the id of the registration
+        let override_id = display_name.clone().map(|display_name| {
+            let uri = SipUri::new(registrar.host_port.clone()).user(user_name.clone().into());
+            NameAddr::new(display_name, uri)
+        });
+        let config = RegistrarConfig {
+            registrar,
+            username: user_name.clone(),
+            override_contact: None,
+            override_id,
+        };
+        let authenticator = DigestAuthenticator::new(credentials.clone());
+        let registration = self
+            .sip_client
+            .register(config, authenticator)
+            .await
+            .map_err(|err| anyhow::Error::msg(err.to_string()))?;
+
+        let event = UserAgentEvent::Registered {
+            user_name: user_name.clone(),
+            registrar_host: registrar_host.to_string(),
+        };
+        let reg_data = RegData {
+            registration,
+            credentials,
+            registrar_host,
+            srtp,
+            user_name,
+            display_name,
+            registered_at: Instant::now(),
+        };
+        self.reg_data = Some(reg_data);
+
+        self.emit(event);
+        Ok(())
+    }
+
+    /// Withdraws the presence, ends the subscriptions and removes the binding (Expires 0) in
+    /// the background.
+    pub fn unregister(&mut self) {
+        self.remove_registration().detach_all();
+    }
+
+    /// Unregisters like [`UserAgent::unregister`] and waits for the final responses, e.g.
+    /// before the exit.
+    pub async fn unregister_and_wait(&mut self) {
+        let mut removals = self.remove_registration();
+        while removals.join_next().await.is_some() {}
+    }
+
+    fn remove_registration(&mut self) -> JoinSet<()> {
+        let mut removals = JoinSet::new();
+        self.remove_presence(&mut removals);
+        self.remove_subscriptions(&mut removals, |_| true);
+        if let Some(reg_data) = self.reg_data.take() {
+            removals.spawn(async move {
+                if let Err(err) = reg_data.registration.unregister().await {
+                    tracing::debug!("Unregistering err: {err}");
+                }
+            });
+            self.emit(UserAgentEvent::Unregistered);
+        }
+        removals
+    }
+
+    /// Starts the outbound call, its events carry the returned id. The subject goes to the
+    /// Subject of the INVITE. The call is attached to the bus: its audio, the playback and the
+    /// recording go through it.
+    pub async fn make_call(
+        &mut self,
+        target_user_name: &str,
+        subject: Option<&str>,
+        bus: &AudioBus,
+    ) -> Result<CallId> {
+        let reg_data = self
+            .reg_data
+            .as_ref()
+            .ok_or(anyhow::Error::msg("The user agent is not registered"))?;
+
+        let target = misc::make_sip_uri(target_user_name, &reg_data.registrar_host)?;
+        let authenticator = reg_data.create_authenticator();
+        let media = self.create_media(reg_data.srtp)?;
+        let mut headers = Headers::new();
+        if let Some(subject) = subject {
+            headers.insert(Name::SUBJECT, subject.to_owned());
+        }
+        let outbound_call = reg_data
+            .registration
+            .make_call_with_headers(target, headers, authenticator, media)
+            .await?;
+        let call = call::Call::from_outgoing(
+            outbound_call,
+            bus.add_source(),
+            bus.add_sink(),
+            self.call_timeout,
+            self.latency,
+            self.capture.clone(),
+        );
+        call.attach_to(bus);
+        self.call = Some(call);
+        self.call_id += 1;
+
+        self.emit(UserAgentEvent::Calling {
+            call_id: self.call_id,
+            target: target_user_name.to_owned(),
+        });
+        Ok(self.call_id)
+    }
+
+    /// Sends the text as the out-of-dialog MESSAGE (RFC 3428) to the user at the registrar,
+    /// the returned future resolves once it's accepted and doesn't borrow the user agent.
+    pub fn send_message(
+        &self,
+        target_user_name: &str,
+        text: &str,
+    ) -> Result<impl Future<Output = Result<()>> + Send + 'static> {
+        let identity = self.identity()?;
+        let target = identity.uri_of(target_user_name);
+        let request = Request {
+            method: Method::MESSAGE,
+            uri: target.clone(),
+            to: target,
+            headers: Vec::new(),
+            body: Some(("text/plain;charset=UTF-8".to_owned(), text.to_owned())),
+        };
+        let transactions = self.transactions.clone();
+        let target_user_name = target_user_name.to_owned();
+        Ok(async move {
+            let response = transactions.send(&identity, &request).await?;
+            match response.status_code() {
+                Some(200..=299) => Ok(()),
+                _ => Err(anyhow::Error::msg(format!(
+                    "The message to {target_user_name} is rejected: {}",
+                    response.status()
+                ))),
+            }
+        })
+    }
+
+    /// Sends the OPTIONS to the host ("ip:port", 5060 by default) or to the registrar in the
+    /// background, the response comes as [`UserAgentEvent::PingAnswered`]. The unregistered
+    /// user agent sends it from the anonymous user.
+    pub fn ping(&self, host: Option<&str>) -> Result<()> {
+        let identity = match (self.identity(), host) {
+            (Ok(identity), _) => identity,
+            (Err(_), Some(host)) => Identity {
+                user_name: "anonymous".to_owned(),
+                credentials: DigestCredentials::new(),
+                domain: host.to_owned(),
+                display_name: None,
+            },
+            (Err(err), None) => return Err(err),
+        };
+        let uri = format!("sip:{}", host.unwrap_or(identity.domain.as_str()));
+        let request = Request {
+            method: Method::OPTIONS,
+            uri: uri.clone(),
+            to: uri,
+            headers: vec![("Accept", "application/sdp".to_owned())],
+            body: None,
+        };
+        let transactions = self.transactions.clone();
+        let events = self.events.clone();
+        let host = host.map(str::to_owned);
+        tokio::spawn(async move {
+            let started = Instant::now();
+            let event = match transactions.send_once(&identity, &request).await {
+                Ok(response) => UserAgentEvent::PingAnswered {
+                    host,
+                    response: PingResponse {
+                        status: response.status().to_owned(),
+                        round_trip: started.elapsed(),
+                    },
+                },
+                Err(err) => UserAgentEvent::PingFailed {
+                    host,
+                    reason: err.to_string(),
+                },
+            };
+            let _ = events.send(event);
+        });
+        Ok(())
+    }
+
+    /// Publishes the presence of the registered user in the PIDF document, it's refreshed
+    /// before the expiry granted by the server until the unregistration.
+    pub async fn publish_presence(
+        &mut self,
+        status: PresenceStatus,
+        note: Option<String>,
+    ) -> Result<()> {
+        let reg_data = self
+            .reg_data
+            .as_ref()
+            .ok_or(anyhow::Error::msg("The user agent is not registered"))?;
+        let identity = reg_data.identity();
+        let body = presence::pidf(&identity.uri(), status, note.as_deref());
+        let etag = self
+            .presence
+            .as_ref()
+            .map(|publication| publication.etag.as_str());
+        let transactions = &self.transactions;
+        let mut response =
+            Self::publish(transactions, &identity, Some(&body), etag, PUBLISH_EXPIRES).await?;
+        // the server forgot the publication, it's made anew
+        if response.status_code() == Some(412) && etag.is_some() {
+            response =
+                Self::publish(transactions, &identity, Some(&body), None, PUBLISH_EXPIRES).await?;
+        }
+        if !matches!(response.status_code(), Some(200..=299)) {
+            return Err(anyhow::Error::msg(format!(
+                "The presence is rejected: {}",
+                response.status()
+            )));
+        }
+        let etag = response
+            .header("sip-etag")
+            .ok_or(anyhow::Error::msg("The presence server sent no SIP-ETag"))?;
+        let expires = response
+            .header("expires")
+            .and_then(|expires| expires.trim().parse().ok())
+            .map_or(PUBLISH_EXPIRES, Duration::from_secs);
+        self.presence = Some(Publication {
+            status,
+            note,
+            etag: etag.trim().to_owned(),
+            refresh_at: misc::refresh_at(expires),
+        });
+        Ok(())
+    }
+
+    // the refresh sends the document again, so the server which lost it gets it back
+    async fn refresh_presence(&mut self) -> Result<()> {
+        let Some(publication) = self.presence.as_ref() else {
+            return Ok(());
+        };
+        if publication.refresh_at > Instant::now() {
+            return Ok(());
+        }
+        let (status, note) = (publication.status, publication.note.clone());
+        let result = self.publish_presence(status, note).await;
+        if result.is_err() {
+            if let Some(publication) = self.presence.as_mut() {
+                publication.refresh_at = Instant::now() + RETRY_INTERVAL;
+            }
+        }
+        result.map_err(|err| anyhow::Error::msg(format!("Presence refreshing err: {err}")))
+    }
+
+    fn remove_presence(&mut self, removals: &mut JoinSet<()>) {
+        let (Some(publication), Some(reg_data)) = (self.presence.take(), self.reg_data.as_ref())
+        else {
+            return;
+        };
+        let transactions = self.transactions.clone();
+        let identity = reg_data.identity();
+        removals.spawn(async move {
+            let removed = Self::publish(
+                &transactions,
+                &identity,
+                None,
+                Some(&publication.etag),
+                Duration::ZERO,
+            )
+            .await;
+            if let Err(err) = removed {
+                tracing::debug!("Presence removing err: {err}");
+            }
+        });
+    }
+
+    async fn publish(
+        transactions: &Transactions,
+        identity: &Identity,
+        body: Option<&str>,
+        etag: Option<&str>,
+        expires: Duration,
+    ) -> Result<Message> {
+        let mut headers = vec![
+            ("Event", "presence".to_owned()),
+            ("Expires", expires.as_secs().to_string()),
+        ];
+        headers.extend(etag.map(|etag| ("SIP-If-Match", etag.to_owned())));
+        let request = Request {
+            method: Method::PUBLISH,
+            uri: identity.uri(),
+            to: identity.uri(),
+            headers,
+            body: body.map(|body| ("application/pidf+xml".to_owned(), body.to_owned())),
+        };
+        transactions.send(identity, &request).await
+    }
+
+    /// Subscribes to the dialogs of the extensions at the registrar (Busy Lamp Field), their
+    /// states come as [`UserAgentEvent::LineStateChanged`] until the unregistration. The
+    /// previous extensions are unwatched, the failed ones are reported together.
+    pub async fn watch_lines(&mut self, extensions: &[String]) -> Result<()> {
+        let identity = self.identity()?;
+        let mut removals = JoinSet::new();
+        self.remove_subscriptions(&mut removals, |package| {
+            matches!(package, Package::Dialog { .. })
+        });
+        removals.detach_all();
+        let mut failures = Vec::new();
+        for extension in extensions {
+            let package = Package::Dialog {
+                extension: extension.clone(),
+                state: None,
+            };
+            if let Err(err) = self.add_subscription(&identity, package).await {
+                failures.push(format!("{extension}: {err}"));
+            }
+        }
+        if !failures.is_empty() {
+            return Err(anyhow::Error::msg(format!(
+                "Could not watch the lines: {}",
+                failures.join("; ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Subscribes to the message summary of the registered user (Message Waiting Indication),
+    /// the voicemail counts come as [`UserAgentEvent::MessageWaiting`] until the
+    /// unregistration.
+    pub async fn watch_mailbox(&mut self) -> Result<()> {
+        let identity = self.identity()?;
+        let mut removals = JoinSet::new();
+        self.remove_subscriptions(&mut removals, |package| {
+            matches!(package, Package::MessageSummary(_))
+        });
+        removals.detach_all();
+        self.add_subscription(&identity, Package::MessageSummary(None))
+            .await
+            .map_err(|err| anyhow::Error::msg(format!("Could not watch the mailbox: {err}")))
+    }
+
+    fn identity(&self) -> Result<Identity> {
+        self.reg_data
+            .as_ref()
+            .map(RegData::identity)
+            .ok_or(anyhow::Error::msg("The user agent is not registered"))
+    }
+
+    async fn add_subscription(&mut self, identity: &Identity, package: Package) -> Result<()> {
+        let mut dialog = self.transactions.dialog();
+        let request = Self::subscribe_request(identity, &package, SUBSCRIBE_EXPIRES);
+        let expires = Self::subscribe(&self.transactions, identity, &request, &mut dialog).await?;
+        self.subscriptions.push(Subscription {
+            package,
+            dialog,
+            refresh_at: misc::refresh_at(expires),
+            renewal: None,
+        });
+        Ok(())
+    }
+
+    // the due subscriptions are refreshed in the background, the one the notifier forgot
+    // (e.g. 481) is made anew out of the dialog
+    fn refresh_subscriptions(&mut self) {
+        let Ok(identity) = self.identity() else {
+            return;
+        };
+        let now = Instant::now();
+        for subscription in self.subscriptions.iter_mut() {
+            if subscription.renewal.is_some() || subscription.refresh_at > now {
+                continue;
+            }
+            let request =
+                Self::subscribe_request(&identity, &subscription.package, SUBSCRIBE_EXPIRES);
+            let mut dialog = subscription.dialog.clone();
+            let mut renewal = self.transactions.dialog();
+            subscription.renewal = Some(renewal.call_id.clone());
+            let transactions = self.transactions.clone();
+            let identity = identity.clone();
+            let refreshed = self.refreshed.0.clone();
+            tokio::spawn(async move {
+                let call_id = dialog.call_id.clone();
+                let result =
+                    match Self::subscribe(&transactions, &identity, &request, &mut dialog).await {
+                        Ok(expires) => Ok((dialog, expires)),
+                        Err(_) => Self::subscribe(&transactions, &identity, &request, &mut renewal)
+                            .await
+                            .map(|expires| (renewal, expires)),
+                    };
+                let _ = refreshed.send(Refreshed { call_id, result }).await;
+            });
+        }
+    }
+
+    // the subscription removed or made anew by its NOTIFY meanwhile keeps its state
+    fn handle_refreshed(&mut self, refreshed: Refreshed) -> Result<()> {
+        let Ok(identity) = self.identity() else {
+            return Ok(());
+        };
+        let Some(subscription) = self
+            .subscriptions
+            .iter_mut()
+            .find(|subscription| subscription.dialog.call_id == refreshed.call_id)
+        else {
+            return Ok(());
+        };
+        subscription.renewal = None;
+        match refreshed.result {
+            Ok((dialog, expires)) => {
+                subscription.dialog = dialog;
+                subscription.refresh_at = misc::refresh_at(expires);
+                Ok(())
+            }
+            Err(err) => {
+                subscription.refresh_at = Instant::now() + RETRY_INTERVAL;
+                Err(anyhow::Error::msg(format!(
+                    "Subscription refreshing err: {}: {err}",
+                    subscription.package.target(&identity)
+                )))
+            }
+        }
+    }
+
+    fn remove_subscriptions(
+        &mut self,
+        removals: &mut JoinSet<()>,
+        filter: impl Fn(&Package) -> bool,
+    ) {
+        let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.subscriptions)
+            .into_iter()
+            .partition(|subscription| filter(&subscription.package));
+        self.subscriptions = kept;
+        let Ok(identity) = self.identity() else {
+            return;
+        };
+        for mut subscription in removed {
+            let transactions = self.transactions.clone();
+            let identity = identity.clone();
+            removals.spawn(async move {
+                let request =
+                    Self::subscribe_request(&identity, &subscription.package, Duration::ZERO);
+                let removed = transactions
+                    .send_in(&identity, &request, &mut subscription.dialog)
+                    .await;
+                if let Err(err) = removed {
+                    tracing::debug!("Subscription removing err: {err}");
+                }
+            });
+        }
+    }
+
+    async fn subscribe(
+        transactions: &Transactions,
+        identity: &Identity,
+        request: &Request,
+        dialog: &mut Dialog,
+    ) -> Result<Duration> {
+        let response = transactions.send_in(identity, request, dialog).await?;
+        if !matches!(response.status_code(), Some(200..=299)) {
+            return Err(anyhow::Error::msg(format!(
+                "The subscription is rejected: {}",
+                response.status()
+            )));
+        }
+        Ok(response
+            .header("expires")
+            .and_then(|expires| expires.trim().parse().ok())
+            .map_or(SUBSCRIBE_EXPIRES, Duration::from_secs))
+    }
+
+    fn subscribe_request(identity: &Identity, package: &Package, expires: Duration) -> Request {
+        let uri = identity.uri_of(package.target(identity));
+        let (event, accept) = package.event();
+        Request {
+            method: Method::SUBSCRIBE,
+            uri: uri.clone(),
+            to: uri,
+            headers: vec![
+                ("Event", event.to_owned()),
+                ("Accept", accept.to_owned()),
+                ("Expires", expires.as_secs().to_string()),
+            ],
+            body: None,
+        }
+    }
+
+    fn handle_endpoint_request(&mut self, request: IncomingRequest) {
+        match request.line.method {
+            Method::MESSAGE => self.receive_message(request),
+            Method::OPTIONS => self.receive_options(request),
+            Method::NOTIFY => self.receive_notify(request),
+            Method::INFO => self
+                .transactions
+                .respond(request, StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST),
+            _ => self
+                .transactions
+                .respond(request, StatusCode::METHOD_NOT_ALLOWED),
+        }
+    }
+
+    fn receive_message(&self, request: IncomingRequest) {
+        let from = Caller::of(&request.base_headers.from);
+        let body = String::from_utf8_lossy(&request.body).into_owned();
+        self.transactions.respond(request, StatusCode::OK);
+        self.emit(UserAgentEvent::MessageReceived { from, body });
+    }
+
+    // the DTMF of application/dtmf-relay ("Signal=5") and application/dtmf ("5") is the digit
+    // of the call. The response goes to the transport of the dialog the INFO came on
+    fn receive_info(&self, request: IncomingRequest) {
+        let Some(call_id) = self.active_call_id() else {
+            self.transactions
+                .respond(request, StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST);
+            return;
+        };
+        let content_type = transaction::header(&request.headers, &Name::CONTENT_TYPE)
+            .map(|content_type| content_type.trim().to_owned())
+            .unwrap_or_default();
+        let body = String::from_utf8_lossy(&request.body).into_owned();
+        self.transactions.respond(request, StatusCode::OK);
+        let subtype = content_type.split(';').next().unwrap_or_default().trim();
+        let digit = if subtype.eq_ignore_ascii_case("application/dtmf-relay") {
+            body.lines().find_map(|line| {
+                let (name, value) = line.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("signal")
+                    .then(|| value.trim().to_owned())
+            })
+        } else if subtype.eq_ignore_ascii_case("application/dtmf") {
+            Some(body.trim().to_owned())
+        } else {
+            None
+        };
+        let digit = digit.and_then(|digit| {
+            let mut chars = digit.chars();
+            let digit = chars.next()?.to_ascii_uppercase();
+            (chars.next().is_none() && digit != dtmf::PAUSE && dtmf::is_valid_symbol(digit))
+                .then_some(digit)
+        });
+        let event = match digit {
+            Some(digit) => UserAgentEvent::DtmfReceived { call_id, digit },
+            None => UserAgentEvent::InfoReceived {
+                call_id,
+                content_type,
+                body,
+            },
+        };
+        self.emit(event);
+    }
+
+    // the qualify probe of the registrar. The OPTIONS with the To tag matches no dialog here,
+    // the one of the call comes within its dialog
+    fn receive_options(&self, request: IncomingRequest) {
+        if request.base_headers.to.tag.is_some() {
+            self.transactions
+                .respond(request, StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST);
+            return;
+        }
+        self.transactions
+            .respond_with(request, StatusCode::OK, self.capabilities());
+    }
+
+    // the INFO and the audit of the call by the SBC (OPTIONS), which tears the call down
+    // without the answer
+    fn handle_dialog_request(&self, request: IncomingRequest) {
+        match request.line.method {
+            Method::INFO => self.receive_info(request),
+            Method::OPTIONS => {
+                self.transactions
+                    .respond_with(request, StatusCode::OK, self.capabilities())
+            }
+            _ => self
+                .transactions
+                .respond(request, StatusCode::METHOD_NOT_ALLOWED),
+        }
+    }
+
+    // RFC 3261 11.2: the methods, the bodies and the SDP of the media which the calls of the
+    // registration would offer, with the port 0
+    fn capabilities(&self) -> Reply {
+        let srtp = self
+            .reg_data
+            .as_ref()
+            .map_or(SrtpPolicy::default(), |reg_data| reg_data.srtp);
+        let profile = match srtp {
+            SrtpPolicy::Disabled | SrtpPolicy::Optional => "RTP/AVP",
+            SrtpPolicy::Required => "RTP/SAVP",
+        };
+        let address_type = if self.ip_addr.is_ipv6() { "IP6" } else { "IP4" };
+        let sdp = format!(
+            "v=0\r\n\
+             o=- 0 0 IN {address_type} {ip}\r\n\
+             s=sipacker\r\n\
+             c=IN {address_type} {ip}\r\n\
+             t=0 0\r\n\
+             m=audio 0 {profile} 8\r\n\
+             a=rtpmap:8 PCMA/8000\r\n\
+             a=ptime:{ptime}\r\n",
+            ip = self.ip_addr,
+            ptime = self.latency.ptime.as_millis(),
+        );
+        Reply {
+            headers: vec![
+                ("Allow", self.transactions.allowed()),
+                (
+                    "Accept",
+                    "application/sdp, text/plain, application/dialog-info+xml, \
+                     application/simple-message-summary"
+                        .to_owned(),
+                ),
+            ],
+            body: Some(("application/sdp".to_owned(), sdp)),
+        }
+    }
+
+    // the terminated subscription is made anew after a while
+    fn receive_notify(&mut self, request: IncomingRequest) {
+        let call_id = &*request.base_headers.call_id.0;
+        let Some(subscription) = self.subscriptions.iter_mut().find(|subscription| {
+            subscription.dialog.call_id == call_id
+                || subscription.renewal.as_deref() == Some(call_id)
+        }) else {
+            self.transactions
+                .respond(request, StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST);
+            return;
+        };
+        let terminated = transaction::header(&request.headers, &Name::SUBSCRIPTION_STATE)
+            .is_some_and(|state| state.trim_start().starts_with("terminated"));
+        let body = String::from_utf8_lossy(&request.body).into_owned();
+        self.transactions.respond(request, StatusCode::OK);
+        if terminated {
+            subscription.dialog = self.transactions.dialog();
+            subscription.refresh_at = Instant::now() + RETRY_INTERVAL;
+            subscription.renewal = None;
+        }
+        if body.trim().is_empty() {
+            return;
+        }
+        let event = subscription.package.update(&body);
+        if let Some(event) = event {
+            self.emit(event);
+        }
+    }
+
+    fn create_media(&self, srtp: SrtpPolicy) -> Result<MediaSession> {
+        let options = Options {
+            offer_transport: srtp.offer_transport(),
+            offer_sdes_with_rtp: srtp == SrtpPolicy::Optional,
+            offer_ice: false,
+            offer_avpf: false,
+            rtcp_mux_policy: RtcpMuxPolicy::Negotiate,
+            bundle_policy: BundlePolicy::MaxCompat,
+        };
+        let mut sdp_session = AsyncSdpSession::new(self.ip_addr, options);
+
+        let audio_media_id = sdp_session
+            .add_local_media(
+                ezk_rtc_proto::Codecs::new(MediaType::Audio)
+                    .with_codec(ezk_rtc_proto::Codec::PCMA)
+                    .with_ptime(self.latency.ptime),
+                1,
+                ezk_rtc_proto::Direction::SendRecv,
+            )
+            .ok_or(anyhow::Error::msg("Could not create audio media"))?;
+        sdp_session.add_media(audio_media_id, ezk_rtc_proto::Direction::SendRecv);
+
+        Ok(MediaSession::new(sdp_session))
+    }
+
+    /// The accepted call is attached to the bus, see [`UserAgent::make_call`].
+    pub async fn accept_incoming_call(&mut self, bus: &AudioBus) -> Result<()> {
+        let sender = self
+            .in_call_action_sender
+            .take()
+            .ok_or(anyhow::Error::msg("There is no incoming call to accept"))?;
+
+        if let Some(call) = &self.call {
+            call.attach_to(bus);
+        }
+        sender
+            .send(call::IncomingCallAction::Accept {
+                audio_sender: bus.add_source(),
+                audio_receiver: bus.add_sink(),
+            })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn decline_incoming_call(&mut self) -> Result<()> {
+        let sender = self
+            .in_call_action_sender
+            .take()
+            .ok_or(anyhow::Error::msg("There is no incoming call to decline"))?;
+
+        sender.send(call::IncomingCallAction::Decline).await?;
+        Ok(())
+    }
+
+    pub async fn terminate_call(&mut self) -> Result<()> {
+        if let Some(call) = self.call.take() {
+            let stats = call.stats();
+            let status = call.final_status();
+            call.terminate().await?;
+            self.in_call_action_sender = None;
+            self.push_call_summary(stats);
+            self.emit(UserAgentEvent::CallTerminated {
+                call_id: self.call_id,
+                status: Some(status),
+            });
+        }
+        Ok(())
+    }
+
+    // the target is the user at the registrar or the SIP URI, the call is left once the remote
+    // accepts the REFER
+    pub async fn transfer_call(&mut self, target: &str, is_uri: bool) -> Result<()> {
+        let reg_data = self
+            .reg_data
+            .as_ref()
+            .ok_or(anyhow::Error::msg("The user agent is not registered"))?;
+        let established = matches!(
+            self.call.as_ref().map(|call| call.status()),
+            Some(CallStatus::Established(_))
+        );
+        if !established {
+            return Err(anyhow::Error::msg(
+                "Can't transfer the call. There is no established call",
+            ));
+        }
+        let target = if is_uri {
+            target
+                .parse::<SipUri>()
+                .map_err(|err: InvalidSipUri| anyhow::Error::msg(err.to_string()))?
+        } else {
+            SipUri::new(reg_data.registrar_host.clone()).user(target.to_owned().into())
+        };
+        if let Some(call) = self.call.as_mut() {
+            call.refer(target).await?;
+        }
+        self.terminate_call().await
+    }
+
+    fn push_call_summary(&mut self, stats: CallStats) {
+        if stats.has_media() {
+            self.emit(UserAgentEvent::CallQuality {
+                call_id: self.call_id,
+                stats,
+            });
+        }
+    }
+
+    pub fn start_recording(&mut self, path: &Path, mode: RecordingMode) -> Result<()> {
+        let call = self
+            .call
+            .as_ref()
+            .ok_or(anyhow::Error::msg("There is no active call to record"))?;
+        call.start_recording(path, mode)?;
+        self.emit(UserAgentEvent::RecordingStarted {
+            call_id: self.call_id,
+            path: path.to_owned(),
+        });
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> Result<()> {
+        let call = self
+            .call
+            .as_ref()
+            .ok_or(anyhow::Error::msg("There is no active call"))?;
+        call.stop_recording()?;
+        self.emit(UserAgentEvent::RecordingStopped {
+            call_id: self.call_id,
+        });
+        Ok(())
+    }
+
+    pub fn start_playback(&mut self, path: &Path, mode: PlaybackMode) -> Result<()> {
+        let call = self
+            .call
+            .as_ref()
+            .ok_or(anyhow::Error::msg("There is no active call to play into"))?;
+        call.start_playback(path, mode)?;
+        self.emit(UserAgentEvent::PlaybackStarted {
+            call_id: self.call_id,
+            path: path.to_owned(),
+        });
+        Ok(())
+    }
+
+    pub fn stop_playback(&mut self) -> Result<()> {
+        let call = self
+            .call
+            .as_ref()
+            .ok_or(anyhow::Error::msg("There is no active call"))?;
+        call.stop_playback()?;
+        self.emit(UserAgentEvent::PlaybackStopped {
+            call_id: self.call_id,
+        });
+        Ok(())
+    }
+
+    pub fn send_dtmf(&mut self, digits: &str) -> Result<()> {
+        let call = self
+            .call
+            .as_ref()
+            .ok_or(anyhow::Error::msg("There is no active call to send DTMF"))?;
+        call.send_dtmf(digits)?;
+        self.emit(UserAgentEvent::DtmfSent {
+            call_id: self.call_id,
+            digits: digits.to_owned(),
+        });
+        Ok(())
+    }
+
+    pub fn status(&self) -> UserAgentStatus {
+        UserAgentStatus {
+            registration: self.reg_data.as_ref().map(|reg_data| RegistrationStatus {
+                user_name: reg_data.user_name.clone(),
+                registrar_host: reg_data.registrar_host.to_string(),
+                srtp: reg_data.srtp,
+                duration: reg_data.registered_at.elapsed(),
+            }),
+            presence: self.presence.as_ref().map(|publication| publication.status),
+            lines: self
+                .subscriptions
+                .iter()
+                .filter_map(|subscription| match &subscription.package {
+                    Package::Dialog { extension, state } => Some((extension.clone(), *state)),
+                    Package::MessageSummary(_) => None,
+                })
+                .collect(),
+            voicemail: self.subscriptions.iter().find_map(|subscription| {
+                match &subscription.package {
+                    Package::MessageSummary(summary) => summary.clone(),
+                    Package::Dialog { .. } => None,
+                }
+            }),
+            call: self.call.as_ref().map(|call| call.status()),
+        }
+    }
+
+    pub fn call_stats(&self) -> Result<CallStats> {
+        self.call
+            .as_ref()
+            .map(|call| call.stats())
+            .ok_or(anyhow::Error::msg("There is no active call"))
+    }
+
+    pub fn audio_stats(&self) -> Result<AudioStats> {
+        self.call
+            .as_ref()
+            .map(|call| call.audio_stats())
+            .ok_or(anyhow::Error::msg("There is no active call"))
+    }
+
+    /// Waits for the next update and handles it, for the consumers without other inputs:
+    /// unlike [`UserAgent::next_update`] it's not cancel-safe.
+    pub async fn run(&mut self) -> Result<()> {
+        let update = self.next_update().await;
+        self.handle(update).await
+    }
+
+    /// Waits until the call or the registration has something to handle. Cancel-safe, so it
+    /// can be a branch of `select!` next to the other inputs of the consumer.
+    pub async fn next_update(&mut self) -> UserAgentUpdate {
+        let registered = self.reg_data.is_some();
+        let refresh_at = self
+            .presence
+            .iter()
+            .map(|publication| publication.refresh_at)
+            .chain(
+                self.subscriptions
+                    .iter()
+                    .filter(|subscription| subscription.renewal.is_none())
+                    .map(|subscription| subscription.refresh_at),
+            )
+            .min();
+        select! {
+            ready = Self::wait_call(self.call.as_mut()) => UserAgentUpdate(Update::Call(ready)),
+            _ = self.incoming_call_poll.tick(), if registered => {
+                UserAgentUpdate(Update::IncomingCallPoll)
+            }
+            Some(request) = self.endpoint_requests.recv() => {
+                UserAgentUpdate(Update::EndpointRequest(request))
+            }
+            _ = Self::wait_until(refresh_at) => UserAgentUpdate(Update::Refresh),
+            Some(refreshed) = self.refreshed.1.recv() => {
+                UserAgentUpdate(Update::Refreshed(refreshed))
+            }
+        }
+    }
+
+    /// Handles the update, the events are sent to the subscribers.
+    pub async fn handle(&mut self, update: UserAgentUpdate) -> Result<()> {
+        match update.0 {
+            Update::Call(ready) => {
+                self.update_call(ready).await;
+                Ok(())
+            }
+            Update::IncomingCallPoll => self.handle_incoming_call_req().await,
+            Update::EndpointRequest(request) => {
+                self.handle_endpoint_request(request);
+                Ok(())
+            }
+            Update::Refresh => {
+                self.refresh_subscriptions();
+                self.refresh_presence().await
+            }
+            Update::Refreshed(refreshed) => self.handle_refreshed(refreshed),
+        }
+    }
+
+    async fn wait_until(deadline: Option<Instant>) {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    async fn wait_call(call: Option<&mut call::Call>) -> call::Ready {
+        match call {
+            Some(call) => call.wait().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    async fn handle_incoming_call_req(&mut self) -> Result<()> {
+        if let Some(reg_data) = &mut self.reg_data {
+            let srtp = reg_data.srtp;
+            let result = self
+                .sip_client
+                .get_incoming_call(reg_data.registration.contact().clone())
+                .await;
+            if let Ok(Some((incoming_call, from))) = result {
+                if self.has_active_call() {
+                    tracing::debug!("Reject incoming call: there is the active call already");
+                    let _ = incoming_call
+                        .decline(
+                            StatusCode::BUSY_HERE,
+                            BytesStr::from("There is an active call").into(),
+                        )
+                        .await
+                        .inspect_err(|err| {
+                            tracing::warn!("Declining error: {err}");
+                        });
+                } else if !incoming_call
+                    .sdp_offer()
+                    .is_none_or(|offer| srtp.accepts(offer))
+                {
+                    tracing::debug!("Reject incoming call: SRTP is required, RTP/AVP is offered");
+                    let _ = incoming_call
+                        .decline(
+                            StatusCode::NOT_ACCEPTABLE_HERE,
+                            BytesStr::from("SRTP is required").into(),
+                        )
+                        .await
+                        .inspect_err(|err| {
+                            tracing::warn!("Declining error: {err}");
+                        });
+                } else {
+                    let (action_tx, action_rx) = mpsc::channel(1);
+                    let invite = IncomingInvite::of(incoming_call.invite());
+                    let incoming_call = incoming_call.with_media(self.create_media(srtp)?);
+                    let call = call::Call::from_incoming(
+                        incoming_call,
+                        action_rx,
+                        self.latency,
+                        self.capture.clone(),
+                    );
+                    self.in_call_action_sender = Some(action_tx);
+                    self.call = Some(call);
+                    self.call_id += 1;
+                    self.emit(UserAgentEvent::IncomingCall {
+                        call_id: self.call_id,
+                        caller: Caller::of(&from),
+                        invite,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn update_call(&mut self, ready: call::Ready) {
+        let mut dialog_request = None;
+        self.call = if let Some(call) = self.call.take() {
+            let call_id = self.call_id;
+            let stats = call.stats_tap();
+            let run_res = call.run(ready).await.inspect_err(|err| {
+                tracing::warn!("Call err: {err}");
+            });
+
+            let (call, events) = match run_res {
+                Ok((call, event)) => {
+                    let events = match event {
+                        Some(call::Event::Progress(line)) => match line.code.into_u16() {
+                            100 => vec![UserAgentEvent::CallTrying { call_id }],
+                            180 => vec![UserAgentEvent::CallRinging { call_id }],
+                            183 => vec![UserAgentEvent::CallSessionProgress { call_id }],
+                            code => vec![UserAgentEvent::CallProgress {
+                                call_id,
+                                code,
+                                reason: line
+                                    .reason
+                                    .as_ref()
+                                    .map(ToString::to_string)
+                                    .or(line.code.text().map(str::to_owned))
+                                    .unwrap_or_default(),
+                            }],
+                        },
+                        Some(call::Event::Established) => {
+                            vec![UserAgentEvent::CallEstablished { call_id }]
+                        }
+                        Some(call::Event::Terminated(status)) => {
+                            vec![UserAgentEvent::CallTerminated {
+                                call_id,
+                                status: Some(status),
+                            }]
+                        }
+                        Some(call::Event::DtmfReceived(digit)) => {
+                            vec![UserAgentEvent::DtmfReceived { call_id, digit }]
+                        }
+                        Some(call::Event::Request(request)) => {
+                            dialog_request = Some(request);
+                            Vec::new()
+                        }
+                        None => Vec::new(),
+                    };
+                    (call, events)
+                }
+                Err(err) => (
+                    None,
+                    vec![
+                        UserAgentEvent::CallFailed {
+                            call_id,
+                            reason: err.to_string(),
+                        },
+                        UserAgentEvent::CallTerminated {
+                            call_id,
+                            status: err
+                                .downcast_ref::<call::Rejected>()
+                                .map(|rejected| rejected.0.code.into_u16()),
+                        },
+                    ],
+                ),
+            };
+
+            if call.is_none() {
+                self.push_call_summary(stats.snapshot());
+            }
+
+            for event in events {
+                self.emit(event);
+            }
+
+            call
+        } else {
+            None
+        };
+
+        if self.call.is_none() {
+            self.in_call_action_sender = None;
+        }
+        if let Some(request) = dialog_request {
+            self.handle_dialog_request(request);
+        }
+    }
+}
+
+impl RegData {
+    fn create_authenticator(&self) -> DigestAuthenticator {
+        DigestAuthenticator::new(self.credentials.clone())
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            user_name: self.user_name.clone(),
+            credentials: self.credentials.clone(),
+            domain: self.registrar_host.to_string(),
+            display_name: self.display_name.clone(),
+        }
+    }
+}
+
+mod misc {
+    use std::time::{Duration, Instant};
+
+    use anyhow::Result;
+    use ezk_sip_types::{
+        host::HostPort,
+        uri::sip::{InvalidSipUri, SipUri},
+    };
+
+    pub fn make_sip_uri(user_name: &str, sip_domain: &HostPort) -> Result<SipUri> {
+        format!("sip:{user_name}@{sip_domain}")
+            .parse()
+            .map_err(|err: InvalidSipUri| anyhow::Error::msg(err.to_string()))
+    }
+
+    // the refresh is due before the expiry, halfway through the short one
+    pub fn refresh_at(expires: Duration) -> Instant {
+        Instant::now() + expires.saturating_sub(super::REFRESH_MARGIN.min(expires / 2))
+    }
+
+    pub fn format_duration(duration: Duration) -> String {
+        let secs = duration.as_secs();
+        format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caller_of_name_addr() {
+        let caller = Caller::of_header("\"Alice\" <sip:1001@pbx;transport=udp>;tag=a1");
+        assert_eq!(caller.uri, "sip:1001@pbx;transport=udp");
+        assert_eq!(caller.display_name.as_deref(), Some("Alice"));
+    }
+
+    #[test]
+    fn caller_of_unquoted_name() {
+        let caller = Caller::of_header("Bob Smith <sip:1002@pbx>");
+        assert_eq!(caller.uri, "sip:1002@pbx");
+        assert_eq!(caller.display_name.as_deref(), Some("Bob Smith"));
+    }
+
+    #[test]
+    fn caller_of_bare_uri() {
+        let caller = Caller::of_header("sip:1001@pbx;tag=a1");
+        assert_eq!(caller.uri, "sip:1001@pbx");
+        assert_eq!(caller.display_name, None);
+    }
+
+    #[test]
+    fn caller_of_empty_name() {
+        let caller = Caller::of_header("\"\" <sip:anonymous@anonymous.invalid>");
+        assert_eq!(caller.uri, "sip:anonymous@anonymous.invalid");
+        assert_eq!(caller.display_name, None);
+    }
+
+    #[test]
+    fn sip_uri_of_user() {
+        let registrar = HostPort::from(SocketAddr::from(([192, 168, 1, 1], 5060)));
+        let uri = misc::make_sip_uri("1002", &registrar).unwrap();
+        assert_eq!(format!("{uri:?}"), "sip:1002@192.168.1.1:5060");
+    }
+}
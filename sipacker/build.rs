@@ -0,0 +1,61 @@
+use std::{env, fs, path::PathBuf, process::Command};
+
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/sipacker.proto");
+        if let Err(err) = tonic_build::compile_protos("proto/sipacker.proto") {
+            panic!("Could not compile the protos (protoc is required): {err}");
+        }
+    }
+
+    // the git checkout and the lock file (it's not committed) may be missing, e.g. in the
+    // source archive: the missing file would rerun the script on every build
+    let workspace = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("..");
+    let lock = workspace.join("Cargo.lock");
+    for path in [
+        workspace.join(".git/HEAD"),
+        workspace.join(".git/refs"),
+        lock.clone(),
+    ] {
+        if path.exists() {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_owned())
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=SIPACKER_GIT_HASH={git_hash}");
+
+    let ezk_sip = fs::read_to_string(&lock)
+        .ok()
+        .and_then(|lock| locked_version(&lock, "ezk-sip"))
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=SIPACKER_EZK_SIP_VERSION={ezk_sip}");
+}
+
+fn locked_version(lock: &str, name: &str) -> Option<String> {
+    let package = lock.split("[[package]]").find(|package| {
+        package
+            .lines()
+            .any(|line| line == format!("name = \"{name}\""))
+    })?;
+    let field = |key: &str| {
+        package.lines().find_map(|line| {
+            line.strip_prefix(key)
+                .and_then(|value| value.strip_prefix(" = "))
+                .map(|value| value.trim_matches('"').to_owned())
+        })
+    };
+    let version = field("version")?;
+    let revision = field("source")
+        .and_then(|source| source.rsplit_once('#').map(|(_, hash)| hash.to_owned()))
+        .map(|hash| format!(" ({})", &hash[..hash.len().min(7)]));
+    Some(format!("{version}{}", revision.unwrap_or_default()))
+}
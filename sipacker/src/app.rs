@@ -1,4 +1,41 @@
+pub(crate) mod answering_machine;
 pub mod application;
 pub mod args;
+pub(crate) mod audit;
+pub(crate) mod capture;
+pub(crate) mod cdr;
 pub(crate) mod cli_input;
 pub(crate) mod command;
+pub(crate) mod completion;
+pub(crate) mod config;
+pub(crate) mod console;
+pub(crate) mod contacts;
+#[cfg(unix)]
+pub(crate) mod control;
+pub(crate) mod exec;
+pub mod exit;
+#[cfg(unix)]
+pub(crate) mod fifo;
+#[cfg(feature = "grpc")]
+pub(crate) mod grpc;
+#[cfg(feature = "http")]
+pub(crate) mod health;
+pub(crate) mod history;
+#[cfg(feature = "hooks")]
+pub(crate) mod hooks;
+#[cfg(feature = "http")]
+pub(crate) mod http;
+pub(crate) mod log_file;
+pub mod plugin;
+pub(crate) mod rpc;
+#[cfg(windows)]
+pub(crate) mod service;
+pub(crate) mod settings;
+pub(crate) mod sip_flow;
+pub(crate) mod sip_trace;
+pub(crate) mod state;
+pub(crate) mod systemd;
+#[cfg(feature = "tui")]
+pub(crate) mod tui;
+pub(crate) mod version;
+pub(crate) mod webhook;
@@ -0,0 +1,89 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub(crate) struct AnsweringMachine {
+    greeting: Option<PathBuf>,
+    record_dir: PathBuf,
+    record_duration: Duration,
+    state: State,
+}
+
+enum State {
+    Idle,
+    Answering,
+    Recording { deadline: Instant },
+}
+
+impl AnsweringMachine {
+    pub fn new(greeting: Option<PathBuf>, record_dir: PathBuf, record_duration: Duration) -> Self {
+        Self {
+            greeting,
+            record_dir,
+            record_duration,
+            state: State::Idle,
+        }
+    }
+
+    pub fn greeting(&self) -> Option<&PathBuf> {
+        self.greeting.as_ref()
+    }
+
+    pub fn record_dir(&self) -> &Path {
+        &self.record_dir
+    }
+
+    pub fn record_duration(&self) -> Duration {
+        self.record_duration
+    }
+
+    pub fn answer(&mut self) {
+        self.state = State::Answering;
+    }
+
+    pub fn is_answering(&self) -> bool {
+        matches!(self.state, State::Answering)
+    }
+
+    pub fn start_recording(&mut self) -> PathBuf {
+        self.state = State::Recording {
+            deadline: Instant::now() + self.record_duration,
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        self.record_dir.join(format!("voicemail_{timestamp}.wav"))
+    }
+
+    pub fn recording_deadline(&self) -> Option<Instant> {
+        match self.state {
+            State::Recording { deadline } => Some(deadline),
+            _ => None,
+        }
+    }
+
+    pub fn is_recording_expired(&self) -> bool {
+        match self.state {
+            State::Recording { deadline } => Instant::now() >= deadline,
+            _ => false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+    }
+}
+
+impl std::fmt::Display for AnsweringMachine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{greeting:{:?}; dir:{:?}; duration:{}s}}",
+            self.greeting,
+            self.record_dir,
+            self.record_duration.as_secs()
+        )
+    }
+}
@@ -1,45 +1,196 @@
+#[cfg(feature = "http")]
+use crate::app::health::{self, Health};
+#[cfg(feature = "hooks")]
+use crate::app::hooks::Hooks;
+#[cfg(feature = "webhooks")]
+use crate::app::webhook::Webhooks;
 use crate::app::{
+    answering_machine::AnsweringMachine,
     args::Args,
-    cli_input,
-    command::{Command, CommandTrait},
+    audit::AuditLog,
+    capture::PacketCapture,
+    cdr::CdrWriter,
+    cli_input::{self, Aliases, CommandLine, SharedInput},
+    command::{self, Command, CommandSource, CommandTrait, ReplyResult},
+    config::{Account, Config},
+    console::{Console, OutputFormat},
+    contacts::Contacts,
+    exec::{self, ExecEvent},
+    exit::{Categorize, ErrorCategory, Shutdown, StopSignal},
+    history::{CallHistory, CallResult, Direction},
+    plugin::{PluginApp, PluginCommand, Plugins},
+    settings::Settings,
+    sip_flow::SipFlow,
+    sip_trace::SipTrace,
+    state::{self, RuntimeState, SavedAnsweringMachine},
+    systemd::Notifier,
+    version,
+    webhook::WebhookEvent,
 };
-use crate::sipacker::{
-    audio::AudioSystem,
-    user_agent::{UserAgent, UserAgentEvent},
+use sipacker_core::{
+    audio::{AudioLevel, AudioSystem},
+    call::CallStatus,
+    capture::SipMessage,
+    dtmf,
+    invite::IncomingInvite,
+    loopback::Loopback,
+    mixer::AudioBus,
+    pipeline::LatencyBudget,
+    player::PlaybackMode,
+    presence::PresenceStatus,
+    recorder::RecordingMode,
+    tone::Tone,
+    user_agent::{CallId, SrtpPolicy, UserAgent, UserAgentEvent},
 };
 
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{IsTerminal, Write};
 use std::net::{Ipv4Addr, SocketAddr};
-use std::time::Duration;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use ezk_sip_auth::DigestCredentials;
 use ezk_sip_types::host::HostPort;
-use tokio::sync::mpsc;
+use serde_json::{json, Value};
+use tokio::{
+    select,
+    sync::{broadcast, mpsc, oneshot},
+    task::{JoinHandle, LocalSet},
+};
+use tokio_util::sync::CancellationToken;
+
+const TUI_LOG_FILE: &str = "sipacker.log";
+// guards against the script which sources itself
+const MAX_PENDING_COMMANDS: usize = 1000;
+const RINGING_ALERT_INTERVAL: Duration = Duration::from_secs(3);
+// the stuck SIP transactions don't hold the exit longer
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const METER_INTERVAL: Duration = Duration::from_millis(500);
+const API_EVENTS_CAPACITY: usize = 100;
+
+pub fn run_app(args: Args) -> Result<Shutdown> {
+    run_app_with_plugins(args, Plugins::default())
+}
 
-pub fn run_app(args: Args) -> Result<()> {
-    init_logging();
+pub fn run_app_with_plugins(args: Args, plugins: Plugins) -> Result<Shutdown> {
+    match args.service_action() {
+        Some(action) => run_service(action, args, plugins),
+        None => start_app(args, plugins),
+    }
+}
+
+pub(crate) fn start_app(args: Args, plugins: Plugins) -> Result<Shutdown> {
+    let config = Config::load(args.config.as_deref(), args.profile.as_deref())
+        .categorize(ErrorCategory::Config)?;
+    let args = args.merge(&config).categorize(ErrorCategory::Config)?;
+    let log_handles = init_logging(&args).categorize(ErrorCategory::Config)?;
     tracing::info!("Initializing the application...");
 
-    let rt = create_async_runtime(args.jobs)?;
+    let rt = create_async_runtime(args.jobs())?;
     tracing::info!("Async runtime is initialized");
-    rt.block_on(run_app_inner(args))?;
-
-    Ok(())
+    // the instances of the config run on the thread of the main user agent, the audio streams
+    // are not sent between the threads
+    rt.block_on(LocalSet::new().run_until(run_app_inner(args, config, log_handles, plugins)))
 }
 
-fn init_logging() {
-    use tracing_subscriber::{
-        filter::LevelFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
-    };
+fn init_logging(args: &Args) -> Result<LogHandles> {
+    use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, Layer, Registry};
 
-    let envfilter = EnvFilter::builder()
-        .with_default_directive(LevelFilter::INFO.into())
-        .with_env_var("RUST_LOG")
-        .from_env_lossy();
+    let (envfilter, sip_trace) = SipTrace::init()?;
+    // the instances share the capture and the flow like the logs
+    let sip_flow = SipFlow::default();
+    let capture = PacketCapture::default();
+    let console: Box<dyn Layer<Registry> + Send + Sync> = if args.is_service() {
+        event_log_layer()?
+    } else if args.tui {
+        // the terminal is owned by the TUI
+        let file = std::fs::File::create(TUI_LOG_FILE)?;
+        fmt::Layer::default()
+            .with_ansi(false)
+            .with_writer(std::sync::Mutex::new(file))
+            .boxed()
+    } else if args.output() == OutputFormat::Json {
+        // the stdout is parsed by the scripts
+        fmt::Layer::default()
+            .with_ansi(args.color())
+            .with_writer(std::io::stderr)
+            .boxed()
+    } else {
+        fmt::Layer::default().with_ansi(args.color()).boxed()
+    };
+    let log_file = args
+        .log_file()
+        .map(|log_file| log_file.layer())
+        .transpose()?;
     tracing_subscriber::registry()
-        .with(envfilter)
-        .with(fmt::Layer::default())
+        .with(console.with_filter(envfilter))
+        .with(log_file)
         .init();
+    Ok(LogHandles {
+        sip_trace,
+        capture,
+        sip_flow,
+    })
+}
+
+pub(super) struct LogHandles {
+    sip_trace: SipTrace,
+    capture: PacketCapture,
+    sip_flow: SipFlow,
+}
+
+impl LogHandles {
+    fn share(&self) -> Self {
+        Self {
+            sip_trace: self.sip_trace.clone(),
+            capture: self.capture.share(),
+            sip_flow: self.sip_flow.clone(),
+        }
+    }
+}
+
+struct Instance {
+    name: String,
+    commands: mpsc::Sender<(CommandSource, Command)>,
+    task: JoinHandle<Result<Shutdown>>,
+}
+
+#[cfg(windows)]
+fn run_service(
+    action: crate::app::args::ServiceAction,
+    args: Args,
+    plugins: Plugins,
+) -> Result<Shutdown> {
+    crate::app::service::run(action, args, plugins)
+}
+
+#[cfg(not(windows))]
+fn run_service(
+    _action: crate::app::args::ServiceAction,
+    _args: Args,
+    _plugins: Plugins,
+) -> Result<Shutdown> {
+    Err(anyhow::Error::msg("The service mode requires Windows"))
+}
+
+#[cfg(windows)]
+fn event_log_layer<S>() -> Result<Box<dyn tracing_subscriber::Layer<S> + Send + Sync>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use tracing_subscriber::{fmt, Layer};
+
+    let event_log = crate::app::service::EventLog::register()?;
+    Ok(fmt::Layer::default()
+        .with_ansi(false)
+        .without_time()
+        .with_writer(event_log)
+        .boxed())
+}
+
+#[cfg(not(windows))]
+fn event_log_layer<S>() -> Result<Box<dyn tracing_subscriber::Layer<S> + Send + Sync>> {
+    Err(anyhow::Error::msg("The Event Log requires Windows"))
 }
 
 fn create_async_runtime(threads_count: usize) -> std::io::Result<tokio::runtime::Runtime> {
@@ -50,108 +201,1600 @@ fn create_async_runtime(threads_count: usize) -> std::io::Result<tokio::runtime:
         .build()
 }
 
-async fn run_app_inner(args: Args) -> Result<()> {
-    let ua_ip: Ipv4Addr = args.ip_addr;
-    let ua_port = args.port;
+async fn run_app_inner(
+    args: Args,
+    config: Config,
+    log_handles: LogHandles,
+    plugins: Plugins,
+) -> Result<Shutdown> {
+    let ua_ip: Ipv4Addr = args.ip_addr().categorize(ErrorCategory::Config)?;
+    let ua_port = args.port();
+
+    let settings = Settings::load();
+    let input = SharedInput {
+        aliases: Aliases::new(settings.aliases.clone()),
+        plugins,
+        ..Default::default()
+    };
+    // the TUI owns the terminal, the password can't be asked there and in the daemon
+    let password_prompt =
+        !args.tui && args.control_socket().is_none() && std::io::stdin().is_terminal();
+    let command_line = CommandLine::new(input.aliases.clone(), &input.plugins, password_prompt);
+    let input_shutdown = CancellationToken::new();
+    let (command_receiver, console, tui_thread) = if args.is_service() {
+        let (_, command_receiver) = mpsc::channel(1);
+        (
+            (CommandSource::Control, command_receiver),
+            service_console(&args),
+            None,
+        )
+    } else if let Some(path) = args.control_socket() {
+        let (command_receiver, console) =
+            run_control_socket(input_shutdown.clone(), command_line, path, args.output())?;
+        ((CommandSource::Control, command_receiver), console, None)
+    } else if args.tui {
+        let (command_receiver, console, thread) =
+            run_tui(input_shutdown.clone(), command_line, args.color())?;
+        (
+            (CommandSource::Tui, command_receiver),
+            console,
+            Some(thread),
+        )
+    } else {
+        let show_prompt = args.output() == OutputFormat::Text && std::io::stdin().is_terminal();
+        let console = Console::stdout(args.color(), args.output());
+        let command_receiver = cli_input::run_input_system(
+            input_shutdown.clone(),
+            command_line,
+            input.clone(),
+            console.clone(),
+            show_prompt,
+        );
+        ((CommandSource::Console, command_receiver), console, None)
+    };
+
+    let mut receivers = vec![command_receiver];
+    if let Some(path) = args.command_fifo.as_deref() {
+        let command_line = CommandLine::new(input.aliases.clone(), &input.plugins, false);
+        let command_receiver = run_command_fifo(input_shutdown.clone(), command_line, path)
+            .categorize(ErrorCategory::Config)?;
+        receivers.push((CommandSource::Fifo, command_receiver));
+    }
+    if let Some(addr) = args.http_api {
+        let command_line = CommandLine::new(input.aliases.clone(), &input.plugins, false);
+        let command_receiver = run_http_api(addr, command_line, input_shutdown.clone()).await?;
+        receivers.push((CommandSource::Http, command_receiver));
+    }
+    let console = match args.grpc_api {
+        Some(addr) => {
+            let (events, _) = broadcast::channel(API_EVENTS_CAPACITY);
+            let command_line = CommandLine::new(input.aliases.clone(), &input.plugins, false);
+            let command_receiver =
+                run_grpc_api(addr, command_line, events.clone(), input_shutdown.clone()).await?;
+            receivers.push((CommandSource::Grpc, command_receiver));
+            console.with_events(events)
+        }
+        None => console,
+    };
+    #[cfg(feature = "http")]
+    let health = match args.health_api {
+        Some(addr) => Some(health::run_health_api(addr, input_shutdown.clone()).await?),
+        None => None,
+    };
+    let command_receiver = merge_commands(receivers);
+
+    let instances = start_instances(&args, &config, &console, &log_handles, &input).await?;
+    let instance_commands = instances
+        .iter()
+        .map(|instance| (instance.name.clone(), instance.commands.clone()))
+        .collect();
+    let app = App::build(
+        (ua_ip, ua_port).into(),
+        args,
+        config,
+        settings,
+        console,
+        log_handles,
+        input,
+    );
+    let result = match app.await {
+        Ok(app) => {
+            #[cfg(feature = "http")]
+            let app = app.with_health(health);
+            app.with_instances(instance_commands)
+                .run(command_receiver)
+                .await
+        }
+        Err(err) => Err(err),
+    };
+    stop_instances(instances).await;
+    input_shutdown.cancel();
+    if let Some(thread) = tui_thread {
+        // the terminal is restored by the TUI thread
+        let _ = tokio::task::spawn_blocking(move || thread.join()).await;
+    }
+    result
+}
+
+async fn start_instances(
+    args: &Args,
+    config: &Config,
+    console: &Console,
+    log_handles: &LogHandles,
+    input: &SharedInput,
+) -> Result<Vec<Instance>> {
+    let mut instances = Vec::new();
+    for name in config.instances.keys() {
+        let config = config.instance(name).categorize(ErrorCategory::Config)?;
+        let args = args
+            .for_instance(name, &config)
+            .categorize(ErrorCategory::Config)?;
+        let ua_ip: Ipv4Addr = args.ip_addr().categorize(ErrorCategory::Config)?;
+        let ua_port = args.port();
+        let input = SharedInput {
+            aliases: input.aliases.clone(),
+            plugins: input.plugins.clone(),
+            ..Default::default()
+        };
+        let app = App::build(
+            (ua_ip, ua_port).into(),
+            args,
+            config,
+            Settings::load(),
+            console.clone().with_instance(name),
+            log_handles.share(),
+            input,
+        )
+        .await
+        .inspect_err(|err| tracing::error!("Instance {name} err: {err}"))?;
+        let (commands, command_receiver) = mpsc::channel(20);
+        let task = tokio::task::spawn_local(async move {
+            let mut app = app;
+            app.run(command_receiver).await
+        });
+        tracing::info!("Instance {name} is listening on {ua_ip}:{ua_port}");
+        instances.push(Instance {
+            name: name.clone(),
+            commands,
+            task,
+        });
+    }
+    Ok(instances)
+}
+
+async fn stop_instances(instances: Vec<Instance>) {
+    for instance in instances {
+        let command = command::StopApp::new(true).into();
+        // the instance may have quit already by the stop signal or by its own quit
+        let _ = instance
+            .commands
+            .send((CommandSource::Signal, command))
+            .await;
+        match instance.task.await {
+            Ok(Ok(_)) => tracing::info!("Instance {} is stopped", instance.name),
+            Ok(Err(err)) => tracing::error!("Instance {} err: {err}", instance.name),
+            Err(err) => tracing::error!("Instance {} err: {err}", instance.name),
+        }
+    }
+}
+
+fn merge_commands(
+    receivers: Vec<(CommandSource, mpsc::Receiver<Command>)>,
+) -> mpsc::Receiver<(CommandSource, Command)> {
+    let (sender, receiver) = mpsc::channel(20);
+    for (source, mut commands) in receivers {
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            while let Some(command) = commands.recv().await {
+                if sender.send((source, command)).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    receiver
+}
+
+#[cfg(unix)]
+fn run_control_socket(
+    shutdown: CancellationToken,
+    command_line: CommandLine,
+    path: &Path,
+    format: OutputFormat,
+) -> Result<(mpsc::Receiver<Command>, Console)> {
+    crate::app::control::run_control_socket(shutdown, command_line, path, format)
+}
+
+#[cfg(not(unix))]
+fn run_control_socket(
+    _shutdown: CancellationToken,
+    _command_line: CommandLine,
+    _path: &Path,
+    _format: OutputFormat,
+) -> Result<(mpsc::Receiver<Command>, Console)> {
+    Err(anyhow::Error::msg(
+        "The daemon mode requires the Unix domain sockets",
+    ))
+}
+
+#[cfg(unix)]
+fn run_command_fifo(
+    shutdown: CancellationToken,
+    command_line: CommandLine,
+    path: &Path,
+) -> Result<mpsc::Receiver<Command>> {
+    crate::app::fifo::run_command_fifo(shutdown, command_line, path)
+}
+
+#[cfg(not(unix))]
+fn run_command_fifo(
+    _shutdown: CancellationToken,
+    _command_line: CommandLine,
+    _path: &Path,
+) -> Result<mpsc::Receiver<Command>> {
+    Err(anyhow::Error::msg(
+        "The command FIFO requires the named pipes of Unix",
+    ))
+}
+
+#[cfg(windows)]
+fn service_console(args: &Args) -> Console {
+    crate::app::service::console(args)
+}
+
+#[cfg(not(windows))]
+fn service_console(args: &Args) -> Console {
+    Console::stdout(false, args.output())
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(
+    shutdown: CancellationToken,
+    command_line: CommandLine,
+    color: bool,
+) -> Result<(
+    mpsc::Receiver<Command>,
+    Console,
+    std::thread::JoinHandle<()>,
+)> {
+    Ok(crate::app::tui::run_tui(shutdown, command_line, color))
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui(
+    _shutdown: CancellationToken,
+    _command_line: CommandLine,
+    _color: bool,
+) -> Result<(
+    mpsc::Receiver<Command>,
+    Console,
+    std::thread::JoinHandle<()>,
+)> {
+    Err(crate::app::args::feature_disabled("The TUI", "tui"))
+}
+
+#[cfg(feature = "http")]
+async fn run_http_api(
+    addr: SocketAddr,
+    command_line: CommandLine,
+    shutdown: CancellationToken,
+) -> Result<mpsc::Receiver<Command>> {
+    crate::app::http::run_http_api(addr, command_line, shutdown).await
+}
+
+#[cfg(not(feature = "http"))]
+async fn run_http_api(
+    _addr: SocketAddr,
+    _command_line: CommandLine,
+    _shutdown: CancellationToken,
+) -> Result<mpsc::Receiver<Command>> {
+    Err(crate::app::args::feature_disabled("The HTTP API", "http"))
+}
+
+#[cfg(feature = "grpc")]
+async fn run_grpc_api(
+    addr: SocketAddr,
+    command_line: CommandLine,
+    events: broadcast::Sender<Value>,
+    shutdown: CancellationToken,
+) -> Result<mpsc::Receiver<Command>> {
+    crate::app::grpc::run_grpc_api(addr, command_line, events, shutdown).await
+}
+
+#[cfg(not(feature = "grpc"))]
+async fn run_grpc_api(
+    _addr: SocketAddr,
+    _command_line: CommandLine,
+    _events: broadcast::Sender<Value>,
+    _shutdown: CancellationToken,
+) -> Result<mpsc::Receiver<Command>> {
+    Err(crate::app::args::feature_disabled("The gRPC API", "grpc"))
+}
+
+async fn log_ua_events(mut events: broadcast::Receiver<UserAgentEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(event) => tracing::debug!("UA event: {event:?}"),
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                tracing::debug!("{count} UA events are not logged");
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+async fn record_sip_messages(
+    mut messages: broadcast::Receiver<SipMessage>,
+    capture: PacketCapture,
+    sip_flow: SipFlow,
+) {
+    loop {
+        match messages.recv().await {
+            Ok(message) => {
+                capture.write_sip(&message);
+                sip_flow.push(&message);
+            }
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                tracing::warn!("{count} SIP messages are not captured");
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+// SIGINT and SIGTERM (Ctrl-C on the other platforms) quit like "quit force", the second one
+// exits at once
+fn listen_stop_signals() -> mpsc::Receiver<StopSignal> {
+    let (sender, receiver) = mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut stop_signals = match StopSignals::new() {
+            Ok(stop_signals) => stop_signals,
+            Err(err) => {
+                tracing::warn!("Could not listen to the stop signals: {err}");
+                return;
+            }
+        };
+        let signal = stop_signals.recv().await;
+        tracing::info!("{signal} is received, quitting");
+        if sender.send(signal).await.is_err() {
+            return;
+        }
+        let signal = stop_signals.recv().await;
+        tracing::warn!("{signal} is received again, exiting without the shutdown");
+        std::process::exit(signal.exit_code().into());
+    });
+    receiver
+}
+
+#[cfg(unix)]
+struct StopSignals {
+    interrupt: tokio::signal::unix::Signal,
+    terminate: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl StopSignals {
+    fn new() -> std::io::Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+
+    async fn recv(&mut self) -> StopSignal {
+        select! {
+            _ = self.interrupt.recv() => StopSignal::Interrupt,
+            _ = self.terminate.recv() => StopSignal::Terminate,
+        }
+    }
+}
 
-    let command_receiver = cli_input::run_input_system();
+#[cfg(not(unix))]
+struct StopSignals;
 
-    let mut app = App::build((ua_ip, ua_port).into()).await?;
-    app.run(command_receiver).await
+#[cfg(not(unix))]
+impl StopSignals {
+    fn new() -> std::io::Result<Self> {
+        Ok(Self)
+    }
+
+    // Ctrl-C is reported as SIGINT, the stop of the Windows service as SIGTERM
+    async fn recv(&mut self) -> StopSignal {
+        let ctrl_c = async {
+            if let Err(err) = tokio::signal::ctrl_c().await {
+                tracing::warn!("Could not listen to Ctrl-C: {err}");
+                std::future::pending::<()>().await;
+            }
+        };
+        #[cfg(windows)]
+        let service_stop = crate::app::service::stop_requested();
+        #[cfg(not(windows))]
+        let service_stop = std::future::pending::<()>();
+        select! {
+            _ = ctrl_c => StopSignal::Interrupt,
+            _ = service_stop => StopSignal::Terminate,
+        }
+    }
+}
+
+fn listen_reload_signal() -> mpsc::Receiver<Command> {
+    let (sender, receiver) = mpsc::channel(1);
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(err) => {
+                tracing::warn!("Could not listen to SIGHUP: {err}");
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            tracing::info!("SIGHUP is received, reloading the config");
+            if sender.send(command::Reload::new().into()).await.is_err() {
+                break;
+            }
+        }
+    });
+    #[cfg(not(unix))]
+    drop(sender);
+    receiver
 }
 
 pub(crate) struct App {
     stop_app: bool,
+    stop_signal: Option<StopSignal>,
+    failure: Option<anyhow::Error>,
     user_agent: UserAgent,
+    ua_events: broadcast::Receiver<UserAgentEvent>,
     audio_system: AudioSystem,
+    answering_machine: Option<AnsweringMachine>,
+    dialing: bool,
+    ringing: Option<(String, Instant)>,
+    call_context: Option<(CallId, String)>,
+    incoming_invite: Option<(CallId, IncomingInvite)>,
+    muted: bool,
+    meter: Option<Instant>,
+    loopback: Option<Loopback>,
+    audio_bus: Option<AudioBus>,
+    settings: Settings,
+    contacts: Contacts,
+    history: CallHistory,
+    console: Console,
+    sip_trace: SipTrace,
+    capture: PacketCapture,
+    sip_flow: SipFlow,
+    input: SharedInput,
+    command_line: CommandLine,
+    pending_commands: VecDeque<(CommandSource, Command)>,
+    pending_confirmation: Option<Command>,
+    systemd: Option<Notifier>,
+    audit_log: Option<AuditLog>,
+    cdr: Option<CdrWriter>,
+    #[cfg(feature = "hooks")]
+    hooks: Option<Hooks>,
+    #[cfg(feature = "webhooks")]
+    webhooks: Option<Webhooks>,
+    #[cfg(feature = "http")]
+    health: Option<Health>,
+    state: Option<RuntimeState>,
+    instances: BTreeMap<String, mpsc::Sender<(CommandSource, Command)>>,
+    args: Args,
+    config: Config,
 }
 
 impl App {
-    pub(super) async fn build(ua_socketaddr: SocketAddr) -> Result<Self> {
-        let user_agent = UserAgent::build(ua_socketaddr).await?;
+    pub(super) async fn build(
+        ua_socketaddr: SocketAddr,
+        args: Args,
+        config: Config,
+        settings: Settings,
+        console: Console,
+        log_handles: LogHandles,
+        input: SharedInput,
+    ) -> Result<Self> {
+        let latency = LatencyBudget::new(Duration::from_millis(settings.latency_ms), args.ptime());
+        tracing::info!("Latency budget: {latency:?}");
+        let mut user_agent = UserAgent::build(ua_socketaddr, latency).await?;
+        user_agent.set_call_timeout(args.call_timeout(&config));
+        let ua_events = user_agent.subscribe();
+        tokio::spawn(log_ua_events(user_agent.subscribe()));
+        tokio::spawn(record_sip_messages(
+            user_agent.subscribe_sip_messages(),
+            log_handles.capture.share(),
+            log_handles.sip_flow.clone(),
+        ));
         tracing::info!("User agent is initialized");
-        let audio_system = AudioSystem::build()?;
+        let mut audio_system =
+            AudioSystem::build(&args.audio_config()).categorize(ErrorCategory::Audio)?;
+        audio_system.set_latency(&latency);
+        audio_system.set_input_volume(settings.input_volume);
+        audio_system.set_output_volume(settings.output_volume);
         tracing::info!("Audio system is initialized");
-        Ok(Self {
+        let command_line = CommandLine::new(input.aliases.clone(), &input.plugins, false);
+        let script = args.script.clone();
+        let audit_log = args
+            .audit_log
+            .as_deref()
+            .map(AuditLog::open)
+            .transpose()
+            .categorize(ErrorCategory::Config)?;
+        let cdr = args
+            .cdr
+            .as_deref()
+            .map(CdrWriter::open)
+            .transpose()
+            .categorize(ErrorCategory::Config)?;
+        #[cfg(feature = "hooks")]
+        let hooks = args
+            .hooks
+            .as_deref()
+            .map(Hooks::load)
+            .transpose()
+            .categorize(ErrorCategory::Config)?;
+        #[cfg(feature = "webhooks")]
+        let webhooks = args.webhooks(&config).categorize(ErrorCategory::Config)?;
+        #[cfg(feature = "webhooks")]
+        let webhooks = (!webhooks.is_empty())
+            .then(|| Webhooks::new(webhooks))
+            .transpose()?;
+        let mut app = Self {
             stop_app: false,
+            stop_signal: None,
+            failure: None,
             user_agent,
+            ua_events,
             audio_system,
-        })
+            answering_machine: None,
+            dialing: false,
+            ringing: None,
+            call_context: None,
+            incoming_invite: None,
+            muted: false,
+            meter: None,
+            loopback: None,
+            audio_bus: None,
+            settings,
+            contacts: Contacts::load(),
+            history: CallHistory::load(),
+            console,
+            sip_trace: log_handles.sip_trace,
+            capture: log_handles.capture,
+            sip_flow: log_handles.sip_flow,
+            input,
+            command_line,
+            pending_commands: VecDeque::new(),
+            pending_confirmation: None,
+            systemd: args.control_socket().and_then(|_| Notifier::from_env()),
+            audit_log,
+            cdr,
+            #[cfg(feature = "hooks")]
+            hooks,
+            #[cfg(feature = "webhooks")]
+            webhooks,
+            #[cfg(feature = "http")]
+            health: None,
+            state: args.persist_state.then(RuntimeState::load),
+            instances: BTreeMap::new(),
+            args,
+            config,
+        };
+        app.update_completions();
+        app.answering_machine = app
+            .state
+            .as_ref()
+            .and_then(|state| state.answering_machine.as_ref())
+            .map(SavedAnsweringMachine::restore);
+        if let Some(script) = script {
+            app.source_script(&script)
+                .categorize(ErrorCategory::Config)?;
+        }
+        app.register_account().categorize(ErrorCategory::Config)?;
+        Ok(app)
+    }
+
+    #[cfg(feature = "http")]
+    fn with_health(mut self, health: Option<Health>) -> Self {
+        self.health = health;
+        self
+    }
+
+    fn with_instances(
+        mut self,
+        instances: BTreeMap<String, mpsc::Sender<(CommandSource, Command)>>,
+    ) -> Self {
+        self.instances = instances;
+        self
     }
 
     pub(super) async fn run(
         &mut self,
-        mut command_receiver: mpsc::Receiver<Command>,
-    ) -> Result<()> {
+        mut command_receiver: mpsc::Receiver<(CommandSource, Command)>,
+    ) -> Result<Shutdown> {
         tracing::info!("The application is running");
-        println!("The application is running");
+        self.console.print("The application is running");
+        let mut reload_signal = listen_reload_signal();
+        let mut stop_signals = listen_stop_signals();
+        let saved_account = self.state.as_ref().and_then(|state| state.account.as_ref());
+        if self.config.accounts.is_empty() && saved_account.is_none() {
+            self.notify_systemd(Notifier::ready);
+        }
+        self.notify_systemd_status();
         while !self.stop_app {
-            self.update_user_agent().await;
-            if let Ok(command) = command_receiver.try_recv() {
-                self.execute_command(command).await;
+            let timer = self.next_timer();
+            select! {
+                update = self.user_agent.next_update() => {
+                    if let Err(err) = self.user_agent.handle(update).await {
+                        tracing::error!("User agent updating err: {err}");
+                    }
+                }
+                event = self.ua_events.recv() => match event {
+                    Ok(event) => self.handle_ua_event(event).await,
+                    Err(err) => tracing::warn!("UA events err: {err}"),
+                },
+                _ = std::future::ready(()), if !self.pending_commands.is_empty() => {
+                    if let Some((source, command)) = self.pending_commands.pop_front() {
+                        self.execute_command(source, command).await;
+                    }
+                }
+                Some((source, command)) = command_receiver.recv(), if self.pending_commands.is_empty() => {
+                    self.execute_command(source, command).await;
+                }
+                Some(command) = reload_signal.recv() => {
+                    self.execute_command(CommandSource::Signal, command).await;
+                }
+                Some(signal) = stop_signals.recv() => {
+                    self.stop_signal = Some(signal);
+                    let command = command::StopApp::new(true).into();
+                    self.execute_command(CommandSource::Signal, command).await;
+                }
+                _ = tokio::time::sleep_until(timer.unwrap_or_else(Instant::now).into()),
+                    if timer.is_some() =>
+                {
+                    self.update_answering_machine().await;
+                    self.update_meter().await;
+                    self.update_ringing();
+                    self.update_health();
+                    if let Some(systemd) = self.systemd.as_mut() {
+                        systemd.ping_watchdog();
+                    }
+                }
             }
-            tokio::time::sleep(Duration::from_millis(100)).await;
         }
-        Ok(())
+        self.shutdown().await;
+        match self.failure.take() {
+            Some(err) => Err(err),
+            None => Ok(self.stop_signal.map_or(Shutdown::Quit, Shutdown::Signal)),
+        }
+    }
+
+    async fn shutdown(&mut self) {
+        tracing::info!("Shutting down the application");
+        self.notify_systemd(Notifier::stopping);
+        let result = tokio::time::timeout(SHUTDOWN_TIMEOUT, async {
+            if self.user_agent.has_active_call() {
+                if let Err(err) = self.user_agent.terminate_call().await {
+                    tracing::warn!("Terminating the call err: {err}");
+                }
+            }
+            if self.user_agent.is_registered() {
+                self.user_agent.unregister_and_wait().await;
+            }
+            while let Ok(event) = self.ua_events.try_recv() {
+                self.handle_ua_event(event).await;
+            }
+        })
+        .await;
+        if result.is_err() {
+            tracing::warn!("The shutdown is timed out after {SHUTDOWN_TIMEOUT:?}");
+        }
+        self.stop_audio_bus();
+        if self.args.instance.is_none() {
+            if let Err(err) = self.history.save() {
+                tracing::warn!("Could not save call history: {err}");
+            }
+        }
+        let _ = std::io::stdout().flush();
+    }
+
+    fn next_timer(&self) -> Option<Instant> {
+        let ringing = self
+            .ringing
+            .as_ref()
+            .map(|(_, last_alert)| *last_alert + RINGING_ALERT_INTERVAL);
+        let meter = self.meter.map(|last_update| last_update + METER_INTERVAL);
+        let voicemail = self
+            .answering_machine
+            .as_ref()
+            .and_then(AnsweringMachine::recording_deadline);
+        let watchdog = self.systemd.as_ref().and_then(Notifier::next_ping);
+        #[cfg(feature = "http")]
+        let heartbeat = self.health.as_ref().map(Health::next_beat);
+        #[cfg(not(feature = "http"))]
+        let heartbeat = None;
+        [ringing, meter, voicemail, watchdog, heartbeat]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    #[cfg(feature = "http")]
+    fn update_health(&self) {
+        if let Some(health) = &self.health {
+            health.beat(self.registration(), self.audio_system.fault());
+        }
+    }
+
+    #[cfg(not(feature = "http"))]
+    fn update_health(&self) {}
+
+    fn notify_systemd(&self, notify: fn(&Notifier)) {
+        if let Some(systemd) = &self.systemd {
+            notify(systemd);
+        }
+    }
+
+    fn notify_systemd_status(&self) {
+        let Some(systemd) = &self.systemd else {
+            return;
+        };
+        let registration = match self.user_agent.status().registration {
+            Some(registration) => format!(
+                "registered as {}@{}",
+                registration.user_name, registration.registrar_host
+            ),
+            None => "not registered".to_owned(),
+        };
+        let call = match &self.call_context {
+            Some((call_id, remote)) => format!("call #{call_id} with {remote}"),
+            None => "no call".to_owned(),
+        };
+        systemd.status(&format!("{registration}, {call}"));
     }
 
-    async fn execute_command(&mut self, command: Command) {
+    async fn execute_command(&mut self, source: CommandSource, command: Command) {
+        let (command, reply) = match command {
+            Command::Reply(reply) => {
+                let (command, sender) = reply.into_parts();
+                (command, Some(sender))
+            }
+            command => (command, None),
+        };
+        let command = match command {
+            Command::ForInstance(forward) => {
+                return self.forward_command(source, forward, reply).await
+            }
+            command => command,
+        };
         tracing::info!("Executing the command: {}", command);
-        let _ = command
-            .execute(self)
-            .await
-            .inspect_err(|err| tracing::warn!("Command execution err: {err}"));
+        let name = command.to_string();
+        let category = ErrorCategory::of_command(&command);
+        let command = match reply {
+            Some(_) => Some(command),
+            None => self.confirm(command),
+        };
+        let result = match command {
+            Some(command) => command.execute(self).await,
+            None => Ok(()),
+        }
+        .inspect_err(|err| tracing::warn!("Command execution err: {err}"));
+        self.audit(source, &name, &result);
+        self.console.print_command_result(&name, &result);
+        if let Some(reply) = reply {
+            let _ = reply.send(
+                result
+                    .as_ref()
+                    .map(|()| self.api_state())
+                    .map_err(|err| err.to_string()),
+            );
+        }
+        // the wrapper scripts branch on the exit code, the interactive inputs keep going
+        if let Err(err) = result {
+            if matches!(source, CommandSource::Script | CommandSource::Config) {
+                self.fail(match category {
+                    Some(category) => category.wrap(err),
+                    None => err,
+                });
+            }
+        }
+    }
+
+    async fn forward_command(
+        &mut self,
+        source: CommandSource,
+        forward: command::ForInstance,
+        reply: Option<oneshot::Sender<ReplyResult>>,
+    ) {
+        let name = forward.to_string();
+        let (instance, command) = forward.into_parts();
+        let command = match reply {
+            Some(reply) => command::Reply::new(command, reply).into(),
+            None => command,
+        };
+        let (command, err) = match self.instances.get(&instance) {
+            Some(sender) => match sender.send((source, command)).await {
+                Ok(()) => return,
+                Err(mpsc::error::SendError((_, command))) => {
+                    (command, format!("The instance {instance} is stopped"))
+                }
+            },
+            None => (command, format!("Unknown instance: {instance}")),
+        };
+        self.console
+            .print_command_result(&name, &Err(anyhow::Error::msg(err.clone())));
+        if let Command::Reply(reply) = command {
+            let (_, sender) = reply.into_parts();
+            let _ = sender.send(Err(err));
+        }
+    }
+
+    fn fail(&mut self, err: anyhow::Error) {
+        if !self.args.exit_on_failure || self.failure.is_some() {
+            return;
+        }
+        tracing::error!("Exiting on the failure: {err}");
+        self.failure = Some(err);
+        self.stop_app = true;
+    }
+
+    pub(crate) fn run_plugin(&mut self, command: Box<dyn PluginCommand>) -> Result<()> {
+        command.execute(&mut PluginApp::new(self))
+    }
+
+    pub(crate) fn print(&self, text: &str) {
+        self.console.print(text);
+    }
+
+    pub(crate) fn registration(&self) -> Option<String> {
+        self.user_agent
+            .status()
+            .registration
+            .map(|reg| format!("{}@{}", reg.user_name, reg.registrar_host))
+    }
+
+    pub(crate) fn call(&self) -> Option<(CallId, String)> {
+        self.call_context.clone()
+    }
+
+    pub(crate) fn queue_command(&mut self, source: CommandSource, line: &str) -> Result<()> {
+        let command = self.command_line.parse(line)?;
+        if self.pending_commands.len() >= MAX_PENDING_COMMANDS {
+            return Err(anyhow::Error::msg("Too many pending commands"));
+        }
+        self.pending_commands.push_back((source, command));
+        Ok(())
+    }
+
+    fn audit(&mut self, source: CommandSource, command: &str, result: &Result<()>) {
+        let account = self.registration();
+        if let Some(audit_log) = self.audit_log.as_mut() {
+            audit_log.record(source, account, command, result);
+        }
     }
 
-    async fn update_user_agent(&mut self) {
-        let result = self.user_agent.run().await;
-        match result {
-            Ok(event) => {
-                if let Some(event) = event {
-                    self.handle_ua_event(event);
+    fn api_state(&self) -> Value {
+        let status = self.user_agent.status();
+        let registration = status.registration.map(|reg| {
+            json!({
+                "user": reg.user_name,
+                "registrar": reg.registrar_host,
+                "srtp": reg.srtp.to_string(),
+                "duration_secs": reg.duration.as_secs(),
+            })
+        });
+        let call = status
+            .call
+            .zip(self.call_context.as_ref())
+            .map(|(call, (id, remote))| {
+                let (state, duration) = match call {
+                    CallStatus::Incoming(duration) => ("incoming", duration),
+                    CallStatus::Outgoing(duration) => ("calling", duration),
+                    CallStatus::Established(duration) => ("established", duration),
+                };
+                json!({
+                    "id": id,
+                    "remote": remote,
+                    "state": state,
+                    "duration_secs": duration.as_secs(),
+                })
+            });
+        json!({
+            "registration": registration,
+            "presence": status.presence.map(|presence| presence.to_string()),
+            "lines": status
+                .lines
+                .iter()
+                .map(|(extension, state)| {
+                    let state = state.map(|state| state.to_string());
+                    (extension.clone(), state.into())
+                })
+                .collect::<serde_json::Map<_, _>>(),
+            "voicemail": status
+                .voicemail
+                .map(|summary| json!({ "new": summary.new, "old": summary.old })),
+            "call": call,
+            "muted": self.muted,
+        })
+    }
+
+    fn confirm(&mut self, command: Command) -> Option<Command> {
+        let pending = self.pending_confirmation.take();
+        if let Command::Confirm(confirm) = &command {
+            return match pending {
+                Some(pending) if confirm.is_accepted() => Some(pending),
+                Some(pending) => {
+                    self.console.print(format!("\"{pending}\" is cancelled"));
+                    None
                 }
+                None => Some(command),
+            };
+        }
+        if let Some(pending) = pending {
+            self.console.print(format!("\"{pending}\" is cancelled"));
+        }
+        if !self.config.ui.confirm || !self.user_agent.has_active_call() {
+            return Some(command);
+        }
+
+        let (question, command) = match command {
+            Command::TerminateCall(_) => ("Terminate the active call?", command),
+            Command::Unregister(_) => ("Unregister during the active call?", command),
+            Command::StopApp(stop) if !stop.is_forced() => (
+                "Terminate the active call and quit?",
+                command::StopApp::new(true).into(),
+            ),
+            command => return Some(command),
+        };
+        self.console.print(format!(
+            "{question} Type \"yes\" to confirm, \"no\" to cancel"
+        ));
+        self.pending_confirmation = Some(command);
+        None
+    }
+
+    async fn handle_ua_event(&mut self, event: UserAgentEvent) {
+        let event = self.read_incoming_invite(event);
+        self.update_health();
+        if let UserAgentEvent::IncomingCall {
+            call_id, caller, ..
+        } = &event
+        {
+            let remote = self.describe_remote(caller.uri.clone());
+            let mut fields = json!({
+                "call_id": call_id,
+                "caller": caller.uri,
+                "display_name": caller.display_name,
+            });
+            if let (Some(fields), Value::Object(invite)) =
+                (fields.as_object_mut(), self.incoming_invite_fields())
+            {
+                fields.extend(invite);
             }
-            Err(err) => {
-                tracing::error!("User agent updating err: {err}");
+            self.notify_webhooks(
+                WebhookEvent::IncomingCall,
+                format!("Incoming call from {remote}{}", self.invite_note()),
+                fields,
+            );
+            self.start_call_context(*call_id, remote);
+        }
+        self.print_ua_event(&event);
+        self.run_exec_event(&event);
+        if let UserAgentEvent::CallFailed { reason, .. } = &event {
+            self.fail(ErrorCategory::Call.wrap(anyhow::Error::msg(reason.clone())));
+        }
+        self.update_prompt(&event);
+        self.update_history(&event);
+        self.update_cdr(&event);
+        self.update_ringing_state(&event);
+        if let UserAgentEvent::CallTerminated { .. } = event {
+            self.stop_audio_bus();
+            self.call_context = None;
+            self.incoming_invite = None;
+            if self.muted {
+                self.muted = false;
+                self.apply_input_volume();
+            }
+        }
+        self.update_call_tones(&event);
+        if let Err(err) = self.run_answering_machine(&event).await {
+            tracing::warn!("Answering machine err: {err}");
+        }
+        if let Err(err) = self.run_intercom(&event).await {
+            tracing::warn!("Intercom err: {err}");
+        }
+        self.run_hooks(&event);
+        match event {
+            UserAgentEvent::AudioLevel(_) => {}
+            UserAgentEvent::Registered { .. } => {
+                self.notify_systemd(Notifier::ready);
+                self.notify_systemd_status();
             }
+            _ => self.notify_systemd_status(),
         }
     }
 
-    fn handle_ua_event(&mut self, event: UserAgentEvent) {
-        tracing::debug!("Handling UA event: {:?}", event);
-        Self::print_ua_event(&event);
-        if let UserAgentEvent::CallTerminated = event {
-            self.audio_system.destroy_input_stream();
-            self.audio_system.destroy_output_stream();
+    fn read_incoming_invite(&mut self, event: UserAgentEvent) -> UserAgentEvent {
+        let UserAgentEvent::IncomingCall {
+            call_id,
+            caller,
+            invite,
+        } = event
+        else {
+            return event;
+        };
+        let caller = match invite.asserted.clone() {
+            Some(asserted) => {
+                tracing::info!("The caller {} is asserted for {}", asserted.uri, caller.uri);
+                asserted
+            }
+            None => caller,
+        };
+        self.incoming_invite = Some((call_id, invite.clone()));
+        UserAgentEvent::IncomingCall {
+            call_id,
+            caller,
+            invite,
+        }
+    }
+
+    fn incoming_invite(&self) -> Option<&IncomingInvite> {
+        self.incoming_invite.as_ref().map(|(_, invite)| invite)
+    }
+
+    fn invite_note(&self) -> String {
+        let Some(invite) = self.incoming_invite() else {
+            return String::new();
+        };
+        let mut note = String::new();
+        if !invite.forwarded_from.is_empty() {
+            note.push_str(&format!(
+                " (forwarded from {})",
+                invite.forwarded_from.join(", ")
+            ));
+        }
+        if let Some(subject) = &invite.subject {
+            note.push_str(&format!(", subject: \"{subject}\""));
+        }
+        note
+    }
+
+    fn incoming_invite_fields(&self) -> Value {
+        match self.incoming_invite() {
+            Some(invite) => json!({
+                "forwarded_from": invite.forwarded_from,
+                "subject": invite.subject,
+            }),
+            None => json!({}),
+        }
+    }
+
+    fn update_history(&mut self, event: &UserAgentEvent) {
+        match event {
+            UserAgentEvent::IncomingCall { caller, .. } => {
+                let caller = self.describe_remote(caller.uri.clone());
+                self.history.start(Direction::Inbound, caller);
+                if let Some(invite) = self.incoming_invite() {
+                    self.history.forward(invite.forwarded_from.clone());
+                }
+            }
+            UserAgentEvent::CallEstablished { .. } => self.history.establish(),
+            UserAgentEvent::CallTerminated { .. } => {
+                let missed = self
+                    .history
+                    .end()
+                    .filter(|record| record.result == Some(CallResult::Missed));
+                if let Some(record) = missed {
+                    let text = format!("Missed call from {}", record.remote);
+                    self.console.print_alert(text.clone());
+                    self.notify_webhooks(
+                        WebhookEvent::MissedCall,
+                        text,
+                        json!({ "caller": record.remote }),
+                    );
+                    self.run_exec(ExecEvent::MissedCall, &[("caller", record.remote.clone())]);
+                }
+                if let Err(err) = self.history.save() {
+                    tracing::warn!("Could not save call history: {err}");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn update_cdr(&mut self, event: &UserAgentEvent) {
+        let Some(cdr) = self.cdr.as_mut() else {
+            return;
+        };
+        match event {
+            UserAgentEvent::IncomingCall {
+                call_id, caller, ..
+            } => cdr.start(*call_id, Direction::Inbound, caller.uri.clone()),
+            UserAgentEvent::Calling { call_id, target } => {
+                let registrar = self
+                    .user_agent
+                    .status()
+                    .registration
+                    .map(|reg| reg.registrar_host);
+                let remote_uri = match registrar {
+                    Some(registrar) => format!("sip:{target}@{registrar}"),
+                    None => target.clone(),
+                };
+                cdr.start(*call_id, Direction::Outbound, remote_uri);
+            }
+            UserAgentEvent::CallEstablished { .. } => cdr.answer(),
+            UserAgentEvent::CallFailed { reason, .. } => cdr.fail(reason),
+            UserAgentEvent::CallQuality { stats, .. } => cdr.set_stats(stats.clone()),
+            UserAgentEvent::CallTerminated { status, .. } => cdr.end(*status),
+            _ => {}
+        }
+    }
+
+    fn update_ringing_state(&mut self, event: &UserAgentEvent) {
+        match event {
+            // the answering machine picks the call up at once
+            UserAgentEvent::IncomingCall { caller, .. } if self.answering_machine.is_none() => {
+                let caller = self.describe_remote(caller.uri.clone());
+                self.ringing = Some((caller, Instant::now()));
+            }
+            UserAgentEvent::CallEstablished { .. } | UserAgentEvent::CallTerminated { .. } => {
+                self.ringing = None
+            }
+            _ => {}
+        }
+    }
+
+    fn update_ringing(&mut self) {
+        let Some((caller, last_alert)) = self.ringing.as_mut() else {
+            return;
+        };
+        if last_alert.elapsed() < RINGING_ALERT_INTERVAL {
+            return;
+        }
+        *last_alert = Instant::now();
+        self.console.print_alert(format!(
+            "The incoming call from {caller} is ringing (accept call | decline call)"
+        ));
+    }
+
+    fn update_prompt(&self, event: &UserAgentEvent) {
+        let remote = || self.call_context.as_ref().map(|(_, remote)| remote.clone());
+        match event {
+            UserAgentEvent::Registered {
+                user_name,
+                registrar_host,
+            } => self
+                .input
+                .prompt
+                .set_registration(Some(format!("{user_name}@{registrar_host}"))),
+            UserAgentEvent::Unregistered => self.input.prompt.set_registration(None),
+            UserAgentEvent::Calling { .. }
+            | UserAgentEvent::IncomingCall { .. }
+            | UserAgentEvent::CallEstablished { .. } => self.input.prompt.set_call(remote()),
+            UserAgentEvent::CallTerminated { .. } => self.input.prompt.set_call(None),
+            _ => {}
+        }
+    }
+
+    fn update_completions(&self) {
+        self.input.completions.update(&self.contacts, &self.history);
+    }
+
+    fn start_call_context(&mut self, call_id: CallId, remote: String) {
+        self.call_context = Some((call_id, remote));
+    }
+
+    fn describe_remote(&self, remote: String) -> String {
+        match self.contacts.find_caller(&remote) {
+            Some(name) => format!("{name} ({remote})"),
+            None => remote,
+        }
+    }
+
+    fn update_call_tones(&mut self, event: &UserAgentEvent) {
+        let result = match event {
+            UserAgentEvent::Calling { .. } => {
+                self.dialing = true;
+                self.play_tones(&[Tone::Dial, Tone::Ringback])
+            }
+            UserAgentEvent::CallEstablished { call_id } if self.is_intercom_call(*call_id) => {
+                self.dialing = false;
+                self.play_tones(&[Tone::Beep])
+            }
+            UserAgentEvent::CallEstablished { .. } => {
+                self.dialing = false;
+                self.stop_tones();
+                Ok(())
+            }
+            // 486 Busy Here and 600 Busy Everywhere, the other failures just stop the ringback
+            UserAgentEvent::CallTerminated { status, .. } if self.dialing => {
+                self.dialing = false;
+                match status {
+                    Some(486 | 600) => self.play_tones(&[Tone::Busy]),
+                    _ => {
+                        self.stop_tones();
+                        Ok(())
+                    }
+                }
+            }
+            UserAgentEvent::DtmfSent { digits, .. } => {
+                let keys: Vec<_> = digits
+                    .chars()
+                    .filter(|digit| *digit != dtmf::PAUSE)
+                    .map(Tone::Key)
+                    .collect();
+                if keys.is_empty() {
+                    Ok(())
+                } else {
+                    self.play_tones(&keys)
+                }
+            }
+            _ => Ok(()),
+        };
+        if let Err(err) = result {
+            tracing::warn!("Tone playing err: {err}");
+        }
+    }
+
+    fn play_tones(&mut self, tones: &[Tone]) -> Result<()> {
+        match &self.audio_bus {
+            Some(bus) => bus.play_tones(tones),
+            None => self.audio_system.play_tones(tones),
+        }
+    }
+
+    fn stop_tones(&mut self) {
+        if let Some(bus) = &self.audio_bus {
+            bus.stop_tone();
+        }
+        self.audio_system.stop_tone();
+    }
+
+    async fn run_answering_machine(&mut self, event: &UserAgentEvent) -> Result<()> {
+        let Some(machine) = self.answering_machine.as_mut() else {
+            return Ok(());
+        };
+
+        match event {
+            UserAgentEvent::IncomingCall { .. } => {
+                tracing::info!("Answering machine is answering the call");
+                machine.answer();
+                self.accept_call().await
+            }
+            UserAgentEvent::CallEstablished { .. } if machine.is_answering() => {
+                let greeting = machine.greeting().cloned();
+                let recording_path = machine.start_recording();
+                if let Some(greeting) = greeting {
+                    self.user_agent
+                        .start_playback(&greeting, PlaybackMode::Replace)?;
+                }
+                self.user_agent
+                    .start_recording(&recording_path, RecordingMode::Remote)
+            }
+            UserAgentEvent::CallTerminated { .. } => {
+                machine.reset();
+                Ok(())
+            }
+            _ => Ok(()),
         }
     }
 
-    fn print_ua_event(event: &UserAgentEvent) {
+    // the answering machine takes the call itself
+    async fn run_intercom(&mut self, event: &UserAgentEvent) -> Result<()> {
+        let UserAgentEvent::IncomingCall { call_id, .. } = event else {
+            return Ok(());
+        };
+        if !self.is_intercom_call(*call_id) || self.answering_machine.is_some() {
+            return Ok(());
+        }
+        self.console.print("The intercom call is answered");
+        self.accept_call().await
+    }
+
+    // the INVITE of the call asks to answer it, not the one of an earlier call
+    fn is_intercom_call(&self, call_id: CallId) -> bool {
+        self.config.intercom
+            && self
+                .incoming_invite
+                .as_ref()
+                .is_some_and(|(invite_call_id, invite)| {
+                    *invite_call_id == call_id && invite.auto_answer
+                })
+    }
+
+    fn run_exec_event(&self, event: &UserAgentEvent) {
+        let remote = || {
+            self.call_context
+                .as_ref()
+                .map(|(_, remote)| remote.clone())
+                .unwrap_or_default()
+        };
         match event {
-            UserAgentEvent::CallEstablished => println!("The call is established"),
-            UserAgentEvent::Calling => println!("Calling..."),
-            UserAgentEvent::CallTerminated => println!("The call is terminated"),
-            UserAgentEvent::IncomingCall(from) => {
-                println!("There is an incoming call from {:?}", from.uri.uri)
+            UserAgentEvent::IncomingCall {
+                call_id, caller, ..
+            } => self.run_exec(
+                ExecEvent::IncomingCall,
+                &[
+                    ("call_id", call_id.to_string()),
+                    ("caller", caller.uri.clone()),
+                    (
+                        "display_name",
+                        caller.display_name.clone().unwrap_or_default(),
+                    ),
+                    (
+                        "forwarded_from",
+                        self.incoming_invite()
+                            .map(|invite| invite.forwarded_from.join(","))
+                            .unwrap_or_default(),
+                    ),
+                    (
+                        "subject",
+                        self.incoming_invite()
+                            .and_then(|invite| invite.subject.clone())
+                            .unwrap_or_default(),
+                    ),
+                ],
+            ),
+            UserAgentEvent::CallEstablished { call_id } => self.run_exec(
+                ExecEvent::CallEstablished,
+                &[("call_id", call_id.to_string()), ("remote", remote())],
+            ),
+            UserAgentEvent::CallTerminated { call_id, .. } => self.run_exec(
+                ExecEvent::CallEnded,
+                &[("call_id", call_id.to_string()), ("remote", remote())],
+            ),
+            UserAgentEvent::Registered {
+                user_name,
+                registrar_host,
+            } => self.run_exec(
+                ExecEvent::Registered,
+                &[
+                    ("user", user_name.clone()),
+                    ("registrar", registrar_host.clone()),
+                ],
+            ),
+            UserAgentEvent::DtmfReceived { call_id, digit } => self.run_exec(
+                ExecEvent::Dtmf,
+                &[
+                    ("call_id", call_id.to_string()),
+                    ("digit", digit.to_string()),
+                ],
+            ),
+            UserAgentEvent::InfoReceived {
+                call_id,
+                content_type,
+                body,
+            } => self.run_exec(
+                ExecEvent::Info,
+                &[
+                    ("call_id", call_id.to_string()),
+                    ("content_type", content_type.clone()),
+                    ("body", body.clone()),
+                ],
+            ),
+            _ => {}
+        }
+    }
+
+    fn run_exec(&self, event: ExecEvent, fields: &[(&str, String)]) {
+        let account = ("account", self.registration().unwrap_or_default());
+        let fields: Vec<_> = fields.iter().cloned().chain([account]).collect();
+        exec::run(&self.config.exec, event, &fields);
+    }
+
+    #[cfg(feature = "hooks")]
+    fn run_hooks(&mut self, event: &UserAgentEvent) {
+        let Some(hooks) = self.hooks.as_ref() else {
+            return;
+        };
+        let commands = match event {
+            UserAgentEvent::IncomingCall { caller, .. } => {
+                hooks.on_incoming_call(&caller.uri, caller.display_name.as_deref())
+            }
+            UserAgentEvent::CallEstablished { .. } => hooks.on_call_established(),
+            UserAgentEvent::Registered {
+                user_name,
+                registrar_host,
+            } => hooks.on_registered(user_name, registrar_host),
+            UserAgentEvent::DtmfReceived { digit, .. } => hooks.on_dtmf(*digit),
+            _ => return,
+        };
+        for line in commands {
+            if let Err(err) = self.queue_command(CommandSource::Hook, &line) {
+                tracing::warn!("Hook command \"{line}\" err: {err}");
+            }
+        }
+    }
+
+    #[cfg(not(feature = "hooks"))]
+    fn run_hooks(&mut self, _event: &UserAgentEvent) {}
+
+    async fn update_answering_machine(&mut self) {
+        let expired = self
+            .answering_machine
+            .as_ref()
+            .is_some_and(|machine| machine.is_recording_expired());
+        if expired {
+            tracing::info!("Answering machine recording time is over");
+            if let Some(machine) = self.answering_machine.as_mut() {
+                machine.reset();
+            }
+            let _ = self
+                .terminate_call()
+                .await
+                .inspect_err(|err| tracing::warn!("Answering machine err: {err}"));
+        }
+    }
+
+    async fn update_meter(&mut self) {
+        let Some(last_update) = self.meter else {
+            return;
+        };
+        if last_update.elapsed() < METER_INTERVAL {
+            return;
+        }
+        self.meter = Some(Instant::now());
+
+        if let Err(err) = self.audio_system.start_meter() {
+            tracing::warn!("Audio meter err: {err}");
+        }
+        if let Some(level) = self.audio_system.take_input_level() {
+            self.handle_ua_event(UserAgentEvent::AudioLevel(level))
+                .await;
+        }
+    }
+
+    fn print_ua_event(&self, event: &UserAgentEvent) {
+        let message = match event {
+            UserAgentEvent::AudioLevel(level) => Self::format_audio_level(level),
+            UserAgentEvent::CallEstablished { .. } => "The call is established".to_owned(),
+            UserAgentEvent::CallFailed { reason, .. } => format!("The call failed: {reason}"),
+            UserAgentEvent::CallProgress { code, reason, .. } => format!("{code} {reason}"),
+            UserAgentEvent::CallQuality { stats, .. } => format!(
+                "Call quality: MOS {:.1}, {:.1}% loss, {:.0} ms jitter",
+                stats.quality().mos,
+                stats.loss_percent,
+                stats.jitter_ms
+            ),
+            UserAgentEvent::CallRinging { .. } => "180 Ringing".to_owned(),
+            UserAgentEvent::CallSessionProgress { .. } => "183 Session Progress".to_owned(),
+            UserAgentEvent::Calling { .. } => "Calling...".to_owned(),
+            UserAgentEvent::CallTerminated { .. } => "The call is terminated".to_owned(),
+            UserAgentEvent::CallTrying { .. } => "100 Trying".to_owned(),
+            UserAgentEvent::DtmfReceived { digit, .. } => format!("DTMF {digit} is received"),
+            UserAgentEvent::DtmfSent { digits, .. } => format!("DTMF {digits} is being sent"),
+            UserAgentEvent::IncomingCall { caller, .. } => format!(
+                "There is an incoming call from {}{}",
+                self.describe_remote(caller.uri.clone()),
+                self.invite_note()
+            ),
+            UserAgentEvent::InfoReceived {
+                content_type, body, ..
+            } => format!("INFO ({content_type}) is received: {}", body.trim_end()),
+            UserAgentEvent::LineStateChanged { extension, state } => format!(
+                "The line of {} is {state}",
+                self.describe_remote(extension.clone())
+            ),
+            UserAgentEvent::MessageWaiting { summary } => format!("Voicemail: {summary}"),
+            UserAgentEvent::MessageReceived { from, body } => format!(
+                "Message from {}: {}",
+                self.describe_remote(from.uri.clone()),
+                body.trim_end()
+            ),
+            UserAgentEvent::PingAnswered { host, response } => format!(
+                "OPTIONS to {}: {} in {} ms",
+                host.as_deref().unwrap_or("the registrar"),
+                response.status,
+                response.round_trip.as_millis()
+            ),
+            UserAgentEvent::PingFailed { host, reason } => format!(
+                "OPTIONS to {} failed: {reason}",
+                host.as_deref().unwrap_or("the registrar")
+            ),
+            UserAgentEvent::PlaybackStarted { path, .. } => {
+                format!("Playing {path:?} into the call")
+            }
+            UserAgentEvent::PlaybackStopped { .. } => "The playback is stopped".to_owned(),
+            UserAgentEvent::RecordingStarted { path, .. } => {
+                format!("The call is being recorded to {path:?}")
             }
-            UserAgentEvent::Registered => println!("The agent is registered"),
-            UserAgentEvent::Unregistered => println!("The agent is unregistered"),
+            UserAgentEvent::RecordingStopped { .. } => "The call recording is stopped".to_owned(),
+            UserAgentEvent::Registered { .. } => "The agent is registered".to_owned(),
+            UserAgentEvent::Unregistered => "The agent is unregistered".to_owned(),
+        };
+        let extra = match event {
+            UserAgentEvent::IncomingCall { .. } => self.incoming_invite_fields(),
+            _ => json!({}),
+        };
+        match (event.call_id(), &self.call_context) {
+            (Some(call_id), Some((_, remote))) => self.console.print_event(
+                event,
+                format!("[call #{call_id} {remote}] {message}"),
+                extra,
+            ),
+            _ => self.console.print_event(event, message, extra),
         }
     }
 
+    fn format_audio_level(level: &AudioLevel) -> String {
+        let bar_width = 40;
+        let to_dbfs = |value: f32| 20.0 * value.max(1e-5).log10();
+        // the bar covers the range [-60 dBFS; 0 dBFS]
+        let rms_dbfs = to_dbfs(level.rms);
+        let filled = (((rms_dbfs + 60.0) / 60.0).clamp(0.0, 1.0) * bar_width as f32) as usize;
+        format!(
+            "Mic [{}{}] rms {:.0} dBFS, peak {:.0} dBFS",
+            "#".repeat(filled),
+            " ".repeat(bar_width - filled),
+            rms_dbfs,
+            to_dbfs(level.peak),
+        )
+    }
+
     pub(crate) async fn register_ua(
         &mut self,
         user_name: &str,
-        credentials: DigestCredentials,
+        password: &str,
         registrar_host: HostPort,
+        srtp: SrtpPolicy,
+        display_name: Option<String>,
     ) -> Result<()> {
-        tracing::info!("Registering the UA: {user_name}");
-        self.user_agent
-            .register(user_name, credentials, registrar_host)
-            .await
+        tracing::info!("Registering the UA: {user_name} (SRTP {srtp})");
+        let registrar = registrar_host.to_string();
+        let result = self
+            .user_agent
+            .register(user_name, password, registrar_host, srtp, display_name)
+            .await;
+        // the lines of the reloaded config are watched from the next registration
+        if result.is_ok() && !self.config.blf.is_empty() {
+            let extensions = self.config.blf.clone();
+            if let Err(err) = self.user_agent.watch_lines(&extensions).await {
+                self.console.print_error(err);
+            }
+        }
+        // the servers without the voicemail reject the subscription
+        if result.is_ok() {
+            if let Err(err) = self.user_agent.watch_mailbox().await {
+                tracing::warn!("{err}");
+            }
+        }
+        if let Err(err) = &result {
+            self.notify_webhooks(
+                WebhookEvent::RegistrationFailed,
+                format!("Registration of {user_name} on {registrar} failed: {err}"),
+                json!({ "user": user_name, "registrar": registrar, "error": err.to_string() }),
+            );
+            self.run_exec(
+                ExecEvent::RegistrationFailed,
+                &[
+                    ("user", user_name.to_owned()),
+                    ("registrar", registrar.clone()),
+                    ("error", err.to_string()),
+                ],
+            );
+        }
+        result
+    }
+
+    #[cfg(feature = "webhooks")]
+    fn notify_webhooks(&self, event: WebhookEvent, text: String, mut fields: Value) {
+        let Some(webhooks) = self.webhooks.as_ref() else {
+            return;
+        };
+        fields["account"] = self.registration().into();
+        webhooks.notify(event, text, fields);
     }
 
-    pub(crate) async fn make_call(&mut self, target_user_name: &str) -> Result<()> {
+    #[cfg(not(feature = "webhooks"))]
+    fn notify_webhooks(&self, _event: WebhookEvent, _text: String, _fields: Value) {}
+
+    pub(crate) async fn make_call(
+        &mut self,
+        target_user_name: &str,
+        subject: Option<&str>,
+    ) -> Result<()> {
         if !self.user_agent.is_registered() {
             Err(anyhow::Error::msg(
                 "Can't make a call. The UA is not registered",
@@ -162,43 +1805,573 @@ impl App {
             ))
         } else {
             tracing::info!("Making a call to {target_user_name}");
-            let audio_sender = self.audio_system.create_output_stream()?;
-            let audio_receiver = self.audio_system.create_input_stream()?;
-            self.user_agent
-                .make_call(target_user_name, audio_sender, audio_receiver)
-                .await
+            self.stop_loopback();
+            let bus = self.start_audio_bus()?;
+            let result = self
+                .user_agent
+                .make_call(target_user_name, subject, &bus)
+                .await;
+            self.audio_bus = Some(bus);
+            match result {
+                Ok(call_id) => {
+                    let callee = self.describe_remote(target_user_name.to_owned());
+                    self.start_call_context(call_id, callee.clone());
+                    self.history.start(Direction::Outbound, callee);
+                    self.update_completions();
+                    Ok(())
+                }
+                Err(err) => {
+                    self.stop_audio_bus();
+                    Err(err)
+                }
+            }
         }
     }
 
-    pub(crate) async fn accept_call(&mut self) -> Result<()> {
-        let audio_sender = self.audio_system.create_output_stream()?;
-        let audio_receiver = self.audio_system.create_input_stream()?;
+    pub(crate) fn contact_user(&self, name: &str) -> Result<String> {
+        self.contacts.user(name).map(str::to_owned)
+    }
+
+    // the number of the config precedes the Message-Account of the notifications
+    pub(crate) fn voicemail_user(&self) -> Result<String> {
+        if let Some(voicemail) = &self.config.voicemail {
+            return Ok(voicemail.clone());
+        }
         self.user_agent
-            .accept_incoming_call(audio_sender, audio_receiver)
-            .await
+            .status()
+            .voicemail
+            .and_then(|summary| summary.account)
+            .and_then(|account| {
+                let (_, user) = account.split_once(':')?;
+                Some(user.split('@').next()?.to_owned())
+            })
+            .ok_or(anyhow::Error::msg(
+                "The voicemail number is unknown: set voicemail in the config",
+            ))
+    }
+
+    pub(crate) fn add_contact(&mut self, name: &str, user: &str) -> Result<()> {
+        self.contacts.add(name, user);
+        self.update_completions();
+        self.console
+            .print(format!("The contact {name} ({user}) is added"));
+        self.contacts.save()
+    }
+
+    pub(crate) fn print_contacts(&self) -> Result<()> {
+        let mut contacts = "==== Contacts ====".to_owned();
+        for (name, user) in self.contacts.iter() {
+            contacts.push_str(&format!("\n\t {name}: {user}"));
+        }
+        self.console.print(contacts);
+        Ok(())
+    }
+
+    pub(crate) async fn accept_call(&mut self) -> Result<()> {
+        self.ringing = None;
+        self.stop_loopback();
+        let bus = self.start_audio_bus()?;
+        let result = self.user_agent.accept_incoming_call(&bus).await;
+        self.audio_bus = Some(bus);
+        if result.is_err() {
+            self.stop_audio_bus();
+        }
+        result
+    }
+
+    // the bus is taken out while the call is attached to it, the caller puts it back
+    fn start_audio_bus(&mut self) -> Result<AudioBus> {
+        if let Some(bus) = self.audio_bus.take() {
+            return Ok(bus);
+        }
+        let output = self
+            .audio_system
+            .create_output_stream()
+            .categorize(ErrorCategory::Audio)?;
+        let input = self
+            .audio_system
+            .create_input_stream()
+            .inspect_err(|_err| self.audio_system.destroy_output_stream())
+            .categorize(ErrorCategory::Audio)?;
+        Ok(AudioBus::start(
+            input,
+            output,
+            self.audio_system.pipe_capacity(),
+        ))
+    }
+
+    fn stop_audio_bus(&mut self) {
+        if let Some(bus) = self.audio_bus.take() {
+            bus.stop();
+            self.audio_system.destroy_input_stream();
+            self.audio_system.destroy_output_stream();
+        }
     }
 
     pub(crate) async fn decline_call(&mut self) -> Result<()> {
-        self.user_agent.decline_incoming_call().await
+        self.user_agent.decline_incoming_call().await?;
+        self.ringing = None;
+        self.history.decline();
+        Ok(())
+    }
+
+    pub(crate) fn export_history(&self, path: &Path) -> Result<()> {
+        let count = self.history.export(path)?;
+        self.console
+            .print(format!("{count} calls are exported to {path:?}"));
+        Ok(())
+    }
+
+    pub(crate) fn print_history(&self, count: usize) -> Result<()> {
+        let mut history = "==== Call history ====".to_owned();
+        for record in self.history.last(count) {
+            history.push_str(&format!("\n\t {record}"));
+        }
+        self.console.print(history);
+        Ok(())
     }
 
-    pub(crate) async fn terminate_call(&mut self) -> Result<()> {
+    pub(crate) async fn terminate_call(&mut self, call_id: Option<u64>) -> Result<()> {
+        let current_id = self.user_agent.active_call_id();
         if !self.user_agent.has_active_call() {
             Err(anyhow::Error::msg(
                 "Can't terminate a call. There is no active call",
             ))
+        } else if call_id.is_some_and(|call_id| Some(call_id) != current_id) {
+            Err(anyhow::Error::msg(format!(
+                "Can't terminate a call. The call #{} is not active",
+                call_id.unwrap_or_default()
+            )))
         } else {
             tracing::info!("Terminating the call.");
             self.user_agent.terminate_call().await
         }
     }
 
+    pub(crate) async fn transfer_call(&mut self, target: &str, is_uri: bool) -> Result<()> {
+        tracing::info!("Transferring the call to {target}");
+        self.user_agent.transfer_call(target, is_uri).await?;
+        self.console
+            .print(format!("The call is transferred to {target}"));
+        Ok(())
+    }
+
+    pub(crate) fn start_recording(&mut self, path: &Path, mode: RecordingMode) -> Result<()> {
+        tracing::info!("Recording the call to {path:?} ({mode})");
+        self.user_agent.start_recording(path, mode)
+    }
+
+    pub(crate) fn stop_recording(&mut self) -> Result<()> {
+        tracing::info!("Stopping the call recording");
+        self.user_agent.stop_recording()
+    }
+
+    pub(crate) fn start_playback(&mut self, path: &Path, mode: PlaybackMode) -> Result<()> {
+        tracing::info!("Playing {path:?} into the call ({mode})");
+        self.user_agent.start_playback(path, mode)
+    }
+
+    pub(crate) fn stop_playback(&mut self) -> Result<()> {
+        tracing::info!("Stopping the playback");
+        self.user_agent.stop_playback()
+    }
+
+    pub(crate) fn send_dtmf(&mut self, digits: &str) -> Result<()> {
+        tracing::info!("Sending DTMF {digits}");
+        self.user_agent.send_dtmf(digits)
+    }
+
+    pub(crate) fn send_message(&mut self, user_name: &str, text: &str) -> Result<()> {
+        tracing::info!("Sending the message to {user_name}");
+        let sending = self.user_agent.send_message(user_name, text)?;
+        let remote = self.describe_remote(user_name.to_owned());
+        let console = self.console.clone();
+        tokio::spawn(async move {
+            match sending.await {
+                Ok(()) => console.print(format!("The message is sent to {remote}")),
+                Err(err) => console.print_error(err),
+            }
+        });
+        Ok(())
+    }
+
+    pub(crate) fn ping(&mut self, host: Option<&str>) -> Result<()> {
+        tracing::info!("Sending OPTIONS to {}", host.unwrap_or("the registrar"));
+        self.user_agent.ping(host)
+    }
+
+    pub(crate) async fn publish_presence(
+        &mut self,
+        status: PresenceStatus,
+        note: Option<String>,
+    ) -> Result<()> {
+        tracing::info!("Publishing the presence: {status}");
+        self.user_agent.publish_presence(status, note).await?;
+        self.console
+            .print(format!("The presence is published: {status}"));
+        Ok(())
+    }
+
+    pub(crate) fn set_answering_machine(
+        &mut self,
+        machine: Option<AnsweringMachine>,
+    ) -> Result<()> {
+        match &machine {
+            Some(machine) => self
+                .console
+                .print(format!("The answering machine is on {machine}")),
+            None => self.console.print("The answering machine is off"),
+        }
+        if let Some(saved) = self.state.as_mut() {
+            saved.answering_machine = machine.as_ref().map(SavedAnsweringMachine::of);
+            self.save_state();
+        }
+        self.answering_machine = machine;
+        Ok(())
+    }
+
+    pub(crate) fn set_meter(&mut self, enabled: bool) -> Result<()> {
+        if enabled {
+            self.audio_system.start_meter()?;
+            self.meter = Some(Instant::now());
+        } else {
+            self.audio_system.stop_meter();
+            self.meter = None;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_sip_trace(&mut self, enabled: bool) -> Result<()> {
+        self.sip_trace.set_enabled(enabled)?;
+        if enabled {
+            self.console.print("The SIP messages are logged");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn start_capture(&mut self, path: &Path, rtp: bool) -> Result<()> {
+        if self.capture.is_active() {
+            return Err(anyhow::Error::msg("The capture is running already"));
+        }
+        let rtp = rtp.then(|| self.user_agent.start_rtp_capture());
+        let with_rtp = if rtp.is_some() {
+            " and the RTP headers"
+        } else {
+            ""
+        };
+        if let Err(err) = self.capture.start(path, rtp) {
+            self.user_agent.stop_rtp_capture();
+            return Err(err);
+        }
+        self.console
+            .print(format!("Capturing the SIP messages{with_rtp} to {path:?}"));
+        Ok(())
+    }
+
+    pub(crate) fn stop_capture(&mut self) -> Result<()> {
+        self.user_agent.stop_rtp_capture();
+        let (path, packets) = self.capture.stop()?;
+        self.console
+            .print(format!("{packets} packets are captured to {path:?}"));
+        Ok(())
+    }
+
+    pub(crate) fn set_loopback(&mut self, enabled: bool) -> Result<()> {
+        if !enabled {
+            self.stop_loopback();
+            return Ok(());
+        }
+        if self.user_agent.has_active_call() {
+            return Err(anyhow::Error::msg(
+                "Can't start the loopback test. There is an active call",
+            ));
+        }
+        if self.loopback.is_some() {
+            return Err(anyhow::Error::msg("The loopback test is running already"));
+        }
+
+        let audio_sender = self.audio_system.create_output_stream()?;
+        let audio_receiver = self
+            .audio_system
+            .create_input_stream()
+            .inspect_err(|_err| self.audio_system.destroy_output_stream())?;
+        self.loopback = Some(Loopback::start(
+            audio_receiver,
+            audio_sender,
+            Duration::from_secs(1),
+        ));
+        self.console
+            .print("The loopback test is running, speak to hear yourself in 1 second");
+        Ok(())
+    }
+
+    fn stop_loopback(&mut self) {
+        if let Some(loopback) = self.loopback.take() {
+            loopback.stop();
+            self.audio_system.destroy_input_stream();
+            self.audio_system.destroy_output_stream();
+            self.console.print("The loopback test is stopped");
+        }
+    }
+
+    pub(crate) fn set_volume(
+        &mut self,
+        input_volume: Option<u32>,
+        output_volume: Option<u32>,
+    ) -> Result<()> {
+        if let Some(volume) = input_volume {
+            self.settings.input_volume = volume;
+            self.apply_input_volume();
+        }
+        if let Some(volume) = output_volume {
+            self.audio_system.set_output_volume(volume);
+            self.settings.output_volume = volume;
+        }
+        self.console.print(format!(
+            "Volume: in {}%, out {}%",
+            self.settings.input_volume, self.settings.output_volume
+        ));
+        self.settings.save()
+    }
+
+    pub(crate) fn set_muted(&mut self, muted: Option<bool>) -> Result<()> {
+        self.muted = muted.unwrap_or(!self.muted);
+        self.apply_input_volume();
+        if self.muted {
+            self.console.print("The microphone is muted");
+        } else {
+            self.console.print("The microphone is unmuted");
+        }
+        Ok(())
+    }
+
+    fn apply_input_volume(&mut self) {
+        let volume = if self.muted {
+            0
+        } else {
+            self.settings.input_volume
+        };
+        self.audio_system.set_input_volume(volume);
+    }
+
+    pub(crate) fn print_call_stats(&self) -> Result<()> {
+        let stats = self.user_agent.call_stats()?;
+        self.console.print(format!("==== Call stats ====\n{stats}"));
+        Ok(())
+    }
+
+    pub(crate) fn print_flow(&self) -> Result<()> {
+        let ladder =
+            self.sip_flow
+                .render()
+                .ok_or(anyhow::Error::msg(
+                    "There is no call in the recent SIP messages",
+                ))?;
+        self.console.print(ladder);
+        Ok(())
+    }
+
+    pub(crate) fn print_version(&self) {
+        self.console.print(version::info());
+    }
+
+    pub(crate) fn print_status(&self) -> Result<()> {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+        let answering_machine = match &self.answering_machine {
+            Some(machine) => format!("on {machine}"),
+            None => "off".to_owned(),
+        };
+        self.console.print(format!(
+            "==== Status ====\n{}\n\
+            Audio: in {} ({}%), out {} ({}%)\n\
+            Answering machine (auto-answer): {answering_machine}\n\
+            Muted: {}, meter: {}, loopback: {}, SIP trace: {}, capture: {}",
+            self.user_agent.status(),
+            self.audio_system.input_name(),
+            self.settings.input_volume,
+            self.audio_system.output_name(),
+            self.settings.output_volume,
+            on_off(self.muted),
+            on_off(self.meter.is_some()),
+            on_off(self.loopback.is_some()),
+            on_off(self.sip_trace.is_enabled()),
+            on_off(self.capture.is_active()),
+        ));
+        Ok(())
+    }
+
+    pub(crate) fn print_audio_stats(&self) -> Result<()> {
+        let stats = self.user_agent.audio_stats()?;
+        self.console
+            .print(format!("==== Audio stats ====\n{stats}"));
+        Ok(())
+    }
+
     pub(crate) async fn unregister(&mut self) -> Result<()> {
         self.user_agent.unregister();
+        self.forget_account();
+        Ok(())
+    }
+
+    // the saved account of --persist-state is the last registered one, it replaces the one of
+    // the config. Its password is the one of the keyring or of the same account of the config
+    fn startup_account(&self) -> Option<Account> {
+        let Some(mut account) = self.state.as_ref().and_then(|state| state.account.clone()) else {
+            return self.config.accounts.first().cloned();
+        };
+        let stored = self
+            .args
+            .keyring
+            .then(|| state::load_password(&account))
+            .flatten();
+        let configured = self
+            .config
+            .accounts
+            .iter()
+            .find(|configured| {
+                configured.user == account.user && configured.registrar == account.registrar
+            })
+            .map(|configured| configured.password.clone());
+        account.password = stored.or(configured).unwrap_or_default();
+        Some(account)
+    }
+
+    pub(crate) fn remember_account(&mut self, account: Account) {
+        let Some(saved) = self.state.as_mut() else {
+            return;
+        };
+        if self.args.keyring && !account.password.is_empty() {
+            if let Err(err) = state::store_password(&account) {
+                tracing::warn!("The password of {} is not saved: {err}", account.user);
+            }
+        }
+        if let Some(previous) = saved.account.replace(account) {
+            self.forget_password(&previous);
+        }
+        self.save_state();
+    }
+
+    fn forget_account(&mut self) {
+        let Some(previous) = self.state.as_mut().and_then(|state| state.account.take()) else {
+            return;
+        };
+        self.forget_password(&previous);
+        self.save_state();
+    }
+
+    fn forget_password(&self, previous: &Account) {
+        let current = self.state.as_ref().and_then(|state| state.account.as_ref());
+        let same_entry = current.is_some_and(|current| {
+            (&current.user, &current.registrar) == (&previous.user, &previous.registrar)
+        });
+        if self.args.keyring && !same_entry {
+            state::delete_password(previous);
+        }
+    }
+
+    fn save_state(&self) {
+        if let Some(Err(err)) = self.state.as_ref().map(RuntimeState::save) {
+            tracing::warn!("Could not save runtime state: {err}");
+        }
+    }
+
+    fn register_account(&mut self) -> Result<()> {
+        if let Some(account) = self.startup_account() {
+            tracing::info!(
+                "Registering the startup account {}@{}",
+                account.user,
+                account.registrar
+            );
+            let command = account.register_command().map_err(|err| {
+                anyhow::Error::msg(format!("Invalid account {}: {err}", account.user))
+            })?;
+            self.pending_commands
+                .push_front((CommandSource::Config, command));
+        }
+        Ok(())
+    }
+
+    // the empty lines and the lines starting with '#' are skipped, nothing is run if a line is invalid
+    pub(crate) fn source_script(&mut self, path: &Path) -> Result<()> {
+        let script = std::fs::read_to_string(path).map_err(|err| {
+            anyhow::Error::msg(format!("Could not read the script {path:?}: {err}"))
+        })?;
+        let mut commands = Vec::new();
+        for (index, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let command = self
+                .command_line
+                .parse(line)
+                .map_err(|err| anyhow::Error::msg(format!("{path:?} line {}: {err}", index + 1)))?;
+            commands.push(command);
+        }
+        if self.pending_commands.len() + commands.len() > MAX_PENDING_COMMANDS {
+            return Err(anyhow::Error::msg(format!(
+                "Too many commands in {path:?}, does the script source itself?"
+            )));
+        }
+
+        tracing::info!("Running {} commands from {path:?}", commands.len());
+        // the commands of a nested script run before the rest of the outer one
+        for command in commands.into_iter().rev() {
+            self.pending_commands
+                .push_front((CommandSource::Script, command));
+        }
         Ok(())
     }
 
-    pub(crate) fn stop_app(&mut self) -> Result<()> {
+    pub(crate) fn reload(&mut self) -> Result<()> {
+        let config = Config::load(self.args.config.as_deref(), self.args.profile.as_deref())?;
+        let config = match &self.args.instance {
+            Some(name) => config.instance(name)?,
+            None => config,
+        };
+        let settings = Settings::load();
+        self.contacts = Contacts::load();
+        self.input.aliases.set(settings.aliases.clone());
+        self.update_completions();
+        self.audio_system.set_output_volume(settings.output_volume);
+        self.user_agent
+            .set_call_timeout(self.args.call_timeout(&config));
+
+        let mut restart_required = self.config.restart_required(&config);
+        if settings.latency_ms != self.settings.latency_ms {
+            restart_required.push("latency_ms");
+        }
+        // the registration isn't changed, the register command applies the account
+        if config.accounts != self.config.accounts {
+            restart_required.push("accounts");
+        }
+        self.config = config;
+        self.settings = settings;
+        self.apply_input_volume();
+
+        self.console.print(
+            "The config is reloaded: the contacts, the aliases, the volume, the call timeout and the exec programs are applied",
+        );
+        if !restart_required.is_empty() {
+            self.console.print_alert(format!(
+                "The restart is required to apply: {}",
+                restart_required.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn stop_app(&mut self, force: bool) -> Result<()> {
+        if self.user_agent.has_active_call() {
+            if !force {
+                return Err(anyhow::Error::msg(
+                    "There is an active call. Type \"quit force\" to terminate it and quit",
+                ));
+            }
+            if let Err(err) = self.user_agent.terminate_call().await {
+                tracing::warn!("Terminating the call err: {err}");
+            }
+        }
         self.stop_app = true;
         Ok(())
     }
@@ -1,14 +1,531 @@
-use std::net::Ipv4Addr;
+#[cfg(feature = "webhooks")]
+use crate::app::webhook::{Webhook, WebhookEvent};
+use crate::app::{
+    config::Config,
+    console::OutputFormat,
+    log_file::{LogFile, LogFormat, LogRotation},
+};
+use sipacker_core::audio::{AudioBackend, AudioConfig, AudioFiles, CaptureChannel};
 
-use clap::{self, Parser};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-#[derive(Parser)]
-#[command(version, about, long_about = None)]
+use anyhow::Result;
+use clap::{self, builder::FalseyValueParser, Parser};
+
+#[derive(Clone, Parser)]
+#[command(version, long_version = crate::app::version::LONG_VERSION.as_str(), about, long_about = None)]
 pub struct Args {
-    #[arg(long, help = "Ip address to listen")]
-    pub ip_addr: Ipv4Addr,
-    #[arg(long, help = "Port to listen", default_value = "5060")]
-    pub port: u16,
-    #[arg(long, help = "Concurrent jobs", default_value = "4")]
-    pub jobs: usize,
+    #[arg(
+        long,
+        env = "SIPACKER_CONFIG",
+        help = "Config file (~/.config/sipacker/sipacker.toml if it exists by default), the args override it"
+    )]
+    pub config: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "SIPACKER_PROFILE",
+        help = "Profile of the config ([profiles.<name>]) overriding its address, audio and accounts"
+    )]
+    pub profile: Option<String>,
+    #[arg(long, env = "SIPACKER_IP_ADDR", help = "Ip address to listen")]
+    pub ip_addr: Option<Ipv4Addr>,
+    #[arg(long, env = "SIPACKER_PORT", help = "Port to listen [default: 5060]")]
+    pub port: Option<u16>,
+    #[arg(long, env = "SIPACKER_JOBS", help = "Concurrent jobs [default: 4]")]
+    pub jobs: Option<usize>,
+    #[arg(
+        long,
+        env = "SIPACKER_PTIME",
+        help = "RTP packetization time in ms: 10, 20, 30 or 40 [default: 20]",
+        value_parser = parse_ptime
+    )]
+    pub ptime: Option<Duration>,
+    #[arg(
+        long,
+        env = "SIPACKER_AUDIO_BACKEND",
+        help = "Audio backend: cpal (sound devices), null (silent capture, discarded playback) or tone[:<Hz>] (440 Hz sine capture by default, discarded playback) [default: cpal]"
+    )]
+    pub audio_backend: Option<AudioBackend>,
+    #[arg(
+        long,
+        env = "SIPACKER_AUDIO_IN_FILE",
+        help = "WAV/OGG file to read the captured audio from instead of the backend"
+    )]
+    pub audio_in_file: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "SIPACKER_AUDIO_IN_LOOP",
+        value_parser = FalseyValueParser::new(),
+        help = "Loop the audio input file instead of playing it once"
+    )]
+    pub audio_in_loop: bool,
+    #[arg(
+        long,
+        env = "SIPACKER_AUDIO_OUT_FILE",
+        help = "WAV file to write the played audio to instead of the backend"
+    )]
+    pub audio_out_file: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "SIPACKER_INPUT_CHANNEL",
+        help = "Input channel to capture (0-based index) or mix to average all channels [default: mix]"
+    )]
+    pub input_channel: Option<CaptureChannel>,
+    #[arg(
+        long,
+        env = "SIPACKER_SAMPLE_RATE",
+        help = "Preferred sample rate of the sound devices in Hz (48000 if supported by default)",
+        value_parser = clap::value_parser!(u32).range(8000..=192000)
+    )]
+    pub sample_rate: Option<u32>,
+    #[arg(
+        long,
+        env = "SIPACKER_BUFFER_SIZE",
+        help = "Buffer size of the sound devices in frames, e.g. 480 for 10 ms at 48 kHz (the device default if not set)",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub buffer_size: Option<u32>,
+    #[arg(
+        long,
+        env = "SIPACKER_CALL_TIMEOUT",
+        help = "Seconds to wait for the outbound call to be answered [default: 10]",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub call_timeout: Option<u64>,
+    #[arg(
+        long,
+        env = "SIPACKER_TUI",
+        value_parser = FalseyValueParser::new(),
+        help = "Interactive terminal UI with the status, the events and the command line. The logs are written to sipacker.log"
+    )]
+    pub tui: bool,
+    #[arg(
+        long,
+        env = "SIPACKER_NO_COLOR",
+        value_parser = FalseyValueParser::new(),
+        help = "Plain output without colors (also if the NO_COLOR environment variable is set)"
+    )]
+    pub no_color: bool,
+    #[arg(
+        long,
+        env = "SIPACKER_OUTPUT",
+        help = "Output format: text or json (a single-line JSON object per event and command result, the logs are written to stderr) [default: text]",
+        conflicts_with = "tui"
+    )]
+    pub output: Option<OutputFormat>,
+    #[arg(
+        long,
+        env = "SIPACKER_SCRIPT",
+        help = "File with the commands to run at the startup before the interactive input, one per line"
+    )]
+    pub script: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "SIPACKER_HTTP_API",
+        help = "Address of the HTTP API, e.g. 127.0.0.1:8080 (it has no authentication, not started by default)"
+    )]
+    pub http_api: Option<SocketAddr>,
+    #[arg(
+        long,
+        env = "SIPACKER_COMMAND_FIFO",
+        help = "Named pipe to read the command lines from next to the input, e.g. /tmp/sipacker.cmd (created if missing)"
+    )]
+    pub command_fifo: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "SIPACKER_GRPC_API",
+        help = "Address of the gRPC API (proto/sipacker.proto), e.g. 127.0.0.1:50051 (it has no authentication, not started by default)"
+    )]
+    pub grpc_api: Option<SocketAddr>,
+    #[arg(
+        long,
+        env = "SIPACKER_HEALTH_API",
+        help = "Address of the /healthz and /readyz probes, e.g. 0.0.0.0:8081 (not started by default)"
+    )]
+    pub health_api: Option<SocketAddr>,
+    #[arg(
+        long,
+        env = "SIPACKER_AUDIT_LOG",
+        help = "File to append the executed commands to: who, when, what and the result, a JSON object per line"
+    )]
+    pub audit_log: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "SIPACKER_CDR",
+        help = "File to append the Call Detail Record of every call to: CSV for .csv, a JSON object per line otherwise"
+    )]
+    pub cdr: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "SIPACKER_HOOKS",
+        help = "Rhai script with the handlers of the events: on_incoming_call, on_call_established, on_registered and on_dtmf"
+    )]
+    pub hooks: Option<PathBuf>,
+    #[arg(
+        long = "webhook",
+        env = "SIPACKER_WEBHOOK",
+        value_delimiter = ',',
+        help = "URL to POST the incoming calls, the missed calls and the registration failures to as JSON, can be repeated"
+    )]
+    pub webhooks: Vec<String>,
+    #[arg(
+        long,
+        env = "SIPACKER_EXIT_ON_FAILURE",
+        value_parser = FalseyValueParser::new(),
+        help = "Exit when a command of the script or the config account fails or a call fails, with the exit code of the failure"
+    )]
+    pub exit_on_failure: bool,
+    #[arg(
+        long,
+        env = "SIPACKER_PERSIST_STATE",
+        value_parser = FalseyValueParser::new(),
+        help = "Save the registered account and the answering machine, restore them and register again at the startup"
+    )]
+    pub persist_state: bool,
+    #[arg(
+        long,
+        env = "SIPACKER_KEYRING",
+        value_parser = FalseyValueParser::new(),
+        help = "Keep the password of the saved account in the OS keyring, it's not saved otherwise"
+    )]
+    pub keyring: bool,
+    #[arg(
+        long,
+        env = "SIPACKER_LOG_FILE",
+        help = "File to write the logs to along with the console, filtered by --log-level instead of RUST_LOG"
+    )]
+    pub log_file: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "SIPACKER_LOG_FORMAT",
+        help = "Format of the log file: plain or json [default: plain]"
+    )]
+    pub log_format: Option<LogFormat>,
+    #[arg(
+        long,
+        env = "SIPACKER_LOG_LEVEL",
+        help = "Level of the log file in the RUST_LOG syntax, e.g. debug or info,ezk_sip_core=trace [default: info]"
+    )]
+    pub log_level: Option<String>,
+    #[arg(
+        long,
+        env = "SIPACKER_LOG_MAX_SIZE",
+        help = "Size in MB the log file is rotated at [default: 10, 0 for no limit]"
+    )]
+    pub log_max_size: Option<u64>,
+    #[arg(
+        long,
+        env = "SIPACKER_LOG_ROTATION",
+        help = "Time the log file is rotated at: never, hourly or daily [default: never]"
+    )]
+    pub log_rotation: Option<LogRotation>,
+    #[arg(
+        long,
+        env = "SIPACKER_LOG_KEEP",
+        help = "Rotated log files to keep (<file>.1 is the newest) [default: 5]"
+    )]
+    pub log_keep: Option<usize>,
+    #[arg(skip)]
+    pub instance: Option<String>,
+    #[command(subcommand)]
+    pub mode: Option<Mode>,
+}
+
+#[derive(Clone, clap::Subcommand)]
+pub enum Mode {
+    #[command(
+        about = "Runs without the terminal: the command lines are read from the clients of the Unix socket, the events and the command results are written to all of them"
+    )]
+    Daemon {
+        #[arg(
+            long,
+            env = "SIPACKER_CONTROL",
+            help = "Unix socket to listen for the clients, e.g. /run/sipacker.sock"
+        )]
+        control: PathBuf,
+    },
+    #[command(
+        about = "Installs, uninstalls or runs the Windows service: the commands come from the APIs and the script, the logs go to the Event Log"
+    )]
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::Subcommand)]
+pub enum ServiceAction {
+    #[command(
+        about = "Installs the service started at the boot with the options given before \"service\""
+    )]
+    Install,
+    #[command(about = "Stops and removes the service")]
+    Uninstall,
+    #[command(about = "Runs the service, it's started by the service manager")]
+    Run,
+}
+
+impl Args {
+    pub(crate) fn merge(mut self, config: &Config) -> Result<Self> {
+        let audio = &config.audio;
+        self.ip_addr = self.ip_addr.or(config.ip_addr);
+        self.port = self.port.or(config.port);
+        self.jobs = self.jobs.or(config.jobs);
+        if self.ptime.is_none() {
+            self.ptime = audio
+                .ptime_ms
+                .map(|ptime| parse_ptime(&ptime.to_string()).map_err(anyhow::Error::msg))
+                .transpose()?;
+        }
+        if self.audio_backend.is_none() {
+            self.audio_backend = audio.backend.as_deref().map(str::parse).transpose()?;
+        }
+        self.audio_in_file = self.audio_in_file.or(audio.in_file.clone());
+        self.audio_in_loop |= audio.in_loop;
+        self.audio_out_file = self.audio_out_file.or(audio.out_file.clone());
+        if self.input_channel.is_none() {
+            self.input_channel = audio.input_channel.as_deref().map(str::parse).transpose()?;
+        }
+        self.sample_rate = self.sample_rate.or(audio.sample_rate);
+        self.buffer_size = self.buffer_size.or(audio.buffer_size);
+        self.tui |= config.ui.tui;
+        self.no_color |= config.ui.color == Some(false);
+        if self.output.is_none() {
+            self.output = config.ui.output.as_deref().map(str::parse).transpose()?;
+        }
+        self.script = self.script.or(config.script.clone());
+        self.http_api = self.http_api.or(config.http_api);
+        self.command_fifo = self.command_fifo.or(config.command_fifo.clone());
+        self.grpc_api = self.grpc_api.or(config.grpc_api);
+        self.health_api = self.health_api.or(config.health_api);
+        self.audit_log = self.audit_log.or(config.audit_log.clone());
+        self.cdr = self.cdr.or(config.cdr.clone());
+        self.hooks = self.hooks.or(config.hooks.clone());
+        let log = &config.log;
+        self.log_file = self.log_file.or(log.file.clone());
+        if self.log_format.is_none() {
+            self.log_format = log.format.as_deref().map(str::parse).transpose()?;
+        }
+        self.log_level = self.log_level.or(log.level.clone());
+        self.log_max_size = self.log_max_size.or(log.max_size_mb);
+        if self.log_rotation.is_none() {
+            self.log_rotation = log.rotation.as_deref().map(str::parse).transpose()?;
+        }
+        self.log_keep = self.log_keep.or(log.keep);
+        if self.tui && self.control_socket().is_some() {
+            return Err(anyhow::Error::msg("The daemon can't be used with the TUI"));
+        }
+        if self.tui && self.output() == OutputFormat::Json {
+            return Err(anyhow::Error::msg(
+                "The JSON output can't be used with the TUI",
+            ));
+        }
+        let features = [
+            (self.tui, cfg!(feature = "tui"), "The TUI", "tui"),
+            (
+                self.http_api.is_some(),
+                cfg!(feature = "http"),
+                "The HTTP API",
+                "http",
+            ),
+            (
+                self.health_api.is_some(),
+                cfg!(feature = "http"),
+                "The health API",
+                "http",
+            ),
+            (
+                self.grpc_api.is_some(),
+                cfg!(feature = "grpc"),
+                "The gRPC API",
+                "grpc",
+            ),
+            (
+                self.hooks.is_some(),
+                cfg!(feature = "hooks"),
+                "The hooks",
+                "hooks",
+            ),
+            (
+                !self.webhooks.is_empty() || !config.webhooks.is_empty(),
+                cfg!(feature = "webhooks"),
+                "The webhooks",
+                "webhooks",
+            ),
+            (
+                self.keyring,
+                cfg!(feature = "keyring"),
+                "The keyring",
+                "keyring",
+            ),
+        ];
+        if let Some((_, _, what, feature)) = features
+            .into_iter()
+            .find(|(used, enabled, ..)| *used && !enabled)
+        {
+            return Err(feature_disabled(what, feature));
+        }
+        Ok(self)
+    }
+
+    // the args of [instances.<name>]: the listen address and the audio of the instance over
+    // the ones of the main user agent. The script, the APIs, the saved state and the records
+    // of the calls are left to the main user agent
+    pub(crate) fn for_instance(&self, name: &str, config: &Config) -> Result<Self> {
+        let args = Self {
+            ip_addr: None,
+            port: None,
+            ptime: None,
+            audio_backend: None,
+            audio_in_file: None,
+            audio_in_loop: false,
+            audio_out_file: None,
+            input_channel: None,
+            sample_rate: None,
+            buffer_size: None,
+            script: None,
+            http_api: None,
+            command_fifo: None,
+            grpc_api: None,
+            health_api: None,
+            audit_log: None,
+            cdr: None,
+            hooks: None,
+            webhooks: Vec::new(),
+            persist_state: false,
+            instance: Some(name.to_owned()),
+            mode: None,
+            ..self.clone()
+        }
+        .merge(config)?;
+        if args.port() == self.port() {
+            return Err(anyhow::Error::msg(format!(
+                "The instance {name} listens on the port of the main user agent: {}",
+                self.port()
+            )));
+        }
+        Ok(Self {
+            ip_addr: args.ip_addr.or(self.ip_addr),
+            ptime: args.ptime.or(self.ptime),
+            audio_backend: args.audio_backend.clone().or(self.audio_backend.clone()),
+            input_channel: args.input_channel.or(self.input_channel),
+            sample_rate: args.sample_rate.or(self.sample_rate),
+            buffer_size: args.buffer_size.or(self.buffer_size),
+            ..args
+        })
+    }
+
+    pub fn ip_addr(&self) -> Result<Ipv4Addr> {
+        self.ip_addr.ok_or(anyhow::Error::msg(
+            "The address to listen is not set: --ip-addr or ip_addr in the config",
+        ))
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(5060)
+    }
+
+    pub fn jobs(&self) -> usize {
+        self.jobs.unwrap_or(4)
+    }
+
+    pub fn ptime(&self) -> Duration {
+        self.ptime.unwrap_or(Duration::from_millis(20))
+    }
+
+    // the arg isn't merged to apply the config timeout on the reload
+    pub(crate) fn call_timeout(&self, config: &Config) -> Duration {
+        Duration::from_secs(
+            self.call_timeout
+                .or(config.timeouts.call_secs)
+                .unwrap_or(10),
+        )
+    }
+
+    pub fn control_socket(&self) -> Option<&Path> {
+        match &self.mode {
+            Some(Mode::Daemon { control }) => Some(control),
+            _ => None,
+        }
+    }
+
+    pub fn service_action(&self) -> Option<ServiceAction> {
+        match &self.mode {
+            Some(Mode::Service { action }) => Some(*action),
+            _ => None,
+        }
+    }
+
+    pub fn is_service(&self) -> bool {
+        self.service_action() == Some(ServiceAction::Run)
+    }
+
+    #[cfg(feature = "webhooks")]
+    pub(crate) fn webhooks(&self, config: &Config) -> Result<Vec<Webhook>> {
+        let mut webhooks: Vec<_> = self
+            .webhooks
+            .iter()
+            .map(|url| Webhook::new(url.clone(), Vec::new()))
+            .collect();
+        for webhook in &config.webhooks {
+            let events = webhook
+                .events
+                .iter()
+                .map(|event| event.parse::<WebhookEvent>())
+                .collect::<Result<_>>()?;
+            webhooks.push(Webhook::new(webhook.url.clone(), events));
+        }
+        Ok(webhooks)
+    }
+
+    pub(crate) fn log_file(&self) -> Option<LogFile> {
+        let max_size_mb = self.log_max_size.unwrap_or(10);
+        self.log_file.clone().map(|path| LogFile {
+            path,
+            format: self.log_format.unwrap_or_default(),
+            level: self.log_level.clone().unwrap_or_else(|| "info".to_owned()),
+            max_size: (max_size_mb > 0).then_some(max_size_mb * 1024 * 1024),
+            rotation: self.log_rotation.unwrap_or_default(),
+            keep: self.log_keep.unwrap_or(5),
+        })
+    }
+
+    pub fn output(&self) -> OutputFormat {
+        self.output.unwrap_or_default()
+    }
+
+    pub fn color(&self) -> bool {
+        !self.no_color && std::env::var_os("NO_COLOR").is_none()
+    }
+
+    pub fn audio_config(&self) -> AudioConfig {
+        AudioConfig {
+            backend: self.audio_backend.clone().unwrap_or(AudioBackend::Cpal),
+            files: AudioFiles {
+                input: self.audio_in_file.clone(),
+                input_looped: self.audio_in_loop,
+                output: self.audio_out_file.clone(),
+            },
+            capture_channel: self.input_channel.unwrap_or_default(),
+            sample_rate: self.sample_rate,
+            buffer_size: self.buffer_size,
+        }
+    }
+}
+
+pub(crate) fn feature_disabled(what: &str, feature: &str) -> anyhow::Error {
+    anyhow::Error::msg(format!(
+        "{what} is not available: sipacker is built without the \"{feature}\" feature"
+    ))
+}
+
+fn parse_ptime(s: &str) -> Result<Duration, String> {
+    match s.parse::<u64>() {
+        Ok(ptime @ (10 | 20 | 30 | 40)) => Ok(Duration::from_millis(ptime)),
+        _ => Err(format!("Unsupported ptime: {s}")),
+    }
 }
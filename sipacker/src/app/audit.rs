@@ -0,0 +1,63 @@
+use crate::app::command::CommandSource;
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde_json::json;
+
+// a JSON line per executed command: who (the OS user, the registered account and the input
+// the command came from), when, what and its result. The file is opened for appending only
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    file: File,
+    user: String,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| {
+                anyhow::Error::msg(format!("Could not open the audit log {path:?}: {err}"))
+            })?;
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_owned());
+        tracing::info!("Auditing the commands of {user} to {path:?}");
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            user,
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        source: CommandSource,
+        account: Option<String>,
+        command: &str,
+        result: &Result<()>,
+    ) {
+        let mut record = json!({
+            "timestamp": chrono::Local::now().to_rfc3339(),
+            "user": self.user,
+            "account": account,
+            "source": source.to_string(),
+            "command": command,
+            "result": if result.is_ok() { "ok" } else { "error" },
+        });
+        if let Err(err) = result {
+            record["error"] = err.to_string().into();
+        }
+        let written = writeln!(self.file, "{record}").and_then(|()| self.file.sync_data());
+        if let Err(err) = written {
+            tracing::error!("Could not write to the audit log {:?}: {err}", self.path);
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use sipacker_core::capture::{Direction, RtpHeader, SipMessage};
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use tokio::{sync::mpsc, task::JoinHandle};
+
+// the raw IPv4/IPv6 packets without the link layer
+const LINKTYPE_RAW: u32 = 101;
+const SNAPLEN: u32 = 65535;
+
+// writes the SIP messages and optionally the RTP headers of the calls to the pcap file with the
+// synthesized IP/UDP headers, so the capture is opened by Wireshark without tcpdump on the box
+#[derive(Default)]
+pub(crate) struct PacketCapture {
+    capture: Arc<Mutex<Option<Capture>>>,
+    rtp_task: Option<JoinHandle<()>>,
+}
+
+impl PacketCapture {
+    pub fn share(&self) -> Self {
+        Self {
+            capture: self.capture.clone(),
+            rtp_task: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        lock(&self.capture).is_some()
+    }
+
+    pub fn start(&mut self, path: &Path, rtp: Option<mpsc::Receiver<RtpHeader>>) -> Result<()> {
+        if self.is_active() {
+            return Err(anyhow::Error::msg("The capture is running already"));
+        }
+        let writer = PcapWriter::create(path).map_err(|err| {
+            anyhow::Error::msg(format!("Could not create the capture {path:?}: {err}"))
+        })?;
+        *lock(&self.capture) = Some(Capture::new(path, writer));
+        self.rtp_task = rtp.map(|mut rtp| {
+            let capture = self.capture.clone();
+            tokio::spawn(async move {
+                while let Some(header) = rtp.recv().await {
+                    if let Some(capture) = lock(&capture).as_mut() {
+                        capture.write_rtp(&header);
+                    }
+                }
+            })
+        });
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> Result<(PathBuf, usize)> {
+        if let Some(task) = self.rtp_task.take() {
+            task.abort();
+        }
+        let capture = lock(&self.capture)
+            .take()
+            .ok_or(anyhow::Error::msg("The capture is not running"))?;
+        Ok((capture.path, capture.packets))
+    }
+
+    pub fn write_sip(&self, message: &SipMessage) {
+        if let Some(capture) = lock(&self.capture).as_mut() {
+            capture.write_sip(message);
+        }
+    }
+}
+
+fn lock(capture: &Mutex<Option<Capture>>) -> std::sync::MutexGuard<'_, Option<Capture>> {
+    capture.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+struct Capture {
+    path: PathBuf,
+    writer: PcapWriter,
+    packets: usize,
+}
+
+impl Capture {
+    fn new(path: &Path, writer: PcapWriter) -> Self {
+        Self {
+            path: path.to_owned(),
+            writer,
+            packets: 0,
+        }
+    }
+
+    fn write_sip(&mut self, message: &SipMessage) {
+        let (src, dst) = match message.direction {
+            Direction::Sent => (message.local, message.remote),
+            Direction::Received => (message.remote, message.local),
+        };
+        self.write(message.time, src, dst, &message.bytes, 0);
+    }
+
+    // the RTP packets are truncated to the header, the lengths of IP and UDP are the original
+    fn write_rtp(&mut self, header: &RtpHeader) {
+        let (src, dst) = match header.direction {
+            Direction::Sent => (header.local, header.remote),
+            Direction::Received => (header.remote, header.local),
+        };
+        let truncated = header.packet_len - header.header.len();
+        self.write(header.time, src, dst, &header.header, truncated);
+    }
+
+    fn write(
+        &mut self,
+        time: SystemTime,
+        src: SocketAddr,
+        dst: SocketAddr,
+        data: &[u8],
+        truncated: usize,
+    ) {
+        match self.writer.write_udp(time, src, dst, data, truncated) {
+            Ok(()) => self.packets += 1,
+            Err(err) => tracing::warn!("Could not write to the capture {:?}: {err}", self.path),
+        }
+    }
+}
+
+// the classic pcap file, each packet is written at once so the file is readable during the capture
+struct PcapWriter {
+    file: File,
+    ip_id: u16,
+}
+
+impl PcapWriter {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        let mut header = Vec::with_capacity(24);
+        // the microsecond timestamps in the little endian
+        header.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&0i32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        file.write_all(&header)?;
+        Ok(Self { file, ip_id: 0 })
+    }
+
+    fn write_udp(
+        &mut self,
+        time: SystemTime,
+        src: SocketAddr,
+        dst: SocketAddr,
+        data: &[u8],
+        truncated: usize,
+    ) -> io::Result<()> {
+        let udp_len = 8 + data.len() + truncated;
+        let mut packet = match (src.ip(), dst.ip()) {
+            (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) => {
+                self.ip_id = self.ip_id.wrapping_add(1);
+                ipv4_header(src_ip, dst_ip, udp_len, self.ip_id)
+            }
+            (src_ip, dst_ip) => ipv6_header(to_ipv6(src_ip), to_ipv6(dst_ip), udp_len),
+        };
+        let udp_start = packet.len();
+        packet.extend_from_slice(&src.port().to_be_bytes());
+        packet.extend_from_slice(&dst.port().to_be_bytes());
+        packet.extend_from_slice(&(udp_len as u16).to_be_bytes());
+        packet.extend_from_slice(&0u16.to_be_bytes());
+        packet.extend_from_slice(data);
+        // the checksum is optional over IPv4 and can't be computed over the truncated data
+        let is_ipv6 = packet[0] >> 4 == 6;
+        if is_ipv6 && truncated == 0 {
+            let checksum = udp6_checksum(&packet[8..40], &packet[udp_start..]);
+            packet[udp_start + 6..udp_start + 8].copy_from_slice(&checksum.to_be_bytes());
+        }
+
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut record = Vec::with_capacity(16 + packet.len());
+        record.extend_from_slice(&(since_epoch.as_secs() as u32).to_le_bytes());
+        record.extend_from_slice(&since_epoch.subsec_micros().to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        record.extend_from_slice(&((packet.len() + truncated) as u32).to_le_bytes());
+        record.extend_from_slice(&packet);
+        self.file.write_all(&record)
+    }
+}
+
+// the mixed families are written as IPv6 with the IPv4-mapped address
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+fn ipv4_header(src: Ipv4Addr, dst: Ipv4Addr, udp_len: usize, id: u16) -> Vec<u8> {
+    let mut header = Vec::with_capacity(20);
+    header.extend_from_slice(&[0x45, 0]);
+    header.extend_from_slice(&((20 + udp_len) as u16).to_be_bytes());
+    header.extend_from_slice(&id.to_be_bytes());
+    // don't fragment, TTL 64, UDP
+    header.extend_from_slice(&[0x40, 0, 64, 17, 0, 0]);
+    header.extend_from_slice(&src.octets());
+    header.extend_from_slice(&dst.octets());
+    let checksum = checksum(&header, 0);
+    header[10..12].copy_from_slice(&checksum.to_be_bytes());
+    header
+}
+
+fn ipv6_header(src: Ipv6Addr, dst: Ipv6Addr, udp_len: usize) -> Vec<u8> {
+    let mut header = Vec::with_capacity(40);
+    header.extend_from_slice(&0x6000_0000u32.to_be_bytes());
+    header.extend_from_slice(&(udp_len as u16).to_be_bytes());
+    // UDP, hop limit 64
+    header.extend_from_slice(&[17, 64]);
+    header.extend_from_slice(&src.octets());
+    header.extend_from_slice(&dst.octets());
+    header
+}
+
+// over the pseudo-header of the addresses, the length and the next header, RFC 8200 8.1
+fn udp6_checksum(addresses: &[u8], udp: &[u8]) -> u16 {
+    let mut pseudo_header = addresses.to_vec();
+    pseudo_header.extend_from_slice(&(udp.len() as u32).to_be_bytes());
+    pseudo_header.extend_from_slice(&17u32.to_be_bytes());
+    let sum = checksum_sum(&pseudo_header, 0);
+    match checksum(udp, sum) {
+        // the zero checksum means no checksum
+        0 => 0xffff,
+        checksum => checksum,
+    }
+}
+
+// the one's complement of the one's complement sum of the 16-bit words, RFC 1071
+fn checksum(data: &[u8], initial: u32) -> u16 {
+    let mut sum = checksum_sum(data, initial);
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn checksum_sum(data: &[u8], initial: u32) -> u32 {
+    data.chunks(2).fold(initial, |sum, word| {
+        let word = u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]);
+        let sum = sum + word as u32;
+        (sum & 0xffff) + (sum >> 16)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sipacker-{}-{name}.pcap", std::process::id()))
+    }
+
+    fn written(path: &Path) -> Vec<u8> {
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn create_writes_the_global_header() {
+        let path = temp_path("header");
+        drop(PcapWriter::create(&path).unwrap());
+        assert_eq!(
+            written(&path),
+            [
+                0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 101,
+                0, 0, 0,
+            ]
+        );
+    }
+
+    #[test]
+    fn write_udp_writes_the_ipv4_record() {
+        let path = temp_path("ipv4");
+        let mut writer = PcapWriter::create(&path).unwrap();
+        let time = UNIX_EPOCH + Duration::from_micros(1_500_000);
+        let src = SocketAddr::from(([192, 168, 0, 1], 5060));
+        let dst = SocketAddr::from(([192, 168, 0, 199], 5062));
+        writer.write_udp(time, src, dst, b"abc", 0).unwrap();
+        drop(writer);
+        let bytes = written(&path);
+        assert_eq!(
+            bytes[24..],
+            [
+                // 1.5 s since the epoch, 31 bytes captured of 31
+                1, 0, 0, 0, 0x20, 0xa1, 0x07, 0, 31, 0, 0, 0, 31, 0, 0, 0,
+                // IPv4 of the id 1
+                0x45, 0, 0, 31, 0, 1, 0x40, 0, 64, 17, 0xb8, 0xb4, 192, 168, 0, 1, 192, 168, 0,
+                199,
+                // UDP without the checksum
+                0x13, 0xc4, 0x13, 0xc6, 0, 11, 0, 0, b'a', b'b', b'c',
+            ]
+        );
+    }
+
+    #[test]
+    fn write_udp_keeps_the_length_of_the_truncated_data() {
+        let path = temp_path("truncated");
+        let mut writer = PcapWriter::create(&path).unwrap();
+        let src = SocketAddr::from(([10, 0, 0, 1], 4000));
+        let dst = SocketAddr::from(([10, 0, 0, 2], 4002));
+        writer.write_udp(UNIX_EPOCH, src, dst, &[0x80; 12], 160).unwrap();
+        drop(writer);
+        let bytes = written(&path);
+        let captured = u32::from_le_bytes(bytes[32..36].try_into().unwrap());
+        let original = u32::from_le_bytes(bytes[36..40].try_into().unwrap());
+        assert_eq!((captured, original), (40, 200));
+        // the lengths of IP and UDP are the ones of the whole packet
+        assert_eq!(bytes[42..44], 200u16.to_be_bytes());
+        assert_eq!(bytes[64..66], 180u16.to_be_bytes());
+        assert_eq!(bytes.len(), 24 + 16 + 40);
+    }
+
+    #[test]
+    fn write_udp_writes_the_ipv6_record_with_the_checksum() {
+        let path = temp_path("ipv6");
+        let mut writer = PcapWriter::create(&path).unwrap();
+        let src = SocketAddr::from(("2001:db8::1".parse::<Ipv6Addr>().unwrap(), 5060));
+        let dst = SocketAddr::from(("2001:db8::2".parse::<Ipv6Addr>().unwrap(), 5062));
+        writer.write_udp(UNIX_EPOCH, src, dst, b"abc", 0).unwrap();
+        drop(writer);
+        let bytes = written(&path);
+        let packet = &bytes[40..];
+        assert_eq!(packet.len(), 40 + 11);
+        assert_eq!(packet[..8], [0x60, 0, 0, 0, 0, 11, 17, 64]);
+        assert_eq!(packet[40..48], [0x13, 0xc4, 0x13, 0xc6, 0, 11, 0xb8, 0x76]);
+    }
+
+    #[test]
+    fn write_udp_maps_the_mixed_families_to_ipv6() {
+        let path = temp_path("mixed");
+        let mut writer = PcapWriter::create(&path).unwrap();
+        let src = SocketAddr::from(([192, 168, 0, 1], 5060));
+        let dst = SocketAddr::from((Ipv6Addr::LOCALHOST, 5060));
+        writer.write_udp(UNIX_EPOCH, src, dst, b"abc", 0).unwrap();
+        drop(writer);
+        let bytes = written(&path);
+        assert_eq!(bytes[40] >> 4, 6);
+        assert_eq!(
+            bytes[48..64],
+            Ipv4Addr::new(192, 168, 0, 1).to_ipv6_mapped().octets()
+        );
+    }
+
+    #[test]
+    fn ipv4_header_has_the_checksum_of_the_reference_packet() {
+        // the example IPv4 header of 192.168.0.1 -> 192.168.0.199 with the checksum b861
+        let header = ipv4_header(
+            Ipv4Addr::new(192, 168, 0, 1),
+            Ipv4Addr::new(192, 168, 0, 199),
+            0x73 - 20,
+            0,
+        );
+        assert_eq!(
+            header,
+            [
+                0x45, 0, 0, 0x73, 0, 0, 0x40, 0, 0x40, 0x11, 0xb8, 0x61, 0xc0, 0xa8, 0, 0x01, 0xc0,
+                0xa8, 0, 0xc7,
+            ]
+        );
+        // the header with its checksum sums to zero
+        assert_eq!(checksum(&header, 0), 0);
+    }
+
+    #[test]
+    fn ipv6_header_has_the_payload_length() {
+        let header = ipv6_header(Ipv6Addr::LOCALHOST, Ipv6Addr::UNSPECIFIED, 200);
+        assert_eq!(header.len(), 40);
+        assert_eq!(header[..8], [0x60, 0, 0, 0, 0, 200, 17, 64]);
+        assert_eq!(header[8..24], Ipv6Addr::LOCALHOST.octets());
+        assert_eq!(header[24..40], [0; 16]);
+    }
+
+    #[test]
+    fn udp6_checksum_covers_the_pseudo_header() {
+        let mut addresses = Vec::new();
+        addresses.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        addresses.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        let udp = [0x13, 0xc4, 0x13, 0xc6, 0, 11, 0, 0, b'a', b'b', b'c'];
+        assert_eq!(udp6_checksum(&addresses, &udp), 0xb876);
+    }
+
+    #[test]
+    fn checksum_sums_the_words_of_rfc_1071() {
+        // the example of RFC 1071 3, the odd byte is padded with zero
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(checksum_sum(&data, 0), 0xddf2);
+        assert_eq!(checksum(&data, 0), 0x220d);
+        assert_eq!(checksum_sum(&[0x12], 0), 0x1200);
+        // the carries are folded into the sum
+        assert_eq!(checksum_sum(&[0xff, 0xff, 0x00, 0x02], 0), 0x0002);
+        assert_eq!(checksum(&[], 0xddf2), 0x220d);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::app::history::{misc, Direction};
+use sipacker_core::{stats::CallStats, user_agent::CallId};
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde_json::{json, Value};
+
+// the audio channel supports only PCMA
+const CODEC: &str = "PCMA";
+const CSV_HEADER: &str = "call_id,direction,remote_uri,started,answered,ended,duration_secs,sip_code,reason,codec,mos,r_factor,loss_percent,jitter_ms,packets_sent,packets_received";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CdrFormat {
+    Csv,
+    JsonLines,
+}
+
+pub(crate) struct CdrWriter {
+    path: PathBuf,
+    file: File,
+    format: CdrFormat,
+    active: Option<CallDetail>,
+}
+
+struct CallDetail {
+    call_id: CallId,
+    direction: Direction,
+    remote_uri: String,
+    started: DateTime<Local>,
+    answered: Option<DateTime<Local>>,
+    reason: Option<String>,
+    stats: Option<CallStats>,
+}
+
+impl CdrWriter {
+    pub fn open(path: &Path) -> Result<Self> {
+        let format = if path.extension().is_some_and(|ext| ext == "csv") {
+            CdrFormat::Csv
+        } else {
+            CdrFormat::JsonLines
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| anyhow::Error::msg(format!("Could not open the CDR {path:?}: {err}")))?;
+        if format == CdrFormat::Csv && file.metadata()?.len() == 0 {
+            writeln!(file, "{CSV_HEADER}")?;
+        }
+        tracing::info!("Call detail records are written to {path:?}");
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            format,
+            active: None,
+        })
+    }
+
+    pub fn start(&mut self, call_id: CallId, direction: Direction, remote_uri: String) {
+        self.active = Some(CallDetail {
+            call_id,
+            direction,
+            remote_uri,
+            started: Local::now(),
+            answered: None,
+            reason: None,
+            stats: None,
+        });
+    }
+
+    pub fn answer(&mut self) {
+        if let Some(detail) = self.active.as_mut() {
+            detail.answered = Some(Local::now());
+        }
+    }
+
+    pub fn fail(&mut self, reason: &str) {
+        if let Some(detail) = self.active.as_mut() {
+            detail.reason = Some(reason.to_owned());
+        }
+    }
+
+    pub fn set_stats(&mut self, stats: CallStats) {
+        if let Some(detail) = self.active.as_mut() {
+            detail.stats = Some(stats);
+        }
+    }
+
+    pub fn end(&mut self, sip_code: Option<u16>) {
+        let Some(detail) = self.active.take() else {
+            return;
+        };
+        let record = detail.record(Local::now(), sip_code);
+        let line = match self.format {
+            CdrFormat::JsonLines => record.to_string(),
+            CdrFormat::Csv => csv_line(&record),
+        };
+        let written = writeln!(self.file, "{line}").and_then(|()| self.file.sync_data());
+        if let Err(err) = written {
+            tracing::error!("Could not write to the CDR {:?}: {err}", self.path);
+        }
+    }
+}
+
+impl CallDetail {
+    fn record(&self, ended: DateTime<Local>, sip_code: Option<u16>) -> Value {
+        let duration = self
+            .answered
+            .and_then(|answered| (ended - answered).to_std().ok())
+            .map_or(0, |duration| duration.as_secs());
+        let quality = self.stats.as_ref().map(CallStats::quality);
+        json!({
+            "call_id": self.call_id,
+            "direction": self.direction,
+            "remote_uri": self.remote_uri,
+            "started": self.started.to_rfc3339(),
+            "answered": self.answered.map(|answered| answered.to_rfc3339()),
+            "ended": ended.to_rfc3339(),
+            "duration_secs": duration,
+            "sip_code": sip_code,
+            "reason": self.reason,
+            "codec": CODEC,
+            "mos": quality.as_ref().map(|quality| (quality.mos * 100.0).round() / 100.0),
+            "r_factor": quality.as_ref().map(|quality| quality.r_factor.round()),
+            "loss_percent": self.stats.as_ref().map(|stats| stats.loss_percent),
+            "jitter_ms": self.stats.as_ref().map(|stats| stats.jitter_ms),
+            "packets_sent": self.stats.as_ref().map(|stats| stats.packets_sent),
+            "packets_received": self.stats.as_ref().map(|stats| stats.packets_received),
+        })
+    }
+}
+
+fn csv_line(record: &Value) -> String {
+    let fields: Vec<_> = CSV_HEADER
+        .split(',')
+        .map(|column| match &record[column] {
+            Value::Null => String::new(),
+            Value::String(value) => misc::csv_field(value),
+            value => value.to_string(),
+        })
+        .collect();
+    fields.join(",")
+}
@@ -1,94 +1,448 @@
-use std::{thread, time::Duration};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
 
-use crate::app::command::{self, Command};
+use crate::app::{
+    answering_machine::AnsweringMachine,
+    command::{self, Command},
+    completion::{CommandCompleter, Completions},
+    console::Console,
+    plugin::{CommandPlugin, Plugins},
+};
+use sipacker_core::{dtmf, player::PlaybackMode, recorder::RecordingMode, user_agent::SrtpPolicy};
 
 use anyhow::Result;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal,
+};
 use enum_dispatch::enum_dispatch;
-use ezk_sip_auth::DigestUser;
+use rustyline::{error::ReadlineError, history::DefaultHistory, Editor};
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
-pub(crate) fn run_input_system() -> mpsc::Receiver<Command> {
+pub(crate) const HOTKEYS_HELP: &str = "Hotkeys: m - mute/unmute, t - terminate call, \
+    y/n - confirm/cancel, 0-9 * # - DTMF, Esc - back to the commands (the hold isn't supported, ezk-sip has no re-INVITE)";
+// the shutdown is checked that often in the hotkeys mode
+const HOTKEYS_POLL_TIMEOUT: Duration = Duration::from_millis(200);
+
+pub(crate) fn hotkey_command(key: char) -> Option<Command> {
+    match key {
+        'm' => Some(command::SetMute::new(None).into()),
+        't' => Some(command::TerminateCall::new(None).into()),
+        'y' => Some(command::Confirm::new(true).into()),
+        'n' => Some(command::Confirm::new(false).into()),
+        '0'..='9' | '*' | '#' => Some(command::SendDtmf::new(&key.to_string()).into()),
+        _ => None,
+    }
+}
+
+// the blocking stdin reading runs on a dedicated thread, it stops after the shutdown is
+// signaled or the app drops the receiver
+pub(crate) fn run_input_system(
+    shutdown: CancellationToken,
+    command_line: CommandLine,
+    input: SharedInput,
+    console: Console,
+    show_prompt: bool,
+) -> mpsc::Receiver<Command> {
     let (command_sender, command_receiver) = mpsc::channel(20);
-    thread::spawn(|| run_input_system_inner(command_sender));
+    thread::spawn(move || {
+        run_input_system_inner(
+            command_sender,
+            shutdown,
+            command_line,
+            input,
+            console,
+            show_prompt,
+        )
+    });
     command_receiver
 }
 
-fn run_input_system_inner(command_sender: mpsc::Sender<Command>) {
-    let mut input_system = CliInputSystem::new(command_sender);
-    if let Err(err) = input_system.run() {
+fn run_input_system_inner(
+    command_sender: mpsc::Sender<Command>,
+    shutdown: CancellationToken,
+    command_line: CommandLine,
+    input: SharedInput,
+    console: Console,
+    show_prompt: bool,
+) {
+    let result = CliInputSystem::new(
+        command_sender,
+        shutdown,
+        command_line,
+        input,
+        console,
+        show_prompt,
+    )
+    .and_then(|mut input_system| input_system.run());
+    if let Err(err) = result {
         tracing::error!("CLI input system err: {err}");
     }
 }
 
 struct CliInputSystem {
     command_sender: mpsc::Sender<Command>,
+    shutdown: CancellationToken,
+    command_line: CommandLine,
+    console: Console,
+    prompt: Option<Prompt>,
+    editor: Editor<CommandCompleter, DefaultHistory>,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct SharedInput {
+    pub aliases: Aliases,
+    pub plugins: Plugins,
+    pub prompt: Prompt,
+    pub completions: Completions,
+}
+
+pub(crate) struct CommandLine {
     parsers: Vec<CommandParser>,
+    aliases: Aliases,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Aliases(Arc<RwLock<BTreeMap<String, String>>>);
+
+impl Aliases {
+    pub fn new(aliases: BTreeMap<String, String>) -> Self {
+        Self(Arc::new(RwLock::new(aliases)))
+    }
+
+    pub fn set(&self, aliases: BTreeMap<String, String>) {
+        *self.0.write().unwrap_or_else(|err| err.into_inner()) = aliases;
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, String>> {
+        self.0.read().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+// the registration and the call shown by the stdin prompt, updated by the app on the events.
+// The prompt is rendered when the line is read, the call timer isn't running while typing
+#[derive(Clone, Default)]
+pub(crate) struct Prompt(Arc<RwLock<PromptState>>);
+
+#[derive(Default)]
+struct PromptState {
+    registration: Option<String>,
+    call: Option<(String, Instant)>,
+}
+
+impl Prompt {
+    pub fn set_registration(&self, registration: Option<String>) {
+        self.write().registration = registration;
+    }
+
+    pub fn set_call(&self, remote: Option<String>) {
+        self.write().call = remote.map(|remote| (remote, Instant::now()));
+    }
+
+    fn render(&self) -> String {
+        let state = self.0.read().unwrap_or_else(|err| err.into_inner());
+        let registration = match &state.registration {
+            Some(registration) => format!("{registration} ✔"),
+            None => "not registered".to_owned(),
+        };
+        let call = match &state.call {
+            Some((remote, since)) => {
+                let secs = since.elapsed().as_secs();
+                format!("call: {remote} {:02}:{:02}", secs / 60, secs % 60)
+            }
+            None => "no call".to_owned(),
+        };
+        format!("[{registration} | {call}] > ")
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, PromptState> {
+        self.0.write().unwrap_or_else(|err| err.into_inner())
+    }
 }
 
 impl CliInputSystem {
-    pub fn new(command_sender: mpsc::Sender<Command>) -> Self {
-        let parsers = vec![
-            RegisterParser::new().into(),
-            UnregisterParser::new().into(),
-            MakeCallParser::new().into(),
-            AcceptCallParser::new().into(),
-            DeclineCallParser::new().into(),
-            TerminateCallParser::new().into(),
-        ];
-        Self {
+    pub fn new(
+        command_sender: mpsc::Sender<Command>,
+        shutdown: CancellationToken,
+        command_line: CommandLine,
+        input: SharedInput,
+        console: Console,
+        show_prompt: bool,
+    ) -> Result<Self> {
+        let mut editor = Editor::new()?;
+        editor.set_helper(Some(CommandCompleter::new(
+            command_line.specs(),
+            input.completions,
+        )));
+        if let Some(path) = Self::history_path() {
+            // the history file doesn't exist at the first start
+            let _ = editor
+                .load_history(&path)
+                .inspect_err(|err| tracing::debug!("Could not load command history: {err}"));
+        }
+        Ok(Self {
             command_sender,
-            parsers,
+            shutdown,
+            command_line,
+            console,
+            prompt: show_prompt.then_some(input.prompt),
+            editor,
+        })
+    }
+
+    fn history_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("sipacker").join("command_history.txt"))
+    }
+
+    // the history is saved with every line, the input thread isn't joined at the exit
+    fn add_history_entry(&mut self, line: &str) {
+        // the passwords aren't kept
+        if line.trim().is_empty() || line.contains("password=") {
+            return;
+        }
+        let _ = self.editor.add_history_entry(line);
+        let Some(path) = Self::history_path() else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(err) = self.editor.save_history(&path) {
+            tracing::warn!("Could not save command history: {err}");
         }
     }
 
     pub fn run(&mut self) -> Result<()> {
         tracing::info!("The CLI input system is running");
-        loop {
-            let command = self.read_command();
-            if let Some(command) = command {
-                self.send_command(command);
+        while !self.shutdown.is_cancelled() {
+            let Some(command) = self.read_command()? else {
+                continue;
+            };
+            if !self.send_command(command) {
+                break;
             }
-
-            thread::sleep(Duration::from_secs(1));
         }
+        tracing::info!("The CLI input system is stopped");
+        Ok(())
     }
 
-    fn send_command<C: Into<Command>>(&mut self, command: C) {
+    fn send_command<C: Into<Command>>(&mut self, command: C) -> bool {
         let result = self.command_sender.blocking_send(command.into());
         match result {
-            Ok(_) => (),
+            Ok(_) => true,
             Err(err) => {
-                tracing::error!("CLI input system err: {err}");
+                tracing::debug!("CLI input system err: {err}");
+                false
             }
         }
     }
 
-    fn read_command(&mut self) -> Option<Command> {
-        let line = misc::read_stdin_line()?;
-        if line.starts_with("help") {
-            self.print_help();
-            None
-        } else {
-            self.parse_command(line)
+    fn run_hotkeys(&mut self) -> Result<()> {
+        println!("{HOTKEYS_HELP}");
+        terminal::enable_raw_mode()?;
+        let result = self.read_hotkeys();
+        terminal::disable_raw_mode()?;
+        result
+    }
+
+    fn read_hotkeys(&mut self) -> Result<()> {
+        while !self.shutdown.is_cancelled() {
+            if !event::poll(HOTKEYS_POLL_TIMEOUT)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let command = match key.code {
+                KeyCode::Esc => break,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                KeyCode::Char(key) => hotkey_command(key),
+                _ => None,
+            };
+            if let Some(command) = command {
+                if !self.send_command(command) {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_command(&mut self) -> Result<Option<Command>> {
+        let prompt = self
+            .prompt
+            .as_ref()
+            .map(|prompt| prompt.render())
+            .unwrap_or_default();
+        let mut line = match self.editor.readline(&prompt) {
+            Ok(line) => line,
+            // Ctrl-C and Ctrl-D stop the app even if there is an active call
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
+                return Ok(Some(command::StopApp::new(true).into()));
+            }
+            Err(err) => return Err(err.into()),
+        };
+        self.add_history_entry(&line);
+        if let Some(help) = self.command_line.help_line(&line) {
+            match help {
+                Ok(help) => println!("{help}"),
+                Err(err) => println!("{err}"),
+            }
+            return Ok(None);
+        }
+        if line.trim() == "keys" {
+            self.run_hotkeys()?;
+            return Ok(None);
+        }
+        // the accidental Enter doesn't stop the app
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        misc::trim_newline(&mut line);
+
+        Ok(self
+            .command_line
+            .parse(&line)
+            .inspect_err(|err| {
+                tracing::warn!("CLI input system parser err: {err:?}");
+                self.console.print_error(err);
+            })
+            .ok())
+    }
+}
+
+impl CommandLine {
+    pub fn new(aliases: Aliases, plugins: &Plugins, password_prompt: bool) -> Self {
+        let mut parsers: Vec<CommandParser> = vec![
+            RegisterParser::new(password_prompt).into(),
+            UnregisterParser::new().into(),
+            MakeCallParser::new().into(),
+            VoicemailParser::new().into(),
+            ContactAddParser::new().into(),
+            ContactsParser::new().into(),
+            // precedes the history parser that would take "export" as a field
+            HistoryExportParser::new().into(),
+            HistoryParser::new().into(),
+            AcceptCallParser::new().into(),
+            DeclineCallParser::new().into(),
+            TerminateCallParser::new().into(),
+            TransferParser::new().into(),
+            RecordParser::new().into(),
+            StopRecordParser::new().into(),
+            PlayParser::new().into(),
+            StopPlayParser::new().into(),
+            DtmfParser::new().into(),
+            MessageParser::new().into(),
+            PresenceParser::new().into(),
+            PingParser::new().into(),
+            AnsweringMachineParser::new().into(),
+            MeterParser::new().into(),
+            MuteParser::new().into(),
+            LoopbackParser::new().into(),
+            TraceSipParser::new().into(),
+            CaptureParser::new().into(),
+            StopCaptureParser::new().into(),
+            VolumeParser::new().into(),
+            CallStatsParser::new().into(),
+            AudioStatsParser::new().into(),
+            FlowParser::new().into(),
+            StatusParser::new().into(),
+            VersionParser::new().into(),
+            SourceParser::new().into(),
+            ReloadParser::new().into(),
+            QuitParser::new().into(),
+            ConfirmParser::new().into(),
+        ];
+        // the built-in commands take precedence
+        parsers.extend(
+            plugins
+                .iter()
+                .map(|plugin| PluginParser::new(plugin.clone()).into()),
+        );
+        Self { parsers, aliases }
+    }
+
+    pub fn help_line(&self, line: &str) -> Option<Result<String>> {
+        let topic = line.trim().strip_prefix("help")?;
+        if !topic.is_empty() && !topic.starts_with(' ') {
+            return None;
         }
+        let topic = topic.trim();
+        Some(if topic.is_empty() {
+            Ok(self.help())
+        } else {
+            self.command_help(topic)
+        })
+    }
+
+    pub fn specs(&self) -> Vec<&'static CommandSpec> {
+        self.parsers.iter().map(|parser| parser.spec()).collect()
     }
 
-    fn print_help(&self) {
-        println!("==== Help ====");
+    pub fn help(&self) -> String {
+        let mut help = "==== Help ====".to_owned();
         for parser in &self.parsers {
-            println!("\t {}", parser.get_help());
+            help.push_str(&format!("\n\t {}", parser.spec().usage()));
         }
+        help.push_str("\n\t help <command>");
+        help.push_str("\n\t keys");
+        let aliases = self.aliases.read();
+        if !aliases.is_empty() {
+            help.push_str("\n==== Aliases ====");
+            for (name, expansion) in aliases.iter() {
+                help.push_str(&format!("\n\t {name} = {expansion}"));
+            }
+        }
+        help
     }
 
-    fn parse_command(&self, mut line: String) -> Option<Command> {
-        if line.is_empty() {
-            return Some(command::StopApp::new().into());
+    pub fn command_help(&self, topic: &str) -> Result<String> {
+        let topic = topic.split_whitespace().collect::<Vec<_>>().join(" ");
+        let specs: Vec<_> = self.parsers.iter().map(|parser| parser.spec()).collect();
+        let matched: Vec<_> = match specs.iter().find(|spec| spec.name == topic) {
+            Some(spec) => vec![*spec],
+            None => specs
+                .into_iter()
+                .filter(|spec| {
+                    spec.name
+                        .strip_prefix(topic.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+                })
+                .collect(),
+        };
+        if matched.is_empty() {
+            return Err(anyhow::Error::msg(format!("Unknown command: {topic}")));
         }
-        misc::trim_newline(&mut line);
+        Ok(matched
+            .iter()
+            .map(|spec| spec.help())
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
 
+    pub fn parse(&self, line: &str) -> Result<Command> {
+        let line = self.expand_alias(line);
+        let line = line.as_str();
+        if let Some(rest) = line.strip_prefix('@') {
+            let (name, command) =
+                rest.split_once(char::is_whitespace)
+                    .ok_or(anyhow::Error::msg(
+                        "The command of the instance is missing: @<name> <command>",
+                    ))?;
+            return Ok(command::ForInstance::new(name, self.parse(command.trim_start())?).into());
+        }
         // skip CommandParserError::Command error, try to find a parser for a command with a specified name
         let result = self.parsers.iter().find_map(|parser| {
-            let result = parser.parse(&line);
+            let result = parser.parse(line);
             if result.is_ok()
                 || result
                     .as_ref()
@@ -101,15 +455,44 @@ impl CliInputSystem {
         });
 
         match result {
-            Some(result) => result
-                .inspect_err(|err| {
-                    tracing::warn!("CLI input system parser err: {err:?}");
-                })
-                .ok(),
-            None => {
-                tracing::warn!("Unknown command");
-                None
-            }
+            Some(Ok(command)) => Ok(command),
+            Some(Err(CommandParserError::Arguments(err))) => Err(anyhow::Error::msg(err)),
+            Some(Err(CommandParserError::Command)) | None => match self.suggest(line) {
+                Some((typed, name)) => Err(anyhow::Error::msg(format!(
+                    "Unknown command '{typed}', did you mean '{name}'?"
+                ))),
+                None => Err(anyhow::Error::msg("Unknown command")),
+            },
+        }
+    }
+
+    fn suggest(&self, line: &str) -> Option<(String, &'static str)> {
+        let words: Vec<_> = line.split_whitespace().collect();
+        self.specs()
+            .into_iter()
+            .map(|spec| {
+                let count = spec.name.split(' ').count().min(words.len());
+                let typed = words[..count].join(" ");
+                (misc::edit_distance(&typed, spec.name), typed, spec.name)
+            })
+            .filter(|(distance, _, name)| *distance <= (name.len() / 3).max(2))
+            .min_by_key(|(distance, _, _)| *distance)
+            .map(|(_, typed, name)| (typed, name))
+    }
+
+    fn expand_alias(&self, line: &str) -> String {
+        let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let aliases = self.aliases.read();
+        let Some(expansion) = aliases.get(name) else {
+            return line.to_owned();
+        };
+        let rest = rest.trim_start();
+        if rest.is_empty() {
+            expansion.clone()
+        } else if expansion.ends_with('=') {
+            format!("{expansion}{rest}")
+        } else {
+            format!("{expansion} {rest}")
         }
     }
 }
@@ -120,10 +503,75 @@ enum CommandParserError {
     Arguments(String),
 }
 
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub args: &'static [ArgSpec],
+    pub examples: &'static [&'static str],
+}
+
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub value: &'static str,
+    pub description: &'static str,
+    pub default: Option<&'static str>,
+    pub required: bool,
+}
+
+impl CommandSpec {
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_owned();
+        for arg in self.args {
+            let arg_usage = arg.usage();
+            if arg.required {
+                usage.push_str(&format!(" {arg_usage}"));
+            } else {
+                usage.push_str(&format!(" [{arg_usage}]"));
+            }
+        }
+        usage
+    }
+
+    pub fn help(&self) -> String {
+        let mut help = format!(
+            "==== {} ====\n{}\nUsage: {}",
+            self.name,
+            self.summary,
+            self.usage()
+        );
+        if !self.args.is_empty() {
+            help.push_str("\nArguments:");
+            for arg in self.args {
+                help.push_str(&format!("\n\t {} - {}", arg.usage(), arg.description));
+                if let Some(default) = arg.default {
+                    help.push_str(&format!(" (default: {default})"));
+                }
+            }
+        }
+        if !self.examples.is_empty() {
+            help.push_str("\nExamples:");
+            for example in self.examples {
+                help.push_str(&format!("\n\t {example}"));
+            }
+        }
+        help
+    }
+}
+
+impl ArgSpec {
+    fn usage(&self) -> String {
+        if self.name.is_empty() {
+            self.value.to_owned()
+        } else {
+            format!("{}={}", self.name, self.value)
+        }
+    }
+}
+
 #[enum_dispatch()]
 trait CommandParserTrait {
     fn parse(&self, line: &str) -> Result<Command, CommandParserError>;
-    fn get_help(&self) -> &str;
+    fn spec(&self) -> &'static CommandSpec;
 }
 
 #[enum_dispatch(CommandParserTrait)]
@@ -131,19 +579,50 @@ enum CommandParser {
     RegisterParser,
     UnregisterParser,
     MakeCallParser,
+    VoicemailParser,
+    ContactAddParser,
+    ContactsParser,
+    HistoryExportParser,
+    HistoryParser,
     AcceptCallParser,
     DeclineCallParser,
     TerminateCallParser,
+    TransferParser,
+    RecordParser,
+    StopRecordParser,
+    PlayParser,
+    StopPlayParser,
+    DtmfParser,
+    MessageParser,
+    PresenceParser,
+    PingParser,
+    AnsweringMachineParser,
+    MeterParser,
+    MuteParser,
+    LoopbackParser,
+    TraceSipParser,
+    CaptureParser,
+    StopCaptureParser,
+    VolumeParser,
+    CallStatsParser,
+    AudioStatsParser,
+    FlowParser,
+    StatusParser,
+    VersionParser,
+    SourceParser,
+    ReloadParser,
+    QuitParser,
+    ConfirmParser,
+    PluginParser,
 }
 
 pub struct RegisterParser {
-    parser: parser::Parser,
+    password_prompt: bool,
 }
 
 impl RegisterParser {
-    pub fn new() -> Self {
-        let parser = parser::Parser::new(["user".into(), "password".into(), "registrar".into()]);
-        Self { parser }
+    pub fn new(password_prompt: bool) -> Self {
+        Self { password_prompt }
     }
 }
 
@@ -152,32 +631,87 @@ impl CommandParserTrait for RegisterParser {
         if !line.starts_with("register") {
             Err(CommandParserError::Command)
         } else {
-            let data = self
-                .parser
+            let data = parser::Parser::of_spec(self.spec())
                 .parse(line.trim_start_matches("register"))
                 .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
 
             let user_name = data.get("user").ok_or(CommandParserError::Arguments(
                 "\"user\" field is missing".to_owned(),
             ))?;
-            let def_password = "".to_owned();
-            let password = data.get("password").unwrap_or(&def_password);
             let registrar = data.get("registrar").ok_or(CommandParserError::Arguments(
                 "\"registrar\" field is missing".to_owned(),
             ))?;
 
-            let credential = DigestUser::new(user_name, password.as_bytes());
             let registrar_host = parser::parse_host_port(registrar)
                 .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+            let srtp = match data.get("srtp") {
+                Some(srtp) => srtp
+                    .parse()
+                    .map_err(|err: anyhow::Error| CommandParserError::Arguments(err.to_string()))?,
+                None => SrtpPolicy::default(),
+            };
+            // asked after the other fields are valid, the typed password isn't echoed
+            let password = match data.get("password") {
+                Some(password) => password.clone(),
+                None if self.password_prompt => {
+                    rpassword::prompt_password("Password: ").map_err(|err| {
+                        CommandParserError::Arguments(format!("Failed to read the password: {err}"))
+                    })?
+                }
+                None => String::new(),
+            };
 
-            let command = command::Register::new(user_name, credential, registrar_host);
+            let display_name = data.get("display_name").cloned();
+            let command =
+                command::Register::new(user_name, password, registrar_host, srtp, display_name);
 
             Ok(command.into())
         }
     }
 
-    fn get_help(&self) -> &str {
-        "register user=<extension_number> [password=<password>] registrar=<ip:port>"
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "register",
+            summary: "Registers the agent on the SIP registrar",
+            args: &[
+                ArgSpec {
+                    name: "user",
+                    value: "<extension_number>",
+                    description: "The user name (phone number) of the agent",
+                    default: None,
+                    required: true,
+                },
+                ArgSpec {
+                    name: "password",
+                    value: "<password>",
+                    description: "The digest password, asked with the echo disabled if not set (empty in the TUI and the scripts)",
+                    default: None,
+                    required: false,
+                },
+                ArgSpec {
+                    name: "registrar",
+                    value: "<ip:port>",
+                    description: "The address of the SIP registrar",
+                    default: None,
+                    required: true,
+                },
+                ArgSpec {
+                    name: "srtp",
+                    value: "disabled|optional|required",
+                    description: "The media encryption: optional offers SDES keys with RTP, required rejects the calls without SRTP",
+                    default: Some("disabled"),
+                    required: false,
+                },
+                ArgSpec {
+                    name: "display_name",
+                    value: "<name>",
+                    description: "The name the called parties see in From, quoted if it has the spaces",
+                    default: Some("the bare URI"),
+                    required: false,
+                },
+            ],
+            examples: &["register user=1001 registrar=192.168.1.1:5060", "register user=1001 password=secret registrar=192.168.1.1:5170 srtp=optional", "register user=1001 registrar=192.168.1.1:5060 display_name=\"Alice Example\""],
+        }
     }
 }
 
@@ -198,19 +732,21 @@ impl CommandParserTrait for UnregisterParser {
         }
     }
 
-    fn get_help(&self) -> &str {
-        "unregister"
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "unregister",
+            summary: "Unregisters the agent",
+            args: &[],
+            examples: &[],
+        }
     }
 }
 
-pub struct MakeCallParser {
-    parser: parser::Parser,
-}
+pub struct MakeCallParser;
 
 impl MakeCallParser {
     pub fn new() -> Self {
-        let parser = parser::Parser::new(["user".into()]);
-        Self { parser }
+        Self {}
     }
 }
 
@@ -219,23 +755,252 @@ impl CommandParserTrait for MakeCallParser {
         if !line.starts_with("call") {
             Err(CommandParserError::Command)
         } else {
-            let data = self
-                .parser
+            let data = parser::Parser::of_spec(self.spec())
                 .parse(line.trim_start_matches("call"))
                 .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
 
-            let target_user_name = data.get("user").ok_or(CommandParserError::Arguments(
+            let target = match (data.get("user"), data.get("name")) {
+                (Some(user), None) => command::CallTarget::User(user.clone()),
+                (None, Some(name)) => command::CallTarget::Contact(name.clone()),
+                _ => {
+                    return Err(CommandParserError::Arguments(
+                        "Either \"user\" or \"name\" field is expected".to_owned(),
+                    ))
+                }
+            };
+
+            let command = command::MakeCall::new(target, data.get("subject").cloned());
+
+            Ok(command.into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "call",
+            summary: "Makes a call to the user or to the contact, one of the args is expected",
+            args: &[
+                ArgSpec {
+                    name: "user",
+                    value: "<extension_number>",
+                    description: "The user name (phone number) to call",
+                    default: None,
+                    required: false,
+                },
+                ArgSpec {
+                    name: "name",
+                    value: "<contact>",
+                    description: "The name of the contact to call",
+                    default: None,
+                    required: false,
+                },
+                ArgSpec {
+                    name: "subject",
+                    value: "<text>",
+                    description: "The Subject of the call, quoted if it has the spaces",
+                    default: None,
+                    required: false,
+                },
+            ],
+            examples: &[
+                "call user=1002",
+                "call name=alice",
+                "call user=100 subject=\"escalation #4211\"",
+            ],
+        }
+    }
+}
+
+pub struct VoicemailParser;
+
+impl VoicemailParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for VoicemailParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("voicemail") {
+            Err(CommandParserError::Command)
+        } else {
+            Ok(command::MakeCall::new(command::CallTarget::Voicemail, None).into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "voicemail",
+            summary: "Calls the voicemail box: the voicemail number of the config or the one the server tells",
+            args: &[],
+            examples: &[],
+        }
+    }
+}
+
+pub struct ContactAddParser;
+
+impl ContactAddParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for ContactAddParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("contact add") {
+            Err(CommandParserError::Command)
+        } else {
+            let data = parser::Parser::of_spec(self.spec())
+                .parse(line.trim_start_matches("contact add"))
+                .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+
+            let name = data.get("name").ok_or(CommandParserError::Arguments(
+                "\"name\" field is missing".to_owned(),
+            ))?;
+            let user = data.get("user").ok_or(CommandParserError::Arguments(
                 "\"user\" field is missing".to_owned(),
             ))?;
 
-            let command = command::MakeCall::new(target_user_name);
+            Ok(command::AddContact::new(name, user).into())
+        }
+    }
 
-            Ok(command.into())
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "contact add",
+            summary:
+                "Adds the contact to the address book, the contact with the same name is replaced",
+            args: &[
+                ArgSpec {
+                    name: "name",
+                    value: "<contact>",
+                    description: "The name of the contact",
+                    default: None,
+                    required: true,
+                },
+                ArgSpec {
+                    name: "user",
+                    value: "<extension_number>",
+                    description: "The user name (phone number) of the contact",
+                    default: None,
+                    required: true,
+                },
+            ],
+            examples: &["contact add name=alice user=1001"],
+        }
+    }
+}
+
+pub struct ContactsParser;
+
+impl ContactsParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for ContactsParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("contacts") {
+            Err(CommandParserError::Command)
+        } else {
+            Ok(command::ShowContacts::new().into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "contacts",
+            summary: "Lists the contacts",
+            args: &[],
+            examples: &[],
+        }
+    }
+}
+
+pub struct HistoryExportParser;
+
+impl HistoryExportParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for HistoryExportParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("history export") {
+            Err(CommandParserError::Command)
+        } else {
+            let data = parser::Parser::of_spec(self.spec())
+                .parse(line.trim_start_matches("history export"))
+                .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+
+            let file = data.get("file").ok_or(CommandParserError::Arguments(
+                "\"file\" field is missing".to_owned(),
+            ))?;
+
+            Ok(command::ExportHistory::new(file.into()).into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "history export",
+            summary: "Exports the call history",
+            args: &[ArgSpec {
+                name: "file",
+                value: "<path.csv|path.json>",
+                description: "The file to write: JSON for the .json extension, CSV otherwise",
+                default: None,
+                required: true,
+            }],
+            examples: &["history export file=calls.csv"],
+        }
+    }
+}
+
+pub struct HistoryParser;
+
+impl HistoryParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for HistoryParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("history") {
+            Err(CommandParserError::Command)
+        } else {
+            let data = parser::Parser::of_spec(self.spec())
+                .parse(line.trim_start_matches("history"))
+                .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+
+            let count = match data.get("count") {
+                Some(count) => count.parse().map_err(|_err| {
+                    CommandParserError::Arguments(format!("Invalid count: {count}"))
+                })?,
+                None => 10,
+            };
+
+            Ok(command::ShowHistory::new(count).into())
         }
     }
 
-    fn get_help(&self) -> &str {
-        "call user=<extension_number>"
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "history",
+            summary: "Lists the last calls, the missed ones are marked",
+            args: &[ArgSpec {
+                name: "count",
+                value: "<number>",
+                description: "The number of the calls to list",
+                default: Some("10"),
+                required: false,
+            }],
+            examples: &["history count=20"],
+        }
     }
 }
 
@@ -256,8 +1021,13 @@ impl CommandParserTrait for AcceptCallParser {
         }
     }
 
-    fn get_help(&self) -> &str {
-        "accept call"
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "accept call",
+            summary: "Accepts the incoming call",
+            args: &[],
+            examples: &[],
+        }
     }
 }
 
@@ -278,8 +1048,13 @@ impl CommandParserTrait for DeclineCallParser {
         }
     }
 
-    fn get_help(&self) -> &str {
-        "decline call"
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "decline call",
+            summary: "Declines the incoming call",
+            args: &[],
+            examples: &[],
+        }
     }
 }
 
@@ -296,65 +1071,1239 @@ impl CommandParserTrait for TerminateCallParser {
         if !line.starts_with("terminate call") {
             Err(CommandParserError::Command)
         } else {
-            Ok(command::TerminateCall::new().into())
+            let data = parser::Parser::of_spec(self.spec())
+                .parse(line.trim_start_matches("terminate call"))
+                .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+
+            let call_id = data
+                .get("id")
+                .map(|id| id.parse::<u64>())
+                .transpose()
+                .map_err(|_| {
+                    CommandParserError::Arguments("\"id\" must be the call number".to_owned())
+                })?;
+
+            Ok(command::TerminateCall::new(call_id).into())
         }
     }
 
-    fn get_help(&self) -> &str {
-        "terminate call"
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "terminate call",
+            summary: "Terminates the active call",
+            args: &[ArgSpec {
+                name: "id",
+                value: "<N>",
+                description: "The number of the call ([call #N] of the events), the call isn't terminated if it's another one",
+                default: None,
+                required: false,
+            }],
+            examples: &["terminate call", "terminate call id=2"],
+        }
     }
 }
 
-mod parser {
-    use std::collections::HashMap;
-
-    use anyhow::Result;
-    use bytesstr::BytesStr;
-    use ezk_sip_types::{host::HostPort, parse::ParseCtx};
+pub struct TransferParser;
 
-    pub struct Parser {
-        fields: Vec<String>,
+impl TransferParser {
+    pub fn new() -> Self {
+        Self {}
     }
+}
 
-    impl Parser {
-        pub fn new<I: IntoIterator<Item = String>>(fields: I) -> Self {
-            let fields = fields.into_iter().collect();
-            Self { fields }
+impl CommandParserTrait for TransferParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("transfer") {
+            return Err(CommandParserError::Command);
         }
 
-        pub fn parse(&self, line: &str) -> Result<HashMap<String, String>> {
-            let tokens = line.split(' ');
-            let mut data = HashMap::new();
-
-            for token in tokens.filter(|token| !token.is_empty()) {
-                let (name, value) = Self::parse_field(token)?;
-                if self.fields.contains(&name.into()) {
-                    let _ = data.insert(name.into(), value.to_owned());
-                } else {
-                    return Err(anyhow::Error::msg(format!("Unknown field: {name}")));
-                }
+        let data = parser::Parser::of_spec(self.spec())
+            .parse(line.trim_start_matches("transfer"))
+            .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+        let target = match (data.get("user"), data.get("uri")) {
+            (Some(user), None) => command::TransferTarget::User(user.clone()),
+            (None, Some(uri)) if uri.starts_with("sip:") || uri.starts_with("sips:") => {
+                command::TransferTarget::Uri(uri.clone())
             }
+            (None, Some(uri)) => {
+                return Err(CommandParserError::Arguments(format!(
+                    "The SIP URI is expected: {uri}"
+                )))
+            }
+            _ => {
+                return Err(CommandParserError::Arguments(
+                    "Either \"user\" or \"uri\" field is expected".to_owned(),
+                ))
+            }
+        };
 
-            Ok(data)
+        Ok(command::TransferCall::new(target).into())
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "transfer",
+            summary: "Transfers the established call to the user or to the URI (blind transfer), one of the args is expected",
+            args: &[
+                ArgSpec {
+                    name: "user",
+                    value: "<extension_number>",
+                    description: "The user name (phone number) at the registrar",
+                    default: None,
+                    required: false,
+                },
+                ArgSpec {
+                    name: "uri",
+                    value: "<sip:user@host>",
+                    description: "The SIP URI of the transfer target",
+                    default: None,
+                    required: false,
+                },
+            ],
+            examples: &["transfer user=1003", "transfer uri=sip:1003@192.168.1.1"],
         }
+    }
+}
 
-        fn parse_field<'a>(token: &'a str) -> Result<(&'a str, &'a str)> {
-            let mut field = token.split('=');
-            let name = field
-                .next()
-                .ok_or(anyhow::Error::msg("Field name is missing"))?;
-            let value = field
-                .next()
+pub struct RecordParser;
+
+impl RecordParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for RecordParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("record") {
+            Err(CommandParserError::Command)
+        } else {
+            let data = parser::Parser::of_spec(self.spec())
+                .parse(line.trim_start_matches("record"))
+                .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+
+            let file = data.get("file").ok_or(CommandParserError::Arguments(
+                "\"file\" field is missing".to_owned(),
+            ))?;
+            let mode = match data.get("mode") {
+                Some(mode) => mode
+                    .parse()
+                    .map_err(|err: anyhow::Error| CommandParserError::Arguments(err.to_string()))?,
+                None => RecordingMode::Mono,
+            };
+
+            let command = command::StartRecording::new(file.into(), mode);
+
+            Ok(command.into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "record",
+            summary: "Records the active call to the WAV file",
+            args: &[
+                ArgSpec {
+                    name: "file",
+                    value: "<path.wav>",
+                    description: "The file to write",
+                    default: None,
+                    required: true,
+                },
+                ArgSpec {
+                    name: "mode",
+                    value: "mono|stereo",
+                    description: "mono mixes both sides, stereo puts the local audio on the left channel and the remote audio on the right",
+                    default: Some("mono"),
+                    required: false,
+                },
+            ],
+            examples: &["record file=call.wav mode=stereo"],
+        }
+    }
+}
+
+pub struct StopRecordParser;
+
+impl StopRecordParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for StopRecordParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("stop record") {
+            Err(CommandParserError::Command)
+        } else {
+            Ok(command::StopRecording::new().into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "stop record",
+            summary: "Stops the call recording",
+            args: &[],
+            examples: &[],
+        }
+    }
+}
+
+pub struct PlayParser;
+
+impl PlayParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for PlayParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("play") {
+            Err(CommandParserError::Command)
+        } else {
+            let data = parser::Parser::of_spec(self.spec())
+                .parse(line.trim_start_matches("play"))
+                .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+
+            let file = data.get("file").ok_or(CommandParserError::Arguments(
+                "\"file\" field is missing".to_owned(),
+            ))?;
+            let mode = match data.get("mode") {
+                Some(mode) => mode
+                    .parse()
+                    .map_err(|err: anyhow::Error| CommandParserError::Arguments(err.to_string()))?,
+                None => PlaybackMode::Mix,
+            };
+
+            let command = command::PlayFile::new(file.into(), mode);
+
+            Ok(command.into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "play",
+            summary: "Plays the file into the active call",
+            args: &[
+                ArgSpec {
+                    name: "file",
+                    value: "<path.wav|path.ogg>",
+                    description: "The file to play",
+                    default: None,
+                    required: true,
+                },
+                ArgSpec {
+                    name: "mode",
+                    value: "mix|replace",
+                    description:
+                        "mix plays the file along with the microphone, replace mutes the microphone",
+                    default: Some("mix"),
+                    required: false,
+                },
+            ],
+            examples: &["play file=announcement.ogg mode=replace"],
+        }
+    }
+}
+
+pub struct StopPlayParser;
+
+impl StopPlayParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for StopPlayParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("stop play") {
+            Err(CommandParserError::Command)
+        } else {
+            Ok(command::StopPlaying::new().into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "stop play",
+            summary: "Stops the playback",
+            args: &[],
+            examples: &[],
+        }
+    }
+}
+
+pub struct DtmfParser;
+
+impl DtmfParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for DtmfParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("dtmf") {
+            return Err(CommandParserError::Command);
+        }
+
+        let digits = line.trim_start_matches("dtmf").trim();
+        if digits.is_empty() {
+            return Err(CommandParserError::Arguments(
+                "DTMF digits are missing".to_owned(),
+            ));
+        }
+        if let Some(digit) = digits.chars().find(|digit| !dtmf::is_valid_symbol(*digit)) {
+            return Err(CommandParserError::Arguments(format!(
+                "Invalid DTMF digit: {digit}, 0-9, *, #, A-D and \",\" (0.5 s pause) are expected"
+            )));
+        }
+        Ok(command::SendDtmf::new(&digits.to_ascii_uppercase()).into())
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "dtmf",
+            summary: "Sends the DTMF digits in-band into the active call",
+            args: &[ArgSpec {
+                name: "",
+                value: "<digits>",
+                description: "The digits: 0-9, *, #, A-D, every \",\" is the 0.5 s pause",
+                default: None,
+                required: true,
+            }],
+            examples: &["dtmf 1234#", "dtmf 1,,2"],
+        }
+    }
+}
+
+pub struct MessageParser;
+
+impl MessageParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for MessageParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("message") {
+            return Err(CommandParserError::Command);
+        }
+
+        let data = parser::Parser::of_spec(self.spec())
+            .parse(line.trim_start_matches("message"))
+            .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+        let user = data.get("user").ok_or(CommandParserError::Arguments(
+            "\"user\" field is missing".to_owned(),
+        ))?;
+        let text = data.get("text").ok_or(CommandParserError::Arguments(
+            "\"text\" field is missing".to_owned(),
+        ))?;
+        Ok(command::SendMessage::new(user, text).into())
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "message",
+            summary: "Sends the instant message (SIP MESSAGE) to the user at the registrar",
+            args: &[
+                ArgSpec {
+                    name: "user",
+                    value: "<extension_number>",
+                    description: "The user name (phone number) to send to",
+                    default: None,
+                    required: true,
+                },
+                ArgSpec {
+                    name: "text",
+                    value: "<text>",
+                    description: "The plain text of the message, quoted if it has the spaces",
+                    default: None,
+                    required: true,
+                },
+            ],
+            examples: &["message user=1002 text=\"The meeting is moved to 3 pm\""],
+        }
+    }
+}
+
+pub struct PresenceParser;
+
+impl PresenceParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for PresenceParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("presence") {
+            return Err(CommandParserError::Command);
+        }
+
+        let args = line.trim_start_matches("presence").trim_start();
+        let (status, fields) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let status = status.parse().map_err(|_| {
+            CommandParserError::Arguments(
+                "\"available\", \"busy\" or \"away\" is expected".to_owned(),
+            )
+        })?;
+        let data = parser::Parser::of_spec(self.spec())
+            .parse(fields)
+            .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+        let note = data.get("note").cloned();
+        Ok(command::SetPresence::new(status, note).into())
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "presence",
+            summary: "Publishes the presence (SIP PUBLISH) to the registrar, it's refreshed until the unregistration",
+            args: &[
+                ArgSpec {
+                    name: "",
+                    value: "available|busy|away",
+                    description: "The status the watchers see",
+                    default: None,
+                    required: true,
+                },
+                ArgSpec {
+                    name: "note",
+                    value: "<text>",
+                    description: "The note next to the status, quoted if it has the spaces",
+                    default: Some("the status"),
+                    required: false,
+                },
+            ],
+            examples: &["presence busy", "presence away note=\"Back at 2 pm\""],
+        }
+    }
+}
+
+pub struct PingParser;
+
+impl PingParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for PingParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("ping") {
+            return Err(CommandParserError::Command);
+        }
+
+        let data = parser::Parser::of_spec(self.spec())
+            .parse(line.trim_start_matches("ping"))
+            .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+        Ok(command::SendPing::new(data.get("host").cloned()).into())
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "ping",
+            summary: "Sends the SIP OPTIONS and prints the status of the response and the round-trip time",
+            args: &[ArgSpec {
+                name: "host",
+                value: "<ip:port>",
+                description: "The host to send to, the port is 5060 if omitted",
+                default: Some("the registrar"),
+                required: false,
+            }],
+            examples: &["ping", "ping host=192.168.1.10:5060"],
+        }
+    }
+}
+
+pub struct AnsweringMachineParser;
+
+impl AnsweringMachineParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for AnsweringMachineParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("answering machine") {
+            return Err(CommandParserError::Command);
+        }
+
+        let line = line.trim_start_matches("answering machine").trim_start();
+        if line.starts_with("off") {
+            return Ok(command::SetAnsweringMachine::new(None).into());
+        } else if !line.starts_with("on") {
+            return Err(CommandParserError::Arguments(
+                "\"on\" or \"off\" is expected".to_owned(),
+            ));
+        }
+
+        let data = parser::Parser::of_spec(self.spec())
+            .parse(line.trim_start_matches("on"))
+            .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+
+        let greeting = data.get("greeting").map(PathBuf::from);
+        let record_dir = data.get("dir").map(PathBuf::from).unwrap_or(".".into());
+        let duration = match data.get("duration") {
+            Some(duration) => duration
+                .parse()
+                .map_err(|_err| CommandParserError::Arguments("Invalid duration".to_owned()))?,
+            None => 30,
+        };
+
+        let machine = AnsweringMachine::new(greeting, record_dir, Duration::from_secs(duration));
+        Ok(command::SetAnsweringMachine::new(Some(machine)).into())
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "answering machine",
+            summary: "Answers the incoming calls automatically, plays the greeting and records the caller",
+            args: &[
+                ArgSpec {
+                    name: "",
+                    value: "on|off",
+                    description: "Turns the answering machine on or off",
+                    default: None,
+                    required: true,
+                },
+                ArgSpec {
+                    name: "greeting",
+                    value: "<path.wav>",
+                    description: "The greeting to play, nothing is played if not set",
+                    default: None,
+                    required: false,
+                },
+                ArgSpec {
+                    name: "dir",
+                    value: "<records dir>",
+                    description: "The directory of the recordings",
+                    default: Some("."),
+                    required: false,
+                },
+                ArgSpec {
+                    name: "duration",
+                    value: "<secs>",
+                    description: "The time to record the caller",
+                    default: Some("30"),
+                    required: false,
+                },
+            ],
+            examples: &["answering machine on greeting=greeting.wav dir=records duration=60", "answering machine off"],
+        }
+    }
+}
+
+pub struct MeterParser;
+
+impl MeterParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for MeterParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("meter") {
+            return Err(CommandParserError::Command);
+        }
+
+        match line.trim_start_matches("meter").trim() {
+            "" | "on" => Ok(command::SetMeter::new(true).into()),
+            "off" => Ok(command::SetMeter::new(false).into()),
+            _ => Err(CommandParserError::Arguments(
+                "\"on\" or \"off\" is expected".to_owned(),
+            )),
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "meter",
+            summary: "Shows the microphone level",
+            args: &[ArgSpec {
+                name: "",
+                value: "on|off",
+                description: "Turns the meter on or off",
+                default: Some("on"),
+                required: false,
+            }],
+            examples: &["meter off"],
+        }
+    }
+}
+
+pub struct MuteParser;
+
+impl MuteParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for MuteParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("mute") {
+            return Err(CommandParserError::Command);
+        }
+
+        match line.trim_start_matches("mute").trim() {
+            "" | "on" => Ok(command::SetMute::new(Some(true)).into()),
+            "off" => Ok(command::SetMute::new(Some(false)).into()),
+            "toggle" => Ok(command::SetMute::new(None).into()),
+            _ => Err(CommandParserError::Arguments(
+                "\"on\", \"off\" or \"toggle\" is expected".to_owned(),
+            )),
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "mute",
+            summary: "Mutes the microphone until the end of the call, the volume setting is kept",
+            args: &[ArgSpec {
+                name: "",
+                value: "on|off|toggle",
+                description: "Mutes, unmutes or switches the microphone",
+                default: Some("on"),
+                required: false,
+            }],
+            examples: &["mute", "mute off"],
+        }
+    }
+}
+
+pub struct TraceSipParser;
+
+impl TraceSipParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for TraceSipParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("trace sip") {
+            return Err(CommandParserError::Command);
+        }
+
+        match line.trim_start_matches("trace sip").trim() {
+            "on" => Ok(command::SetSipTrace::new(true).into()),
+            "off" => Ok(command::SetSipTrace::new(false).into()),
+            _ => Err(CommandParserError::Arguments(
+                "\"on\" or \"off\" is expected".to_owned(),
+            )),
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "trace sip",
+            summary: "Logs the sent and received SIP messages on top of RUST_LOG",
+            args: &[ArgSpec {
+                name: "",
+                value: "on|off",
+                description: "Turns the SIP trace on or off",
+                default: None,
+                required: true,
+            }],
+            examples: &["trace sip on"],
+        }
+    }
+}
+
+pub struct CaptureParser;
+
+impl CaptureParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for CaptureParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("capture start") {
+            return Err(CommandParserError::Command);
+        }
+
+        let data = parser::Parser::of_spec(self.spec())
+            .parse(line.trim_start_matches("capture start"))
+            .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+        let file = data.get("file").ok_or(CommandParserError::Arguments(
+            "\"file\" field is missing".to_owned(),
+        ))?;
+        let rtp = match data.get("rtp").map(String::as_str) {
+            None | Some("off") => false,
+            Some("on") => true,
+            Some(_) => {
+                return Err(CommandParserError::Arguments(
+                    "\"rtp\" must be \"on\" or \"off\"".to_owned(),
+                ))
+            }
+        };
+        Ok(command::StartCapture::new(file.into(), rtp).into())
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "capture start",
+            summary: "Writes the sent and received SIP messages to the pcap file for Wireshark",
+            args: &[
+                ArgSpec {
+                    name: "file",
+                    value: "<path.pcap>",
+                    description: "The file to write",
+                    default: None,
+                    required: true,
+                },
+                ArgSpec {
+                    name: "rtp",
+                    value: "on|off",
+                    description: "Adds the RTP headers of the calls, without the audio",
+                    default: Some("off"),
+                    required: false,
+                },
+            ],
+            examples: &["capture start file=trace.pcap rtp=on"],
+        }
+    }
+}
+
+pub struct StopCaptureParser;
+
+impl StopCaptureParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for StopCaptureParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("capture stop") {
+            Err(CommandParserError::Command)
+        } else {
+            Ok(command::StopCapture::new().into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "capture stop",
+            summary: "Stops the packet capture",
+            args: &[],
+            examples: &[],
+        }
+    }
+}
+
+pub struct LoopbackParser;
+
+impl LoopbackParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for LoopbackParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("audio test loopback") {
+            return Err(CommandParserError::Command);
+        }
+
+        match line.trim_start_matches("audio test loopback").trim() {
+            "" | "on" => Ok(command::SetLoopback::new(true).into()),
+            "off" => Ok(command::SetLoopback::new(false).into()),
+            _ => Err(CommandParserError::Arguments(
+                "\"on\" or \"off\" is expected".to_owned(),
+            )),
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "audio test loopback",
+            summary: "Plays the microphone back with 1 s delay through the G.711 path of the calls",
+            args: &[ArgSpec {
+                name: "",
+                value: "on|off",
+                description: "Turns the loopback test on or off",
+                default: Some("on"),
+                required: false,
+            }],
+            examples: &["audio test loopback off"],
+        }
+    }
+}
+
+pub struct VolumeParser;
+
+impl VolumeParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn parse_volume(value: &str) -> Result<u32, CommandParserError> {
+        let volume = value
+            .trim_end_matches('%')
+            .parse()
+            .map_err(|_err| CommandParserError::Arguments(format!("Invalid volume: {value}")))?;
+        if volume > 200 {
+            Err(CommandParserError::Arguments(
+                "The volume must be in range 0-200%".to_owned(),
+            ))
+        } else {
+            Ok(volume)
+        }
+    }
+}
+
+impl CommandParserTrait for VolumeParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("volume") {
+            Err(CommandParserError::Command)
+        } else {
+            let data = parser::Parser::of_spec(self.spec())
+                .parse(line.trim_start_matches("volume"))
+                .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+
+            let input_volume = data
+                .get("in")
+                .map(|value| Self::parse_volume(value))
+                .transpose()?;
+            let output_volume = data
+                .get("out")
+                .map(|value| Self::parse_volume(value))
+                .transpose()?;
+
+            let command = command::SetVolume::new(input_volume, output_volume);
+
+            Ok(command.into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "volume",
+            summary: "Sets the input and output volume, saved to the settings",
+            args: &[
+                ArgSpec {
+                    name: "in",
+                    value: "<0-200%>",
+                    description: "The microphone volume, unchanged if not set",
+                    default: None,
+                    required: false,
+                },
+                ArgSpec {
+                    name: "out",
+                    value: "<0-200%>",
+                    description: "The speaker volume, unchanged if not set",
+                    default: None,
+                    required: false,
+                },
+            ],
+            examples: &["volume in=80 out=120"],
+        }
+    }
+}
+
+pub struct CallStatsParser;
+
+impl CallStatsParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for CallStatsParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("stats call") {
+            Err(CommandParserError::Command)
+        } else {
+            Ok(command::ShowCallStats::new().into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "stats call",
+            summary:
+                "Shows the RTP statistics of the active call: packets, loss, jitter and bitrate",
+            args: &[],
+            examples: &[],
+        }
+    }
+}
+
+pub struct FlowParser;
+
+impl FlowParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for FlowParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if line != "flow" {
+            Err(CommandParserError::Command)
+        } else {
+            Ok(command::ShowFlow::new().into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "flow",
+            summary: "Shows the SIP messages of the current or the last call as a ladder diagram",
+            args: &[],
+            examples: &[],
+        }
+    }
+}
+
+pub struct AudioStatsParser;
+
+impl AudioStatsParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for AudioStatsParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("stats audio") {
+            Err(CommandParserError::Command)
+        } else {
+            Ok(command::ShowAudioStats::new().into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "stats audio",
+            summary: "Shows the jitter buffer and the audio channels statistics of the active call",
+            args: &[],
+            examples: &[],
+        }
+    }
+}
+
+pub struct StatusParser;
+
+impl StatusParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for StatusParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("status") {
+            Err(CommandParserError::Command)
+        } else {
+            Ok(command::ShowStatus::new().into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "status",
+            summary:
+                "Shows the registration, the call, the audio devices and the answering machine",
+            args: &[],
+            examples: &[],
+        }
+    }
+}
+
+pub struct VersionParser;
+
+impl VersionParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for VersionParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("version") {
+            Err(CommandParserError::Command)
+        } else {
+            Ok(command::ShowVersion::new().into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "version",
+            summary: "Shows the version, the git hash, the features and the ezk-sip version for the bug reports",
+            args: &[],
+            examples: &[],
+        }
+    }
+}
+
+pub struct SourceParser;
+
+impl SourceParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for SourceParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("source") {
+            Err(CommandParserError::Command)
+        } else {
+            let data = parser::Parser::of_spec(self.spec())
+                .parse(line.trim_start_matches("source"))
+                .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+
+            let file = data.get("file").ok_or(CommandParserError::Arguments(
+                "\"file\" field is missing".to_owned(),
+            ))?;
+
+            Ok(command::SourceScript::new(file.into()).into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "source",
+            summary:
+                "Runs the commands of the file, one per line, the lines starting with # are skipped",
+            args: &[ArgSpec {
+                name: "file",
+                value: "<path>",
+                description: "The script to run",
+                default: None,
+                required: true,
+            }],
+            examples: &["source file=startup.txt"],
+        }
+    }
+}
+
+pub struct ReloadParser;
+
+impl ReloadParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for ReloadParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        if !line.starts_with("reload") {
+            Err(CommandParserError::Command)
+        } else {
+            Ok(command::Reload::new().into())
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "reload",
+            summary: "Reloads the config, the settings and the contacts, the options which require a restart are reported",
+            args: &[],
+            examples: &[],
+        }
+    }
+}
+
+pub struct QuitParser;
+
+impl QuitParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for QuitParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        let Some(rest) = line
+            .strip_prefix("quit")
+            .or_else(|| line.strip_prefix("exit"))
+        else {
+            return Err(CommandParserError::Command);
+        };
+
+        match rest.trim() {
+            "" => Ok(command::StopApp::new(false).into()),
+            "force" => Ok(command::StopApp::new(true).into()),
+            _ => Err(CommandParserError::Arguments(
+                "Only \"force\" is expected".to_owned(),
+            )),
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "quit",
+            summary: "Quits the app (exit is the same), Ctrl-C and Ctrl-D quit with force",
+            args: &[ArgSpec {
+                name: "",
+                value: "force",
+                description: "Terminates the active call and quits, the app doesn't quit with the active call otherwise",
+                default: None,
+                required: false,
+            }],
+            examples: &["quit", "exit force"],
+        }
+    }
+}
+
+pub struct ConfirmParser;
+
+impl ConfirmParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandParserTrait for ConfirmParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        match line.trim() {
+            "yes" => Ok(command::Confirm::new(true).into()),
+            "no" => Ok(command::Confirm::new(false).into()),
+            _ => Err(CommandParserError::Command),
+        }
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        &CommandSpec {
+            name: "yes",
+            summary: "Confirms the pending terminate call, unregister or quit (no cancels it, any other command too), see confirm in the [ui] config",
+            args: &[],
+            examples: &["yes", "no"],
+        }
+    }
+}
+
+pub struct PluginParser {
+    plugin: Arc<dyn CommandPlugin>,
+}
+
+impl PluginParser {
+    pub fn new(plugin: Arc<dyn CommandPlugin>) -> Self {
+        Self { plugin }
+    }
+}
+
+impl CommandParserTrait for PluginParser {
+    fn parse(&self, line: &str) -> Result<Command, CommandParserError> {
+        let name = self.plugin.spec().name;
+        let Some(args) = line.strip_prefix(name) else {
+            return Err(CommandParserError::Command);
+        };
+        if !args.is_empty() && !args.starts_with(' ') {
+            return Err(CommandParserError::Command);
+        }
+        let command = self
+            .plugin
+            .parse(args.trim())
+            .map_err(|err| CommandParserError::Arguments(err.to_string()))?;
+        Ok(command::RunPlugin::new(command).into())
+    }
+
+    fn spec(&self) -> &'static CommandSpec {
+        self.plugin.spec()
+    }
+}
+
+pub(crate) mod parser {
+    use std::collections::HashMap;
+
+    use anyhow::Result;
+    use bytesstr::BytesStr;
+    use ezk_sip_types::{host::HostPort, parse::ParseCtx};
+
+    use super::CommandSpec;
+
+    pub struct Parser {
+        fields: Vec<String>,
+    }
+
+    impl Parser {
+        pub fn new<I: IntoIterator<Item = String>>(fields: I) -> Self {
+            let fields = fields.into_iter().collect();
+            Self { fields }
+        }
+
+        pub fn of_spec(spec: &CommandSpec) -> Self {
+            Self::new(
+                spec.args
+                    .iter()
+                    .filter(|arg| !arg.name.is_empty())
+                    .map(|arg| arg.name.to_owned()),
+            )
+        }
+
+        pub fn parse(&self, line: &str) -> Result<HashMap<String, String>> {
+            let mut data = HashMap::new();
+
+            for token in tokenize(line)? {
+                let (name, value) = Self::parse_field(&token)?;
+                if self.fields.contains(&name.into()) {
+                    let _ = data.insert(name.into(), value.to_owned());
+                } else {
+                    return Err(anyhow::Error::msg(format!("Unknown field: {name}")));
+                }
+            }
+
+            Ok(data)
+        }
+
+        fn parse_field(token: &str) -> Result<(&str, &str)> {
+            let (name, value) = token
+                .split_once('=')
                 .ok_or(anyhow::Error::msg("Field value is missing"))?;
+            if name.is_empty() {
+                return Err(anyhow::Error::msg("Field name is missing"));
+            }
+            Ok((name, value))
+        }
+    }
 
-            if let Some(_) = field.next() {
-                Err(anyhow::Error::msg(
-                    "There are more than 1 \'=\' in the field",
-                ))
-            } else {
-                Ok((name, value))
+    // splits the line by the whitespaces, the quoted parts keep them and '\' escapes the next
+    // char (only '"' and '\' in the quotes): password="p@ss w=rd" or password=p@ss\ w=rd
+    pub fn tokenize(line: &str) -> Result<Vec<String>> {
+        let mut tokens = Vec::new();
+        // the quoted empty value starts the token too
+        let mut token: Option<String> = None;
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                // the quoted Windows paths keep the backslashes
+                '\\' if quoted && !matches!(chars.peek(), Some('"' | '\\')) => {
+                    token.get_or_insert_with(String::new).push(c);
+                }
+                '\\' => {
+                    let escaped = chars.next().ok_or(anyhow::Error::msg(
+                        "Nothing to escape at the end of the line",
+                    ))?;
+                    token.get_or_insert_with(String::new).push(escaped);
+                }
+                '"' => {
+                    quoted = !quoted;
+                    token.get_or_insert_with(String::new);
+                }
+                c if c.is_whitespace() && !quoted => tokens.extend(token.take()),
+                c => token.get_or_insert_with(String::new).push(c),
             }
         }
+        if quoted {
+            return Err(anyhow::Error::msg("The quote is not closed"));
+        }
+        tokens.extend(token);
+        Ok(tokens)
     }
 
     pub fn parse_host_port(s: &str) -> Result<HostPort> {
@@ -366,18 +2315,89 @@ mod parser {
             .map_err(|err| anyhow::Error::msg(err.to_string()));
         res
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn parser() -> Parser {
+            Parser::new(["user".into(), "password".into(), "file".into()])
+        }
+
+        #[test]
+        fn tokenize_splits_by_whitespaces() {
+            let tokens = tokenize("  user=1001\tpassword=secret ").unwrap();
+            assert_eq!(tokens, ["user=1001", "password=secret"]);
+        }
+
+        #[test]
+        fn tokenize_keeps_quoted_whitespaces() {
+            let tokens = tokenize(r#"user=1001 password="p@ss w=rd""#).unwrap();
+            assert_eq!(tokens, ["user=1001", "password=p@ss w=rd"]);
+        }
+
+        #[test]
+        fn tokenize_escapes_chars() {
+            let tokens = tokenize(r#"password=p@ss\ w\"rd\\"#).unwrap();
+            assert_eq!(tokens, [r#"password=p@ss w"rd\"#]);
+        }
+
+        #[test]
+        fn tokenize_keeps_quoted_backslashes() {
+            let tokens = tokenize(r#"file="C:\calls\a \"b\".wav""#).unwrap();
+            assert_eq!(tokens, [r#"file=C:\calls\a "b".wav"#]);
+        }
+
+        #[test]
+        fn tokenize_keeps_empty_quoted_value() {
+            let tokens = tokenize(r#"password="""#).unwrap();
+            assert_eq!(tokens, ["password="]);
+        }
+
+        #[test]
+        fn tokenize_rejects_unclosed_quote() {
+            assert!(tokenize(r#"password="secret"#).is_err());
+        }
+
+        #[test]
+        fn tokenize_rejects_trailing_backslash() {
+            assert!(tokenize(r"password=secret\").is_err());
+        }
+
+        #[test]
+        fn parse_takes_value_after_first_equal_sign() {
+            let data = parser().parse(r#" user=1001 password="a=b c""#).unwrap();
+            assert_eq!(data["user"], "1001");
+            assert_eq!(data["password"], "a=b c");
+        }
+
+        #[test]
+        fn parse_rejects_unknown_field() {
+            assert!(parser().parse("registrar=127.0.0.1").is_err());
+        }
+
+        #[test]
+        fn parse_rejects_missing_value() {
+            assert!(parser().parse("user").is_err());
+            assert!(parser().parse("=1001").is_err());
+        }
+    }
 }
 
 mod misc {
-    pub fn read_stdin_line() -> Option<String> {
-        let mut buf = String::new();
-        std::io::stdin()
-            .read_line(&mut buf)
-            .inspect_err(|err| {
-                tracing::warn!("CLI input system err: {err}");
-            })
-            .map(|_| buf)
-            .ok()
+    // the Levenshtein distance over the chars
+    pub fn edit_distance(a: &str, b: &str) -> usize {
+        let b: Vec<_> = b.chars().collect();
+        let mut previous: Vec<_> = (0..=b.len()).collect();
+        for (i, a_char) in a.chars().enumerate() {
+            let mut current = vec![i + 1];
+            for (j, b_char) in b.iter().enumerate() {
+                let substitution = previous[j] + usize::from(a_char != *b_char);
+                current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+            }
+            previous = current;
+        }
+        previous[b.len()]
     }
 
     pub fn trim_newline(s: &mut String) {
@@ -389,3 +2409,48 @@ mod misc {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command_line() -> CommandLine {
+        CommandLine::new(Aliases::new(BTreeMap::new()), &Plugins::default(), false)
+    }
+
+    #[test]
+    fn edit_distance_counts_the_edits() {
+        assert_eq!(misc::edit_distance("kitten", "sitting"), 3);
+        assert_eq!(misc::edit_distance("call", "call"), 0);
+        assert_eq!(misc::edit_distance("", "call"), 4);
+        assert_eq!(misc::edit_distance("call", ""), 4);
+    }
+
+    #[test]
+    fn suggest_the_closest_command() {
+        let suggestion = command_line().suggest("regster user");
+        assert_eq!(suggestion, Some(("regster".to_owned(), "register")));
+    }
+
+    #[test]
+    fn suggest_the_multi_word_command() {
+        let suggestion = command_line().suggest("contract add bob");
+        assert_eq!(suggestion, Some(("contract add".to_owned(), "contact add")));
+    }
+
+    #[test]
+    fn suggest_nothing_for_the_unrelated_line() {
+        assert_eq!(command_line().suggest("xyzzyplugh"), None);
+    }
+
+    #[test]
+    fn parse_the_unknown_command_with_the_suggestion() {
+        let Err(err) = command_line().parse("unregistr") else {
+            panic!("the unknown command is parsed");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Unknown command 'unregistr', did you mean 'unregister'?"
+        );
+    }
+}
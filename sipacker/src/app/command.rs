@@ -1,11 +1,16 @@
-use crate::app::application::App;
+use crate::app::{
+    answering_machine::AnsweringMachine, application::App, config::Account, plugin::PluginCommand,
+};
+use sipacker_core::{
+    player::PlaybackMode, presence::PresenceStatus, recorder::RecordingMode, user_agent::SrtpPolicy,
+};
 
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf};
 
 use anyhow::Result;
 use enum_dispatch::enum_dispatch;
-use ezk_sip_auth::{DigestCredentials, DigestUser};
 use ezk_sip_types::host::HostPort;
+use tokio::sync::oneshot;
 
 #[enum_dispatch]
 pub trait CommandTrait {
@@ -22,10 +27,42 @@ pub enum Command {
     Register,
     Unregister,
     MakeCall,
+    AddContact,
+    ShowContacts,
+    ShowHistory,
+    ExportHistory,
     AcceptCall,
     DeclineCall,
     TerminateCall,
+    TransferCall,
+    StartRecording,
+    StopRecording,
+    PlayFile,
+    StopPlaying,
+    SendDtmf,
+    SendMessage,
+    SendPing,
+    SetAnsweringMachine,
+    SetLoopback,
+    SetMeter,
+    SetMute,
+    SetPresence,
+    SetSipTrace,
+    StartCapture,
+    StopCapture,
+    SetVolume,
+    ShowAudioStats,
+    ShowCallStats,
+    ShowFlow,
+    ShowStatus,
+    ShowVersion,
+    SourceScript,
+    Reload,
     StopApp,
+    Confirm,
+    Reply,
+    ForInstance,
+    RunPlugin,
 }
 
 impl Display for Command {
@@ -34,28 +71,88 @@ impl Display for Command {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandSource {
+    Console,
+    Tui,
+    Control,
+    Fifo,
+    Http,
+    Grpc,
+    Script,
+    Plugin,
+    #[cfg(feature = "hooks")]
+    Hook,
+    Config,
+    Signal,
+}
+
+impl Display for CommandSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match self {
+            Self::Console => "console",
+            Self::Tui => "tui",
+            Self::Control => "control",
+            Self::Fifo => "fifo",
+            Self::Http => "http",
+            Self::Grpc => "grpc",
+            Self::Script => "script",
+            Self::Plugin => "plugin",
+            #[cfg(feature = "hooks")]
+            Self::Hook => "hook",
+            Self::Config => "config",
+            Self::Signal => "signal",
+        };
+        write!(f, "{source}")
+    }
+}
+
 pub struct Register {
     user_name: String,
-    credential: DigestUser,
+    // kept for the runtime state, it's not displayed
+    password: String,
     registrar_host: HostPort,
+    srtp: SrtpPolicy,
+    display_name: Option<String>,
 }
 
 impl Register {
-    pub fn new(user_name: &str, credential: DigestUser, registrar_host: HostPort) -> Self {
+    pub fn new(
+        user_name: &str,
+        password: String,
+        registrar_host: HostPort,
+        srtp: SrtpPolicy,
+        display_name: Option<String>,
+    ) -> Self {
         Self {
             user_name: user_name.to_owned(),
-            credential,
+            password,
             registrar_host,
+            srtp,
+            display_name,
         }
     }
 }
 
 impl CommandTrait for Register {
     async fn execute(self, app: &mut App) -> Result<()> {
-        let mut credentials = DigestCredentials::new();
-        credentials.set_default(self.credential);
-        app.register_ua(&self.user_name, credentials, self.registrar_host)
-            .await
+        let account = Account {
+            user: self.user_name.clone(),
+            password: self.password.clone(),
+            registrar: self.registrar_host.to_string(),
+            srtp: Some(self.srtp.to_string()),
+            display_name: self.display_name.clone(),
+        };
+        app.register_ua(
+            &self.user_name,
+            &self.password,
+            self.registrar_host,
+            self.srtp,
+            self.display_name,
+        )
+        .await?;
+        app.remember_account(account);
+        Ok(())
     }
 }
 
@@ -63,10 +160,15 @@ impl DisplayExt for Register {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "register {{user:{}; registrar:{}}}",
+            "register {{user:{}; registrar:{}; srtp:{}",
             self.user_name,
             self.registrar_host.to_string(),
-        )
+            self.srtp,
+        )?;
+        if let Some(display_name) = &self.display_name {
+            write!(f, "; display_name:{display_name}")?;
+        }
+        write!(f, "}}")
     }
 }
 
@@ -91,70 +193,906 @@ impl DisplayExt for Unregister {
     }
 }
 
+#[derive(Debug)]
+pub enum CallTarget {
+    User(String),
+    Contact(String),
+    Voicemail,
+}
+
 #[derive(Debug)]
 pub struct MakeCall {
-    target_user_name: String,
+    target: CallTarget,
+    subject: Option<String>,
 }
 
 impl MakeCall {
-    pub fn new(target_user_name: &str) -> Self {
-        Self {
-            target_user_name: target_user_name.to_owned(),
-        }
+    pub fn new(target: CallTarget, subject: Option<String>) -> Self {
+        Self { target, subject }
     }
 }
 
 impl CommandTrait for MakeCall {
     async fn execute(self, app: &mut App) -> Result<()> {
-        app.make_call(&self.target_user_name).await
+        let target_user_name = match self.target {
+            CallTarget::User(user) => user,
+            CallTarget::Contact(name) => app.contact_user(&name)?,
+            CallTarget::Voicemail => app.voicemail_user()?,
+        };
+        app.make_call(&target_user_name, self.subject.as_deref())
+            .await
     }
 }
 
 impl DisplayExt for MakeCall {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "make call {{user:{}}}", self.target_user_name)
+        match &self.target {
+            CallTarget::User(user) => write!(f, "make call {{user:{user}}}")?,
+            CallTarget::Contact(name) => write!(f, "make call {{name:{name}}}")?,
+            CallTarget::Voicemail => write!(f, "voicemail")?,
+        }
+        if let Some(subject) = &self.subject {
+            write!(f, " subject={subject:?}")?;
+        }
+        Ok(())
     }
 }
 
 #[derive(Debug)]
-pub struct TerminateCall;
+pub struct AddContact {
+    name: String,
+    user: String,
+}
 
-impl TerminateCall {
+impl AddContact {
+    pub fn new(name: &str, user: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            user: user.to_owned(),
+        }
+    }
+}
+
+impl CommandTrait for AddContact {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.add_contact(&self.name, &self.user)
+    }
+}
+
+impl DisplayExt for AddContact {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "contact add {{name:{}; user:{}}}", self.name, self.user)
+    }
+}
+
+#[derive(Debug)]
+pub struct ShowContacts;
+
+impl ShowContacts {
     pub fn new() -> Self {
         Self {}
     }
 }
 
+impl CommandTrait for ShowContacts {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.print_contacts()
+    }
+}
+
+impl DisplayExt for ShowContacts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "contacts")
+    }
+}
+
+#[derive(Debug)]
+pub struct ShowHistory {
+    count: usize,
+}
+
+impl ShowHistory {
+    pub fn new(count: usize) -> Self {
+        Self { count }
+    }
+}
+
+impl CommandTrait for ShowHistory {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.print_history(self.count)
+    }
+}
+
+impl DisplayExt for ShowHistory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "history {{count:{}}}", self.count)
+    }
+}
+
+#[derive(Debug)]
+pub struct ExportHistory {
+    path: PathBuf,
+}
+
+impl ExportHistory {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl CommandTrait for ExportHistory {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.export_history(&self.path)
+    }
+}
+
+impl DisplayExt for ExportHistory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "history export {{file:{:?}}}", self.path)
+    }
+}
+
+#[derive(Debug)]
+pub struct TerminateCall {
+    // the number of the call since the start, any active call if not set
+    call_id: Option<u64>,
+}
+
+impl TerminateCall {
+    pub fn new(call_id: Option<u64>) -> Self {
+        Self { call_id }
+    }
+}
+
 impl CommandTrait for TerminateCall {
     async fn execute(self, app: &mut App) -> Result<()> {
-        app.terminate_call().await
+        app.terminate_call(self.call_id).await
     }
 }
 
 impl DisplayExt for TerminateCall {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "terminate call")
+        match self.call_id {
+            Some(call_id) => write!(f, "terminate call id={call_id}"),
+            None => write!(f, "terminate call"),
+        }
     }
 }
 
 #[derive(Debug)]
-pub struct StopApp;
+pub enum TransferTarget {
+    User(String),
+    Uri(String),
+}
+
+#[derive(Debug)]
+pub struct TransferCall {
+    target: TransferTarget,
+}
 
-impl StopApp {
+impl TransferCall {
+    pub fn new(target: TransferTarget) -> Self {
+        Self { target }
+    }
+}
+
+impl CommandTrait for TransferCall {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        match self.target {
+            TransferTarget::User(user) => app.transfer_call(&user, false).await,
+            TransferTarget::Uri(uri) => app.transfer_call(&uri, true).await,
+        }
+    }
+}
+
+impl DisplayExt for TransferCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.target {
+            TransferTarget::User(user) => write!(f, "transfer call {{user:{user}}}"),
+            TransferTarget::Uri(uri) => write!(f, "transfer call {{uri:{uri}}}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StartRecording {
+    path: PathBuf,
+    mode: RecordingMode,
+}
+
+impl StartRecording {
+    pub fn new(path: PathBuf, mode: RecordingMode) -> Self {
+        Self { path, mode }
+    }
+}
+
+impl CommandTrait for StartRecording {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.start_recording(&self.path, self.mode)
+    }
+}
+
+impl DisplayExt for StartRecording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "record {{file:{:?}; mode:{}}}", self.path, self.mode)
+    }
+}
+
+#[derive(Debug)]
+pub struct StopRecording;
+
+impl StopRecording {
     pub fn new() -> Self {
         Self {}
     }
 }
 
-impl CommandTrait for StopApp {
+impl CommandTrait for StopRecording {
     async fn execute(self, app: &mut App) -> Result<()> {
-        app.stop_app()
+        app.stop_recording()
     }
 }
 
-impl DisplayExt for StopApp {
+impl DisplayExt for StopRecording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stop recording")
+    }
+}
+
+#[derive(Debug)]
+pub struct PlayFile {
+    path: PathBuf,
+    mode: PlaybackMode,
+}
+
+impl PlayFile {
+    pub fn new(path: PathBuf, mode: PlaybackMode) -> Self {
+        Self { path, mode }
+    }
+}
+
+impl CommandTrait for PlayFile {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.start_playback(&self.path, self.mode)
+    }
+}
+
+impl DisplayExt for PlayFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "play {{file:{:?}; mode:{}}}", self.path, self.mode)
+    }
+}
+
+#[derive(Debug)]
+pub struct StopPlaying;
+
+impl StopPlaying {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandTrait for StopPlaying {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.stop_playback()
+    }
+}
+
+impl DisplayExt for StopPlaying {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stop playing")
+    }
+}
+
+#[derive(Debug)]
+pub struct SendDtmf {
+    digits: String,
+}
+
+impl SendDtmf {
+    pub fn new(digits: &str) -> Self {
+        Self {
+            digits: digits.to_owned(),
+        }
+    }
+}
+
+impl CommandTrait for SendDtmf {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.send_dtmf(&self.digits)
+    }
+}
+
+impl DisplayExt for SendDtmf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "dtmf {{digits:{}}}", self.digits)
+    }
+}
+
+pub struct SendMessage {
+    user_name: String,
+    text: String,
+}
+
+impl SendMessage {
+    pub fn new(user_name: &str, text: &str) -> Self {
+        Self {
+            user_name: user_name.to_owned(),
+            text: text.to_owned(),
+        }
+    }
+}
+
+impl CommandTrait for SendMessage {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.send_message(&self.user_name, &self.text)
+    }
+}
+
+// the text isn't logged and audited, like the password
+impl DisplayExt for SendMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "message {{user:{}}}", self.user_name)
+    }
+}
+
+#[derive(Debug)]
+pub struct SendPing {
+    host: Option<String>,
+}
+
+impl SendPing {
+    pub fn new(host: Option<String>) -> Self {
+        Self { host }
+    }
+}
+
+impl CommandTrait for SendPing {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.ping(self.host.as_deref())
+    }
+}
+
+impl DisplayExt for SendPing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.host {
+            Some(host) => write!(f, "ping {{host:{host}}}"),
+            None => write!(f, "ping"),
+        }
+    }
+}
+
+pub struct SetAnsweringMachine {
+    machine: Option<AnsweringMachine>,
+}
+
+impl SetAnsweringMachine {
+    pub fn new(machine: Option<AnsweringMachine>) -> Self {
+        Self { machine }
+    }
+}
+
+impl CommandTrait for SetAnsweringMachine {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.set_answering_machine(self.machine)
+    }
+}
+
+impl DisplayExt for SetAnsweringMachine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.machine {
+            Some(machine) => write!(f, "answering machine on {machine}"),
+            None => write!(f, "answering machine off"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SetMeter {
+    enabled: bool,
+}
+
+impl SetMeter {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl CommandTrait for SetMeter {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.set_meter(self.enabled)
+    }
+}
+
+impl DisplayExt for SetMeter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "meter {}", if self.enabled { "on" } else { "off" })
+    }
+}
+
+#[derive(Debug)]
+pub struct SetMute {
+    // toggles if not set
+    muted: Option<bool>,
+}
+
+impl SetMute {
+    pub fn new(muted: Option<bool>) -> Self {
+        Self { muted }
+    }
+}
+
+impl CommandTrait for SetMute {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.set_muted(self.muted)
+    }
+}
+
+impl DisplayExt for SetMute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.muted {
+            Some(true) => write!(f, "mute on"),
+            Some(false) => write!(f, "mute off"),
+            None => write!(f, "mute toggle"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SetPresence {
+    status: PresenceStatus,
+    note: Option<String>,
+}
+
+impl SetPresence {
+    pub fn new(status: PresenceStatus, note: Option<String>) -> Self {
+        Self { status, note }
+    }
+}
+
+impl CommandTrait for SetPresence {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.publish_presence(self.status, self.note).await
+    }
+}
+
+impl DisplayExt for SetPresence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "presence {}", self.status)?;
+        if let Some(note) = &self.note {
+            write!(f, " note={note:?}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct SetSipTrace {
+    enabled: bool,
+}
+
+impl SetSipTrace {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl CommandTrait for SetSipTrace {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.set_sip_trace(self.enabled)
+    }
+}
+
+impl DisplayExt for SetSipTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "trace sip {}", if self.enabled { "on" } else { "off" })
+    }
+}
+
+#[derive(Debug)]
+pub struct StartCapture {
+    path: PathBuf,
+    rtp: bool,
+}
+
+impl StartCapture {
+    pub fn new(path: PathBuf, rtp: bool) -> Self {
+        Self { path, rtp }
+    }
+}
+
+impl CommandTrait for StartCapture {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.start_capture(&self.path, self.rtp)
+    }
+}
+
+impl DisplayExt for StartCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "capture start {{file:{:?}; rtp:{}}}",
+            self.path, self.rtp
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct StopCapture;
+
+impl StopCapture {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandTrait for StopCapture {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.stop_capture()
+    }
+}
+
+impl DisplayExt for StopCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "capture stop")
+    }
+}
+
+#[derive(Debug)]
+pub struct SetLoopback {
+    enabled: bool,
+}
+
+impl SetLoopback {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl CommandTrait for SetLoopback {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.set_loopback(self.enabled)
+    }
+}
+
+impl DisplayExt for SetLoopback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "audio test loopback {}",
+            if self.enabled { "on" } else { "off" }
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct SetVolume {
+    input_volume: Option<u32>,
+    output_volume: Option<u32>,
+}
+
+impl SetVolume {
+    pub fn new(input_volume: Option<u32>, output_volume: Option<u32>) -> Self {
+        Self {
+            input_volume,
+            output_volume,
+        }
+    }
+}
+
+impl CommandTrait for SetVolume {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.set_volume(self.input_volume, self.output_volume)
+    }
+}
+
+impl DisplayExt for SetVolume {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "volume {{in:{:?}; out:{:?}}}",
+            self.input_volume, self.output_volume
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct ShowAudioStats;
+
+impl ShowAudioStats {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandTrait for ShowAudioStats {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.print_audio_stats()
+    }
+}
+
+impl DisplayExt for ShowAudioStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stats audio")
+    }
+}
+
+#[derive(Debug)]
+pub struct ShowCallStats;
+
+impl ShowCallStats {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandTrait for ShowCallStats {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.print_call_stats()
+    }
+}
+
+impl DisplayExt for ShowCallStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stats call")
+    }
+}
+
+#[derive(Debug)]
+pub struct ShowFlow;
+
+impl ShowFlow {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandTrait for ShowFlow {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.print_flow()
+    }
+}
+
+impl DisplayExt for ShowFlow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "flow")
+    }
+}
+
+#[derive(Debug)]
+pub struct ShowStatus;
+
+impl ShowStatus {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandTrait for ShowStatus {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.print_status()
+    }
+}
+
+impl DisplayExt for ShowStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "status")
+    }
+}
+
+#[derive(Debug)]
+pub struct ShowVersion;
+
+impl ShowVersion {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandTrait for ShowVersion {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.print_version();
+        Ok(())
+    }
+}
+
+impl DisplayExt for ShowVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "version")
+    }
+}
+
+#[derive(Debug)]
+pub struct SourceScript {
+    path: PathBuf,
+}
+
+impl SourceScript {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl CommandTrait for SourceScript {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.source_script(&self.path)
+    }
+}
+
+impl DisplayExt for SourceScript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "source {{file:{:?}}}", self.path)
+    }
+}
+
+#[derive(Debug)]
+pub struct Reload;
+
+impl Reload {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl CommandTrait for Reload {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.reload()
+    }
+}
+
+impl DisplayExt for Reload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "reload")
+    }
+}
+
+#[derive(Debug)]
+pub struct StopApp {
+    // quits even if there is an active call
+    force: bool,
+}
+
+impl StopApp {
+    pub fn new(force: bool) -> Self {
+        Self { force }
+    }
+}
+
+impl StopApp {
+    pub fn is_forced(&self) -> bool {
+        self.force
+    }
+}
+
+impl CommandTrait for StopApp {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.stop_app(self.force).await
+    }
+}
+
+impl DisplayExt for StopApp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.force {
+            write!(f, "quit force")
+        } else {
+            write!(f, "quit")
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Confirm {
+    accepted: bool,
+}
+
+impl Confirm {
+    pub fn new(accepted: bool) -> Self {
+        Self { accepted }
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        self.accepted
+    }
+}
+
+impl CommandTrait for Confirm {
+    async fn execute(self, _app: &mut App) -> Result<()> {
+        Err(anyhow::Error::msg("There is nothing to confirm"))
+    }
+}
+
+impl DisplayExt for Confirm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.accepted {
+            write!(f, "yes")
+        } else {
+            write!(f, "no")
+        }
+    }
+}
+
+pub type ReplyResult = std::result::Result<serde_json::Value, String>;
+
+pub struct Reply {
+    command: Box<Command>,
+    sender: oneshot::Sender<ReplyResult>,
+}
+
+impl Reply {
+    pub fn new(command: Command, sender: oneshot::Sender<ReplyResult>) -> Self {
+        Self {
+            command: Box::new(command),
+            sender,
+        }
+    }
+
+    pub fn into_parts(self) -> (Command, oneshot::Sender<ReplyResult>) {
+        (*self.command, self.sender)
+    }
+}
+
+impl CommandTrait for Reply {
+    async fn execute(self, _app: &mut App) -> Result<()> {
+        Err(anyhow::Error::msg("The reply is sent by the app"))
+    }
+}
+
+impl DisplayExt for Reply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        DisplayExt::fmt(self.command.as_ref(), f)
+    }
+}
+
+pub struct ForInstance {
+    name: String,
+    command: Box<Command>,
+}
+
+impl ForInstance {
+    pub fn new(name: &str, command: Command) -> Self {
+        Self {
+            name: name.to_owned(),
+            command: Box::new(command),
+        }
+    }
+
+    pub fn into_parts(self) -> (String, Command) {
+        (self.name, *self.command)
+    }
+}
+
+impl CommandTrait for ForInstance {
+    async fn execute(self, _app: &mut App) -> Result<()> {
+        Err(anyhow::Error::msg("The command is forwarded by the app"))
+    }
+}
+
+impl DisplayExt for ForInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "@{} ", self.name)?;
+        DisplayExt::fmt(self.command.as_ref(), f)
+    }
+}
+
+pub struct RunPlugin {
+    command: Box<dyn PluginCommand>,
+}
+
+impl RunPlugin {
+    pub fn new(command: Box<dyn PluginCommand>) -> Self {
+        Self { command }
+    }
+}
+
+impl CommandTrait for RunPlugin {
+    async fn execute(self, app: &mut App) -> Result<()> {
+        app.run_plugin(self.command)
+    }
+}
+
+impl DisplayExt for RunPlugin {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "stop app")
+        write!(f, "{}", self.command)
     }
 }
 
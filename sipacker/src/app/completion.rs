@@ -0,0 +1,158 @@
+use crate::app::{
+    cli_input::CommandSpec,
+    contacts::Contacts,
+    history::{CallHistory, Direction},
+};
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, RwLock},
+};
+
+use rustyline::{
+    completion::Completer, highlight::Highlighter, hint::Hinter, validate::Validator, Context,
+    Helper,
+};
+
+const INPUT_COMMANDS: [&str; 3] = ["help", "keys", "exit"];
+
+#[derive(Clone, Default)]
+pub(crate) struct Completions(Arc<RwLock<Values>>);
+
+#[derive(Default)]
+struct Values {
+    names: BTreeSet<String>,
+    users: BTreeSet<String>,
+}
+
+impl Completions {
+    pub fn update(&self, contacts: &Contacts, history: &CallHistory) {
+        let mut values = Values::default();
+        for (name, user) in contacts.iter() {
+            values.names.insert(name.to_owned());
+            values.users.insert(user.to_owned());
+        }
+        let callees = history
+            .last(usize::MAX)
+            .filter(|record| record.direction == Direction::Outbound)
+            .map(|record| match record.remote.rsplit_once(" (") {
+                Some((_name, user)) => user.trim_end_matches(')'),
+                None => record.remote.as_str(),
+            });
+        values.users.extend(callees.map(str::to_owned));
+        *self.0.write().unwrap_or_else(|err| err.into_inner()) = values;
+    }
+
+    fn values(&self, arg: &str) -> Vec<String> {
+        let values = self.0.read().unwrap_or_else(|err| err.into_inner());
+        match arg {
+            "name" => values.names.iter().cloned().collect(),
+            "user" => values.users.iter().cloned().collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+pub(crate) struct CommandCompleter {
+    specs: Vec<&'static CommandSpec>,
+    completions: Completions,
+}
+
+impl CommandCompleter {
+    pub fn new(specs: Vec<&'static CommandSpec>, completions: Completions) -> Self {
+        Self { specs, completions }
+    }
+
+    fn complete_command(&self, offset: usize, typed: &str) -> (usize, Vec<String>) {
+        let names = self
+            .specs
+            .iter()
+            .map(|spec| spec.name)
+            .chain(INPUT_COMMANDS)
+            .filter(|name| name.starts_with(typed))
+            .map(str::to_owned)
+            .collect();
+        (offset, names)
+    }
+
+    fn complete_argument(
+        &self,
+        spec: &CommandSpec,
+        line: &str,
+        start: usize,
+        word: &str,
+    ) -> (usize, Vec<String>) {
+        if let Some((key, value)) = word.split_once('=') {
+            let values = match spec.args.iter().find(|arg| arg.name == key) {
+                Some(arg) => self.values(arg.name, arg.value),
+                None => Vec::new(),
+            };
+            let values = values
+                .into_iter()
+                .filter(|candidate| candidate.starts_with(value))
+                .collect();
+            return (start + key.len() + 1, values);
+        }
+
+        let mut candidates = Vec::new();
+        for arg in spec.args {
+            if arg.name.is_empty() {
+                candidates.extend(self.values(arg.name, arg.value));
+            } else if !line.contains(&format!("{}=", arg.name)) {
+                candidates.push(format!("{}=", arg.name));
+            }
+        }
+        let candidates = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .collect();
+        (start, candidates)
+    }
+
+    fn values(&self, name: &str, value: &str) -> Vec<String> {
+        if value.starts_with('<') {
+            return self.completions.values(name);
+        }
+        value.split('|').map(str::to_owned).collect()
+    }
+}
+
+impl Completer for CommandCompleter {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        if let Some(topic) = line.strip_prefix("help ") {
+            return Ok(self.complete_command("help ".len(), topic));
+        }
+        // the longest name wins: "history export" over "history"
+        let spec = self
+            .specs
+            .iter()
+            .filter(|spec| {
+                line.strip_prefix(spec.name)
+                    .is_some_and(|rest| rest.starts_with(' '))
+            })
+            .max_by_key(|spec| spec.name.len());
+        let Some(spec) = spec else {
+            return Ok(self.complete_command(0, line));
+        };
+        let start = line.rfind(' ').map_or(0, |index| index + 1);
+        Ok(self.complete_argument(spec, line, start, &line[start..]))
+    }
+}
+
+impl Hinter for CommandCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for CommandCompleter {}
+
+impl Validator for CommandCompleter {}
+
+impl Helper for CommandCompleter {}
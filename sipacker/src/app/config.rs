@@ -0,0 +1,301 @@
+use crate::app::{
+    cli_input::parser,
+    command::{self, Command},
+};
+use sipacker_core::user_agent::SrtpPolicy;
+
+use std::{
+    collections::BTreeMap,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    pub ip_addr: Option<Ipv4Addr>,
+    pub port: Option<u16>,
+    pub jobs: Option<usize>,
+    pub script: Option<PathBuf>,
+    pub command_fifo: Option<PathBuf>,
+    pub http_api: Option<SocketAddr>,
+    pub grpc_api: Option<SocketAddr>,
+    pub health_api: Option<SocketAddr>,
+    pub audit_log: Option<PathBuf>,
+    pub cdr: Option<PathBuf>,
+    pub hooks: Option<PathBuf>,
+    pub webhooks: Vec<WebhookSection>,
+    pub exec: ExecSection,
+    pub audio: AudioSection,
+    pub timeouts: TimeoutsSection,
+    pub ui: UiSection,
+    pub log: LogSection,
+    // the first account is registered at the startup
+    pub accounts: Vec<Account>,
+    pub blf: Vec<String>,
+    pub voicemail: Option<String>,
+    pub intercom: bool,
+    pub profile: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+    pub instances: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Profile {
+    pub ip_addr: Option<Ipv4Addr>,
+    pub port: Option<u16>,
+    pub audio: AudioSection,
+    pub accounts: Vec<Account>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AudioSection {
+    pub backend: Option<String>,
+    pub in_file: Option<PathBuf>,
+    pub in_loop: bool,
+    pub out_file: Option<PathBuf>,
+    pub input_channel: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub buffer_size: Option<u32>,
+    pub ptime_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TimeoutsSection {
+    pub call_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct UiSection {
+    pub tui: bool,
+    pub color: Option<bool>,
+    pub output: Option<String>,
+    pub confirm: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct LogSection {
+    pub file: Option<PathBuf>,
+    pub format: Option<String>,
+    pub level: Option<String>,
+    pub max_size_mb: Option<u64>,
+    pub rotation: Option<String>,
+    pub keep: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct WebhookSection {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ExecSection {
+    pub on_incoming_call: Option<String>,
+    pub on_call_established: Option<String>,
+    pub on_call_ended: Option<String>,
+    pub on_missed_call: Option<String>,
+    pub on_registered: Option<String>,
+    pub on_registration_failed: Option<String>,
+    pub on_dtmf: Option<String>,
+    pub on_info: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Account {
+    pub user: String,
+    #[serde(default)]
+    pub password: String,
+    pub registrar: String,
+    #[serde(default)]
+    pub srtp: Option<String>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+}
+
+impl Config {
+    // the default file is optional, the file set explicitly must exist
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> Result<Self> {
+        let config = Self::load_file(path)?;
+        let config = match profile.map(str::to_owned).or(config.profile.clone()) {
+            Some(profile) => config.with_profile(&profile)?,
+            None => config,
+        };
+        config.with_env()
+    }
+
+    fn load_file(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let content = std::fs::read_to_string(&path).map_err(|err| {
+            anyhow::Error::msg(format!("Could not read the config {path:?}: {err}"))
+        })?;
+        let config = toml::from_str(&content)
+            .map_err(|err| anyhow::Error::msg(format!("Invalid config {path:?}: {err}")))?;
+        tracing::info!("Config is loaded from {path:?}");
+        Ok(config)
+    }
+
+    // the options without the args, the other SIPACKER_* variables are read by clap. The
+    // account of the environment replaces the ones of the config and the profile
+    fn with_env(mut self) -> Result<Self> {
+        let var = |name: &str| std::env::var(format!("SIPACKER_{name}")).ok();
+        if let Some(confirm) = var("CONFIRM") {
+            self.ui.confirm = confirm.parse().map_err(|_| {
+                anyhow::Error::msg(format!(
+                    "Invalid SIPACKER_CONFIRM: {confirm}, expected true or false"
+                ))
+            })?;
+        }
+        match (var("ACCOUNT_USER"), var("ACCOUNT_REGISTRAR")) {
+            (Some(user), Some(registrar)) => {
+                tracing::info!("Config account {user} is taken from the environment");
+                self.accounts = vec![Account {
+                    user,
+                    password: var("ACCOUNT_PASSWORD").unwrap_or_default(),
+                    registrar,
+                    srtp: var("ACCOUNT_SRTP"),
+                    display_name: var("ACCOUNT_DISPLAY_NAME"),
+                }];
+            }
+            (None, None) => {}
+            _ => {
+                return Err(anyhow::Error::msg(
+                    "SIPACKER_ACCOUNT_USER and SIPACKER_ACCOUNT_REGISTRAR are set together",
+                ))
+            }
+        }
+        Ok(self)
+    }
+
+    fn with_profile(mut self, name: &str) -> Result<Self> {
+        let profile = self.profiles.get(name).cloned().ok_or_else(|| {
+            let known: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+            anyhow::Error::msg(format!(
+                "Unknown profile: {name} (the config has: {})",
+                known.join(", ")
+            ))
+        })?;
+        tracing::info!("Config profile: {name}");
+        self.ip_addr = profile.ip_addr.or(self.ip_addr);
+        self.port = profile.port.or(self.port);
+        self.audio.overlay(profile.audio);
+        if !profile.accounts.is_empty() {
+            self.accounts = profile.accounts;
+        }
+        Ok(self)
+    }
+
+    // the config of [instances.<name>]: the listen address, the audio and the accounts of its
+    // own, the timeouts, the ui and the log of the config. The port is required, the instance
+    // doesn't share the one of the main user agent
+    pub fn instance(&self, name: &str) -> Result<Self> {
+        let instance =
+            self.instances.get(name).cloned().ok_or_else(|| {
+                anyhow::Error::msg(format!("Unknown instance of the config: {name}"))
+            })?;
+        let port = instance.port.ok_or_else(|| {
+            anyhow::Error::msg(format!("The instance {name} of the config has no port"))
+        })?;
+        Ok(Self {
+            ip_addr: instance.ip_addr.or(self.ip_addr),
+            port: Some(port),
+            jobs: self.jobs,
+            audio: instance.audio,
+            timeouts: self.timeouts.clone(),
+            ui: self.ui.clone(),
+            log: self.log.clone(),
+            accounts: instance.accounts,
+            ..Self::default()
+        })
+    }
+
+    // the sections which are read at the startup only
+    pub fn restart_required(&self, other: &Self) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if (self.ip_addr, self.port, self.jobs) != (other.ip_addr, other.port, other.jobs) {
+            changed.push("listen address");
+        }
+        if self.script != other.script {
+            changed.push("script");
+        }
+        if self.audio != other.audio {
+            changed.push("audio");
+        }
+        if self.ui != other.ui {
+            changed.push("ui");
+        }
+        if self.log != other.log {
+            changed.push("log");
+        }
+        if self.audit_log != other.audit_log {
+            changed.push("audit log");
+        }
+        if self.cdr != other.cdr {
+            changed.push("cdr");
+        }
+        if self.hooks != other.hooks {
+            changed.push("hooks");
+        }
+        if self.webhooks != other.webhooks {
+            changed.push("webhooks");
+        }
+        if self.instances != other.instances {
+            changed.push("instances");
+        }
+        changed
+    }
+
+    fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("sipacker").join("sipacker.toml"))
+    }
+}
+
+impl AudioSection {
+    fn overlay(&mut self, other: AudioSection) {
+        self.backend = other.backend.or(self.backend.take());
+        self.in_file = other.in_file.or(self.in_file.take());
+        self.in_loop |= other.in_loop;
+        self.out_file = other.out_file.or(self.out_file.take());
+        self.input_channel = other.input_channel.or(self.input_channel.take());
+        self.sample_rate = other.sample_rate.or(self.sample_rate);
+        self.buffer_size = other.buffer_size.or(self.buffer_size);
+        self.ptime_ms = other.ptime_ms.or(self.ptime_ms);
+    }
+}
+
+impl Account {
+    pub fn register_command(&self) -> Result<Command> {
+        let registrar_host = parser::parse_host_port(&self.registrar)?;
+        let srtp = match &self.srtp {
+            Some(srtp) => srtp.parse()?,
+            None => SrtpPolicy::default(),
+        };
+        let command = command::Register::new(
+            &self.user,
+            self.password.clone(),
+            registrar_host,
+            srtp,
+            self.display_name.clone(),
+        );
+        Ok(command.into())
+    }
+}
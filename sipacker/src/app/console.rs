@@ -0,0 +1,377 @@
+use sipacker_core::{rtcp_xr, user_agent::UserAgentEvent};
+
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::mpsc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use chrono::Local;
+use crossterm::{
+    style::{Color, Stylize},
+    terminal,
+};
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+
+// the terminal bell is rung with the alerts
+pub(crate) const BELL: char = '\x07';
+
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+pub(crate) enum ConsoleMessage {
+    Text(Severity, String),
+    Event(UserAgentEvent, String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow::Error::msg(format!("Unknown output format: {s}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Severity {
+    Info,
+    Success,
+    // needs the user's attention: the incoming call or the message
+    Alert,
+    Error,
+}
+
+impl Severity {
+    pub fn of(event: &UserAgentEvent) -> Self {
+        match event {
+            UserAgentEvent::IncomingCall { .. } | UserAgentEvent::MessageReceived { .. } => {
+                Self::Alert
+            }
+            UserAgentEvent::MessageWaiting { summary } if summary.new > 0 => Self::Alert,
+            UserAgentEvent::Registered { .. } | UserAgentEvent::CallEstablished { .. } => {
+                Self::Success
+            }
+            UserAgentEvent::CallFailed { .. } | UserAgentEvent::PingFailed { .. } => Self::Error,
+            _ => Self::Info,
+        }
+    }
+
+    fn icon(&self) -> Option<&'static str> {
+        match self {
+            Self::Info => None,
+            Self::Success => Some("✔"),
+            Self::Alert => Some("☎"),
+            Self::Error => Some("✖"),
+        }
+    }
+
+    fn color(&self) -> Option<Color> {
+        match self {
+            Self::Info => None,
+            Self::Success => Some(Color::Green),
+            Self::Alert => Some(Color::Yellow),
+            Self::Error => Some(Color::Red),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Console {
+    forward: Option<mpsc::Sender<ConsoleMessage>>,
+    events: Option<broadcast::Sender<Value>>,
+    color: bool,
+    format: OutputFormat,
+    instance: Option<String>,
+}
+
+impl Console {
+    pub fn stdout(color: bool, format: OutputFormat) -> Self {
+        Self {
+            forward: None,
+            events: None,
+            color,
+            format,
+            instance: None,
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    pub fn tui(sender: mpsc::Sender<ConsoleMessage>) -> Self {
+        Self {
+            forward: Some(sender),
+            events: None,
+            color: false,
+            format: OutputFormat::Text,
+            instance: None,
+        }
+    }
+
+    pub fn daemon(sender: mpsc::Sender<ConsoleMessage>, format: OutputFormat) -> Self {
+        Self {
+            forward: Some(sender),
+            events: None,
+            color: false,
+            format,
+            instance: None,
+        }
+    }
+
+    pub fn with_events(mut self, events: broadcast::Sender<Value>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn with_instance(mut self, name: &str) -> Self {
+        self.instance = Some(name.to_owned());
+        self
+    }
+
+    pub fn print(&self, message: impl Display) {
+        if self.format == OutputFormat::Json {
+            return self.print_json("message", json!({ "text": message.to_string() }));
+        }
+        self.print_with(Severity::Info, message);
+    }
+
+    pub fn print_alert(&self, message: impl Display) {
+        if self.format == OutputFormat::Json {
+            return self.print_json("alert", json!({ "text": message.to_string() }));
+        }
+        self.print_with(Severity::Alert, Self::with_timestamp(message));
+    }
+
+    pub fn print_error(&self, err: impl Display) {
+        if self.format == OutputFormat::Json {
+            return self.print_json("error", json!({ "error": err.to_string() }));
+        }
+        self.print_with(Severity::Error, format!("Error: {err}"));
+    }
+
+    // the successful commands are reported in the JSON output only
+    pub fn print_command_result(&self, command: &str, result: &Result<()>) {
+        match (self.format, result) {
+            (OutputFormat::Json, Ok(())) => {
+                self.print_json("command", json!({ "command": command, "result": "ok" }))
+            }
+            (OutputFormat::Json, Err(err)) => self.print_json(
+                "command",
+                json!({ "command": command, "result": "error", "error": err.to_string() }),
+            ),
+            (OutputFormat::Text, Ok(())) => {}
+            (OutputFormat::Text, Err(err)) => self.print_error(err),
+        }
+    }
+
+    pub fn print_event(&self, event: &UserAgentEvent, message: impl Display, extra: Value) {
+        if self.format == OutputFormat::Json || self.events.is_some() {
+            let (kind, mut fields) = Self::event_fields(event);
+            if let (Some(fields), Value::Object(extra)) = (fields.as_object_mut(), extra) {
+                fields.extend(extra);
+            }
+            fields["message"] = message.to_string().into();
+            if let Some(call_id) = event.call_id() {
+                fields["call_id"] = call_id.into();
+            }
+            let object = self.json_object(kind, fields);
+            if let Some(events) = &self.events {
+                let _ = events.send(object.clone());
+            }
+            if self.format == OutputFormat::Json {
+                return self.print_json_object(object);
+            }
+        }
+        let message = Self::with_icon(
+            Severity::of(event),
+            self.tagged(Self::with_timestamp(message)),
+        );
+        match &self.forward {
+            // the TUI shows the state of the main user agent only
+            Some(tui) if self.instance.is_some() => {
+                let _ = tui.send(ConsoleMessage::Text(Severity::of(event), message));
+            }
+            Some(tui) => {
+                let _ = tui.send(ConsoleMessage::Event(event.clone(), message));
+            }
+            None => self.print_stdout(Severity::of(event), message),
+        }
+    }
+
+    fn print_with(&self, severity: Severity, message: impl Display) {
+        let message = Self::with_icon(severity, self.tagged(message));
+        match &self.forward {
+            Some(tui) => {
+                let _ = tui.send(ConsoleMessage::Text(severity, message));
+            }
+            None => self.print_stdout(severity, message),
+        }
+    }
+
+    fn print_stdout(&self, severity: Severity, message: String) {
+        // the raw mode of the hotkeys doesn't return the carriage
+        let message = if terminal::is_raw_mode_enabled().unwrap_or(false) {
+            format!("{}\r", message.replace('\n', "\r\n"))
+        } else {
+            message
+        };
+        if severity == Severity::Alert {
+            print!("{BELL}");
+        }
+        match severity.color() {
+            Some(color) if self.color => println!("{}", message.with(color).bold()),
+            _ => println!("{message}"),
+        }
+    }
+
+    fn with_timestamp(message: impl Display) -> String {
+        format!("{} {message}", Local::now().format("%H:%M:%S"))
+    }
+
+    fn tagged(&self, message: impl Display) -> String {
+        match &self.instance {
+            Some(instance) => format!("[{instance}] {message}"),
+            None => message.to_string(),
+        }
+    }
+
+    fn with_icon(severity: Severity, message: impl Display) -> String {
+        match severity.icon() {
+            Some(icon) => format!("{icon} {message}"),
+            None => message.to_string(),
+        }
+    }
+
+    fn print_json(&self, kind: &str, fields: Value) {
+        self.print_json_object(self.json_object(kind, fields));
+    }
+
+    fn json_object(&self, kind: &str, fields: Value) -> Value {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis() as u64)
+            .unwrap_or_default();
+        let mut object = json!({ "type": kind, "timestamp": timestamp });
+        if let Some(instance) = &self.instance {
+            object["instance"] = instance.as_str().into();
+        }
+        if let (Some(object), Value::Object(fields)) = (object.as_object_mut(), fields) {
+            object.extend(fields);
+        }
+        object
+    }
+
+    fn print_json_object(&self, object: Value) {
+        match &self.forward {
+            Some(forward) => {
+                let _ = forward.send(ConsoleMessage::Text(Severity::Info, object.to_string()));
+            }
+            None => println!("{object}"),
+        }
+    }
+
+    fn event_fields(event: &UserAgentEvent) -> (&'static str, Value) {
+        match event {
+            UserAgentEvent::AudioLevel(level) => (
+                "audio_level",
+                json!({ "rms": level.rms, "peak": level.peak }),
+            ),
+            UserAgentEvent::CallEstablished { .. } => ("call_established", json!({})),
+            UserAgentEvent::CallFailed { reason, .. } => {
+                ("call_failed", json!({ "reason": reason }))
+            }
+            UserAgentEvent::CallProgress { code, reason, .. } => (
+                "call_progress",
+                json!({ "code": code, "reason": reason }),
+            ),
+            UserAgentEvent::CallQuality { stats, .. } => (
+                "call_quality",
+                json!({
+                    "mos": stats.quality().mos,
+                    "loss_percent": stats.loss_percent,
+                    "jitter_ms": stats.jitter_ms,
+                    "peer_mos": stats
+                        .remote_metrics
+                        .as_ref()
+                        .filter(|metrics| metrics.mos_lq != rtcp_xr::UNAVAILABLE)
+                        .map(|metrics| metrics.mos_lq as f64 / 10.0),
+                }),
+            ),
+            UserAgentEvent::CallRinging { .. } => ("call_ringing", json!({})),
+            UserAgentEvent::CallSessionProgress { .. } => ("call_session_progress", json!({})),
+            UserAgentEvent::Calling { target, .. } => ("calling", json!({ "target": target })),
+            UserAgentEvent::CallTerminated { status, .. } => {
+                ("call_terminated", json!({ "status": status }))
+            }
+            UserAgentEvent::CallTrying { .. } => ("call_trying", json!({})),
+            UserAgentEvent::DtmfReceived { digit, .. } => {
+                ("dtmf_received", json!({ "digit": digit.to_string() }))
+            }
+            UserAgentEvent::DtmfSent { digits, .. } => ("dtmf_sent", json!({ "digits": digits })),
+            UserAgentEvent::IncomingCall { caller, .. } => (
+                "incoming_call",
+                json!({ "from": caller.uri, "display_name": caller.display_name }),
+            ),
+            UserAgentEvent::InfoReceived {
+                content_type, body, ..
+            } => (
+                "info_received",
+                json!({ "content_type": content_type, "body": body }),
+            ),
+            UserAgentEvent::LineStateChanged { extension, state } => (
+                "line_state_changed",
+                json!({ "extension": extension, "state": state.to_string() }),
+            ),
+            UserAgentEvent::MessageWaiting { summary } => (
+                "message_waiting",
+                json!({
+                    "waiting": summary.waiting,
+                    "new": summary.new,
+                    "old": summary.old,
+                    "account": summary.account,
+                }),
+            ),
+            UserAgentEvent::MessageReceived { from, body } => (
+                "message_received",
+                json!({ "from": from.uri, "display_name": from.display_name, "body": body }),
+            ),
+            UserAgentEvent::PingAnswered { host, response } => (
+                "ping_answered",
+                json!({
+                    "host": host,
+                    "status": response.status,
+                    "round_trip_ms": response.round_trip.as_millis() as u64,
+                }),
+            ),
+            UserAgentEvent::PingFailed { host, reason } => {
+                ("ping_failed", json!({ "host": host, "reason": reason }))
+            }
+            UserAgentEvent::PlaybackStarted { path, .. } => {
+                ("playback_started", json!({ "path": path }))
+            }
+            UserAgentEvent::PlaybackStopped { .. } => ("playback_stopped", json!({})),
+            UserAgentEvent::RecordingStarted { path, .. } => {
+                ("recording_started", json!({ "path": path }))
+            }
+            UserAgentEvent::RecordingStopped { .. } => ("recording_stopped", json!({})),
+            UserAgentEvent::Registered {
+                user_name,
+                registrar_host,
+            } => (
+                "registered",
+                json!({ "user": user_name, "registrar": registrar_host }),
+            ),
+            UserAgentEvent::Unregistered => ("unregistered", json!({})),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Contacts {
+    contacts: BTreeMap<String, String>,
+}
+
+impl Contacts {
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        if !path.exists() {
+            return Self::default();
+        }
+
+        std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| toml::from_str(&content).map_err(anyhow::Error::from))
+            .inspect(|_| tracing::info!("Contacts are loaded from {path:?}"))
+            .inspect_err(|err| tracing::warn!("Could not load contacts from {path:?}: {err}"))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or(anyhow::Error::msg("Config directory is not found"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, toml::to_string_pretty(self)?)?;
+        tracing::debug!("Contacts are saved to {path:?}");
+        Ok(())
+    }
+
+    pub fn add(&mut self, name: &str, user: &str) {
+        self.contacts.insert(name.to_owned(), user.to_owned());
+    }
+
+    pub fn user(&self, name: &str) -> Result<&str> {
+        self.contacts
+            .get(name)
+            .map(String::as_str)
+            .ok_or(anyhow::Error::msg(format!("Unknown contact: {name}")))
+    }
+
+    // the caller is matched if the user of the contact is a whole token of its URI,
+    // e.g. "1001" matches "sip:1001@example.com" but not "sip:11001@example.com"
+    pub fn find_caller(&self, caller: &str) -> Option<&str> {
+        self.contacts
+            .iter()
+            .find(|(_name, user)| {
+                caller
+                    .split(|c: char| !c.is_alphanumeric() && c != '_' && c != '-' && c != '.')
+                    .any(|token| token == user.as_str())
+            })
+            .map(|(name, _user)| name.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.contacts
+            .iter()
+            .map(|(name, user)| (name.as_str(), user.as_str()))
+    }
+
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("sipacker").join("contacts.toml"))
+    }
+}
@@ -0,0 +1,221 @@
+use crate::app::{
+    cli_input::CommandLine,
+    command::Command,
+    console::{Console, ConsoleMessage, OutputFormat},
+    rpc,
+};
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc as std_mpsc, Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+const ACCEPT_POLL_TIMEOUT: Duration = Duration::from_millis(200);
+// the client which doesn't read the messages is disconnected instead of blocking the others
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// the daemon mode: the command lines are read from the clients of the Unix socket instead of
+// the stdin, the console messages are written to all the connected clients. The client
+// sending a JSON-RPC request receives the response and the messages as the notifications
+pub(crate) fn run_control_socket(
+    shutdown: CancellationToken,
+    command_line: CommandLine,
+    path: &Path,
+    format: OutputFormat,
+) -> Result<(mpsc::Receiver<Command>, Console)> {
+    let listener = bind(path)?;
+    tracing::info!("Control socket is listening on {path:?}");
+    let (command_sender, command_receiver) = mpsc::channel(20);
+    let (message_sender, message_receiver) = std_mpsc::channel();
+    let clients = Clients::default();
+
+    {
+        let clients = clients.clone();
+        thread::spawn(move || clients.forward(message_receiver));
+    }
+    let path = path.to_owned();
+    let command_line = Arc::new(command_line);
+    thread::spawn(move || {
+        if let Err(err) = accept(listener, &shutdown, command_sender, command_line, clients) {
+            tracing::error!("Control socket err: {err}");
+        }
+        let _ = fs::remove_file(&path);
+    });
+    Ok((command_receiver, Console::daemon(message_sender, format)))
+}
+
+// the socket left by the previous run is replaced, the file is accessible by the owner only
+fn bind(path: &Path) -> Result<UnixListener> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(anyhow::Error::msg(format!(
+                "The control path {path:?} exists and is not a socket"
+            )));
+        }
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)
+        .map_err(|err| anyhow::Error::msg(format!("Could not bind {path:?}: {err}")))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+fn accept(
+    listener: UnixListener,
+    shutdown: &CancellationToken,
+    command_sender: mpsc::Sender<Command>,
+    command_line: Arc<CommandLine>,
+    clients: Clients,
+) -> Result<()> {
+    let mut client_id = 0;
+    while !shutdown.is_cancelled() && !command_sender.is_closed() {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_POLL_TIMEOUT);
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        client_id += 1;
+        tracing::info!("Control client #{client_id} is connected");
+        stream.set_nonblocking(false)?;
+        let writer = stream.try_clone()?;
+        writer.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let rpc = clients.add(writer);
+        let command_sender = command_sender.clone();
+        let command_line = command_line.clone();
+        thread::spawn(move || {
+            if let Err(err) = read_commands(stream, command_sender, &command_line, &rpc) {
+                tracing::warn!("Control client #{client_id} err: {err}");
+            }
+            tracing::info!("Control client #{client_id} is disconnected");
+        });
+    }
+    Ok(())
+}
+
+fn read_commands(
+    stream: UnixStream,
+    command_sender: mpsc::Sender<Command>,
+    command_line: &CommandLine,
+    rpc: &AtomicBool,
+) -> Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('{') {
+            rpc.store(true, Ordering::Relaxed);
+            if let Some(response) = handle_request(line, &command_sender, command_line) {
+                writeln!(writer, "{response}")?;
+            }
+            continue;
+        }
+        if let Some(help) = command_line.help_line(line) {
+            match help {
+                Ok(help) => writeln!(writer, "{help}")?,
+                Err(err) => writeln!(writer, "Error: {err}")?,
+            }
+            continue;
+        }
+        match command_line.parse(line) {
+            Ok(command) => {
+                if command_sender.blocking_send(command).is_err() {
+                    break;
+                }
+            }
+            Err(err) => writeln!(writer, "Error: {err}")?,
+        }
+    }
+    Ok(())
+}
+
+fn handle_request(
+    line: &str,
+    command_sender: &mpsc::Sender<Command>,
+    command_line: &CommandLine,
+) -> Option<String> {
+    let request = match rpc::parse_request(line, &command_line.specs()) {
+        Ok(request) => request,
+        Err((id, err)) => return Some(rpc::response(id, Err(err))),
+    };
+    let result = execute_request(&request.line, command_sender, command_line);
+    request.id.map(|id| rpc::response(id, result))
+}
+
+fn execute_request(
+    line: &str,
+    command_sender: &mpsc::Sender<Command>,
+    command_line: &CommandLine,
+) -> Result<Value, rpc::Error> {
+    match rpc::prepare(line, command_line)? {
+        rpc::Prepared::Answer(answer) => Ok(answer),
+        rpc::Prepared::Command(command, reply_receiver) => {
+            command_sender
+                .blocking_send(command)
+                .map_err(|_| rpc::Error::new(rpc::COMMAND_ERROR, "The app is stopped"))?;
+            rpc::reply_result(reply_receiver.blocking_recv())
+        }
+    }
+}
+
+struct Client {
+    stream: UnixStream,
+    rpc: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Default)]
+struct Clients(Arc<Mutex<Vec<Client>>>);
+
+impl Clients {
+    fn add(&self, stream: UnixStream) -> Arc<AtomicBool> {
+        let rpc = Arc::new(AtomicBool::new(false));
+        self.lock().push(Client {
+            stream,
+            rpc: rpc.clone(),
+        });
+        rpc
+    }
+
+    fn forward(&self, message_receiver: std_mpsc::Receiver<ConsoleMessage>) {
+        while let Ok(message) = message_receiver.recv() {
+            let text = match message {
+                ConsoleMessage::Text(_, text) | ConsoleMessage::Event(_, text) => text,
+            };
+            let notification = rpc::notification(&text);
+            self.lock().retain_mut(|client| {
+                let line = if client.rpc.load(Ordering::Relaxed) {
+                    &notification
+                } else {
+                    &text
+                };
+                writeln!(client.stream, "{line}").is_ok()
+            });
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Client>> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
@@ -0,0 +1,178 @@
+use crate::app::config::ExecSection;
+
+use std::{fmt::Display, process::Stdio, time::Duration};
+
+const EXEC_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExecEvent {
+    IncomingCall,
+    CallEstablished,
+    CallEnded,
+    MissedCall,
+    Registered,
+    RegistrationFailed,
+    Dtmf,
+    Info,
+}
+
+impl ExecEvent {
+    fn template(self, section: &ExecSection) -> Option<&str> {
+        let template = match self {
+            Self::IncomingCall => &section.on_incoming_call,
+            Self::CallEstablished => &section.on_call_established,
+            Self::CallEnded => &section.on_call_ended,
+            Self::MissedCall => &section.on_missed_call,
+            Self::Registered => &section.on_registered,
+            Self::RegistrationFailed => &section.on_registration_failed,
+            Self::Dtmf => &section.on_dtmf,
+            Self::Info => &section.on_info,
+        };
+        template.as_deref()
+    }
+}
+
+impl Display for ExecEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let event = match self {
+            Self::IncomingCall => "on_incoming_call",
+            Self::CallEstablished => "on_call_established",
+            Self::CallEnded => "on_call_ended",
+            Self::MissedCall => "on_missed_call",
+            Self::Registered => "on_registered",
+            Self::RegistrationFailed => "on_registration_failed",
+            Self::Dtmf => "on_dtmf",
+            Self::Info => "on_info",
+        };
+        write!(f, "{event}")
+    }
+}
+
+// the program of the event, e.g. on_incoming_call = "/usr/local/bin/blink-light.sh {caller}".
+// The template is split by the whitespaces before the fields are substituted, so a field is
+// a single argument, no shell sees it. The absent values (e.g. the display name) and the
+// unknown fields are empty
+pub(crate) fn run(section: &ExecSection, event: ExecEvent, fields: &[(&str, String)]) {
+    let Some(template) = event.template(section) else {
+        return;
+    };
+    let mut args = args(template, fields).into_iter();
+    let Some(program) = args.next() else {
+        return;
+    };
+    let mut command = tokio::process::Command::new(&program);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            tracing::warn!("Could not run {program} of {event}: {err}");
+            return;
+        }
+    };
+    tracing::debug!("{program} of {event} is started");
+    // the output isn't waited for by the app, the TUI owns the terminal
+    tokio::spawn(async move {
+        match tokio::time::timeout(EXEC_TIMEOUT, child.wait_with_output()).await {
+            Ok(Ok(output)) if output.status.success() => {}
+            Ok(Ok(output)) => tracing::warn!(
+                "{program} of {event} failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            ),
+            Ok(Err(err)) => tracing::warn!("{program} of {event} err: {err}"),
+            Err(_) => tracing::warn!("{program} of {event} is killed after {EXEC_TIMEOUT:?}"),
+        }
+    });
+}
+
+fn args(template: &str, fields: &[(&str, String)]) -> Vec<String> {
+    template
+        .split_whitespace()
+        .map(|arg| substitute(arg, fields))
+        .collect()
+}
+
+// in one pass, the braces in the values are kept as they are
+fn substitute(arg: &str, fields: &[(&str, String)]) -> String {
+    let mut substituted = String::with_capacity(arg.len());
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        substituted.push_str(&rest[..start]);
+        let name = rest[start + 1..]
+            .split_once('}')
+            .map(|(name, _)| name)
+            .filter(|name| {
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            });
+        match name {
+            Some(name) => {
+                let value = fields
+                    .iter()
+                    .find(|(field, _)| *field == name)
+                    .map(|(_, value)| value.as_str());
+                substituted.push_str(value.unwrap_or_default());
+                rest = &rest[start + name.len() + 2..];
+            }
+            None => {
+                substituted.push('{');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    substituted.push_str(rest);
+    substituted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitute_replaces_the_fields() {
+        let fields = [("caller", "1001".to_owned()), ("call_id", "3".to_owned())];
+        assert_eq!(substitute("--from={caller}#{call_id}", &fields), "--from=1001#3");
+        assert_eq!(substitute("{caller}{caller}", &fields), "10011001");
+    }
+
+    #[test]
+    fn substitute_leaves_the_missing_fields_empty() {
+        let fields = [("display_name", String::new())];
+        assert_eq!(substitute("--name={display_name}", &fields), "--name=");
+        assert_eq!(substitute("--digit={digit}!", &fields), "--digit=!");
+    }
+
+    #[test]
+    fn substitute_keeps_the_other_braces() {
+        let fields = [("caller", "{call_id}".to_owned()), ("call_id", "3".to_owned())];
+        assert_eq!(substitute("{caller}", &fields), "{call_id}");
+        assert_eq!(substitute("{}{ x}{caller", &fields), "{}{ x}{caller");
+        assert_eq!(substitute("{{call_id}}", &fields), "{3}");
+    }
+
+    #[test]
+    fn args_keep_each_value_in_one_argument() {
+        let fields = [
+            ("caller", "Alice \"Sales\" <sip:1001@pbx>".to_owned()),
+            ("remote", "1001; rm -rf /".to_owned()),
+        ];
+        assert_eq!(
+            args("notify.sh  --caller={caller}\t{remote} {missing}", &fields),
+            [
+                "notify.sh",
+                "--caller=Alice \"Sales\" <sip:1001@pbx>",
+                "1001; rm -rf /",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn args_are_empty_without_the_program() {
+        assert!(args("  ", &[]).is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::app::command::Command;
+
+use std::{fmt::Display, process::ExitCode};
+
+use anyhow::Result;
+
+// the exit codes for the wrapper scripts, see the README. clap exits with 2 on the invalid args
+const SUCCESS: u8 = 0;
+const FAILURE: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    Config,
+    Registration,
+    Call,
+    Audio,
+}
+
+impl ErrorCategory {
+    fn exit_code(self) -> u8 {
+        match self {
+            Self::Config => 3,
+            Self::Registration => 4,
+            Self::Call => 5,
+            Self::Audio => 6,
+        }
+    }
+
+    // the message of the error is kept as is, the first category too, e.g. the audio failure
+    // of the call
+    pub(crate) fn wrap(self, error: anyhow::Error) -> anyhow::Error {
+        if error_category(&error).is_some() {
+            return error;
+        }
+        anyhow::Error::new(Categorized {
+            category: self,
+            error,
+        })
+    }
+
+    pub(crate) fn of_command(command: &Command) -> Option<Self> {
+        match command {
+            Command::Register(_) => Some(Self::Registration),
+            Command::MakeCall(_)
+            | Command::AcceptCall(_)
+            | Command::TransferCall(_)
+            | Command::PlayFile(_)
+            | Command::SendDtmf(_) => Some(Self::Call),
+            Command::SourceScript(_) | Command::Reload(_) => Some(Self::Config),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopSignal {
+    Interrupt,
+    Terminate,
+}
+
+impl StopSignal {
+    // 128 + the number of the signal, like the shells report it
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Interrupt => 130,
+            Self::Terminate => 143,
+        }
+    }
+}
+
+impl Display for StopSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let signal = match self {
+            Self::Interrupt => "SIGINT",
+            Self::Terminate => "SIGTERM",
+        };
+        write!(f, "{signal}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shutdown {
+    Quit,
+    Signal(StopSignal),
+}
+
+pub fn exit_code(result: &Result<Shutdown>) -> ExitCode {
+    ExitCode::from(exit_status(result))
+}
+
+pub fn exit_status(result: &Result<Shutdown>) -> u8 {
+    match result {
+        Ok(Shutdown::Quit) => SUCCESS,
+        Ok(Shutdown::Signal(signal)) => signal.exit_code(),
+        Err(err) => error_category(err).map_or(FAILURE, ErrorCategory::exit_code),
+    }
+}
+
+pub fn error_category(err: &anyhow::Error) -> Option<ErrorCategory> {
+    err.chain()
+        .find_map(|err| err.downcast_ref::<Categorized>())
+        .map(|err| err.category)
+}
+
+pub(crate) trait Categorize<T> {
+    fn categorize(self, category: ErrorCategory) -> Result<T>;
+}
+
+impl<T> Categorize<T> for Result<T> {
+    fn categorize(self, category: ErrorCategory) -> Result<T> {
+        self.map_err(|error| category.wrap(error))
+    }
+}
+
+#[derive(Debug)]
+struct Categorized {
+    category: ErrorCategory,
+    error: anyhow::Error,
+}
+
+impl Display for Categorized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for Categorized {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
@@ -0,0 +1,94 @@
+use crate::app::{cli_input::CommandLine, command::Command};
+
+use std::{
+    ffi::CString,
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader},
+    os::unix::{ffi::OsStrExt, fs::FileTypeExt},
+    path::{Path, PathBuf},
+    thread,
+};
+
+use anyhow::Result;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+// the command lines written to the named pipe by the local processes, next to the stdin:
+//   echo "call user=100" > /tmp/sipacker.cmd
+// the FIFO is created if there is no one and removed at the exit then. The errors go to the
+// logs, the writer has no way back
+pub(crate) fn run_command_fifo(
+    shutdown: CancellationToken,
+    command_line: CommandLine,
+    path: &Path,
+) -> Result<mpsc::Receiver<Command>> {
+    let created = create(path)?;
+    // the pipe opened for writing too never reaches the end of the file between the writers
+    let fifo = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|err| anyhow::Error::msg(format!("Could not open the FIFO {path:?}: {err}")))?;
+    tracing::info!("Reading the commands from the FIFO {path:?}");
+    let (command_sender, command_receiver) = mpsc::channel(20);
+    let reader_path = path.to_owned();
+    thread::spawn(move || {
+        if let Err(err) = read_commands(BufReader::new(fifo), &command_sender, &command_line) {
+            tracing::error!("FIFO {reader_path:?} err: {err}");
+        }
+    });
+    if let Some(path) = created {
+        tokio::spawn(async move {
+            shutdown.cancelled().await;
+            let _ = fs::remove_file(&path);
+        });
+    }
+    Ok(command_receiver)
+}
+
+fn create(path: &Path) -> Result<Option<PathBuf>> {
+    if let Ok(metadata) = fs::metadata(path) {
+        if !metadata.file_type().is_fifo() {
+            return Err(anyhow::Error::msg(format!(
+                "The command FIFO {path:?} exists and is not a FIFO"
+            )));
+        }
+        return Ok(None);
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    // accessible by the owner only, like the control socket
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(anyhow::Error::msg(format!(
+            "Could not create the FIFO {path:?}: {}",
+            std::io::Error::last_os_error()
+        )));
+    }
+    Ok(Some(path.to_owned()))
+}
+
+fn read_commands(
+    reader: impl BufRead,
+    command_sender: &mpsc::Sender<Command>,
+    command_line: &CommandLine,
+) -> Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if command_line.help_line(line).is_some() {
+            tracing::warn!("FIFO command \"{line}\": the help is printed to the console only");
+            continue;
+        }
+        match command_line.parse(line) {
+            Ok(command) => {
+                if command_sender.blocking_send(command).is_err() {
+                    break;
+                }
+            }
+            Err(err) => tracing::warn!("FIFO command \"{line}\" err: {err}"),
+        }
+    }
+    Ok(())
+}
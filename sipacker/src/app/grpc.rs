@@ -0,0 +1,236 @@
+use crate::app::{cli_input::CommandLine, command::Command, rpc};
+
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::{
+    wrappers::{BroadcastStream, TcpListenerStream},
+    Stream, StreamExt,
+};
+use tokio_util::sync::CancellationToken;
+use tonic::{Request, Response, Status};
+
+pub(crate) mod proto {
+    tonic::include_proto!("sipacker");
+}
+
+use proto::sipacker_control_server::{SipackerControl, SipackerControlServer};
+
+pub(crate) async fn run_grpc_api(
+    addr: SocketAddr,
+    command_line: CommandLine,
+    events: broadcast::Sender<Value>,
+    shutdown: CancellationToken,
+) -> Result<mpsc::Receiver<Command>> {
+    let listener = tokio::net::TcpListener::bind(addr).await.map_err(|err| {
+        anyhow::Error::msg(format!("Could not bind the gRPC API to {addr}: {err}"))
+    })?;
+    tracing::info!("gRPC API is listening on {addr}");
+    let (command_sender, command_receiver) = mpsc::channel(20);
+    let control = Control {
+        command_sender,
+        command_line: Arc::new(command_line),
+        events,
+    };
+    tokio::spawn(async move {
+        let result = tonic::transport::Server::builder()
+            .add_service(SipackerControlServer::new(control))
+            .serve_with_incoming_shutdown(
+                TcpListenerStream::new(listener),
+                shutdown.cancelled_owned(),
+            )
+            .await;
+        if let Err(err) = result {
+            tracing::error!("gRPC API err: {err}");
+        }
+    });
+    Ok(command_receiver)
+}
+
+struct Control {
+    command_sender: mpsc::Sender<Command>,
+    command_line: Arc<CommandLine>,
+    events: broadcast::Sender<Value>,
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl SipackerControl for Control {
+    async fn register(
+        &self,
+        request: Request<proto::RegisterRequest>,
+    ) -> Result<Response<proto::State>, Status> {
+        let request = request.into_inner();
+        let params = non_empty_params([
+            ("user", request.user),
+            ("password", request.password),
+            ("registrar", request.registrar),
+            ("srtp", request.srtp),
+        ]);
+        self.run_command("register", params).await
+    }
+
+    async fn unregister(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::State>, Status> {
+        self.run_command("unregister", Value::Null).await
+    }
+
+    async fn make_call(
+        &self,
+        request: Request<proto::MakeCallRequest>,
+    ) -> Result<Response<proto::State>, Status> {
+        let params = match request.into_inner().target {
+            Some(proto::make_call_request::Target::User(user)) => {
+                non_empty_params([("user", user)])
+            }
+            Some(proto::make_call_request::Target::Name(name)) => {
+                non_empty_params([("name", name)])
+            }
+            None => return Err(Status::invalid_argument("The user or the name is expected")),
+        };
+        self.run_command("call", params).await
+    }
+
+    async fn accept_call(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::State>, Status> {
+        self.run_command("accept call", Value::Null).await
+    }
+
+    async fn decline_call(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::State>, Status> {
+        self.run_command("decline call", Value::Null).await
+    }
+
+    async fn terminate_call(
+        &self,
+        request: Request<proto::TerminateCallRequest>,
+    ) -> Result<Response<proto::State>, Status> {
+        let params = match request.into_inner().id {
+            Some(id) => json!({ "id": id }),
+            None => Value::Null,
+        };
+        self.run_command("terminate call", params).await
+    }
+
+    async fn send_dtmf(
+        &self,
+        request: Request<proto::DtmfRequest>,
+    ) -> Result<Response<proto::State>, Status> {
+        let params = json!({ "value": request.into_inner().digits });
+        self.run_command("dtmf", params).await
+    }
+
+    async fn get_status(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::State>, Status> {
+        self.run_command("status", Value::Null).await
+    }
+
+    async fn execute(
+        &self,
+        request: Request<proto::ExecuteRequest>,
+    ) -> Result<Response<proto::State>, Status> {
+        let request = request.into_inner();
+        let params: Map<_, _> = request
+            .params
+            .into_iter()
+            .map(|(key, value)| (key, Value::from(value)))
+            .collect();
+        self.run_command(&request.method, Value::Object(params))
+            .await
+    }
+
+    type EventsStream = EventStream;
+
+    // the events missed by the slow subscriber are skipped
+    async fn events(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<Self::EventsStream>, Status> {
+        let stream = BroadcastStream::new(self.events.subscribe())
+            .filter_map(|event| event.ok().map(|event| Ok(to_event(event))));
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+impl Control {
+    async fn run_command(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<Response<proto::State>, Status> {
+        let line = rpc::command_line(method, Some(&params), &self.command_line.specs())
+            .map_err(to_status)?;
+        match rpc::prepare(&line, &self.command_line).map_err(to_status)? {
+            rpc::Prepared::Answer(_) => Err(Status::invalid_argument(
+                "The help is not available over gRPC",
+            )),
+            rpc::Prepared::Command(command, reply_receiver) => {
+                self.command_sender
+                    .send(command)
+                    .await
+                    .map_err(|_| Status::unavailable("The app is stopped"))?;
+                let state = rpc::reply_result(reply_receiver.await).map_err(to_status)?;
+                Ok(Response::new(to_state(&state)))
+            }
+        }
+    }
+}
+
+// the empty strings of proto3 are the params which are not set
+fn non_empty_params<const N: usize>(params: [(&str, String); N]) -> Value {
+    let params: Map<_, _> = params
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| (key.to_owned(), Value::from(value)))
+        .collect();
+    Value::Object(params)
+}
+
+fn to_status(err: rpc::Error) -> Status {
+    match err.code() {
+        rpc::METHOD_NOT_FOUND => Status::not_found(err.message()),
+        rpc::INVALID_PARAMS => Status::invalid_argument(err.message()),
+        _ => Status::failed_precondition(err.message()),
+    }
+}
+
+fn to_state(state: &Value) -> proto::State {
+    let text = |value: &Value| value.as_str().unwrap_or_default().to_owned();
+    let registration = &state["registration"];
+    let call = &state["call"];
+    proto::State {
+        registration: registration.is_object().then(|| proto::Registration {
+            user: text(&registration["user"]),
+            registrar: text(&registration["registrar"]),
+            srtp: text(&registration["srtp"]),
+            duration_secs: registration["duration_secs"].as_u64().unwrap_or_default(),
+        }),
+        call: call.is_object().then(|| proto::Call {
+            id: call["id"].as_u64().unwrap_or_default(),
+            remote: text(&call["remote"]),
+            state: text(&call["state"]),
+            duration_secs: call["duration_secs"].as_u64().unwrap_or_default(),
+        }),
+        muted: state["muted"].as_bool().unwrap_or_default(),
+    }
+}
+
+fn to_event(event: Value) -> proto::Event {
+    proto::Event {
+        r#type: event["type"].as_str().unwrap_or_default().to_owned(),
+        timestamp: event["timestamp"].as_u64().unwrap_or_default(),
+        message: event["message"].as_str().unwrap_or_default().to_owned(),
+        json: event.to_string(),
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde_json::{json, Value};
+use tokio_util::sync::CancellationToken;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+// the longest command (e.g. the registration waiting for its timeout) doesn't look wedged
+const WEDGED_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Default)]
+pub(crate) struct Health(Arc<Mutex<HealthState>>);
+
+#[derive(Default)]
+struct HealthState {
+    heartbeat: Option<Instant>,
+    registration: Option<String>,
+    audio_fault: Option<String>,
+}
+
+impl Health {
+    pub fn beat(&self, registration: Option<String>, audio_fault: Option<String>) {
+        let mut state = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        state.heartbeat = Some(Instant::now());
+        state.registration = registration;
+        state.audio_fault = audio_fault;
+    }
+
+    pub fn next_beat(&self) -> Instant {
+        let state = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        state
+            .heartbeat
+            .map_or_else(Instant::now, |heartbeat| heartbeat + HEARTBEAT_INTERVAL)
+    }
+
+    fn liveness(&self) -> (StatusCode, Json<Value>) {
+        let state = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        let age = state.heartbeat.map(|heartbeat| heartbeat.elapsed());
+        let alive = age.is_some_and(|age| age < WEDGED_AFTER);
+        let body = json!({
+            "alive": alive,
+            "heartbeat_age_ms": age.map(|age| age.as_millis() as u64),
+        });
+        (status_code(alive), Json(body))
+    }
+
+    fn readiness(&self) -> (StatusCode, Json<Value>) {
+        let state = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        let ready = state.registration.is_some() && state.audio_fault.is_none();
+        let body = json!({
+            "ready": ready,
+            "registration": state.registration,
+            "audio": state.audio_fault.as_deref().unwrap_or("ok"),
+        });
+        (status_code(ready), Json(body))
+    }
+}
+
+fn status_code(ok: bool) -> StatusCode {
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+// the probes of Kubernetes and the load balancers, separate from the control APIs so that
+// they can be exposed:
+//   GET /healthz is 200 while the loop of the app runs, 503 once it's wedged
+//   GET /readyz is 200 while the account is registered and the audio works, 503 otherwise
+pub(crate) async fn run_health_api(
+    addr: SocketAddr,
+    shutdown: CancellationToken,
+) -> Result<Health> {
+    let listener = tokio::net::TcpListener::bind(addr).await.map_err(|err| {
+        anyhow::Error::msg(format!("Could not bind the health API to {addr}: {err}"))
+    })?;
+    tracing::info!("Health API is listening on {addr}");
+    let health = Health::default();
+    let router = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(health.clone());
+    tokio::spawn(async move {
+        let result = axum::serve(listener, router)
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await;
+        if let Err(err) = result {
+            tracing::error!("Health API err: {err}");
+        }
+    });
+    Ok(health)
+}
+
+async fn healthz(State(health): State<Health>) -> (StatusCode, Json<Value>) {
+    health.liveness()
+}
+
+async fn readyz(State(health): State<Health>) -> (StatusCode, Json<Value>) {
+    health.readiness()
+}
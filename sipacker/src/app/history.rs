@@ -0,0 +1,285 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+const MAX_RECORDS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CallResult {
+    Answered,
+    Missed,
+    Declined,
+    NotAnswered,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CallRecord {
+    pub direction: Direction,
+    pub remote: String,
+    pub started: DateTime<Local>,
+    pub established: Option<DateTime<Local>>,
+    pub ended: Option<DateTime<Local>>,
+    pub result: Option<CallResult>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forwarded_from: Vec<String>,
+    #[serde(skip)]
+    declined: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct CallHistory {
+    records: VecDeque<CallRecord>,
+}
+
+impl CallHistory {
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        if !path.exists() {
+            return Self::default();
+        }
+
+        std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| serde_json::from_str(&content).map_err(anyhow::Error::from))
+            .map(|records| Self { records })
+            .inspect(|_| tracing::info!("Call history is loaded from {path:?}"))
+            .inspect_err(|err| tracing::warn!("Could not load call history from {path:?}: {err}"))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or(anyhow::Error::msg("Data directory is not found"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let finished: Vec<_> = self.finished().collect();
+        std::fs::write(&path, serde_json::to_string(&finished)?)?;
+        tracing::debug!("Call history is saved to {path:?}");
+        Ok(())
+    }
+
+    pub fn export(&self, path: &Path) -> Result<usize> {
+        let records: Vec<_> = self.finished().collect();
+        let content = if path.extension().is_some_and(|ext| ext == "json") {
+            let records: Vec<_> = records
+                .iter()
+                .map(|record| {
+                    let [
+                        direction,
+                        remote,
+                        started,
+                        established,
+                        ended,
+                        result,
+                        duration,
+                        forwarded_from,
+                    ] = record.export_fields();
+                    json!({
+                        "direction": direction,
+                        "remote": remote,
+                        "started": started,
+                        "established": established,
+                        "ended": ended,
+                        "result": result,
+                        "duration_secs": duration,
+                        "forwarded_from": forwarded_from,
+                    })
+                })
+                .collect();
+            serde_json::to_string_pretty(&records)?
+        } else {
+            let mut csv =
+                "direction,remote,started,established,ended,result,duration_secs,forwarded_from\n"
+                    .to_owned();
+            for record in &records {
+                let fields = record.export_fields().map(|field| misc::csv_field(&field));
+                csv.push_str(&fields.join(","));
+                csv.push('\n');
+            }
+            csv
+        };
+        std::fs::write(path, content)?;
+        Ok(records.len())
+    }
+
+    pub fn start(&mut self, direction: Direction, remote: String) {
+        if self.records.len() == MAX_RECORDS {
+            self.records.pop_front();
+        }
+        self.records.push_back(CallRecord {
+            direction,
+            remote,
+            started: Local::now(),
+            established: None,
+            ended: None,
+            result: None,
+            forwarded_from: Vec::new(),
+            declined: false,
+        });
+    }
+
+    pub fn forward(&mut self, forwarded_from: Vec<String>) {
+        if let Some(record) = self.active_mut() {
+            record.forwarded_from = forwarded_from;
+        }
+    }
+
+    pub fn establish(&mut self) {
+        if let Some(record) = self.active_mut() {
+            record.established = Some(Local::now());
+        }
+    }
+
+    pub fn decline(&mut self) {
+        if let Some(record) = self.active_mut() {
+            record.declined = true;
+        }
+    }
+
+    pub fn end(&mut self) -> Option<&CallRecord> {
+        let record = self.active_mut()?;
+        record.ended = Some(Local::now());
+        record.result = Some(
+            match (record.direction, record.established, record.declined) {
+                (_, Some(_), _) => CallResult::Answered,
+                (Direction::Inbound, None, true) => CallResult::Declined,
+                (Direction::Inbound, None, false) => CallResult::Missed,
+                (Direction::Outbound, None, _) => CallResult::NotAnswered,
+            },
+        );
+        Some(record)
+    }
+
+    pub fn last(&self, count: usize) -> impl Iterator<Item = &CallRecord> {
+        self.records
+            .iter()
+            .skip(self.records.len().saturating_sub(count))
+    }
+
+    fn active_mut(&mut self) -> Option<&mut CallRecord> {
+        self.records
+            .back_mut()
+            .filter(|record| record.result.is_none())
+    }
+
+    fn finished(&self) -> impl Iterator<Item = &CallRecord> {
+        self.records.iter().filter(|record| record.result.is_some())
+    }
+
+    fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("sipacker").join("history.json"))
+    }
+}
+
+impl CallRecord {
+    fn export_fields(&self) -> [String; 8] {
+        let time =
+            |time: Option<DateTime<Local>>| time.map(|time| time.to_rfc3339()).unwrap_or_default();
+        let result = match self.result {
+            Some(CallResult::Answered) => "answered",
+            Some(CallResult::Missed) => "missed",
+            Some(CallResult::Declined) => "declined",
+            Some(CallResult::NotAnswered) => "not_answered",
+            None => "",
+        };
+        [
+            self.direction.to_string(),
+            self.remote.clone(),
+            self.started.to_rfc3339(),
+            time(self.established),
+            time(self.ended),
+            result.to_owned(),
+            self.duration()
+                .map(|duration| duration.as_secs().to_string())
+                .unwrap_or_default(),
+            self.forwarded_from.join(","),
+        ]
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        let talk_time = self.ended.unwrap_or_else(Local::now) - self.established?;
+        talk_time.to_std().ok()
+    }
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Inbound => write!(f, "in"),
+            Self::Outbound => write!(f, "out"),
+        }
+    }
+}
+
+impl Display for CallResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Answered => write!(f, "answered"),
+            Self::Missed => write!(f, "MISSED"),
+            Self::Declined => write!(f, "declined"),
+            Self::NotAnswered => write!(f, "not answered"),
+        }
+    }
+}
+
+impl Display for CallRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:<3} {} ",
+            self.started.format("%Y-%m-%d %H:%M:%S"),
+            self.direction,
+            self.remote
+        )?;
+        if !self.forwarded_from.is_empty() {
+            write!(f, "(forwarded from {}) ", self.forwarded_from.join(", "))?;
+        }
+        match self.result {
+            Some(result) => write!(f, "{result}")?,
+            None => write!(f, "active")?,
+        }
+        if let Some(ended) = self.ended {
+            write!(f, ", ended {}", ended.format("%H:%M:%S"))?;
+        }
+        if let Some(duration) = self.duration() {
+            let secs = duration.as_secs();
+            write!(
+                f,
+                ", {:02}:{:02}:{:02}",
+                secs / 3600,
+                secs / 60 % 60,
+                secs % 60
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) mod misc {
+    // RFC 4180: the field with a comma, a quote or a line break is quoted
+    pub fn csv_field(field: &str) -> String {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_owned()
+        }
+    }
+}
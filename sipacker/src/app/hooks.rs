@@ -0,0 +1,117 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use rhai::{CallFnOptions, Dynamic, Engine, FuncArgs, Scope, AST};
+
+// the handler runs on the loop of the app, the endless loops of the script must not stall
+// the calls
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+// the Rhai script run on the events of the user agent, e.g. the auto-attendant:
+//   fn on_incoming_call(caller, display_name) { accept(); }
+//   fn on_call_established() { play("menu.wav"); }
+//   fn on_dtmf(digit) { if digit == "1" { play("sales.wav"); } else { command("transfer user=1002"); } }
+// every handler is optional, the commands it calls run after it in the order of the calls
+pub(crate) struct Hooks {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    commands: Arc<Mutex<Vec<String>>>,
+}
+
+impl Hooks {
+    pub fn load(path: &Path) -> Result<Self> {
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| tracing::info!("Hook: {text}"));
+        let queue = |commands: &Arc<Mutex<Vec<String>>>| {
+            let commands = commands.clone();
+            move |line: String| {
+                commands
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .push(line)
+            }
+        };
+        let command = queue(&commands);
+        engine.register_fn("command", move |line: &str| command(line.to_owned()));
+        let accept = queue(&commands);
+        engine.register_fn("accept", move || accept("accept call".to_owned()));
+        let decline = queue(&commands);
+        engine.register_fn("decline", move || decline("decline call".to_owned()));
+        let hang_up = queue(&commands);
+        engine.register_fn("hang_up", move || hang_up("terminate call".to_owned()));
+        let play = queue(&commands);
+        engine.register_fn("play", move |file: &str| {
+            play(format!("play file=\"{}\"", escape(file)))
+        });
+        let dtmf = queue(&commands);
+        engine.register_fn("dtmf", move |digits: &str| dtmf(format!("dtmf {digits}")));
+
+        let ast = engine.compile_file(path.to_owned()).map_err(|err| {
+            anyhow::Error::msg(format!("Could not load the hooks {path:?}: {err}"))
+        })?;
+        engine.run_ast(&ast).map_err(|err| {
+            anyhow::Error::msg(format!("Could not run the hooks {path:?}: {err}"))
+        })?;
+        let handlers: Vec<_> = ast.iter_functions().map(|func| func.name).collect();
+        tracing::info!("Hooks {path:?} are loaded: {}", handlers.join(", "));
+        Ok(Self {
+            path: path.to_owned(),
+            engine,
+            ast,
+            commands,
+        })
+    }
+
+    pub fn on_incoming_call(&self, caller: &str, display_name: Option<&str>) -> Vec<String> {
+        let display_name = display_name.unwrap_or_default().to_owned();
+        self.call("on_incoming_call", (caller.to_owned(), display_name))
+    }
+
+    pub fn on_call_established(&self) -> Vec<String> {
+        self.call("on_call_established", ())
+    }
+
+    pub fn on_registered(&self, user_name: &str, registrar_host: &str) -> Vec<String> {
+        self.call(
+            "on_registered",
+            (user_name.to_owned(), registrar_host.to_owned()),
+        )
+    }
+
+    pub fn on_dtmf(&self, digit: char) -> Vec<String> {
+        self.call("on_dtmf", (digit.to_string(),))
+    }
+
+    fn call(&self, name: &str, args: impl FuncArgs) -> Vec<String> {
+        if !self.ast.iter_functions().any(|func| func.name == name) {
+            return Vec::new();
+        }
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            &self.ast,
+            name,
+            args,
+        );
+        let commands =
+            std::mem::take(&mut *self.commands.lock().unwrap_or_else(|err| err.into_inner()));
+        match result {
+            Ok(_) => commands,
+            Err(err) => {
+                tracing::warn!("Hook {name} of {:?} failed: {err}", self.path);
+                Vec::new()
+            }
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,116 @@
+use crate::app::{cli_input::CommandLine, command::Command, rpc};
+
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+pub(crate) async fn run_http_api(
+    addr: SocketAddr,
+    command_line: CommandLine,
+    shutdown: CancellationToken,
+) -> Result<mpsc::Receiver<Command>> {
+    let listener = tokio::net::TcpListener::bind(addr).await.map_err(|err| {
+        anyhow::Error::msg(format!("Could not bind the HTTP API to {addr}: {err}"))
+    })?;
+    tracing::info!("HTTP API is listening on {addr}");
+    let (command_sender, command_receiver) = mpsc::channel(20);
+    let api = Api {
+        command_sender,
+        command_line: Arc::new(command_line),
+    };
+    let router = Router::new()
+        .route("/status", get(status))
+        .route("/register", post(register).delete(unregister))
+        .route("/calls", post(make_call))
+        .route("/calls/{id}", delete(terminate_call))
+        .route("/commands/{method}", post(run_command))
+        .with_state(api);
+    tokio::spawn(async move {
+        let result = axum::serve(listener, router)
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await;
+        if let Err(err) = result {
+            tracing::error!("HTTP API err: {err}");
+        }
+    });
+    Ok(command_receiver)
+}
+
+#[derive(Clone)]
+struct Api {
+    command_sender: mpsc::Sender<Command>,
+    command_line: Arc<CommandLine>,
+}
+
+type Response = (StatusCode, Json<Value>);
+
+impl Api {
+    async fn execute(&self, method: &str, params: Option<&Value>) -> Response {
+        let result = self.try_execute(method, params).await;
+        match result {
+            Ok(state) => (StatusCode::OK, Json(state)),
+            Err(err) => {
+                let status = match err.code() {
+                    rpc::METHOD_NOT_FOUND => StatusCode::NOT_FOUND,
+                    rpc::INVALID_PARAMS => StatusCode::BAD_REQUEST,
+                    _ => StatusCode::CONFLICT,
+                };
+                (status, Json(json!({ "error": err.message() })))
+            }
+        }
+    }
+
+    async fn try_execute(&self, method: &str, params: Option<&Value>) -> Result<Value, rpc::Error> {
+        let line = rpc::command_line(method, params, &self.command_line.specs())?;
+        match rpc::prepare(&line, &self.command_line)? {
+            rpc::Prepared::Answer(answer) => Ok(json!({ "help": answer })),
+            rpc::Prepared::Command(command, reply_receiver) => {
+                self.command_sender
+                    .send(command)
+                    .await
+                    .map_err(|_| rpc::Error::new(rpc::COMMAND_ERROR, "The app is stopped"))?;
+                rpc::reply_result(reply_receiver.await)
+            }
+        }
+    }
+}
+
+async fn status(State(api): State<Api>) -> Response {
+    api.execute("status", None).await
+}
+
+// {"user": "1001", "password": "secret", "registrar": "192.168.1.1:5060", "srtp": "optional"}
+async fn register(State(api): State<Api>, Json(params): Json<Value>) -> Response {
+    api.execute("register", Some(&params)).await
+}
+
+async fn unregister(State(api): State<Api>) -> Response {
+    api.execute("unregister", None).await
+}
+
+async fn make_call(State(api): State<Api>, Json(params): Json<Value>) -> Response {
+    api.execute("call", Some(&params)).await
+}
+
+async fn terminate_call(State(api): State<Api>, Path(id): Path<u64>) -> Response {
+    api.execute("terminate call", Some(&json!({ "id": id })))
+        .await
+}
+
+async fn run_command(
+    State(api): State<Api>,
+    Path(method): Path<String>,
+    params: Option<Json<Value>>,
+) -> Response {
+    api.execute(&method, params.as_ref().map(|Json(params)| params))
+        .await
+}
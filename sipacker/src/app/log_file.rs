@@ -0,0 +1,178 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use tracing::Subscriber;
+use tracing_subscriber::{fmt, registry::LookupSpan, EnvFilter, Layer};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Plain,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow::Error::msg(format!("Unknown log format: {s}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogRotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl FromStr for LogRotation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "never" => Ok(Self::Never),
+            "hourly" => Ok(Self::Hourly),
+            "daily" => Ok(Self::Daily),
+            _ => Err(anyhow::Error::msg(format!("Unknown log rotation: {s}"))),
+        }
+    }
+}
+
+impl LogRotation {
+    fn period(&self) -> Option<String> {
+        let now = chrono::Local::now();
+        match self {
+            Self::Never => None,
+            Self::Hourly => Some(now.format("%Y-%m-%d %H").to_string()),
+            Self::Daily => Some(now.format("%Y-%m-%d").to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct LogFile {
+    pub path: PathBuf,
+    pub format: LogFormat,
+    pub level: String,
+    pub max_size: Option<u64>,
+    pub rotation: LogRotation,
+    pub keep: usize,
+}
+
+impl LogFile {
+    pub fn layer<S>(&self) -> Result<Box<dyn Layer<S> + Send + Sync>>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let filter = EnvFilter::try_new(&self.level).map_err(|err| {
+            anyhow::Error::msg(format!("Invalid log level {}: {err}", self.level))
+        })?;
+        let file = RotatingFile::open(self).map_err(|err| {
+            anyhow::Error::msg(format!(
+                "Could not open the log file {:?}: {err}",
+                self.path
+            ))
+        })?;
+        let layer = fmt::Layer::default()
+            .with_ansi(false)
+            .with_writer(Mutex::new(file));
+        Ok(match self.format {
+            LogFormat::Plain => layer.with_filter(filter).boxed(),
+            LogFormat::Json => layer.json().with_filter(filter).boxed(),
+        })
+    }
+}
+
+// appends to the file, renames it to path.1 (shifting the older ones) when it's too large or
+// the period of the rotation is over
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: Option<u64>,
+    rotation: LogRotation,
+    period: Option<String>,
+    keep: usize,
+}
+
+impl RotatingFile {
+    fn open(log: &LogFile) -> io::Result<Self> {
+        if let Some(dir) = log.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = Self::append(&log.path)?;
+        Ok(Self {
+            path: log.path.clone(),
+            size: file.metadata()?.len(),
+            file,
+            max_size: log.max_size,
+            rotation: log.rotation,
+            period: log.rotation.period(),
+            keep: log.keep,
+        })
+    }
+
+    fn append(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    fn is_rotation_due(&self, len: usize) -> bool {
+        let too_large = self
+            .max_size
+            .is_some_and(|max_size| self.size > 0 && self.size + len as u64 > max_size);
+        too_large || self.rotation.period() != self.period
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated(self.keep));
+            for index in (1..self.keep).rev() {
+                let from = self.rotated(index);
+                if from.exists() {
+                    fs::rename(from, self.rotated(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = Self::append(&self.path)?;
+        self.size = 0;
+        self.period = self.rotation.period();
+        Ok(())
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.is_rotation_due(buf.len()) {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -0,0 +1,64 @@
+use crate::app::{application::App, command::CommandSource};
+pub use crate::app::cli_input::{parser::Parser, ArgSpec, CommandSpec};
+use sipacker_core::user_agent::CallId;
+
+use std::{fmt::Display, sync::Arc};
+
+use anyhow::Result;
+
+// the organization-specific command of the downstream build (e.g. the CRM lookup), it's
+// listed in the help and completed like the built-in commands, which take precedence
+pub trait CommandPlugin: Send + Sync {
+    // the line starting with the name of the spec is passed to parse
+    fn spec(&self) -> &'static CommandSpec;
+    // the rest of the line after the name, e.g. "number=1002"
+    fn parse(&self, args: &str) -> Result<Box<dyn PluginCommand>>;
+}
+
+pub trait PluginCommand: Display + Send {
+    fn execute(self: Box<Self>, app: &mut PluginApp<'_>) -> Result<()>;
+}
+
+#[derive(Clone, Default)]
+pub struct Plugins(Arc<Vec<Arc<dyn CommandPlugin>>>);
+
+impl Plugins {
+    pub fn register(self, plugin: impl CommandPlugin + 'static) -> Self {
+        let mut plugins = self.0.as_ref().clone();
+        plugins.push(Arc::new(plugin));
+        Self(Arc::new(plugins))
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Arc<dyn CommandPlugin>> {
+        self.0.iter()
+    }
+}
+
+// what the plugin command can do with the app: it reads the state and runs the built-in
+// commands, the app itself stays private
+pub struct PluginApp<'a> {
+    app: &'a mut App,
+}
+
+impl<'a> PluginApp<'a> {
+    pub(crate) fn new(app: &'a mut App) -> Self {
+        Self { app }
+    }
+
+    pub fn print(&self, text: &str) {
+        self.app.print(text);
+    }
+
+    pub fn registration(&self) -> Option<String> {
+        self.app.registration()
+    }
+
+    pub fn call(&self) -> Option<(CallId, String)> {
+        self.app.call()
+    }
+
+    // the command line (e.g. "call user=1002") runs after the plugin command, before the input
+    pub fn run(&mut self, line: &str) -> Result<()> {
+        self.app.queue_command(CommandSource::Plugin, line)
+    }
+}
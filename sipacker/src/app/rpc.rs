@@ -0,0 +1,211 @@
+use crate::app::{
+    cli_input::{CommandLine, CommandSpec},
+    command::{self, Command, ReplyResult},
+};
+
+use std::fmt::Display;
+
+use serde_json::{json, Map, Value};
+use tokio::sync::oneshot;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+pub(crate) const METHOD_NOT_FOUND: i64 = -32601;
+pub(crate) const INVALID_PARAMS: i64 = -32602;
+// the command is parsed, but its execution failed
+pub(crate) const COMMAND_ERROR: i64 = -32000;
+
+const POSITIONAL_PARAM: &str = "value";
+
+// the request is mapped onto the command line: the method is the command name ("accept" or
+// "accept_call" for "accept call"), the params are its key=value arguments. The HTTP API maps
+// its requests the same way
+pub(crate) struct Request {
+    // the notification without the id isn't answered
+    pub id: Option<Value>,
+    pub line: String,
+}
+
+pub(crate) struct Error {
+    code: i64,
+    message: String,
+}
+
+impl Error {
+    pub fn new(code: i64, message: impl Display) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn code(&self) -> i64 {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+pub(crate) enum Prepared {
+    Answer(Value),
+    Command(Command, oneshot::Receiver<ReplyResult>),
+}
+
+pub(crate) fn parse_request(
+    text: &str,
+    specs: &[&'static CommandSpec],
+) -> Result<Request, (Value, Error)> {
+    let request: Value =
+        serde_json::from_str(text).map_err(|err| (Value::Null, Error::new(PARSE_ERROR, err)))?;
+    let id = request.get("id").cloned();
+    let fail = |code, message: String| (id.clone().unwrap_or_default(), Error::new(code, message));
+
+    if request.get("jsonrpc") != Some(&json!("2.0")) {
+        return Err(fail(
+            INVALID_REQUEST,
+            "\"jsonrpc\": \"2.0\" is expected".to_owned(),
+        ));
+    }
+    let method = request
+        .get("method")
+        .and_then(Value::as_str)
+        .ok_or_else(|| fail(INVALID_REQUEST, "\"method\" is missing".to_owned()))?;
+    let line = command_line(method, request.get("params"), specs)
+        .map_err(|err| (id.clone().unwrap_or_default(), err))?;
+    Ok(Request { id, line })
+}
+
+pub(crate) fn command_line(
+    method: &str,
+    params: Option<&Value>,
+    specs: &[&'static CommandSpec],
+) -> Result<String, Error> {
+    let name = resolve_method(method, specs).map_err(|err| Error::new(METHOD_NOT_FOUND, err))?;
+    let args = arguments(params).map_err(|err| Error::new(INVALID_PARAMS, err))?;
+    let line = std::iter::once(name.to_owned())
+        .chain(args)
+        .collect::<Vec<_>>();
+    Ok(line.join(" "))
+}
+
+pub(crate) fn prepare(line: &str, command_line: &CommandLine) -> Result<Prepared, Error> {
+    if let Some(help) = command_line.help_line(line) {
+        return help
+            .map(|help| Prepared::Answer(Value::from(help)))
+            .map_err(|err| Error::new(INVALID_PARAMS, err));
+    }
+    let command = command_line
+        .parse(line)
+        .map_err(|err| Error::new(INVALID_PARAMS, err))?;
+    let (reply_sender, reply_receiver) = oneshot::channel();
+    Ok(Prepared::Command(
+        command::Reply::new(command, reply_sender).into(),
+        reply_receiver,
+    ))
+}
+
+pub(crate) fn reply_result(
+    reply: Result<ReplyResult, oneshot::error::RecvError>,
+) -> Result<Value, Error> {
+    match reply {
+        Ok(Ok(state)) => Ok(state),
+        Ok(Err(err)) => Err(Error::new(COMMAND_ERROR, err)),
+        Err(_) => Err(Error::new(COMMAND_ERROR, "The app is stopped")),
+    }
+}
+
+pub(crate) fn response(id: Value, result: Result<Value, Error>) -> String {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(err) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": err.code, "message": err.message },
+        }),
+    }
+    .to_string()
+}
+
+pub(crate) fn notification(message: &str) -> String {
+    match serde_json::from_str::<Value>(message) {
+        Ok(Value::Object(event)) if event.contains_key("type") => {
+            json!({ "jsonrpc": "2.0", "method": "event", "params": event })
+        }
+        _ => json!({ "jsonrpc": "2.0", "method": "message", "params": { "text": message } }),
+    }
+    .to_string()
+}
+
+fn resolve_method(method: &str, specs: &[&'static CommandSpec]) -> Result<&'static str, String> {
+    let method = method.replace('_', " ");
+    if method == "help" {
+        return Ok("help");
+    }
+    if let Some(spec) = specs.iter().find(|spec| spec.name == method) {
+        return Ok(spec.name);
+    }
+    let matched: Vec<_> = specs
+        .iter()
+        .filter(|spec| {
+            spec.name
+                .strip_prefix(method.as_str())
+                .is_some_and(|rest| rest.starts_with(' '))
+        })
+        .map(|spec| spec.name)
+        .collect();
+    match matched.as_slice() {
+        [name] => Ok(*name),
+        [] => Err(format!("Unknown method: {method}")),
+        names => Err(format!("Ambiguous method {method}: {}", names.join(", "))),
+    }
+}
+
+fn arguments(params: Option<&Value>) -> Result<Vec<String>, String> {
+    match params {
+        None | Some(Value::Null) => Ok(vec![]),
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| argument_value(value).map(|value| quote(&value)))
+            .collect(),
+        Some(Value::Object(params)) => object_arguments(params),
+        Some(_) => Err("\"params\" must be an object or an array".to_owned()),
+    }
+}
+
+fn object_arguments(params: &Map<String, Value>) -> Result<Vec<String>, String> {
+    let positional = params
+        .get(POSITIONAL_PARAM)
+        .map(|value| argument_value(value).map(|value| quote(&value)));
+    let named = params
+        .iter()
+        .filter(|(key, _)| key.as_str() != POSITIONAL_PARAM)
+        .map(|(key, value)| {
+            argument_value(value)
+                .map(|value| format!("{key}={}", quote(&value)))
+                .map_err(|err| format!("{key}: {err}"))
+        });
+    positional.into_iter().chain(named).collect()
+}
+
+fn argument_value(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Bool(true) => Ok("on".to_owned()),
+        Value::Bool(false) => Ok("off".to_owned()),
+        _ => Err("A string, a number or a boolean is expected".to_owned()),
+    }
+}
+
+fn quote(value: &str) -> String {
+    if !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\\')
+    {
+        return value.to_owned();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,279 @@
+use crate::app::{
+    application,
+    args::{Args, ServiceAction},
+    console::{Console, ConsoleMessage, Severity},
+    exit::{self, Shutdown},
+    plugin::Plugins,
+};
+
+use std::{
+    ffi::{OsStr, OsString},
+    io::Write,
+    sync::{mpsc, OnceLock},
+    time::Duration,
+};
+
+use anyhow::Result;
+use tokio::sync::Notify;
+use tracing::{Level, Metadata};
+use tracing_subscriber::fmt::MakeWriter;
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+use windows_sys::Win32::System::EventLog::{
+    DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+    EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE, REPORT_EVENT_TYPE,
+};
+
+const SERVICE_NAME: &str = "sipacker";
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+// the service main of the dispatcher takes no state
+static SERVICE_APP: OnceLock<(Args, Plugins)> = OnceLock::new();
+// the stop of the service manager quits like SIGTERM
+static STOP: Notify = Notify::const_new();
+
+define_windows_service!(ffi_service_main, service_main);
+
+pub(crate) fn run(action: ServiceAction, args: Args, plugins: Plugins) -> Result<Shutdown> {
+    match action {
+        ServiceAction::Install => install()?,
+        ServiceAction::Uninstall => uninstall()?,
+        ServiceAction::Run => {
+            let _ = SERVICE_APP.set((args, plugins));
+            service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(|err| {
+                anyhow::Error::msg(format!(
+                    "Could not run the service, it's started by the service manager: {err}"
+                ))
+            })?;
+        }
+    }
+    Ok(Shutdown::Quit)
+}
+
+// never completes outside of the service
+pub(crate) async fn stop_requested() {
+    STOP.notified().await
+}
+
+pub(crate) fn console(args: &Args) -> Console {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for message in receiver {
+            match message {
+                ConsoleMessage::Text(Severity::Error, text) => tracing::warn!("{text}"),
+                ConsoleMessage::Text(_, text) | ConsoleMessage::Event(_, text) => {
+                    tracing::info!("{text}")
+                }
+            }
+        }
+    });
+    Console::daemon(sender, args.output())
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    let Some((args, plugins)) = SERVICE_APP.get().cloned() else {
+        return;
+    };
+    if let Err(err) = run_service(args, plugins) {
+        tracing::error!("Service err: {err}");
+    }
+}
+
+fn run_service(args: Args, plugins: Plugins) -> Result<()> {
+    let status_handle = service_control_handler::register(SERVICE_NAME, |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            STOP.notify_one();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+    let status = |current_state, controls_accepted, exit_code| ServiceStatus {
+        service_type: SERVICE_TYPE,
+        current_state,
+        controls_accepted,
+        exit_code,
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    };
+    status_handle.set_service_status(status(
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        ServiceExitCode::Win32(0),
+    ))?;
+
+    let result = application::start_app(args, plugins);
+    if let Err(err) = &result {
+        tracing::error!("The service failed: {err:#}");
+    }
+    // the codes of the README, the stop of the manager isn't a failure
+    let exit_code = match exit::exit_status(&result) {
+        0 | 143 => ServiceExitCode::Win32(0),
+        code => ServiceExitCode::ServiceSpecific(code.into()),
+    };
+    status_handle.set_service_status(status(
+        ServiceState::Stopped,
+        ServiceControlAccept::empty(),
+        exit_code,
+    ))?;
+    Ok(())
+}
+
+fn install() -> Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+    let info = ServiceInfo {
+        name: SERVICE_NAME.into(),
+        display_name: "Sipacker SIP user agent".into(),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: launch_arguments(),
+        dependencies: Vec::new(),
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .map_err(|err| anyhow::Error::msg(format!("Could not install the service: {err}")))?;
+    service.set_description("Registers the SIP account and handles its calls")?;
+    println!("The service {SERVICE_NAME} is installed, start it with \"sc start {SERVICE_NAME}\"");
+    Ok(())
+}
+
+fn uninstall() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .map_err(|err| anyhow::Error::msg(format!("Could not open the service: {err}")))?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    // the service is removed once it's stopped
+    service.delete()?;
+    println!("The service {SERVICE_NAME} is uninstalled");
+    Ok(())
+}
+
+// "sipacker [OPTIONS] service install" is started as "sipacker [OPTIONS] service run"
+fn launch_arguments() -> Vec<OsString> {
+    let mut arguments: Vec<_> = std::env::args_os()
+        .skip(1)
+        .take_while(|arg| arg.as_os_str() != "service")
+        .collect();
+    arguments.extend(["service", "run"].map(OsString::from));
+    arguments
+}
+
+// the logs of the service go to the Application log of the Event Viewer under the source of
+// the service. There is no message file, the viewer shows the text after its note
+pub(crate) struct EventLog {
+    // HANDLE isn't Send
+    source: usize,
+}
+
+impl EventLog {
+    pub fn register() -> Result<Self> {
+        let name = wide(OsStr::new(SERVICE_NAME));
+        let source = unsafe { RegisterEventSourceW(std::ptr::null(), name.as_ptr()) };
+        if source.is_null() {
+            return Err(anyhow::Error::msg(format!(
+                "Could not register the event source: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(Self {
+            source: source as usize,
+        })
+    }
+
+    fn entry(&self, kind: REPORT_EVENT_TYPE) -> EventLogEntry<'_> {
+        EventLogEntry {
+            log: self,
+            kind,
+            text: Vec::new(),
+        }
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        unsafe { DeregisterEventSource(self.source as _) };
+    }
+}
+
+impl<'a> MakeWriter<'a> for EventLog {
+    type Writer = EventLogEntry<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.entry(EVENTLOG_INFORMATION_TYPE)
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        let kind = match *meta.level() {
+            Level::ERROR => EVENTLOG_ERROR_TYPE,
+            Level::WARN => EVENTLOG_WARNING_TYPE,
+            _ => EVENTLOG_INFORMATION_TYPE,
+        };
+        self.entry(kind)
+    }
+}
+
+pub(crate) struct EventLogEntry<'a> {
+    log: &'a EventLog,
+    kind: REPORT_EVENT_TYPE,
+    text: Vec<u8>,
+}
+
+impl Write for EventLogEntry<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.text.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for EventLogEntry<'_> {
+    fn drop(&mut self) {
+        let text = String::from_utf8_lossy(&self.text);
+        let text = wide(OsStr::new(text.trim_end()));
+        let strings = [text.as_ptr()];
+        unsafe {
+            ReportEventW(
+                self.log.source as _,
+                self.kind,
+                0,
+                0,
+                std::ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                std::ptr::null(),
+            )
+        };
+    }
+}
+
+fn wide(text: &OsStr) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+
+    text.encode_wide().chain(Some(0)).collect()
+}
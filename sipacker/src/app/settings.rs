@@ -0,0 +1,59 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    pub input_volume: u32,
+    pub output_volume: u32,
+    // the latency target of the audio buffers (jitter buffer, capture and playback channels):
+    // the lower one reduces the mouth-to-ear delay but makes the dropouts more likely on the
+    // jittery networks and loaded hosts, the higher one is more robust but adds the delay
+    pub latency_ms: u64,
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            input_volume: 100,
+            output_volume: 100,
+            latency_ms: 60,
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        if !path.exists() {
+            return Self::default();
+        }
+
+        std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| toml::from_str(&content).map_err(anyhow::Error::from))
+            .inspect(|_| tracing::info!("Settings are loaded from {path:?}"))
+            .inspect_err(|err| tracing::warn!("Could not load settings from {path:?}: {err}"))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or(anyhow::Error::msg("Config directory is not found"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, toml::to_string_pretty(self)?)?;
+        tracing::debug!("Settings are saved to {path:?}");
+        Ok(())
+    }
+
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("sipacker").join("settings.toml"))
+    }
+}
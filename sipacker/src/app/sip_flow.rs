@@ -0,0 +1,314 @@
+use sipacker_core::capture::{Direction, SipMessage};
+
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Local};
+
+// the older messages are dropped, a call takes a dozen of them
+const FLOW_CAPACITY: usize = 500;
+const ARROW_WIDTH: usize = 44;
+const TIME_WIDTH: usize = 13;
+
+#[derive(Clone, Default)]
+pub(crate) struct SipFlow {
+    messages: Arc<Mutex<VecDeque<FlowMessage>>>,
+}
+
+struct FlowMessage {
+    time: DateTime<Local>,
+    direction: Direction,
+    local: SocketAddr,
+    remote: SocketAddr,
+    label: String,
+    is_request: bool,
+    call_id: Option<String>,
+    cseq_method: Option<String>,
+    has_sdp: bool,
+}
+
+impl SipFlow {
+    pub fn push(&self, message: &SipMessage) {
+        let Some(message) = FlowMessage::of(message) else {
+            return;
+        };
+        let mut messages = self.lock();
+        if messages.len() == FLOW_CAPACITY {
+            messages.pop_front();
+        }
+        messages.push_back(message);
+    }
+
+    pub fn render(&self) -> Option<String> {
+        let messages = self.lock();
+        let call_id = last_invite_call_id(&messages)?;
+        let dialog: Vec<_> = messages
+            .iter()
+            .filter(|message| message.call_id.as_ref() == Some(&call_id))
+            .collect();
+        let first = dialog.first()?;
+        let (local, remote) = (first.local, first.remote);
+
+        let mut ladder = format!("==== SIP flow, Call-ID: {call_id}");
+        if let Some(status) = final_status(&messages, &call_id) {
+            let _ = write!(ladder, ", final response: {status}");
+        }
+        ladder.push_str(" ====\n");
+        let local = local.to_string();
+        let remote_column = remote.to_string();
+        let gap = ARROW_WIDTH.saturating_sub(local.len() + remote_column.len());
+        let _ = write!(
+            ladder,
+            "{:TIME_WIDTH$}{local}{:gap$}{remote_column}",
+            "", ""
+        );
+        let mut previous: Option<DateTime<Local>> = None;
+        for message in dialog {
+            let mut label = message.label.clone();
+            if message.has_sdp {
+                label.push_str(" (SDP)");
+            }
+            let _ = write!(
+                ladder,
+                "\n{} {}",
+                message.time.format("%H:%M:%S%.3f"),
+                arrow(&label, message.direction)
+            );
+            if let Some(previous) = previous {
+                let delta = (message.time - previous).num_milliseconds();
+                let _ = write!(ladder, " +{}.{:03}s", delta / 1000, delta % 1000);
+            }
+            if message.remote != remote {
+                let _ = write!(ladder, " {}", message.remote);
+            }
+            previous = Some(message.time);
+        }
+        Some(ladder)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<FlowMessage>> {
+        self.messages.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl FlowMessage {
+    fn of(message: &SipMessage) -> Option<Self> {
+        let text = String::from_utf8_lossy(&message.bytes);
+        let mut lines = text.split("\r\n");
+        let start_line = lines.next()?;
+        let (label, is_request) = match start_line.strip_prefix("SIP/2.0 ") {
+            Some(status) => (status.to_owned(), false),
+            None => (start_line.split(' ').next()?.to_owned(), true),
+        };
+        let mut call_id = None;
+        let mut cseq_method = None;
+        let mut has_sdp = false;
+        for line in lines.take_while(|line| !line.is_empty()) {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "call-id" | "i" => call_id = Some(value.to_owned()),
+                "cseq" => cseq_method = value.split_whitespace().nth(1).map(str::to_owned),
+                "content-type" | "c" => has_sdp = value.starts_with("application/sdp"),
+                _ => {}
+            }
+        }
+        Some(Self {
+            time: message.time.into(),
+            direction: message.direction,
+            local: message.local,
+            remote: message.remote,
+            label,
+            is_request,
+            call_id,
+            cseq_method,
+            has_sdp,
+        })
+    }
+}
+
+fn last_invite_call_id(messages: &VecDeque<FlowMessage>) -> Option<String> {
+    messages
+        .iter()
+        .rev()
+        .find(|message| message.is_request && message.label == "INVITE")?
+        .call_id
+        .clone()
+}
+
+// the code of the last final response to the INVITE of the call
+fn final_status(messages: &VecDeque<FlowMessage>, call_id: &str) -> Option<u16> {
+    messages
+        .iter()
+        .rev()
+        .filter(|message| !message.is_request && message.call_id.as_deref() == Some(call_id))
+        .filter(|message| message.cseq_method.as_deref() == Some("INVITE"))
+        .find_map(|message| {
+            let code: u16 = message.label.split(' ').next()?.parse().ok()?;
+            (code >= 200).then_some(code)
+        })
+}
+
+// |------- INVITE ------->| or |<------ 200 OK -------|
+fn arrow(label: &str, direction: Direction) -> String {
+    let label = format!(" {label} ");
+    let dashes = (ARROW_WIDTH - 2).saturating_sub(label.len()).max(2);
+    let left = dashes / 2;
+    let right = dashes - left;
+    match direction {
+        Direction::Sent => format!("|{}{label}{}>|", "-".repeat(left), "-".repeat(right - 1)),
+        Direction::Received => format!("|<{}{label}{}|", "-".repeat(left - 1), "-".repeat(right)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const CALL_ID: &str = "a84b4c76e66710";
+
+    fn local() -> SocketAddr {
+        SocketAddr::from(([192, 168, 1, 10], 5060))
+    }
+
+    fn registrar() -> SocketAddr {
+        SocketAddr::from(([192, 168, 1, 1], 5060))
+    }
+
+    fn message(direction: Direction, millis: u64, start_line: &str, headers: &str) -> SipMessage {
+        SipMessage {
+            direction,
+            time: UNIX_EPOCH + Duration::from_millis(millis),
+            local: local(),
+            remote: registrar(),
+            bytes: format!("{start_line}\r\n{headers}\r\n").into_bytes().into(),
+        }
+    }
+
+    fn invite(call_id: &str) -> SipMessage {
+        let headers = format!(
+            "Call-ID: {call_id}\r\nCSeq: 1 INVITE\r\nContent-Type: application/sdp\r\n"
+        );
+        message(
+            Direction::Sent,
+            0,
+            "INVITE sip:1002@192.168.1.1 SIP/2.0",
+            &headers,
+        )
+    }
+
+    fn response(millis: u64, status: &str, cseq: &str) -> SipMessage {
+        let headers = format!("Call-ID: {CALL_ID}\r\nCSeq: {cseq}\r\n");
+        message(
+            Direction::Received,
+            millis,
+            &format!("SIP/2.0 {status}"),
+            &headers,
+        )
+    }
+
+    fn flow(messages: &[SipMessage]) -> SipFlow {
+        let flow = SipFlow::default();
+        for message in messages {
+            flow.push(message);
+        }
+        flow
+    }
+
+    // the times are local, the ladder is compared without them
+    fn without_times(ladder: &str) -> Vec<String> {
+        ladder
+            .lines()
+            .enumerate()
+            .map(|(index, line)| match index {
+                0 | 1 => line.to_owned(),
+                _ => line[TIME_WIDTH..].to_owned(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn render_draws_the_ladder_of_the_last_call() {
+        let ack = message(
+            Direction::Sent,
+            2502,
+            "ACK sip:1002@192.168.1.1 SIP/2.0",
+            &format!("i: {CALL_ID}\r\nCSeq: 1 ACK\r\n"),
+        );
+        let flow = flow(&[
+            invite("previous"),
+            invite(CALL_ID),
+            response(10, "100 Trying", "1 INVITE"),
+            response(2500, "486 Busy Here", "1 INVITE"),
+            ack,
+        ]);
+        let ladder = flow.render().unwrap();
+        assert_eq!(
+            without_times(&ladder),
+            [
+                "==== SIP flow, Call-ID: a84b4c76e66710, final response: 486 ====",
+                "             192.168.1.10:5060           192.168.1.1:5060",
+                "|-------------- INVITE (SDP) ------------->|",
+                "|<-------------- 100 Trying ---------------| +0.010s",
+                "|<------------ 486 Busy Here --------------| +2.490s",
+                "|------------------ ACK ------------------>| +0.002s",
+            ]
+        );
+        let first_time = DateTime::<Local>::from(UNIX_EPOCH).format("%H:%M:%S%.3f");
+        assert!(ladder.lines().nth(2).unwrap().starts_with(&first_time.to_string()));
+    }
+
+    #[test]
+    fn render_shows_the_other_remote() {
+        let mut forked = response(40, "180 Ringing", "1 INVITE");
+        forked.remote = SocketAddr::from(([192, 168, 1, 20], 5062));
+        let ladder = flow(&[invite(CALL_ID), forked]).render().unwrap();
+        assert!(ladder.ends_with("| +0.040s 192.168.1.20:5062"));
+        assert!(!ladder.contains("final response"));
+    }
+
+    #[test]
+    fn render_needs_the_invite() {
+        let options = message(
+            Direction::Sent,
+            0,
+            "OPTIONS sip:192.168.1.1 SIP/2.0",
+            &format!("Call-ID: {CALL_ID}\r\nCSeq: 1 OPTIONS\r\n"),
+        );
+        assert_eq!(flow(&[options]).render(), None);
+        assert!(flow(&[SipMessage {
+            bytes: "not a SIP message".into(),
+            ..invite(CALL_ID)
+        }])
+        .render()
+        .is_none());
+    }
+
+    #[test]
+    fn final_status_is_the_last_final_response_to_the_invite() {
+        let flow = flow(&[
+            invite(CALL_ID),
+            response(10, "100 Trying", "1 INVITE"),
+            response(20, "407 Proxy Authentication Required", "1 INVITE"),
+            response(40, "200 OK", "2 INVITE"),
+            response(60, "200 OK", "3 BYE"),
+        ]);
+        let messages = flow.lock();
+        assert_eq!(final_status(&messages, CALL_ID), Some(200));
+        assert_eq!(final_status(&messages, "other"), None);
+    }
+
+    #[test]
+    fn final_status_skips_the_provisional_responses() {
+        let flow = flow(&[invite(CALL_ID), response(10, "183 Session Progress", "1 INVITE")]);
+        assert_eq!(final_status(&flow.lock(), CALL_ID), None);
+    }
+}
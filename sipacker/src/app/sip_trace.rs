@@ -0,0 +1,43 @@
+use anyhow::Result;
+use tracing_subscriber::{filter::LevelFilter, reload, EnvFilter, Registry};
+
+// the SIP messages are logged by the transport of ezk at the trace level
+const SIP_TRACE_DIRECTIVE: &str = "ezk_sip_core=trace";
+
+#[derive(Clone)]
+pub(crate) struct SipTrace {
+    handle: reload::Handle<EnvFilter, Registry>,
+    enabled: bool,
+}
+
+impl SipTrace {
+    pub fn init() -> Result<(reload::Layer<EnvFilter, Registry>, Self)> {
+        let (filter, handle) = reload::Layer::new(Self::env_filter(false)?);
+        let trace = Self {
+            handle,
+            enabled: false,
+        };
+        Ok((filter, trace))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> Result<()> {
+        self.handle.reload(Self::env_filter(enabled)?)?;
+        self.enabled = enabled;
+        Ok(())
+    }
+
+    fn env_filter(enabled: bool) -> Result<EnvFilter> {
+        let filter = EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .with_env_var("RUST_LOG")
+            .from_env_lossy();
+        if !enabled {
+            return Ok(filter);
+        }
+        Ok(filter.add_directive(SIP_TRACE_DIRECTIVE.parse()?))
+    }
+}
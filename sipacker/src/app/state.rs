@@ -0,0 +1,121 @@
+use crate::app::{answering_machine::AnsweringMachine, config::Account};
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "sipacker";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct RuntimeState {
+    pub account: Option<Account>,
+    pub answering_machine: Option<SavedAnsweringMachine>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedAnsweringMachine {
+    greeting: Option<PathBuf>,
+    record_dir: PathBuf,
+    record_secs: u64,
+}
+
+impl RuntimeState {
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        if !path.exists() {
+            return Self::default();
+        }
+
+        std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| toml::from_str(&content).map_err(anyhow::Error::from))
+            .inspect(|_| tracing::info!("Runtime state is loaded from {path:?}"))
+            .inspect_err(|err| tracing::warn!("Could not load runtime state from {path:?}: {err}"))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or(anyhow::Error::msg("Data directory is not found"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut state = self.clone();
+        if let Some(account) = state.account.as_mut() {
+            account.password.clear();
+        }
+        std::fs::write(&path, toml::to_string_pretty(&state)?)?;
+        tracing::debug!("Runtime state is saved to {path:?}");
+        Ok(())
+    }
+
+    fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("sipacker").join("state.toml"))
+    }
+}
+
+impl SavedAnsweringMachine {
+    pub fn of(machine: &AnsweringMachine) -> Self {
+        Self {
+            greeting: machine.greeting().cloned(),
+            record_dir: machine.record_dir().to_owned(),
+            record_secs: machine.record_duration().as_secs(),
+        }
+    }
+
+    pub fn restore(&self) -> AnsweringMachine {
+        AnsweringMachine::new(
+            self.greeting.clone(),
+            self.record_dir.clone(),
+            Duration::from_secs(self.record_secs),
+        )
+    }
+}
+
+#[cfg(feature = "keyring")]
+pub(crate) fn store_password(account: &Account) -> Result<()> {
+    keyring_entry(account)?
+        .set_password(&account.password)
+        .map_err(|err| anyhow::Error::msg(format!("Could not store the password: {err}")))
+}
+
+#[cfg(feature = "keyring")]
+pub(crate) fn load_password(account: &Account) -> Option<String> {
+    keyring_entry(account)
+        .and_then(|entry| entry.get_password().map_err(anyhow::Error::from))
+        .inspect_err(|err| tracing::warn!("Could not load the password of {}: {err}", account.user))
+        .ok()
+}
+
+#[cfg(feature = "keyring")]
+pub(crate) fn delete_password(account: &Account) {
+    let deleted = keyring_entry(account)
+        .and_then(|entry| entry.delete_credential().map_err(anyhow::Error::from));
+    if let Err(err) = deleted {
+        tracing::debug!("Could not delete the password of {}: {err}", account.user);
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_entry(account: &Account) -> Result<keyring::Entry> {
+    let name = format!("{}@{}", account.user, account.registrar);
+    Ok(keyring::Entry::new(KEYRING_SERVICE, &name)?)
+}
+
+// --keyring is rejected by the args when the keyring feature is off
+#[cfg(not(feature = "keyring"))]
+pub(crate) fn store_password(_account: &Account) -> Result<()> {
+    Err(anyhow::Error::msg("The keyring feature is not enabled"))
+}
+
+#[cfg(not(feature = "keyring"))]
+pub(crate) fn load_password(_account: &Account) -> Option<String> {
+    None
+}
+
+#[cfg(not(feature = "keyring"))]
+pub(crate) fn delete_password(_account: &Account) {}
@@ -0,0 +1,129 @@
+use std::{
+    ffi::OsStr,
+    io,
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+type Socket = std::os::unix::net::UnixDatagram;
+#[cfg(not(unix))]
+type Socket = ();
+
+// the sd_notify protocol of the Type=notify services: the states are the datagrams to
+// $NOTIFY_SOCKET, the watchdog is pinged at the half of $WATCHDOG_USEC
+pub(crate) struct Notifier {
+    socket: Socket,
+    watchdog: Option<Watchdog>,
+}
+
+struct Watchdog {
+    interval: Duration,
+    last_ping: Instant,
+}
+
+impl Notifier {
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var_os("NOTIFY_SOCKET")?;
+        let socket = connect(&path)
+            .inspect_err(|err| tracing::warn!("Could not connect to NOTIFY_SOCKET {path:?}: {err}"))
+            .ok()?;
+        let watchdog = watchdog_interval().map(|interval| Watchdog {
+            interval,
+            last_ping: Instant::now(),
+        });
+        let interval = watchdog.as_ref().map(|watchdog| watchdog.interval);
+        tracing::info!("systemd notifications are enabled, watchdog ping interval: {interval:?}");
+        Some(Self { socket, watchdog })
+    }
+
+    pub fn ready(&self) {
+        self.notify("READY=1");
+    }
+
+    pub fn status(&self, status: &str) {
+        self.notify(&format!("STATUS={}", status.replace('\n', " ")));
+    }
+
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    pub fn next_ping(&self) -> Option<Instant> {
+        self.watchdog
+            .as_ref()
+            .map(|watchdog| watchdog.last_ping + watchdog.interval)
+    }
+
+    pub fn ping_watchdog(&mut self) {
+        let Some(watchdog) = self.watchdog.as_mut() else {
+            return;
+        };
+        if watchdog.last_ping.elapsed() < watchdog.interval {
+            return;
+        }
+        watchdog.last_ping = Instant::now();
+        self.notify("WATCHDOG=1");
+    }
+
+    fn notify(&self, state: &str) {
+        if let Err(err) = send(&self.socket, state) {
+            tracing::warn!("Could not notify systemd of {state}: {err}");
+        }
+    }
+}
+
+// the watchdog of another process (e.g. the parent shell) isn't ours
+fn watchdog_interval() -> Option<Duration> {
+    let pid = std::env::var("WATCHDOG_PID").ok();
+    if pid.is_some_and(|pid| pid.parse::<u32>().ok() != Some(std::process::id())) {
+        return None;
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+// the socket name starting with '@' is in the abstract namespace
+#[cfg(unix)]
+fn connect(path: &OsStr) -> io::Result<Socket> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let socket = Socket::unbound()?;
+    match path.as_bytes().strip_prefix(b"@") {
+        Some(name) => connect_abstract(&socket, name)?,
+        None => socket.connect(path)?,
+    }
+    Ok(socket)
+}
+
+#[cfg(target_os = "linux")]
+fn connect_abstract(socket: &Socket, name: &[u8]) -> io::Result<()> {
+    use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+    socket.connect_addr(&SocketAddr::from_abstract_name(name)?)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn connect_abstract(_socket: &Socket, _name: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "The abstract sockets are supported on Linux only",
+    ))
+}
+
+#[cfg(not(unix))]
+fn connect(_path: &OsStr) -> io::Result<Socket> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "The notifications require the Unix domain sockets",
+    ))
+}
+
+#[cfg(unix)]
+fn send(socket: &Socket, state: &str) -> io::Result<()> {
+    socket.send(state.as_bytes()).map(|_| ())
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &Socket, _state: &str) -> io::Result<()> {
+    Ok(())
+}
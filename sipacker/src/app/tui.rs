@@ -0,0 +1,328 @@
+use crate::app::{
+    cli_input::{self, CommandLine},
+    command::{self, Command},
+    console::{self, Console, ConsoleMessage, Severity},
+};
+use sipacker_core::{blf::LineState, mwi::MessageSummary, user_agent::UserAgentEvent};
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Display,
+    io::Write,
+    sync::mpsc as std_mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Style},
+    widgets::{Block, List, ListItem, Paragraph},
+    DefaultTerminal, Frame,
+};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+const MAX_MESSAGES: usize = 500;
+const POLL_TIMEOUT: Duration = Duration::from_millis(200);
+
+// the terminal is restored when the thread is finished, it must be joined before the exit
+pub(crate) fn run_tui(
+    shutdown: CancellationToken,
+    command_line: CommandLine,
+    color: bool,
+) -> (mpsc::Receiver<Command>, Console, thread::JoinHandle<()>) {
+    let (command_sender, command_receiver) = mpsc::channel(20);
+    let (message_sender, message_receiver) = std_mpsc::channel();
+    let thread = thread::spawn(move || {
+        let terminal = ratatui::init();
+        let result = Tui::new(
+            command_sender,
+            message_receiver,
+            shutdown,
+            command_line,
+            color,
+        )
+        .run(terminal);
+        ratatui::restore();
+        if let Err(err) = result {
+            tracing::error!("TUI err: {err}");
+        }
+    });
+    (command_receiver, Console::tui(message_sender), thread)
+}
+
+struct Tui {
+    command_sender: mpsc::Sender<Command>,
+    message_receiver: std_mpsc::Receiver<ConsoleMessage>,
+    shutdown: CancellationToken,
+    command_line: CommandLine,
+    color: bool,
+    input: String,
+    hotkeys: bool,
+    messages: VecDeque<(Severity, String)>,
+    registered: bool,
+    call: CallState,
+    lines: BTreeMap<String, LineState>,
+    voicemail: Option<MessageSummary>,
+}
+
+enum CallState {
+    Idle,
+    Calling(Instant),
+    Incoming(String, Instant),
+    Established(Instant),
+}
+
+impl Display for CallState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let timer = |since: &Instant| {
+            let secs = since.elapsed().as_secs();
+            format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+        };
+        match self {
+            Self::Idle => write!(f, "no call"),
+            Self::Calling(since) => write!(f, "calling {}", timer(since)),
+            Self::Incoming(from, since) => {
+                write!(
+                    f,
+                    "INCOMING from {from} {} (accept call | decline call)",
+                    timer(since)
+                )
+            }
+            Self::Established(since) => write!(f, "established {}", timer(since)),
+        }
+    }
+}
+
+impl Tui {
+    fn new(
+        command_sender: mpsc::Sender<Command>,
+        message_receiver: std_mpsc::Receiver<ConsoleMessage>,
+        shutdown: CancellationToken,
+        command_line: CommandLine,
+        color: bool,
+    ) -> Self {
+        Self {
+            command_sender,
+            message_receiver,
+            shutdown,
+            command_line,
+            color,
+            input: String::new(),
+            hotkeys: false,
+            messages: VecDeque::with_capacity(MAX_MESSAGES),
+            registered: false,
+            call: CallState::Idle,
+            lines: BTreeMap::new(),
+            voicemail: None,
+        }
+    }
+
+    fn run(mut self, mut terminal: DefaultTerminal) -> Result<()> {
+        while !self.shutdown.is_cancelled() {
+            while let Ok(message) = self.message_receiver.try_recv() {
+                self.handle_message(message);
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if !event::poll(POLL_TIMEOUT)? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key.code, key.modifiers) {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_message(&mut self, message: ConsoleMessage) {
+        let (severity, text) = match message {
+            ConsoleMessage::Text(severity, text) => (severity, text),
+            ConsoleMessage::Event(event, text) => {
+                self.update_state(&event);
+                (Severity::of(&event), text)
+            }
+        };
+        if severity == Severity::Alert {
+            Self::ring_bell();
+        }
+        for line in text.lines() {
+            self.push_message(severity, line.to_owned());
+        }
+    }
+
+    // the bell doesn't move the cursor, it's written to the terminal past the frames
+    fn ring_bell() {
+        let mut stdout = std::io::stdout();
+        let _ = write!(stdout, "{}", console::BELL).and_then(|_| stdout.flush());
+    }
+
+    fn update_state(&mut self, event: &UserAgentEvent) {
+        match event {
+            UserAgentEvent::Registered { .. } => self.registered = true,
+            UserAgentEvent::Unregistered => {
+                self.registered = false;
+                self.lines.clear();
+                self.voicemail = None;
+            }
+            UserAgentEvent::LineStateChanged { extension, state } => {
+                self.lines.insert(extension.clone(), *state);
+            }
+            UserAgentEvent::MessageWaiting { summary } => self.voicemail = Some(summary.clone()),
+            UserAgentEvent::Calling { .. } => self.call = CallState::Calling(Instant::now()),
+            UserAgentEvent::IncomingCall { caller, .. } => {
+                self.call = CallState::Incoming(caller.uri.clone(), Instant::now())
+            }
+            UserAgentEvent::CallEstablished { .. } => {
+                self.call = CallState::Established(Instant::now())
+            }
+            UserAgentEvent::CallTerminated { .. } => self.call = CallState::Idle,
+            _ => {}
+        }
+    }
+
+    fn push_message(&mut self, severity: Severity, message: String) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((severity, message));
+    }
+
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        match code {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                return self.send_command(command::StopApp::new(true).into());
+            }
+            KeyCode::Esc if self.hotkeys => self.hotkeys = false,
+            KeyCode::Char(c) if self.hotkeys => {
+                if let Some(command) = cli_input::hotkey_command(c) {
+                    return self.send_command(command);
+                }
+            }
+            _ if self.hotkeys => {}
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Esc => self.input.clear(),
+            KeyCode::Enter => return self.submit(),
+            _ => {}
+        }
+        true
+    }
+
+    fn submit(&mut self) -> bool {
+        let line = std::mem::take(&mut self.input).trim().to_owned();
+        if line.is_empty() {
+            return true;
+        }
+        self.push_message(Severity::Info, format!("> {line}"));
+        if let Some(help) = self.command_line.help_line(&line) {
+            match help {
+                Ok(help) => help
+                    .lines()
+                    .for_each(|line| self.push_message(Severity::Info, line.to_owned())),
+                Err(err) => self.push_message(Severity::Error, format!("✖ Error: {err}")),
+            }
+            return true;
+        }
+        if line == "keys" {
+            self.hotkeys = true;
+            return true;
+        }
+        match self.command_line.parse(&line) {
+            Ok(command) => self.send_command(command),
+            Err(err) => {
+                self.push_message(Severity::Error, format!("✖ Error: {err}"));
+                true
+            }
+        }
+    }
+
+    fn send_command(&self, command: Command) -> bool {
+        self.command_sender.blocking_send(command).is_ok()
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [status_area, messages_area, input_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(3),
+        ])
+        .areas(frame.area());
+
+        let mut status = format!(
+            "{} | {}",
+            if self.registered {
+                "registered"
+            } else {
+                "not registered"
+            },
+            self.call
+        );
+        if !self.lines.is_empty() {
+            let lines: Vec<_> = self
+                .lines
+                .iter()
+                .map(|(extension, state)| format!("{extension} {state}"))
+                .collect();
+            status.push_str(&format!(" | {}", lines.join(", ")));
+        }
+        if let Some(voicemail) = &self.voicemail {
+            status.push_str(&format!(" | voicemail {voicemail}"));
+        }
+        frame.render_widget(
+            Paragraph::new(status).block(Block::bordered().title("Status")),
+            status_area,
+        );
+
+        let height = messages_area.height.saturating_sub(2) as usize;
+        let messages: Vec<_> = self
+            .messages
+            .iter()
+            .skip(self.messages.len().saturating_sub(height))
+            .map(|(severity, message)| {
+                ListItem::new(message.as_str()).style(self.message_style(*severity))
+            })
+            .collect();
+        frame.render_widget(
+            List::new(messages).block(Block::bordered().title("Events")),
+            messages_area,
+        );
+
+        if self.hotkeys {
+            frame.render_widget(
+                Paragraph::new(cli_input::HOTKEYS_HELP).block(Block::bordered().title("Hotkeys")),
+                input_area,
+            );
+            return;
+        }
+        frame.render_widget(
+            Paragraph::new(format!("> {}", self.input))
+                .block(Block::bordered().title("Command (help, Ctrl-C to quit)")),
+            input_area,
+        );
+        frame.set_cursor_position((
+            input_area.x + 3 + self.input.chars().count() as u16,
+            input_area.y + 1,
+        ));
+    }
+
+    fn message_style(&self, severity: Severity) -> Style {
+        let color = match severity {
+            Severity::Info => None,
+            Severity::Success => Some(Color::Green),
+            Severity::Alert => Some(Color::Yellow),
+            Severity::Error => Some(Color::Red),
+        };
+        match color {
+            Some(color) if self.color => Style::default().fg(color),
+            _ => Style::default(),
+        }
+    }
+}
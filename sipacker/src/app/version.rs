@@ -0,0 +1,41 @@
+use std::sync::LazyLock;
+
+const MEDIA: &str =
+    "SIP transport: UDP, TLS: no, codecs: PCMA (G.711 A-law), Opus: no, SRTP: SDES, ZRTP: no";
+
+const FEATURES: [(&str, bool); 6] = [
+    ("tui", cfg!(feature = "tui")),
+    ("http", cfg!(feature = "http")),
+    ("grpc", cfg!(feature = "grpc")),
+    ("hooks", cfg!(feature = "hooks")),
+    ("webhooks", cfg!(feature = "webhooks")),
+    ("keyring", cfg!(feature = "keyring")),
+];
+
+pub(crate) static LONG_VERSION: LazyLock<String> = LazyLock::new(|| {
+    let features: Vec<_> = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect();
+    let features = if features.is_empty() {
+        "none".to_owned()
+    } else {
+        features.join(", ")
+    };
+    format!(
+        "{} ({})\nezk-sip {}\n{MEDIA}\nfeatures: {features}",
+        env!("CARGO_PKG_VERSION"),
+        env!("SIPACKER_GIT_HASH"),
+        env!("SIPACKER_EZK_SIP_VERSION"),
+    )
+});
+
+pub(crate) fn info() -> String {
+    format!(
+        "sipacker {}\nplatform: {} {}",
+        *LONG_VERSION,
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+}
@@ -0,0 +1,156 @@
+use std::{fmt::Display, str::FromStr};
+#[cfg(feature = "webhooks")]
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+#[cfg(feature = "webhooks")]
+use serde_json::{json, Value};
+
+#[cfg(feature = "webhooks")]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// 1, 2, 4 and 8 s between the attempts, the notification is dropped after the last one
+#[cfg(feature = "webhooks")]
+const MAX_ATTEMPTS: u32 = 5;
+#[cfg(feature = "webhooks")]
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WebhookEvent {
+    IncomingCall,
+    MissedCall,
+    RegistrationFailed,
+}
+
+impl WebhookEvent {
+    const ALL: [Self; 3] = [
+        Self::IncomingCall,
+        Self::MissedCall,
+        Self::RegistrationFailed,
+    ];
+}
+
+impl Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let event = match self {
+            Self::IncomingCall => "incoming_call",
+            Self::MissedCall => "missed_call",
+            Self::RegistrationFailed => "registration_failed",
+        };
+        write!(f, "{event}")
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|event| event.to_string() == value)
+            .ok_or_else(|| {
+                anyhow::Error::msg(format!(
+                    "Unknown webhook event: {value}, expected incoming_call, missed_call or registration_failed"
+                ))
+            })
+    }
+}
+
+#[cfg(feature = "webhooks")]
+#[derive(Debug, Clone)]
+pub(crate) struct Webhook {
+    url: String,
+    events: Vec<WebhookEvent>,
+}
+
+#[cfg(feature = "webhooks")]
+impl Webhook {
+    pub fn new(url: String, events: Vec<WebhookEvent>) -> Self {
+        Self { url, events }
+    }
+
+    fn accepts(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+#[cfg(feature = "webhooks")]
+pub(crate) struct Webhooks {
+    client: reqwest::Client,
+    webhooks: Arc<Vec<Webhook>>,
+}
+
+#[cfg(feature = "webhooks")]
+impl Webhooks {
+    pub fn new(webhooks: Vec<Webhook>) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|err| {
+                anyhow::Error::msg(format!("Could not create the webhook client: {err}"))
+            })?;
+        for webhook in &webhooks {
+            tracing::info!("Webhook: {}", webhook.url);
+        }
+        Ok(Self {
+            client,
+            webhooks: Arc::new(webhooks),
+        })
+    }
+
+    pub fn notify(&self, event: WebhookEvent, text: String, fields: Value) {
+        let mut payload = json!({
+            "event": event.to_string(),
+            "timestamp": chrono::Local::now().to_rfc3339(),
+            "text": text,
+        });
+        if let (Some(payload), Value::Object(fields)) = (payload.as_object_mut(), fields) {
+            payload.extend(fields);
+        }
+        for webhook in self
+            .webhooks
+            .iter()
+            .filter(|webhook| webhook.accepts(event))
+        {
+            tokio::spawn(deliver(
+                self.client.clone(),
+                webhook.url.clone(),
+                payload.clone(),
+            ));
+        }
+    }
+}
+
+// the network errors, 429 and 5xx are retried, the other responses are final
+#[cfg(feature = "webhooks")]
+async fn deliver(client: reqwest::Client, url: String, payload: Value) {
+    let mut backoff = FIRST_BACKOFF;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let error = match client.post(&url).json(&payload).send().await {
+            Ok(response) if response.status().is_success() => {
+                tracing::debug!("Webhook {url} is notified of {}", payload["event"]);
+                return;
+            }
+            Ok(response)
+                if response.status().is_server_error()
+                    || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS =>
+            {
+                format!("HTTP {}", response.status())
+            }
+            Ok(response) => {
+                tracing::warn!(
+                    "Webhook {url} rejected the notification: HTTP {}",
+                    response.status()
+                );
+                return;
+            }
+            Err(err) => err.to_string(),
+        };
+        if attempt == MAX_ATTEMPTS {
+            tracing::warn!("Webhook {url} failed after {MAX_ATTEMPTS} attempts: {error}");
+            return;
+        }
+        tracing::debug!("Webhook {url} attempt {attempt} failed: {error}, retrying in {backoff:?}");
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
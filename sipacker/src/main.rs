@@ -1,9 +1,14 @@
-use anyhow::Result;
+use std::process::ExitCode;
 
 use clap::Parser;
-use sipacker_ua::app::{application, args};
+use sipacker_ua::app::{application, args, exit};
 
-fn main() -> Result<()> {
-    let args = args::Args::try_parse()?;
-    application::run_app(args)
+// clap exits with 2 on the invalid args
+fn main() -> ExitCode {
+    let args = args::Args::parse();
+    let result = application::run_app(args);
+    if let Err(err) = &result {
+        eprintln!("Error: {err:#}");
+    }
+    exit::exit_code(&result)
 }
@@ -1,3 +0,0 @@
-pub mod audio;
-pub(crate) mod call;
-pub mod user_agent;